@@ -27,6 +27,11 @@ pub mod cached;
 #[cfg(feature = "std")]
 pub use cached::CachedOnDiskCorpus;
 
+#[cfg(feature = "std")]
+pub mod shared;
+#[cfg(feature = "std")]
+pub use shared::SharedCorpus;
+
 #[cfg(all(feature = "cmin", unix))]
 pub mod minimizer;
 
@@ -0,0 +1,112 @@
+//! The [`SharedCorpus`] shares the inputs found by fuzzing threads in the same process.
+//!
+//! Each thread keeps its own corpus and scheduler. Threads publish their new corpus entries to the
+//! [`SharedCorpus`] and import the entries of the other threads, see
+//! [`crate::stages::SharedCorpusSyncStage`]. Unlike syncing through an event manager, the inputs
+//! are shared without serialization.
+
+use alloc::{sync::Arc, vec::Vec};
+use std::sync::RwLock;
+
+use crate::Error;
+
+/// An entry of the [`SharedCorpus`]
+#[derive(Debug)]
+struct SharedEntry<I> {
+    thread_id: usize,
+    input: I,
+}
+
+/// The corpus entries of all fuzzing threads in this process, appended in the order they were found.
+#[derive(Debug)]
+pub struct SharedCorpus<I> {
+    entries: Arc<RwLock<Vec<SharedEntry<I>>>>,
+}
+
+impl<I> Clone for SharedCorpus<I> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<I> Default for SharedCorpus<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I> SharedCorpus<I> {
+    /// Creates a new, empty [`SharedCorpus`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            entries: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    /// Publishes a corpus entry found by the thread `thread_id`
+    pub fn publish(&self, thread_id: usize, input: I) -> Result<(), Error> {
+        self.entries
+            .write()
+            .map_err(|_| Error::illegal_state("SharedCorpus lock poisoned"))?
+            .push(SharedEntry { thread_id, input });
+        Ok(())
+    }
+
+    /// The number of entries published by all threads
+    pub fn len(&self) -> Result<usize, Error> {
+        Ok(self
+            .entries
+            .read()
+            .map_err(|_| Error::illegal_state("SharedCorpus lock poisoned"))?
+            .len())
+    }
+
+    /// Returns `true` if no thread published an entry yet
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Returns the entries published by the other threads since `cursor`, and the new cursor
+    pub fn fetch_since(&self, thread_id: usize, cursor: usize) -> Result<(Vec<I>, usize), Error>
+    where
+        I: Clone,
+    {
+        let entries = self
+            .entries
+            .read()
+            .map_err(|_| Error::illegal_state("SharedCorpus lock poisoned"))?;
+        let inputs = entries
+            .iter()
+            .skip(cursor)
+            .filter(|entry| entry.thread_id != thread_id)
+            .map(|entry| entry.input.clone())
+            .collect();
+        Ok((inputs, entries.len().max(cursor)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedCorpus;
+
+    #[test]
+    fn test_fetch_since() {
+        let shared = SharedCorpus::new();
+        shared.publish(0, 1_u32).unwrap();
+        shared.publish(1, 2).unwrap();
+        shared.publish(0, 3).unwrap();
+
+        let (inputs, cursor) = shared.fetch_since(1, 0).unwrap();
+        assert_eq!(inputs, vec![1, 3]);
+        assert_eq!(cursor, 3);
+
+        shared.publish(0, 4).unwrap();
+        let (inputs, cursor) = shared.fetch_since(1, cursor).unwrap();
+        assert_eq!(inputs, vec![4]);
+        assert_eq!(cursor, 4);
+        assert!(shared.fetch_since(0, cursor).unwrap().0.is_empty());
+    }
+}
@@ -49,7 +49,7 @@ use typed_builder::TypedBuilder;
 
 use super::HasTimeout;
 #[cfg(target_os = "linux")]
-use crate::executors::hooks::ExecutorHooksTuple;
+use crate::executors::{hooks::ExecutorHooksTuple, sandbox::Sandbox};
 use crate::{
     Error,
     executors::{Executor, ExitKind, HasObservers},
@@ -95,6 +95,9 @@ pub struct StdCommandConfigurator {
     input_location: InputLocation,
     /// The Command to execute
     command: Command,
+    /// The sandbox to spawn the child in
+    #[cfg(target_os = "linux")]
+    sandbox: Option<Sandbox>,
 }
 
 impl<I> CommandConfigurator<I> for StdCommandConfigurator
@@ -148,6 +151,10 @@ where
                 if let Some(cwd) = self.command.get_current_dir() {
                     cmd.current_dir(cwd);
                 }
+                #[cfg(target_os = "linux")]
+                if let Some(sandbox) = &self.sandbox {
+                    sandbox.apply(&mut cmd);
+                }
                 Ok(cmd.spawn()?)
             }
             InputLocation::StdIn => {
@@ -528,6 +535,8 @@ pub struct CommandExecutorBuilder {
     cwd: Option<PathBuf>,
     envs: Vec<(OsString, OsString)>,
     timeout: Duration,
+    #[cfg(target_os = "linux")]
+    sandbox: Option<Sandbox>,
 }
 
 impl Default for CommandExecutorBuilder {
@@ -550,6 +559,8 @@ impl CommandExecutorBuilder {
             envs: vec![],
             timeout: Duration::from_secs(5),
             debug_child: false,
+            #[cfg(target_os = "linux")]
+            sandbox: None,
         }
     }
 
@@ -680,6 +691,14 @@ impl CommandExecutorBuilder {
         self
     }
 
+    /// Spawns the child in the given [`Sandbox`].
+    /// Wrap the executor in a [`crate::executors::SandboxExecutor`] to detect resource exhaustion.
+    #[cfg(target_os = "linux")]
+    pub fn sandbox(&mut self, sandbox: Sandbox) -> &mut CommandExecutorBuilder {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
     /// Builds the `CommandExecutor`
     pub fn build<I, OT, S>(
         &self,
@@ -726,6 +745,11 @@ impl CommandExecutorBuilder {
             command.stderr(Stdio::piped());
         }
 
+        #[cfg(target_os = "linux")]
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.apply(&mut command);
+        }
+
         let configurator = StdCommandConfigurator {
             debug_child: self.debug_child,
             stdout_observer: self.stdout.clone(),
@@ -733,6 +757,8 @@ impl CommandExecutorBuilder {
             input_location: self.input_location.clone(),
             timeout: self.timeout,
             command,
+            #[cfg(target_os = "linux")]
+            sandbox: self.sandbox.clone(),
         };
         Ok(
             <StdCommandConfigurator as CommandConfigurator<I>>::into_executor::<OT, S>(
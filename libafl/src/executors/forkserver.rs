@@ -38,13 +38,15 @@ use nix::{
 };
 
 use super::HasTimeout;
+#[cfg(target_os = "linux")]
+use crate::executors::sandbox::Sandbox;
 #[cfg(feature = "regex")]
 use crate::observers::{
     AsanBacktraceObserver, get_asan_runtime_flags, get_asan_runtime_flags_with_log_path,
 };
 use crate::{
     Error,
-    executors::{Executor, ExitKind, HasObservers},
+    executors::{BatchExecutor, Executor, ExitKind, HasObservers},
     inputs::{BytesInput, HasTargetBytes, Input, NopTargetBytesConverter, TargetBytesConverter},
     mutators::Tokens,
     observers::{MapObserver, Observer, ObserversTuple},
@@ -76,6 +78,10 @@ const FS_NEW_OPT_AUTODTCT: i32 = 0x00000800_u32 as i32;
 #[expect(clippy::cast_possible_wrap)]
 const FS_OPT_AUTODTCT: i32 = 0x10000000_u32 as i32;
 
+/// The target runs batches of inputs from the shared memory testcase in its persistent loop
+#[expect(clippy::cast_possible_wrap)]
+const FS_NEW_OPT_BATCH: i32 = 0x00001000_u32 as i32;
+
 #[expect(clippy::cast_possible_wrap)]
 const FS_ERROR_MAP_SIZE: i32 = 1_u32 as i32;
 #[expect(clippy::cast_possible_wrap)]
@@ -121,6 +127,11 @@ fn report_error_and_exit(status: i32) -> Result<(), Error> {
 
 /// The length of header bytes which tells shmem size
 const SHMEM_FUZZ_HDR_SIZE: usize = 4;
+/// The environment variable for the shared memory of batch results
+const SHMEM_BATCH_ENV_VAR: &str = "__AFL_SHM_BATCH_ID";
+/// The header of the batch results: the number of finished inputs, the coverage map stride, and
+/// whether the shared memory testcase holds a batch or a single input
+const SHMEM_BATCH_HDR_SIZE: usize = 12;
 const MAX_INPUT_SIZE_DEFAULT: usize = 1024 * 1024;
 const MIN_INPUT_SIZE_DEFAULT: usize = 1;
 
@@ -363,6 +374,40 @@ impl Forkserver {
         coverage_map_size: Option<usize>,
         debug_output: bool,
         kill_signal: Signal,
+    ) -> Result<Self, Error> {
+        Self::spawn(
+            target,
+            args,
+            envs,
+            input_filefd,
+            use_stdin,
+            memlimit,
+            is_persistent,
+            is_deferred_frksrv,
+            dump_asan_logs,
+            coverage_map_size,
+            debug_output,
+            kill_signal,
+            &|_| {},
+        )
+    }
+
+    /// Spawns the [`Forkserver`], calling `configure` on its [`Command`] before
+    #[expect(clippy::too_many_arguments)]
+    fn spawn(
+        target: OsString,
+        args: Vec<OsString>,
+        envs: Vec<(OsString, OsString)>,
+        input_filefd: RawFd,
+        use_stdin: bool,
+        memlimit: u64,
+        is_persistent: bool,
+        is_deferred_frksrv: bool,
+        dump_asan_logs: bool,
+        coverage_map_size: Option<usize>,
+        debug_output: bool,
+        kill_signal: Signal,
+        configure: &dyn Fn(&mut Command),
     ) -> Result<Self, Error> {
         let Some(coverage_map_size) = coverage_map_size else {
             return Err(Error::unknown(
@@ -415,6 +460,8 @@ impl Forkserver {
             command.env("__AFL_DEFER_FORKSRV", "1");
         }
 
+        configure(&mut command);
+
         #[cfg(feature = "regex")]
         {
             let asan_options = if dump_asan_logs {
@@ -498,6 +545,12 @@ impl Forkserver {
         self.status = status;
     }
 
+    /// The pid of the forkserver, the parent of all children
+    #[must_use]
+    pub fn forkserver_pid(&self) -> Pid {
+        Pid::from_raw(self.fsrv_handle.id().try_into().unwrap())
+    }
+
     /// The child pid
     #[must_use]
     pub fn child_pid(&self) -> Pid {
@@ -625,6 +678,11 @@ pub struct ForkserverExecutor<I, OT, S, SHM, TC> {
     asan_obs: Handle<AsanBacktraceObserver>,
     timeout: TimeSpec,
     crash_exitcode: Option<i8>,
+    /// The shared memory of the batch results, in batched mode
+    batch: Option<SHM>,
+    batch_size: usize,
+    /// The number of inputs of the last batch with their coverage in the batch results
+    batch_finished: usize,
 }
 
 impl<I, OT, S, SHM, TC> Debug for ForkserverExecutor<I, OT, S, SHM, TC>
@@ -643,6 +701,7 @@ where
             .field("forkserver", &self.forkserver)
             .field("observers", &self.observers)
             .field("map", &self.map)
+            .field("batch_size", &self.batch_size)
             .finish_non_exhaustive()
     }
 }
@@ -707,6 +766,12 @@ where
     /// Execute input, but side-step the execution counter.
     #[inline]
     fn execute_input_uncounted(&mut self, input: &I) -> Result<ExitKind, Error> {
+        if let Some(batch) = &mut self.batch {
+            // Single inputs use the classic protocol, their coverage stays in the regular map
+            batch.as_slice_mut()[8..SHMEM_BATCH_HDR_SIZE].fill(0);
+            self.batch_finished = 0;
+        }
+
         let mut exit_kind = ExitKind::Ok;
 
         let last_run_timed_out = self.forkserver.last_run_timed_out_raw();
@@ -798,6 +863,154 @@ where
 
         Ok(exit_kind)
     }
+
+    /// Writes a batch into the shared memory testcase. Returns the number of inputs written.
+    fn write_batch(&mut self, inputs: &[I]) -> usize {
+        let count = inputs.len().min(self.batch_size);
+        let inputs: Vec<_> = inputs[..count]
+            .iter()
+            .map(|input| self.target_bytes_converter.to_target_bytes(input))
+            .collect();
+        let map = self.map.as_mut().unwrap();
+        write_batch_into(
+            map.as_slice_mut(),
+            &inputs,
+            self.min_input_size,
+            self.max_input_size,
+        )
+    }
+
+    /// Execute a batch, but side-step the execution counter.
+    fn execute_batch_uncounted(&mut self, inputs: &[I]) -> Result<Vec<ExitKind>, Error> {
+        let count = self.write_batch(inputs);
+        if count == 0 {
+            return Ok(vec![]);
+        }
+        let batch = self.batch.as_mut().unwrap();
+        batch.as_slice_mut()[..4].fill(0);
+        batch.as_slice_mut()[8..SHMEM_BATCH_HDR_SIZE].copy_from_slice(&1_u32.to_ne_bytes());
+        self.batch_finished = 0;
+
+        let last_run_timed_out = self.forkserver.last_run_timed_out_raw();
+        self.forkserver.set_last_run_timed_out(false);
+        if let Err(err) = self.forkserver.write_ctl(last_run_timed_out) {
+            return Err(Error::unknown(format!(
+                "Unable to request new process from fork server (OOM?): {err:?}"
+            )));
+        }
+        let pid = self.forkserver.read_st().map_err(|err| {
+            Error::unknown(format!(
+                "Unable to request new process from fork server (OOM?): {err:?}"
+            ))
+        })?;
+        if pid <= 0 {
+            return Err(Error::unknown(
+                "Fork server is misbehaving (OOM?)".to_string(),
+            ));
+        }
+        self.forkserver.set_child_pid(Pid::from_raw(pid));
+
+        // Each input gets the full timeout
+        let timeout = Duration::from(self.timeout) * u32::try_from(count).unwrap_or(u32::MAX);
+        let last_exit_kind = if let Some(status) = self
+            .forkserver
+            .read_st_timed(&TimeSpec::from_duration(timeout))?
+        {
+            self.forkserver.set_status(status);
+            let exitcode_is_crash = if let Some(crash_exitcode) = self.crash_exitcode {
+                (libc::WEXITSTATUS(status) as i8) == crash_exitcode
+            } else {
+                false
+            };
+            if libc::WIFSIGNALED(status) || exitcode_is_crash {
+                #[cfg(feature = "regex")]
+                if let Some(asan_observer) = self.observers.get_mut(&self.asan_obs) {
+                    asan_observer.parse_asan_output_from_asan_log_file(pid)?;
+                }
+                Some(ExitKind::Crash)
+            } else {
+                None
+            }
+        } else {
+            self.forkserver.set_last_run_timed_out(true);
+            let _ = kill(self.forkserver().child_pid(), self.forkserver.kill_signal);
+            if let Err(err) = self.forkserver.read_st() {
+                return Err(Error::unknown(format!(
+                    "Could not kill timed-out child: {err:?}"
+                )));
+            }
+            Some(ExitKind::Timeout)
+        };
+
+        if !libc::WIFSTOPPED(self.forkserver().status()) {
+            self.forkserver.reset_child_pid();
+        }
+
+        let batch = self.batch.as_ref().unwrap();
+        let finished = u32::from_ne_bytes(batch.as_slice()[..4].try_into().unwrap()) as usize;
+        self.batch_finished = finished.min(count);
+
+        let mut exit_kinds = vec![ExitKind::Ok; self.batch_finished];
+        if self.batch_finished < count {
+            // The input that did not finish ended the batch, a normal exit counts as `Ok`
+            exit_kinds.push(last_exit_kind.unwrap_or(ExitKind::Ok));
+        } else if let Some(exit_kind) = last_exit_kind {
+            // The child died after finishing all inputs, blame the last one. Its coverage was
+            // saved in the batch results already.
+            exit_kinds[count - 1] = exit_kind;
+        }
+        Ok(exit_kinds)
+    }
+}
+
+/// Lays out a batch in the shared memory testcase `buf`: the total length, the number of inputs,
+/// their lengths, then their contents. The inputs are clamped to `min_input_size..=max_input_size`
+/// like single inputs, and the batch ends at the first input not fitting into `buf`. The first
+/// input is truncated instead, so that every batch makes progress.
+///
+/// Returns the number of inputs written.
+fn write_batch_into(
+    buf: &mut [u8],
+    inputs: &[OwnedSlice<'_, u8>],
+    min_input_size: usize,
+    max_input_size: usize,
+) -> usize {
+    let capacity = buf.len() - SHMEM_FUZZ_HDR_SIZE;
+
+    let mut lens = Vec::with_capacity(inputs.len());
+    let mut data_len = 0;
+    for input in inputs {
+        let mut len = input.as_slice().len().clamp(min_input_size, max_input_size);
+        // The count, and the length of each input so far
+        let header_len = 4 * (lens.len() + 2);
+        let Some(room) = capacity.checked_sub(header_len + data_len) else {
+            break;
+        };
+        if len > room {
+            if !lens.is_empty() {
+                break;
+            }
+            len = room;
+        }
+        lens.push(len);
+        data_len += len;
+    }
+
+    let (total, buf) = buf.split_at_mut(SHMEM_FUZZ_HDR_SIZE);
+    let header_len = 4 * (lens.len() + 1);
+    buf[..4].copy_from_slice(&(lens.len() as u32).to_ne_bytes());
+    let mut offset = header_len;
+    for (i, (input, len)) in inputs.iter().zip(&lens).enumerate() {
+        buf[4 * (i + 1)..4 * (i + 2)].copy_from_slice(&(*len as u32).to_ne_bytes());
+        let bytes = input.as_slice();
+        let copied = bytes.len().min(*len);
+        buf[offset..offset + copied].copy_from_slice(&bytes[..copied]);
+        // Extend like AFL++ does
+        buf[offset + copied..offset + len].fill(0);
+        offset += len;
+    }
+    total.copy_from_slice(&(offset as u32).to_ne_bytes()[..SHMEM_FUZZ_HDR_SIZE]);
+    lens.len()
 }
 
 /// The builder for `ForkserverExecutor`
@@ -824,6 +1037,10 @@ pub struct ForkserverExecutorBuilder<'a, TC, SP> {
     asan_obs: Option<Handle<AsanBacktraceObserver>>,
     crash_exitcode: Option<i8>,
     target_bytes_converter: TC,
+    #[cfg(target_os = "linux")]
+    sandbox: Option<Sandbox>,
+    batch_size: usize,
+    target_supports_batch: bool,
 }
 
 impl<'a, TC, SHM, SP> ForkserverExecutorBuilder<'a, TC, SP>
@@ -845,7 +1062,7 @@ where
         OT: ObserversTuple<I, S>,
         TC: TargetBytesConverter<I>,
     {
-        let (forkserver, input_file, map) = self.build_helper()?;
+        let (forkserver, input_file, map, batch) = self.build_helper()?;
 
         let target = self.program.take().unwrap();
         log::info!(
@@ -895,6 +1112,9 @@ where
                 .unwrap_or(AsanBacktraceObserver::default().handle()),
             crash_exitcode: self.crash_exitcode,
             target_bytes_converter: self.target_bytes_converter,
+            batch_size: if batch.is_some() { self.batch_size } else { 1 },
+            batch,
+            batch_finished: 0,
         })
     }
 
@@ -911,7 +1131,7 @@ where
         MO: MapObserver + Truncate, // TODO maybe enforce Entry = u8 for the cov map
         OT: ObserversTuple<I, S> + Prepend<MO>,
     {
-        let (forkserver, input_file, map) = self.build_helper()?;
+        let (forkserver, input_file, map, batch) = self.build_helper()?;
 
         let target = self.program.take().unwrap();
         log::info!(
@@ -959,11 +1179,14 @@ where
                 .unwrap_or(AsanBacktraceObserver::default().handle()),
             crash_exitcode: self.crash_exitcode,
             target_bytes_converter: self.target_bytes_converter,
+            batch_size: if batch.is_some() { self.batch_size } else { 1 },
+            batch,
+            batch_finished: 0,
         })
     }
 
     #[expect(clippy::pedantic)]
-    fn build_helper(&mut self) -> Result<(Forkserver, InputFile, Option<SHM>), Error> {
+    fn build_helper(&mut self) -> Result<(Forkserver, InputFile, Option<SHM>, Option<SHM>), Error> {
         let input_filename = match &self.input_filename {
             Some(name) => name.clone(),
             None => {
@@ -991,8 +1214,26 @@ where
             }
         };
 
+        let batch = if self.batch_size > 1 {
+            let (Some(provider), Some(map_size)) = (&mut self.shmem_provider, self.map_size) else {
+                return Err(Error::illegal_argument(
+                    "Batched execution needs a shmem_provider and a coverage_map_size",
+                ));
+            };
+            let mut shmem =
+                provider.new_shmem(SHMEM_BATCH_HDR_SIZE + self.batch_size * map_size)?;
+            shmem.as_slice_mut()[4..8].copy_from_slice(&(map_size as u32).to_ne_bytes());
+            self.envs.push((
+                OsString::from(SHMEM_BATCH_ENV_VAR),
+                OsString::from(shmem.id().to_string()),
+            ));
+            Some(shmem)
+        } else {
+            None
+        };
+
         let mut forkserver = match &self.program {
-            Some(t) => Forkserver::with_kill_signal(
+            Some(t) => Forkserver::spawn(
                 t.clone(),
                 self.arguments.clone(),
                 self.envs.clone(),
@@ -1005,6 +1246,12 @@ where
                 self.map_size,
                 self.debug_child,
                 self.kill_signal.unwrap_or(KILL_SIGNAL_DEFAULT),
+                &|command| {
+                    #[cfg(target_os = "linux")]
+                    if let Some(sandbox) = &self.sandbox {
+                        sandbox.apply(command);
+                    }
+                },
             )?,
             None => {
                 return Err(Error::illegal_argument(
@@ -1028,7 +1275,13 @@ where
         } else {
             self.initialize_forkserver(version_status, map.as_ref(), &mut forkserver)?;
         }
-        Ok((forkserver, input_file, map))
+
+        if batch.is_some() && !(self.uses_shmem_testcase && self.target_supports_batch) {
+            return Err(Error::illegal_state(
+                "The target does not support batched execution. It needs shared memory fuzzing and the persistent loop of the libafl_targets forkserver.",
+            ));
+        }
+        Ok((forkserver, input_file, map, batch))
     }
 
     fn is_old_forkserver(version_status: i32) -> bool {
@@ -1097,6 +1350,10 @@ where
             }
         }
 
+        if status & FS_NEW_OPT_BATCH != 0 {
+            self.target_supports_batch = true;
+        }
+
         if status & FS_NEW_OPT_AUTODTCT != 0 {
             // Here unlike shmem input fuzzing, we are forced to read things
             // hence no self.autotokens.is_some() to check if we proceed
@@ -1459,6 +1716,27 @@ where
         self
     }
 
+    /// Runs up to `batch_size` inputs per roundtrip with the forkserver, back-to-back in the
+    /// persistent loop of the target. Needs a [`Self::shmem_provider`], a [`Self::coverage_map_size`]
+    /// and a target built with the `libafl_targets` forkserver. Every input gets its own coverage
+    /// map in an additional shared memory, see [`BatchExecutor`]. Only batching stages, such as
+    /// [`crate::stages::BatchMutationalStage`], use batches; single executions keep the classic
+    /// protocol.
+    #[must_use]
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Spawns the forkserver, and thereby all children, in the given [`Sandbox`].
+    /// Wrap the executor in a [`crate::executors::SandboxExecutor`] to detect resource exhaustion.
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
     /// Determine if the asan observer is present (always false if feature "regex" is disabled)
     #[cfg(feature = "regex")]
     pub fn has_asan_obs(&self) -> bool {
@@ -1503,6 +1781,10 @@ impl<'a> ForkserverExecutorBuilder<'a, NopTargetBytesConverter<BytesInput>, Unix
             asan_obs: None,
             crash_exitcode: None,
             target_bytes_converter: NopTargetBytesConverter::new(),
+            #[cfg(target_os = "linux")]
+            sandbox: None,
+            batch_size: 1,
+            target_supports_batch: false,
         }
     }
 }
@@ -1536,6 +1818,10 @@ impl<'a, TC> ForkserverExecutorBuilder<'a, TC, UnixShMemProvider> {
             asan_obs: self.asan_obs,
             crash_exitcode: self.crash_exitcode,
             target_bytes_converter: self.target_bytes_converter,
+            #[cfg(target_os = "linux")]
+            sandbox: self.sandbox,
+            batch_size: self.batch_size,
+            target_supports_batch: self.target_supports_batch,
         }
     }
 }
@@ -1569,6 +1855,10 @@ impl<'a, TC, SP> ForkserverExecutorBuilder<'a, TC, SP> {
             asan_obs: self.asan_obs,
             crash_exitcode: self.crash_exitcode,
             target_bytes_converter,
+            #[cfg(target_os = "linux")]
+            sandbox: self.sandbox,
+            batch_size: self.batch_size,
+            target_supports_batch: self.target_supports_batch,
         }
     }
 }
@@ -1600,6 +1890,47 @@ where
     }
 }
 
+impl<I, OT, S, SHM, TC> BatchExecutor<I, S> for ForkserverExecutor<I, OT, S, SHM, TC>
+where
+    OT: ObserversTuple<I, S>,
+    S: HasExecutions,
+    TC: TargetBytesConverter<I>,
+    SHM: ShMem,
+{
+    /// The maximum number of inputs per batch, 1 if batching is disabled
+    fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    fn batch_coverage(&self, index: usize) -> Option<&[u8]> {
+        let batch = self.batch.as_ref()?;
+        if index >= self.batch_finished {
+            return None;
+        }
+        let slice = batch.as_slice();
+        let stride = u32::from_ne_bytes(slice[4..8].try_into().unwrap()) as usize;
+        let len = self
+            .map_size
+            .map_or(stride, |map_size| map_size.min(stride));
+        let start = SHMEM_BATCH_HDR_SIZE + index * stride;
+        Some(&slice[start..start + len])
+    }
+
+    /// Batches also end early when the inputs do not fit into the shared memory testcase.
+    fn run_batch(&mut self, state: &mut S, inputs: &[I]) -> Result<Vec<ExitKind>, Error> {
+        let exit_kinds = if self.batch.is_some() {
+            self.execute_batch_uncounted(inputs)?
+        } else {
+            match inputs.first() {
+                Some(input) => vec![self.execute_input_uncounted(input)?],
+                None => vec![],
+            }
+        };
+        *state.executions_mut() += exit_kinds.len() as u64;
+        Ok(exit_kinds)
+    }
+}
+
 impl<I, OT, S, SHM, TC> HasTimeout for ForkserverExecutor<I, OT, S, SHM, TC> {
     #[inline]
     fn timeout(&self) -> Duration {
@@ -1635,6 +1966,7 @@ mod tests {
 
     use libafl_bolts::{
         AsSliceMut,
+        ownedref::OwnedSlice,
         shmem::{ShMem, ShMemProvider, UnixShMemProvider},
         tuples::tuple_list,
     };
@@ -1643,7 +1975,10 @@ mod tests {
     use crate::{
         Error,
         corpus::NopCorpus,
-        executors::forkserver::{FAILED_TO_START_FORKSERVER_MSG, ForkserverExecutor},
+        executors::forkserver::{
+            FAILED_TO_START_FORKSERVER_MSG, ForkserverExecutor, SHMEM_FUZZ_HDR_SIZE,
+            write_batch_into,
+        },
         inputs::BytesInput,
         observers::{ConstMapObserver, HitcountsMapObserver},
     };
@@ -1692,4 +2027,42 @@ mod tests {
         };
         assert!(result);
     }
+
+    fn read_u32(buf: &[u8], at: usize) -> usize {
+        u32::from_ne_bytes(buf[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let mut buf = vec![0xff; SHMEM_FUZZ_HDR_SIZE + 64];
+        let inputs = [
+            OwnedSlice::from(vec![1_u8, 2, 3]),
+            OwnedSlice::from(vec![4_u8]),
+        ];
+        assert_eq!(write_batch_into(&mut buf, &inputs, 2, 64), 2);
+
+        // The count and the lengths, then the contents, extended to the minimum size
+        assert_eq!(read_u32(&buf, 0), 17);
+        assert_eq!(read_u32(&buf, 4), 2);
+        assert_eq!(read_u32(&buf, 8), 3);
+        assert_eq!(read_u32(&buf, 12), 2);
+        assert_eq!(buf[16..21], [1, 2, 3, 4, 0]);
+    }
+
+    #[test]
+    fn test_write_batch_max_size() {
+        const MAX_INPUT_SIZE: usize = 64;
+        let mut buf = vec![0xff; SHMEM_FUZZ_HDR_SIZE + MAX_INPUT_SIZE];
+        let inputs = [
+            OwnedSlice::from(vec![1_u8; MAX_INPUT_SIZE]),
+            OwnedSlice::from(vec![2_u8; 4]),
+        ];
+
+        // The first input is truncated to leave room for the header, the second one does not fit
+        assert_eq!(write_batch_into(&mut buf, &inputs, 0, MAX_INPUT_SIZE), 1);
+        assert_eq!(read_u32(&buf, 0), MAX_INPUT_SIZE);
+        assert_eq!(read_u32(&buf, 4), 1);
+        assert_eq!(read_u32(&buf, 8), MAX_INPUT_SIZE - 8);
+        assert!(buf[12..].iter().all(|&b| b == 1));
+    }
 }
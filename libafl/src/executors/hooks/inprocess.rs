@@ -201,7 +201,7 @@ impl<I, S> ExecutorHook<I, S> for InProcessHooks<I, S> {
         // Don't remove these pointer settings.
         // Imagine there are two executors, you have to set the correct crash handlers for each of the executor.
         unsafe {
-            let data = &raw mut GLOBAL_STATE;
+            let data = current_handler_data();
             assert!((*data).crash_handler.is_null());
             // usually timeout handler and crash handler is set together
             // so no check for timeout handler is null or not
@@ -221,7 +221,7 @@ impl<I, S> ExecutorHook<I, S> for InProcessHooks<I, S> {
         self.timer_mut().unset_timer();
         #[cfg(feature = "std")]
         unsafe {
-            let data = &raw mut GLOBAL_STATE;
+            let data = current_handler_data();
             (*data).crash_handler = null();
             (*data).timeout_handler = null();
         }
@@ -267,7 +267,13 @@ impl<I, S> InProcessHooks<I, S> {
             #[cfg(feature = "std")]
             timeout_handler: unix_signal_handler::inproc_timeout_handler::<E, EM, I, OF, S, Z>
                 as *const _,
-            #[cfg(feature = "std")]
+            #[cfg(all(feature = "std", target_os = "linux"))]
+            timer: if thread_local_handler_data_enabled() {
+                TimerStruct::for_current_thread(exec_tmout)
+            } else {
+                TimerStruct::new(exec_tmout)
+            },
+            #[cfg(all(feature = "std", not(target_os = "linux")))]
             timer: TimerStruct::new(exec_tmout),
             phantom: PhantomData,
         })
@@ -408,6 +414,38 @@ impl InProcessExecutorHandlerData {
     #[cfg(feature = "std")]
     const SIGNAL_HANDLER_MAX_DEPTH: usize = 3;
 
+    /// Handler data outside of any target run
+    const fn new() -> Self {
+        Self {
+            // The state ptr for signal handling
+            state_ptr: null_mut(),
+            // The event manager ptr for signal handling
+            event_mgr_ptr: null_mut(),
+            // The fuzzer ptr for signal handling
+            fuzzer_ptr: null_mut(),
+            // The executor ptr for signal handling
+            executor_ptr: null(),
+            // The current input for signal handling
+            current_input_ptr: null(),
+
+            #[cfg(feature = "std")]
+            signal_handler_depth: 0,
+
+            // The crash handler fn
+            #[cfg(feature = "std")]
+            crash_handler: null(),
+            // The timeout handler fn
+            #[cfg(feature = "std")]
+            timeout_handler: null(),
+            #[cfg(all(windows, feature = "std"))]
+            ptp_timer: None,
+            #[cfg(all(windows, feature = "std"))]
+            in_target: 0,
+            #[cfg(all(windows, feature = "std"))]
+            critical: null_mut(),
+        }
+    }
+
     /// # Safety
     /// Only safe if not called twice and if the executor is not used from another borrow after this.
     #[cfg(all(feature = "std", any(unix, windows)))]
@@ -465,6 +503,14 @@ impl InProcessExecutorHandlerData {
         self.signal_handler_depth -= 1;
     }
 
+    /// Returns true while a crash, timeout or panic is handled, e.g. for runtimes injecting faults
+    /// into the target to stop before the objectives run
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn in_signal_handler(&self) -> bool {
+        self.signal_handler_depth > 0
+    }
+
     /// if data is valid, safely report a crash and return true.
     /// return false otherwise.
     ///
@@ -528,34 +574,42 @@ impl InProcessExecutorHandlerData {
 }
 
 /// Exception handling needs some nasty unsafe.
-pub static mut GLOBAL_STATE: InProcessExecutorHandlerData = InProcessExecutorHandlerData {
-    // The state ptr for signal handling
-    state_ptr: null_mut(),
-    // The event manager ptr for signal handling
-    event_mgr_ptr: null_mut(),
-    // The fuzzer ptr for signal handling
-    fuzzer_ptr: null_mut(),
-    // The executor ptr for signal handling
-    executor_ptr: null(),
-    // The current input for signal handling
-    current_input_ptr: null(),
+pub static mut GLOBAL_STATE: InProcessExecutorHandlerData = InProcessExecutorHandlerData::new();
 
-    #[cfg(feature = "std")]
-    signal_handler_depth: 0,
+#[cfg(all(unix, feature = "std"))]
+std::thread_local! {
+    /// The handler data of a fuzzing thread, see [`enable_thread_local_handler_data`]
+    static THREAD_STATE: core::cell::UnsafeCell<InProcessExecutorHandlerData> =
+        const { core::cell::UnsafeCell::new(InProcessExecutorHandlerData::new()) };
+    static THREAD_STATE_ENABLED: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+}
 
-    // The crash handler fn
-    #[cfg(feature = "std")]
-    crash_handler: null(),
-    // The timeout handler fn
-    #[cfg(feature = "std")]
-    timeout_handler: null(),
-    #[cfg(all(windows, feature = "std"))]
-    ptp_timer: None,
-    #[cfg(all(windows, feature = "std"))]
-    in_target: 0,
-    #[cfg(all(windows, feature = "std"))]
-    critical: null_mut(),
-};
+/// Makes in-process executors created on the calling thread use handler data local to this thread,
+/// instead of the process-wide [`GLOBAL_STATE`]. Crashes and panics are then attributed to the input of
+/// the thread they happen on, and timeouts are delivered to the thread that armed them.
+/// Call this at the start of each fuzzing thread, before creating its executor.
+#[cfg(all(unix, feature = "std"))]
+pub fn enable_thread_local_handler_data() {
+    THREAD_STATE_ENABLED.with(|enabled| enabled.set(true));
+}
+
+/// Returns whether [`enable_thread_local_handler_data`] was called on this thread.
+#[cfg(all(unix, feature = "std"))]
+#[must_use]
+pub fn thread_local_handler_data_enabled() -> bool {
+    THREAD_STATE_ENABLED.with(core::cell::Cell::get)
+}
+
+/// The handler data of the calling thread: the thread-local data in a fuzzing thread,
+/// else the process-wide [`GLOBAL_STATE`].
+#[must_use]
+pub fn current_handler_data() -> *mut InProcessExecutorHandlerData {
+    #[cfg(all(unix, feature = "std"))]
+    if thread_local_handler_data_enabled() {
+        return THREAD_STATE.with(core::cell::UnsafeCell::get);
+    }
+    &raw mut GLOBAL_STATE
+}
 
 /// Get the inprocess State
 ///
@@ -563,7 +617,7 @@ pub static mut GLOBAL_STATE: InProcessExecutorHandlerData = InProcessExecutorHan
 /// Only safe if not called twice and if the state is not accessed from another borrow while this one is alive.
 #[must_use]
 pub unsafe fn inprocess_get_state<'a, S>() -> Option<&'a mut S> {
-    unsafe { (GLOBAL_STATE.state_ptr as *mut S).as_mut() }
+    unsafe { ((*current_handler_data()).state_ptr as *mut S).as_mut() }
 }
 
 /// Get the `EventManager`
@@ -572,7 +626,7 @@ pub unsafe fn inprocess_get_state<'a, S>() -> Option<&'a mut S> {
 /// Only safe if not called twice and if the event manager is not accessed from another borrow while this one is alive.
 #[must_use]
 pub unsafe fn inprocess_get_event_manager<'a, EM>() -> Option<&'a mut EM> {
-    unsafe { (GLOBAL_STATE.event_mgr_ptr as *mut EM).as_mut() }
+    unsafe { ((*current_handler_data()).event_mgr_ptr as *mut EM).as_mut() }
 }
 
 /// Gets the inprocess [`crate::fuzzer::Fuzzer`]
@@ -581,7 +635,7 @@ pub unsafe fn inprocess_get_event_manager<'a, EM>() -> Option<&'a mut EM> {
 /// Only safe if not called twice and if the fuzzer is not accessed from another borrow while this one is alive.
 #[must_use]
 pub unsafe fn inprocess_get_fuzzer<'a, F>() -> Option<&'a mut F> {
-    unsafe { (GLOBAL_STATE.fuzzer_ptr as *mut F).as_mut() }
+    unsafe { ((*current_handler_data()).fuzzer_ptr as *mut F).as_mut() }
 }
 
 /// Gets the inprocess [`Executor`]
@@ -590,7 +644,7 @@ pub unsafe fn inprocess_get_fuzzer<'a, F>() -> Option<&'a mut F> {
 /// Only safe if not called twice and if the executor is not accessed from another borrow while this one is alive.
 #[must_use]
 pub unsafe fn inprocess_get_executor<'a, E>() -> Option<&'a mut E> {
-    unsafe { (GLOBAL_STATE.executor_ptr as *mut E).as_mut() }
+    unsafe { ((*current_handler_data()).executor_ptr as *mut E).as_mut() }
 }
 
 /// Gets the inprocess input
@@ -599,5 +653,5 @@ pub unsafe fn inprocess_get_executor<'a, E>() -> Option<&'a mut E> {
 /// Only safe if not called concurrently and if the input is not used mutably while this reference is alive.
 #[must_use]
 pub unsafe fn inprocess_get_input<'a, I>() -> Option<&'a I> {
-    unsafe { (GLOBAL_STATE.current_input_ptr as *const I).as_ref() }
+    unsafe { ((*current_handler_data()).current_input_ptr as *const I).as_ref() }
 }
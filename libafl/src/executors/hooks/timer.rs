@@ -238,6 +238,35 @@ impl TimerStruct {
         }
     }
 
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    /// Create a `TimerStruct` with the specified timeout, signalling the calling thread only.
+    /// Used by fuzzing threads sharing one process, so that each thread handles its own timeouts.
+    ///
+    /// # Panics
+    /// If the timer can't be created
+    pub fn for_current_thread(exec_tmout: Duration) -> Self {
+        let mut me = Self::new(exec_tmout);
+        #[cfg(not(miri))]
+        unsafe {
+            libc::timer_delete(me.timerid);
+            let mut sigevent: libc::sigevent = zeroed();
+            sigevent.sigev_notify = libc::SIGEV_THREAD_ID;
+            sigevent.sigev_signo = libc::SIGALRM;
+            sigevent.sigev_notify_thread_id = libc::gettid();
+            assert_eq!(
+                libc::timer_create(
+                    libc::CLOCK_MONOTONIC,
+                    &raw mut sigevent,
+                    &raw mut me.timerid,
+                ),
+                0,
+                "timer_create failed!"
+            );
+        }
+        me
+    }
+
     #[cfg(target_os = "linux")]
     #[must_use]
     /// Constructor but use batch mode
@@ -15,7 +15,7 @@ pub mod unix_signal_handler {
         events::{EventFirer, EventRestarter},
         executors::{
             Executor, ExitKind, HasObservers, common_signals,
-            hooks::inprocess::{GLOBAL_STATE, HasTimeout, InProcessExecutorHandlerData},
+            hooks::inprocess::{HasTimeout, InProcessExecutorHandlerData, current_handler_data},
             inprocess::{HasInProcessHooks, run_observers_and_save_state},
         },
         feedbacks::Feedback,
@@ -52,7 +52,7 @@ pub mod unix_signal_handler {
             context: Option<&mut ucontext_t>,
         ) {
             unsafe {
-                let data = &raw mut GLOBAL_STATE;
+                let data = current_handler_data();
                 let (max_depth_reached, signal_depth) = (*data).signal_handler_enter();
 
                 if max_depth_reached {
@@ -99,7 +99,7 @@ pub mod unix_signal_handler {
         let old_hook = panic::take_hook();
         panic::set_hook(Box::new(move |panic_info| unsafe {
             old_hook(panic_info);
-            let data = &raw mut GLOBAL_STATE;
+            let data = current_handler_data();
             let (max_depth_reached, signal_depth) = (*data).signal_handler_enter();
 
             if max_depth_reached {
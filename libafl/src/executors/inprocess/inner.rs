@@ -20,7 +20,7 @@ use crate::{
         Executor, HasObservers,
         hooks::{
             ExecutorHooksTuple,
-            inprocess::{GLOBAL_STATE, InProcessHooks},
+            inprocess::{InProcessHooks, current_handler_data},
         },
         inprocess::HasInProcessHooks,
     },
@@ -87,7 +87,7 @@ where
         executor_ptr: *const c_void,
     ) {
         unsafe {
-            let data = &raw mut GLOBAL_STATE;
+            let data = current_handler_data();
             write_volatile(
                 &raw mut (*data).current_input_ptr,
                 ptr::from_ref(input) as *const c_void,
@@ -115,7 +115,7 @@ where
     #[inline]
     pub fn leave_target(&mut self, _fuzzer: &mut Z, _state: &mut S, _mgr: &mut EM, _input: &I) {
         unsafe {
-            let data = &raw mut GLOBAL_STATE;
+            let data = current_handler_data();
 
             write_volatile(&raw mut (*data).current_input_ptr, null());
             compiler_fence(Ordering::SeqCst);
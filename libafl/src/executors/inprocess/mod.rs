@@ -34,6 +34,9 @@ use crate::{
 pub mod inner;
 /// A version of `InProcessExecutor` with a state accessible from the harness.
 pub mod stateful;
+/// Several fuzzing threads with in-process executors, sharing one process.
+#[cfg(all(unix, feature = "std"))]
+pub mod threaded;
 
 /// The process executor simply calls a target function, as mutable reference to a closure.
 pub type InProcessExecutor<'a, EM, H, I, OT, S, Z> =
@@ -0,0 +1,137 @@
+//! Multi-threaded in-process fuzzing: several fuzzing threads share one process, and thereby
+//! the one-time initialization of the target, instead of one process per core.
+//!
+//! Each thread builds its own state, scheduler, and [`crate::executors::InProcessExecutor`].
+//! Crashes, panics and timeouts are handled on the thread they happen on, using the handler data
+//! of that thread. Coverage must be collected per thread as well, e.g. with the thread-local
+//! edges maps of `libafl_targets` (the `thread_local_maps` feature). Corpus entries are shared
+//! between threads with a [`SharedCorpus`] and the [`crate::stages::SharedCorpusSyncStage`].
+//!
+//! A crash or timeout still ends the whole process, once the thread that found it saved its input.
+//! Timeouts are per thread on Linux only, other platforms share one process-wide timer.
+
+use alloc::{format, vec::Vec};
+use std::thread;
+
+use libafl_bolts::core_affinity::{CoreId, Cores};
+
+use crate::{
+    Error, corpus::SharedCorpus, executors::hooks::inprocess::enable_thread_local_handler_data,
+};
+
+/// A fuzzing thread started by [`launch_fuzzing_threads`]
+#[derive(Debug)]
+pub struct FuzzingThread<I> {
+    thread_id: usize,
+    core_id: CoreId,
+    shared_corpus: SharedCorpus<I>,
+}
+
+impl<I> FuzzingThread<I> {
+    /// The index of this thread, from `0` to the number of threads
+    #[must_use]
+    pub fn thread_id(&self) -> usize {
+        self.thread_id
+    }
+
+    /// The core this thread is bound to
+    #[must_use]
+    pub fn core_id(&self) -> CoreId {
+        self.core_id
+    }
+
+    /// The corpus shared by all threads of this process
+    #[must_use]
+    pub fn shared_corpus(&self) -> &SharedCorpus<I> {
+        &self.shared_corpus
+    }
+}
+
+/// Runs `run` on one fuzzing thread per core in `cores`, and waits for all of them.
+///
+/// Each thread is bound to its core, and uses thread-local handler data for the in-process
+/// executors it creates. Returns the first error of a thread, if any.
+pub fn launch_fuzzing_threads<I, F>(
+    cores: &Cores,
+    shared_corpus: &SharedCorpus<I>,
+    run: F,
+) -> Result<(), Error>
+where
+    I: Send + Sync,
+    F: Fn(FuzzingThread<I>) -> Result<(), Error> + Sync,
+{
+    thread::scope(|scope| {
+        let mut handles = Vec::with_capacity(cores.ids.len());
+        for (thread_id, core_id) in cores.ids.iter().enumerate() {
+            let fuzzing_thread = FuzzingThread {
+                thread_id,
+                core_id: *core_id,
+                shared_corpus: shared_corpus.clone(),
+            };
+            let run = &run;
+            let handle = thread::Builder::new()
+                .name(format!("fuzzer-{thread_id}"))
+                .spawn_scoped(scope, move || {
+                    fuzzing_thread.core_id.set_affinity()?;
+                    enable_thread_local_handler_data();
+                    run(fuzzing_thread)
+                })?;
+            handles.push(handle);
+        }
+
+        let mut result = Ok(());
+        for (thread_id, handle) in handles.into_iter().enumerate() {
+            let thread_result = handle.join().unwrap_or_else(|_| {
+                Err(Error::unknown(format!(
+                    "Fuzzing thread {thread_id} panicked"
+                )))
+            });
+            if result.is_ok() {
+                result = thread_result;
+            }
+        }
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use libafl_bolts::core_affinity::Cores;
+
+    use super::launch_fuzzing_threads;
+    use crate::{corpus::SharedCorpus, executors::hooks::inprocess::current_handler_data};
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_launch_fuzzing_threads() {
+        let cores = Cores::from_cmdline("0,0").unwrap();
+        let shared = SharedCorpus::new();
+        let handler_data = std::sync::Mutex::new(vec![]);
+        let ran = AtomicUsize::new(0);
+
+        launch_fuzzing_threads(&cores, &shared, |thread| {
+            shared.publish(thread.thread_id(), thread.thread_id())?;
+            handler_data
+                .lock()
+                .unwrap()
+                .push(current_handler_data() as usize);
+            ran.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(ran.load(Ordering::SeqCst), 2);
+        assert_eq!(shared.len().unwrap(), 2);
+        // The threads use their own handler data, not the process-wide one
+        let global = current_handler_data() as usize;
+        assert!(
+            handler_data
+                .into_inner()
+                .unwrap()
+                .iter()
+                .all(|data| *data != global)
+        );
+    }
+}
@@ -0,0 +1,838 @@
+//! An in-process executor that restores the writable memory of the target between runs, on Linux.
+//!
+//! The [`InProcessSnapshotExecutor`] wraps another (in-process) executor. On the first run, after
+//! the harness has been initialized, it saves the writable mappings of the selected modules.
+//! Before every following run, only the pages written during the previous run are copied back.
+//! Written pages are found using the soft-dirty bits of `/proc/self/pagemap`, or by comparing
+//! each page to the snapshot if the kernel does not support soft-dirty tracking.
+//!
+//! Restoring lazily, right before the next run, means that observers and feedbacks still see the
+//! memory of the last run, and that coverage maps placed in the restored modules are restored
+//! to the state they had after being reset.
+//!
+//! Heap state can be rolled back as well, using the [`SnapshotAllocator`] as `#[global_allocator]`,
+//! see [`InProcessSnapshotExecutor::with_heap_rollback`].
+
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::{Cell, UnsafeCell},
+    fmt::Debug,
+    hint,
+    ops::Range,
+    ptr, slice,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    os::unix::fs::FileExt,
+};
+
+use libafl_bolts::tuples::RefIndexable;
+
+use crate::{
+    Error,
+    executors::{Executor, ExitKind, HasObservers},
+};
+
+/// The soft-dirty bit of an entry in `/proc/self/pagemap`
+const PM_SOFT_DIRTY: u64 = 1 << 55;
+
+/// The size of an entry in `/proc/self/pagemap`
+const PM_ENTRY_LEN: usize = 8;
+
+/// The value written to `/proc/self/clear_refs` to clear all soft-dirty bits
+const CLEAR_SOFT_DIRTY: &[u8] = b"4";
+
+/// A mapping of `/proc/self/maps`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MapsEntry {
+    range: Range<usize>,
+    writable: bool,
+    private: bool,
+    path: String,
+}
+
+/// Parses the content of `/proc/self/maps`, skipping lines that cannot be parsed
+fn parse_maps(maps: &str) -> Vec<MapsEntry> {
+    maps.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (start, end) = fields.next()?.split_once('-')?;
+            let perms = fields.next()?.as_bytes();
+            // offset, device and inode
+            let path = fields.nth(3).unwrap_or("").to_string();
+            Some(MapsEntry {
+                range: usize::from_str_radix(start, 16).ok()?
+                    ..usize::from_str_radix(end, 16).ok()?,
+                writable: perms.get(1) == Some(&b'w'),
+                private: perms.get(3) == Some(&b'p'),
+                path,
+            })
+        })
+        .collect()
+}
+
+/// Selects the private writable mappings of the modules whose path satisfies `matches`.
+/// An anonymous mapping directly following a selected mapping is the `.bss` of the module, and
+/// selected as well.
+fn select_mappings<F>(entries: &[MapsEntry], matches: F) -> Vec<Range<usize>>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if !entry.writable || !entry.private {
+            continue;
+        }
+        let selected = if entry.path.is_empty() {
+            i > 0 && {
+                let prev = &entries[i - 1];
+                prev.range.end == entry.range.start && !prev.path.is_empty() && matches(&prev.path)
+            }
+        } else {
+            matches(&entry.path)
+        };
+        if selected {
+            ranges.push(entry.range.clone());
+        }
+    }
+    ranges
+}
+
+/// Splits `range` into the parts not covered by any of the sorted, non-overlapping `excluded` ranges
+fn subtract_ranges(range: Range<usize>, excluded: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut parts = Vec::new();
+    let mut cursor = range.start;
+    for ex in excluded {
+        if ex.end <= cursor || ex.start >= range.end {
+            continue;
+        }
+        if ex.start > cursor {
+            parts.push(cursor..ex.start);
+        }
+        cursor = cursor.max(ex.end);
+    }
+    if cursor < range.end {
+        parts.push(cursor..range.end);
+    }
+    parts
+}
+
+fn page_size() -> usize {
+    // # Safety
+    // `sysconf` has no preconditions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    usize::try_from(page_size).unwrap_or(4096)
+}
+
+/// Checks that writing to a page sets its soft-dirty bit. Kernels built without soft-dirty
+/// support accept clearing the bits, but never set them.
+fn soft_dirty_works(mut clear_refs: &File, pagemap: &File, page_size: usize) -> bool {
+    let mut probe = vec![0_u8; 2 * page_size];
+    let offset = probe.as_ptr().align_offset(page_size);
+    let page = probe[offset..].as_mut_ptr();
+    if clear_refs.write_all(CLEAR_SOFT_DIRTY).is_err() {
+        return false;
+    }
+    // # Safety
+    // The page is part of `probe`
+    unsafe { page.write_volatile(1) };
+    let mut entry = [0_u8; PM_ENTRY_LEN];
+    let pagemap_offset = (page as usize / page_size * PM_ENTRY_LEN) as u64;
+    pagemap.read_exact_at(&mut entry, pagemap_offset).is_ok()
+        && u64::from_ne_bytes(entry) & PM_SOFT_DIRTY != 0
+}
+
+/// A saved writable mapping
+#[derive(Debug)]
+struct SavedMapping {
+    start: usize,
+    data: Vec<u8>,
+}
+
+/// The saved memory, and the pages written since the soft-dirty bits were last cleared
+#[derive(Debug)]
+struct MemorySnapshot {
+    mappings: Vec<SavedMapping>,
+    page_size: usize,
+    /// `clear_refs` and `pagemap`, if soft-dirty tracking is supported
+    soft_dirty: Option<(File, File)>,
+    /// The dirty pages, as (mapping index, page index)
+    dirty: Vec<(usize, usize)>,
+    pagemap_buf: Vec<u8>,
+}
+
+impl MemorySnapshot {
+    /// Saves the content of the given page-aligned ranges
+    fn take(ranges: &[Range<usize>]) -> Self {
+        let page_size = page_size();
+        let mappings = ranges
+            .iter()
+            .map(|range| {
+                // # Safety
+                // The range is a readable mapping of this process
+                let data = unsafe { slice::from_raw_parts(range.start as *const u8, range.len()) }
+                    .to_vec();
+                SavedMapping {
+                    start: range.start,
+                    data,
+                }
+            })
+            .collect();
+
+        let soft_dirty = OpenOptions::new()
+            .write(true)
+            .open("/proc/self/clear_refs")
+            .and_then(|clear_refs| Ok((clear_refs, File::open("/proc/self/pagemap")?)))
+            .ok()
+            .filter(|(clear_refs, pagemap)| soft_dirty_works(clear_refs, pagemap, page_size));
+        if soft_dirty.is_none() {
+            log::warn!("Soft-dirty bits are not available, comparing all pages after each run");
+        }
+
+        Self {
+            mappings,
+            page_size,
+            soft_dirty,
+            dirty: Vec::new(),
+            pagemap_buf: Vec::new(),
+        }
+    }
+
+    /// The number of saved bytes
+    fn len(&self) -> usize {
+        self.mappings.iter().map(|m| m.data.len()).sum()
+    }
+
+    /// Starts tracking the pages written from now on
+    fn clear_dirty(&mut self) -> Result<(), Error> {
+        if let Some((clear_refs, _)) = &mut self.soft_dirty {
+            clear_refs.write_all(CLEAR_SOFT_DIRTY)?;
+        }
+        Ok(())
+    }
+
+    /// Collects the pages written since the last call to [`Self::clear_dirty`]
+    fn collect_dirty(&mut self) -> Result<(), Error> {
+        self.dirty.clear();
+        for (idx, mapping) in self.mappings.iter().enumerate() {
+            let pages = mapping.data.len() / self.page_size;
+            if let Some((_, pagemap)) = &self.soft_dirty {
+                self.pagemap_buf.resize(pages * PM_ENTRY_LEN, 0);
+                let offset = mapping.start / self.page_size * PM_ENTRY_LEN;
+                pagemap.read_exact_at(&mut self.pagemap_buf, offset as u64)?;
+                for (page, entry) in self.pagemap_buf.chunks_exact(PM_ENTRY_LEN).enumerate() {
+                    let entry = u64::from_ne_bytes(entry.try_into().unwrap());
+                    if entry & PM_SOFT_DIRTY != 0 {
+                        self.dirty.push((idx, page));
+                    }
+                }
+            } else {
+                for (page, saved) in mapping.data.chunks_exact(self.page_size).enumerate() {
+                    let addr = mapping.start + page * self.page_size;
+                    // # Safety
+                    // The page is part of a saved mapping
+                    let current = unsafe { slice::from_raw_parts(addr as *const u8, saved.len()) };
+                    if current != saved {
+                        self.dirty.push((idx, page));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the saved content of the dirty pages back, except for the `excluded` ranges
+    ///
+    /// # Safety
+    /// Overwrites memory of the process, references into the restored pages become invalid.
+    unsafe fn restore_dirty(&mut self, excluded: &[Range<usize>]) {
+        for &(idx, page) in &self.dirty {
+            let mapping = &self.mappings[idx];
+            let start = mapping.start + page * self.page_size;
+            for part in subtract_ranges(start..start + self.page_size, excluded) {
+                let saved = &mapping.data[part.start - mapping.start..part.end - mapping.start];
+                unsafe {
+                    ptr::copy_nonoverlapping(saved.as_ptr(), part.start as *mut u8, part.len());
+                }
+            }
+        }
+        self.dirty.clear();
+    }
+}
+
+/// An allocator that can free all allocations made while it was tracking, see [`SnapshotAllocator`]
+pub trait RollbackAllocator: Debug + Sync {
+    /// Starts tracking the allocations of the current thread
+    fn start_tracking(&self);
+
+    /// Stops tracking the allocations of the current thread
+    fn stop_tracking(&self);
+
+    /// Frees all tracked allocations that are still alive
+    ///
+    /// # Safety
+    /// No references to the freed allocations may be used afterwards.
+    unsafe fn rollback(&self);
+}
+
+/// The maximum number of live allocations a [`SnapshotAllocator`] can track
+const MAX_TRACKED_ALLOCATIONS: usize = 1 << 16;
+
+/// A tracked allocation: pointer, size and alignment. A pointer of 0 marks a free slot.
+type TrackedAllocation = (usize, usize, usize);
+
+/// The open-addressing hash table of tracked allocations
+struct AllocationTable(UnsafeCell<[TrackedAllocation; MAX_TRACKED_ALLOCATIONS]>);
+
+// # Safety
+// Only accessed while holding `TABLE_LOCK`
+unsafe impl Sync for AllocationTable {}
+
+static TABLE: AllocationTable =
+    AllocationTable(UnsafeCell::new([(0, 0, 0); MAX_TRACKED_ALLOCATIONS]));
+static TABLE_LOCK: AtomicBool = AtomicBool::new(false);
+static TRACKED_COUNT: AtomicUsize = AtomicUsize::new(0);
+static TABLE_OVERFLOWED: AtomicBool = AtomicBool::new(false);
+
+std::thread_local! {
+    static TRACKING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn is_tracking() -> bool {
+    TRACKING.try_with(Cell::get).unwrap_or(false)
+}
+
+/// Runs `f` on the table of tracked allocations, holding the lock
+fn with_table<R>(f: impl FnOnce(&mut [TrackedAllocation; MAX_TRACKED_ALLOCATIONS]) -> R) -> R {
+    while TABLE_LOCK
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        hint::spin_loop();
+    }
+    // # Safety
+    // We hold the lock
+    let ret = f(unsafe { &mut *TABLE.0.get() });
+    TABLE_LOCK.store(false, Ordering::Release);
+    ret
+}
+
+fn slot_of(ptr: usize) -> usize {
+    (ptr >> 4).wrapping_mul(0x9e37_79b9_7f4a_7c15) % MAX_TRACKED_ALLOCATIONS
+}
+
+fn track(ptr: *mut u8, layout: Layout) {
+    let ptr = ptr as usize;
+    with_table(|table| {
+        // Keep some slots free, so lookups of untracked pointers terminate quickly
+        if TRACKED_COUNT.load(Ordering::Relaxed) >= MAX_TRACKED_ALLOCATIONS / 4 * 3 {
+            TABLE_OVERFLOWED.store(true, Ordering::Relaxed);
+            return;
+        }
+        let mut slot = slot_of(ptr);
+        while table[slot].0 != 0 {
+            slot = (slot + 1) % MAX_TRACKED_ALLOCATIONS;
+        }
+        table[slot] = (ptr, layout.size(), layout.align());
+        TRACKED_COUNT.fetch_add(1, Ordering::Relaxed);
+    });
+}
+
+/// Removes `ptr` from the table, returns if it was tracked
+fn untrack(ptr: *mut u8) -> bool {
+    let ptr = ptr as usize;
+    if TRACKED_COUNT.load(Ordering::Relaxed) == 0 {
+        return false;
+    }
+    with_table(|table| {
+        let mut slot = slot_of(ptr);
+        while table[slot].0 != ptr {
+            if table[slot].0 == 0 {
+                return false;
+            }
+            slot = (slot + 1) % MAX_TRACKED_ALLOCATIONS;
+        }
+        // Backward-shift deletion, keeping all probe sequences intact
+        let mut hole = slot;
+        let mut next = (hole + 1) % MAX_TRACKED_ALLOCATIONS;
+        while table[next].0 != 0 {
+            let home = slot_of(table[next].0);
+            let dist_next = (next + MAX_TRACKED_ALLOCATIONS - home) % MAX_TRACKED_ALLOCATIONS;
+            let dist_hole = (hole + MAX_TRACKED_ALLOCATIONS - home) % MAX_TRACKED_ALLOCATIONS;
+            if dist_hole <= dist_next {
+                table[hole] = table[next];
+                hole = next;
+            }
+            next = (next + 1) % MAX_TRACKED_ALLOCATIONS;
+        }
+        table[hole] = (0, 0, 0);
+        TRACKED_COUNT.fetch_sub(1, Ordering::Relaxed);
+        true
+    })
+}
+
+fn is_tracked(ptr: *mut u8) -> bool {
+    let ptr = ptr as usize;
+    if TRACKED_COUNT.load(Ordering::Relaxed) == 0 {
+        return false;
+    }
+    with_table(|table| {
+        let mut slot = slot_of(ptr);
+        loop {
+            match table[slot].0 {
+                0 => return false,
+                p if p == ptr => return true,
+                _ => slot = (slot + 1) % MAX_TRACKED_ALLOCATIONS,
+            }
+        }
+    })
+}
+
+/// A [`GlobalAlloc`] wrapper that can roll the heap back to the state of the snapshot of an
+/// [`InProcessSnapshotExecutor`].
+///
+/// While the executor runs the target, all allocations of the fuzzing thread are tracked, and
+/// freed before the next run. Blocks allocated before the run and freed or reallocated by the
+/// target are kept alive, as the restored memory may still point to them.
+///
+/// Limitations:
+/// - the content of heap objects allocated before the snapshot is not restored,
+/// - only allocations through the Rust global allocator are seen, not direct calls to `malloc`,
+/// - at most 49152 live allocations are tracked per run, further ones leak.
+///
+/// ```rust,ignore
+/// #[global_allocator]
+/// static ALLOCATOR: SnapshotAllocator<std::alloc::System> =
+///     SnapshotAllocator::new(std::alloc::System);
+/// ```
+#[derive(Debug)]
+pub struct SnapshotAllocator<A> {
+    inner: A,
+}
+
+impl<A> SnapshotAllocator<A> {
+    /// Wraps the `inner` allocator
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+unsafe impl<A> GlobalAlloc for SnapshotAllocator<A>
+where
+    A: GlobalAlloc,
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if !ptr.is_null() && is_tracking() {
+            track(ptr, layout);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc_zeroed(layout) };
+        if !ptr.is_null() && is_tracking() {
+            track(ptr, layout);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Blocks from before the run stay alive until the snapshot is restored
+        if untrack(ptr) || !is_tracking() {
+            unsafe { self.inner.dealloc(ptr, layout) };
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // # Safety
+        // The caller guarantees `new_size` is valid for `layout.align()`
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        if is_tracking() && !is_tracked(ptr) {
+            // Keep the block from before the run alive, and move it to a tracked one
+            let new = unsafe { self.alloc(new_layout) };
+            if !new.is_null() {
+                unsafe { ptr::copy_nonoverlapping(ptr, new, layout.size().min(new_size)) };
+            }
+            return new;
+        }
+        let tracked = untrack(ptr);
+        let new = unsafe { self.inner.realloc(ptr, layout, new_size) };
+        if tracked {
+            if new.is_null() {
+                track(ptr, layout);
+            } else {
+                track(new, new_layout);
+            }
+        }
+        new
+    }
+}
+
+impl<A> RollbackAllocator for SnapshotAllocator<A>
+where
+    A: GlobalAlloc + Debug + Sync,
+{
+    fn start_tracking(&self) {
+        TRACKING.with(|t| t.set(true));
+    }
+
+    fn stop_tracking(&self) {
+        TRACKING.with(|t| t.set(false));
+    }
+
+    unsafe fn rollback(&self) {
+        if TABLE_OVERFLOWED.swap(false, Ordering::Relaxed) {
+            log::warn!(
+                "More than {} allocations in one run, some of them were leaked",
+                MAX_TRACKED_ALLOCATIONS / 4 * 3
+            );
+        }
+        with_table(|table| {
+            for (ptr, size, align) in table.iter_mut() {
+                if *ptr != 0 {
+                    // # Safety
+                    // The layout was valid when the block was allocated
+                    unsafe {
+                        self.inner.dealloc(
+                            *ptr as *mut u8,
+                            Layout::from_size_align_unchecked(*size, *align),
+                        );
+                    }
+                    *ptr = 0;
+                }
+            }
+            TRACKED_COUNT.store(0, Ordering::Relaxed);
+        });
+    }
+}
+
+/// An in-process executor giving fork-like isolation between runs, by restoring the writable
+/// memory of the target modules to a snapshot taken before the first run.
+///
+/// The memory to restore has to be selected: the modules of the target with [`Self::with_module`],
+/// or any range of memory with [`Self::with_range`]. Ranges that must survive between runs, such as
+/// memory shared with the fuzzer, are excluded with [`Self::with_excluded_range`].
+///
+/// The writable mappings of the main executable can be restored with
+/// [`Self::with_main_executable`], if the target is a separate binary the fuzzer is loaded into.
+/// If the fuzzer is linked into the main executable, this would also roll back the statics of the
+/// fuzzer and of its allocator, so they have to be excluded.
+///
+/// Only one [`InProcessSnapshotExecutor`] may run at a time, and the target must be
+/// single-threaded.
+#[derive(Debug)]
+pub struct InProcessSnapshotExecutor<E> {
+    executor: E,
+    main_executable: bool,
+    modules: Vec<String>,
+    ranges: Vec<Range<usize>>,
+    excluded: Vec<Range<usize>>,
+    allocator: Option<&'static dyn RollbackAllocator>,
+    snapshot: Option<MemorySnapshot>,
+}
+
+impl<E> InProcessSnapshotExecutor<E> {
+    /// Wraps the given in-process `executor`. The memory to restore is selected with
+    /// [`Self::with_module`], [`Self::with_range`] and [`Self::with_main_executable`].
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            main_executable: false,
+            modules: Vec::new(),
+            ranges: Vec::new(),
+            excluded: Vec::new(),
+            allocator: None,
+            snapshot: None,
+        }
+    }
+
+    /// Sets if the writable mappings of the main executable are restored, `false` by default.
+    /// Only enable this if the fuzzer does not live in the main executable, or exclude its statics
+    /// with [`Self::with_excluded_range`].
+    #[must_use]
+    pub fn with_main_executable(mut self, main_executable: bool) -> Self {
+        self.main_executable = main_executable;
+        self
+    }
+
+    /// Also restores the writable mappings of all modules whose path contains `module`
+    #[must_use]
+    pub fn with_module(mut self, module: &str) -> Self {
+        self.modules.push(module.to_string());
+        self
+    }
+
+    /// Also restores the given range of memory, extended to whole pages.
+    /// The range has to stay mapped and writable while the executor is used.
+    #[must_use]
+    pub fn with_range(mut self, range: Range<usize>) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    /// Never restores the given range of memory
+    #[must_use]
+    pub fn with_excluded_range(mut self, range: Range<usize>) -> Self {
+        self.excluded.push(range);
+        self.excluded.sort_by_key(|r| r.start);
+        self
+    }
+
+    /// Frees all allocations made during a run before the next one, using the given allocator,
+    /// which has to be the `#[global_allocator]`.
+    #[must_use]
+    pub fn with_heap_rollback(mut self, allocator: &'static dyn RollbackAllocator) -> Self {
+        self.allocator = Some(allocator);
+        self
+    }
+
+    /// The wrapped executor
+    pub fn inner(&self) -> &E {
+        &self.executor
+    }
+
+    /// The wrapped executor, mutable
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.executor
+    }
+
+    /// The number of bytes saved in the snapshot, 0 before the first run
+    pub fn snapshot_len(&self) -> usize {
+        self.snapshot.as_ref().map_or(0, MemorySnapshot::len)
+    }
+
+    /// The number of pages written during the last run
+    pub fn dirty_pages(&self) -> usize {
+        self.snapshot.as_ref().map_or(0, |s| s.dirty.len())
+    }
+
+    /// The page-aligned ranges to save
+    fn snapshot_ranges(&self) -> Result<Vec<Range<usize>>, Error> {
+        let exe = std::env::current_exe()?;
+        let exe = exe.to_string_lossy();
+        let entries = parse_maps(&fs::read_to_string("/proc/self/maps")?);
+        let mut ranges = select_mappings(&entries, |path| {
+            (self.main_executable && path == exe)
+                || self.modules.iter().any(|m| path.contains(m.as_str()))
+        });
+
+        let page_size = page_size();
+        for range in &self.ranges {
+            let start = range.start / page_size * page_size;
+            let end = range.end.div_ceil(page_size) * page_size;
+            if start < end {
+                ranges.push(start..end);
+            }
+        }
+        ranges.sort_by_key(|r| r.start);
+        ranges.dedup_by(|next, prev| {
+            if next.start <= prev.end {
+                prev.end = prev.end.max(next.end);
+                true
+            } else {
+                false
+            }
+        });
+        if ranges.is_empty() {
+            return Err(Error::illegal_state("No writable memory to snapshot found"));
+        }
+        Ok(ranges)
+    }
+}
+
+impl<E, EM, I, S, Z> Executor<EM, I, S, Z> for InProcessSnapshotExecutor<E>
+where
+    E: Executor<EM, I, S, Z>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut S,
+        mgr: &mut EM,
+        input: &I,
+    ) -> Result<ExitKind, Error> {
+        if let Some(snapshot) = &mut self.snapshot {
+            // # Safety
+            // The target is not running, and nothing may keep references into its memory
+            unsafe {
+                if let Some(allocator) = self.allocator {
+                    allocator.rollback();
+                }
+                snapshot.restore_dirty(&self.excluded);
+            }
+        } else {
+            let snapshot = MemorySnapshot::take(&self.snapshot_ranges()?);
+            log::info!(
+                "Took a snapshot of {} bytes of writable memory",
+                snapshot.len()
+            );
+            self.snapshot = Some(snapshot);
+        }
+
+        let snapshot = self.snapshot.as_mut().unwrap();
+        snapshot.clear_dirty()?;
+        if let Some(allocator) = self.allocator {
+            allocator.start_tracking();
+        }
+        let ret = self.executor.run_target(fuzzer, state, mgr, input);
+        if let Some(allocator) = self.allocator {
+            allocator.stop_tracking();
+        }
+        snapshot.collect_dirty()?;
+        ret
+    }
+}
+
+impl<E> HasObservers for InProcessSnapshotExecutor<E>
+where
+    E: HasObservers,
+{
+    type Observers = E::Observers;
+
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        self.executor.observers()
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        self.executor.observers_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use core::ptr;
+
+    use super::{InProcessSnapshotExecutor, parse_maps, select_mappings, subtract_ranges};
+    use crate::{
+        Error,
+        events::NopEventManager,
+        executors::{Executor, ExitKind},
+        fuzzer::NopFuzzer,
+        inputs::BytesInput,
+        state::NopState,
+    };
+
+    const MAPS: &str = "\
+55d0c0000000-55d0c0001000 r--p 00000000 08:01 1234    /usr/bin/target
+55d0c0001000-55d0c0002000 rw-p 00001000 08:01 1234    /usr/bin/target
+55d0c0002000-55d0c0004000 rw-p 00000000 00:00 0
+55d0c1000000-55d0c1021000 rw-p 00000000 00:00 0       [heap]
+7f0000000000-7f0000001000 rw-p 00020000 08:01 99      /usr/lib/libc.so.6
+7f0000001000-7f0000002000 rw-s 00000000 00:05 7       /dev/shm/map
+";
+
+    #[test]
+    fn test_select_mappings() {
+        let entries = parse_maps(MAPS);
+        assert_eq!(entries.len(), 6);
+        assert_eq!(entries[3].path, "[heap]");
+
+        let ranges = select_mappings(&entries, |path| path == "/usr/bin/target");
+        assert_eq!(
+            ranges,
+            vec![
+                0x55d0_c000_1000..0x55d0_c000_2000,
+                0x55d0_c000_2000..0x55d0_c000_4000
+            ]
+        );
+        let ranges = select_mappings(&entries, |path| path.contains("shm"));
+        assert!(ranges.is_empty());
+    }
+
+    #[test]
+    fn test_subtract_ranges() {
+        assert_eq!(subtract_ranges(0..100, &[]), vec![0..100]);
+        assert_eq!(
+            subtract_ranges(0..100, &[10..20, 50..60]),
+            vec![0..10, 20..50, 60..100]
+        );
+        assert_eq!(subtract_ranges(0..100, &[0..10, 90..200]), vec![10..90]);
+        assert!(subtract_ranges(10..20, &[0..30, 40..50]).is_empty());
+    }
+
+    /// Writes the input to the memory at `addr`, and fails if it was not restored before
+    struct WritingExecutor {
+        addr: *mut u8,
+        /// The first bytes at `addr`, as seen at the start of the last run
+        seen: [u8; 3],
+    }
+
+    impl<EM, S, Z> Executor<EM, BytesInput, S, Z> for WritingExecutor {
+        fn run_target(
+            &mut self,
+            _fuzzer: &mut Z,
+            _state: &mut S,
+            _mgr: &mut EM,
+            input: &BytesInput,
+        ) -> Result<ExitKind, Error> {
+            let bytes: &[u8] = input.as_ref();
+            unsafe {
+                ptr::copy_nonoverlapping(self.addr, self.seen.as_mut_ptr(), self.seen.len());
+                if *self.addr.add(2) != 0 || *self.addr.add(4096) != 0 {
+                    return Ok(ExitKind::Crash);
+                }
+                ptr::copy_nonoverlapping(bytes.as_ptr(), self.addr, bytes.len());
+                *self.addr.add(4096) = 1;
+            }
+            Ok(ExitKind::Ok)
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_restore() {
+        let len = 3 * 4096;
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(addr, libc::MAP_FAILED);
+        let start = addr as usize;
+
+        let mut executor = InProcessSnapshotExecutor::new(WritingExecutor {
+            addr: addr.cast(),
+            seen: [0; 3],
+        })
+        .with_range(start..start + len)
+        .with_excluded_range(start + 1..start + 2);
+        let mut fuzzer = NopFuzzer::new();
+        let mut mgr: NopEventManager = NopEventManager::new();
+        let mut state: NopState<BytesInput> = NopState::new();
+
+        for _ in 0..3 {
+            let ret = executor
+                .run_target(
+                    &mut fuzzer,
+                    &mut state,
+                    &mut mgr,
+                    &BytesInput::new(vec![0, 42, 7]),
+                )
+                .unwrap();
+            assert_eq!(ret, ExitKind::Ok);
+            assert_eq!(executor.dirty_pages(), 2);
+        }
+        assert_eq!(executor.snapshot_len(), len);
+        // Before each run, the excluded byte is kept, the others are restored
+        assert_eq!(executor.inner().seen, [0, 42, 0]);
+        // After the last run, nothing is restored yet
+        let mem = unsafe { core::slice::from_raw_parts(addr.cast::<u8>(), 3) };
+        assert_eq!(mem, &[0, 42, 7]);
+
+        unsafe { libc::munmap(addr, len) };
+    }
+}
@@ -12,9 +12,15 @@ pub use forkserver::{Forkserver, ForkserverExecutor};
 pub use inprocess::InProcessExecutor;
 #[cfg(all(feature = "std", feature = "fork", unix))]
 pub use inprocess_fork::InProcessForkExecutor;
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub use inprocess_snapshot::{InProcessSnapshotExecutor, SnapshotAllocator};
 #[cfg(unix)]
 use libafl_bolts::os::unix_signals::Signal;
 use libafl_bolts::tuples::RefIndexable;
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+pub use ptrace::PtraceExecutor;
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub use sandbox::{Sandbox, SandboxExecutor};
 use serde::{Deserialize, Serialize};
 pub use shadow::ShadowExecutor;
 pub use with_observers::WithObservers;
@@ -35,6 +41,18 @@ pub mod sand;
 #[cfg(all(feature = "std", unix))]
 pub mod inprocess_fork;
 
+/// The module for the inproc executor restoring a memory snapshot between runs
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub mod inprocess_snapshot;
+
+/// The module for the executor tracing the syscalls of a target with `ptrace`
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+pub mod ptrace;
+
+/// The module for the cgroup and namespace sandbox of child processes
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub mod sandbox;
+
 pub mod shadow;
 
 pub mod with_observers;
@@ -53,7 +71,7 @@ pub enum ExitKind {
     Ok,
     /// The run resulted in a target crash.
     Crash,
-    /// The run hit an out of memory error.
+    /// The run hit an out of memory error, or exhausted another resource limit.
     Oom,
     /// The run timed out
     Timeout,
@@ -138,6 +156,23 @@ pub trait HasTimeout {
     fn set_timeout(&mut self, timeout: Duration);
 }
 
+/// An executor running several inputs per roundtrip with the target, such as the batched mode of
+/// the `ForkserverExecutor`
+pub trait BatchExecutor<I, S> {
+    /// The maximum number of inputs per batch
+    fn batch_size(&self) -> usize;
+
+    /// Runs a prefix of `inputs` in a single batch, and increases the execution counter.
+    /// Returns the [`ExitKind`] of each executed input. A batch ends early when an input crashes
+    /// or times out.
+    fn run_batch(&mut self, state: &mut S, inputs: &[I]) -> Result<Vec<ExitKind>, Error>;
+
+    /// The coverage map of the input at `index` in the last batch.
+    /// Returns `None` if the coverage of this input is in the regular coverage map, as for the
+    /// input that ended a batch early with a crash or timeout.
+    fn batch_coverage(&self, index: usize) -> Option<&[u8]>;
+}
+
 /// Like [`crate::observers::ObserversTuple`], a list of executors
 pub trait ExecutorsTuple<EM, I, S, Z> {
     /// Execute the executors and stop if any of them returns a crash
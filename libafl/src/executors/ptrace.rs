@@ -0,0 +1,671 @@
+//! The [`PtraceExecutor`] runs an unmodified binary under `ptrace`, recording every syscall it
+//! makes and injecting syscall failures scheduled by the input.
+//!
+//! Each run forks and `exec`s the target, which is stopped at each syscall entry and exit.
+//! Syscalls are recorded into a [`SyscallObserver`], if one is set. The [`SyscallFault`]s of the
+//! input are injected at syscall entry: failing syscalls are skipped and return the scheduled
+//! `errno`, the reading syscalls of short reads get their byte count capped. This makes the error handling paths of
+//! binaries without any instrumentation reachable for fuzzing.
+//!
+//! Timeouts are delivered as `SIGALRM` to the target. If the target was blocked in a syscall when
+//! it timed out, this syscall is recorded as [`SyscallObserver::blocked_syscall`].
+//!
+//! Only the main thread of the target is traced; threads and child processes of the target run
+//! untraced. Targets blocking `SIGALRM` are not stopped on timeout.
+
+use alloc::{ffi::CString, format, vec::Vec};
+use core::{
+    ffi::{CStr, c_char},
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    ptr,
+    time::Duration,
+};
+use std::{
+    env,
+    ffi::OsStr,
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd},
+        unix::ffi::OsStrExt,
+    },
+};
+
+use hashbrown::HashMap;
+use libafl_bolts::{
+    AsSlice,
+    tuples::{Handle, MatchName, RefIndexable},
+};
+use nix::{
+    sys::{
+        ptrace,
+        signal::{Signal, kill},
+        wait::{WaitStatus, waitpid},
+    },
+    unistd::{ForkResult, Pid, fork},
+};
+
+use crate::{
+    Error,
+    executors::{Executor, ExitKind, HasObservers, HasTimeout, command::InputLocation},
+    inputs::{FaultKind, FaultTarget, HasFaults, HasTargetBytes, SyscallFault},
+    observers::{ObserversTuple, SyscallObserver, SyscallRecord},
+    state::HasExecutions,
+};
+
+/// The syscall number the kernel skips a syscall for, returning `-ENOSYS`
+const SKIPPED_SYSCALL: u64 = u64::MAX;
+
+/// The internal `errno`s of syscalls interrupted by a signal, `ERESTARTSYS` to
+/// `ERESTART_RESTARTBLOCK`
+const RESTART_ERRNOS: core::ops::RangeInclusive<i64> = 512..=516;
+
+/// The most `iovec`s a vectored syscall takes, `UIO_MAXIOV`
+const MAX_IOVECS: u64 = 1024;
+
+/// A syscall the target entered, but did not return from yet
+#[derive(Debug)]
+struct PendingSyscall {
+    record: SyscallRecord,
+    errno: Option<i32>,
+    saved_counts: Vec<SavedCount>,
+}
+
+/// A byte count capped for a short read, restored when the syscall returns
+#[derive(Debug, Clone, Copy)]
+enum SavedCount {
+    /// The count argument, in `rdx`
+    Register(u64),
+    /// The `iov_len` at this address in the memory of the target
+    Iovec(u64, u64),
+}
+
+/// Caps the byte count of the reading syscall of `regs` to `max`, returning the counts to restore
+#[expect(clippy::cast_sign_loss, clippy::cast_possible_wrap)] // registers and words are unsigned
+fn cap_read_count(
+    child: Pid,
+    regs: &mut libc::user_regs_struct,
+    max: u64,
+) -> Result<Vec<SavedCount>, Error> {
+    match regs.orig_rax as i64 {
+        libc::SYS_readv | libc::SYS_preadv => cap_iovecs(child, regs.rsi, regs.rdx, max),
+        libc::SYS_recvmsg => {
+            // `msg_iov` and `msg_iovlen` of the `struct msghdr`
+            let iov = ptrace::read(child, (regs.rsi + 16) as ptrace::AddressType)?;
+            let iovlen = ptrace::read(child, (regs.rsi + 24) as ptrace::AddressType)?;
+            cap_iovecs(child, iov as u64, iovlen as u64, max)
+        }
+        _ if regs.rdx > max => {
+            let saved = SavedCount::Register(regs.rdx);
+            regs.rdx = max;
+            Ok(vec![saved])
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Caps the total length of the `count` `iovec`s at `addr` to `max`
+#[expect(clippy::cast_sign_loss, clippy::cast_possible_wrap)] // words are unsigned
+fn cap_iovecs(child: Pid, addr: u64, count: u64, max: u64) -> Result<Vec<SavedCount>, Error> {
+    let mut saved = Vec::new();
+    let mut remaining = max;
+    for i in 0..count.min(MAX_IOVECS) {
+        // `iov_len` follows `iov_base`
+        let len_addr = addr + i * 16 + 8;
+        let len = ptrace::read(child, len_addr as ptrace::AddressType)? as u64;
+        let capped = len.min(remaining);
+        remaining -= capped;
+        if capped != len {
+            ptrace::write(child, len_addr as ptrace::AddressType, capped as i64)?;
+            saved.push(SavedCount::Iovec(len_addr, len));
+        }
+    }
+    Ok(saved)
+}
+
+/// The result of tracing one run
+#[derive(Debug)]
+struct Trace {
+    exit_kind: ExitKind,
+    records: Vec<SyscallRecord>,
+    blocked: Option<SyscallRecord>,
+}
+
+/// Creates a [`CString`] from the bytes up to the first null byte
+fn to_cstring(bytes: &[u8]) -> CString {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    CString::new(&bytes[..end]).unwrap()
+}
+
+/// Returns a null-terminated array of pointers to the given strings
+fn to_ptr_array(strings: &[CString]) -> Vec<*const c_char> {
+    strings
+        .iter()
+        .map(|s| s.as_ptr())
+        .chain(core::iter::once(ptr::null()))
+        .collect()
+}
+
+/// An executor tracing the syscalls of a target program with `ptrace`, see the module docs.
+pub struct PtraceExecutor<I, OT, S> {
+    program: CString,
+    args: Vec<CString>,
+    env: Vec<CString>,
+    input_location: InputLocation,
+    stdin_file: Option<File>,
+    timeout: Duration,
+    observers: OT,
+    syscall_observer: Option<Handle<SyscallObserver>>,
+    phantom: PhantomData<(I, S)>,
+}
+
+impl<I, OT, S> Debug for PtraceExecutor<I, OT, S>
+where
+    OT: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PtraceExecutor")
+            .field("program", &self.program)
+            .field("args", &self.args)
+            .field("input_location", &self.input_location)
+            .field("timeout", &self.timeout)
+            .field("observers", &self.observers)
+            .field("syscall_observer", &self.syscall_observer)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I, OT, S> PtraceExecutor<I, OT, S> {
+    /// Creates a new [`PtraceExecutor`] running `program` with `args`.
+    ///
+    /// The target inherits the environment of the fuzzer. For [`InputLocation::Arg`], `argnum`
+    /// is the index into `args`; for [`InputLocation::File`], the file has to be passed in `args`.
+    pub fn new<P, A>(
+        program: P,
+        args: &[A],
+        input_location: InputLocation,
+        timeout: Duration,
+        observers: OT,
+    ) -> Result<Self, Error>
+    where
+        P: AsRef<OsStr>,
+        A: AsRef<OsStr>,
+    {
+        let to_cstring = |s: &OsStr| {
+            CString::new(s.as_bytes())
+                .map_err(|_| Error::illegal_argument(format!("{s:?} contains a null byte")))
+        };
+        let program = to_cstring(program.as_ref())?;
+        let args = args
+            .iter()
+            .map(|arg| to_cstring(arg.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let env = env::vars_os()
+            .filter_map(|(key, value)| {
+                let mut var = key.as_bytes().to_vec();
+                var.push(b'=');
+                var.extend_from_slice(value.as_bytes());
+                CString::new(var).ok()
+            })
+            .collect();
+        if let InputLocation::Arg { argnum } = input_location
+            && argnum > args.len()
+        {
+            return Err(Error::illegal_argument(format!(
+                "If you want to fuzz arg {argnum}, you have to specify the other {argnum} (static) args."
+            )));
+        }
+
+        let stdin_file = if input_location == InputLocation::StdIn {
+            // A memfd instead of a pipe, so inputs larger than the pipe buffer don't block
+            let fd = unsafe { libc::memfd_create(c"libafl_ptrace_input".as_ptr(), 0) };
+            if fd < 0 {
+                return Err(Error::last_os_error("Failed to create the memfd for stdin"));
+            }
+            Some(unsafe { File::from_raw_fd(fd) })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            program,
+            args,
+            env,
+            input_location,
+            stdin_file,
+            timeout,
+            observers,
+            syscall_observer: None,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Records the syscalls of each run into the given [`SyscallObserver`]
+    #[must_use]
+    pub fn with_syscall_observer(mut self, syscall_observer: Handle<SyscallObserver>) -> Self {
+        self.syscall_observer = Some(syscall_observer);
+        self
+    }
+
+    /// Forks and stops the child right before it `exec`s the target
+    fn spawn_child(&mut self, input: &I) -> Result<Pid, Error>
+    where
+        I: HasTargetBytes,
+    {
+        let mut argv = Vec::with_capacity(self.args.len() + 1);
+        argv.push(self.program.clone());
+        argv.extend(self.args.iter().cloned());
+        match &mut self.input_location {
+            InputLocation::Arg { argnum } => {
+                let arg = to_cstring(input.target_bytes().as_slice());
+                if *argnum + 1 == argv.len() {
+                    argv.push(arg);
+                } else {
+                    argv[*argnum + 1] = arg;
+                }
+            }
+            InputLocation::StdIn => {
+                let file = self.stdin_file.as_mut().unwrap();
+                file.set_len(0)?;
+                file.seek(SeekFrom::Start(0))?;
+                file.write_all(input.target_bytes().as_slice())?;
+                file.seek(SeekFrom::Start(0))?;
+            }
+            InputLocation::File { out_file } => {
+                out_file.write_buf(input.target_bytes().as_slice())?;
+            }
+        }
+
+        // Prepare everything before forking, the child must not allocate
+        let argv_ptrs = to_ptr_array(&argv);
+        let env_ptrs = to_ptr_array(&self.env);
+        let stdin_fd = self.stdin_file.as_ref().map(AsRawFd::as_raw_fd);
+        let timer = libc::itimerval {
+            it_interval: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            it_value: libc::timeval {
+                tv_sec: self.timeout.as_secs().try_into()?,
+                tv_usec: self.timeout.subsec_micros().into(),
+            },
+        };
+
+        match unsafe { fork() }? {
+            ForkResult::Parent { child } => Ok(child),
+            ForkResult::Child => unsafe {
+                if let Some(fd) = stdin_fd {
+                    libc::dup2(fd, libc::STDIN_FILENO);
+                }
+                if ptrace::traceme().is_err() {
+                    libc::_exit(126);
+                }
+                libc::raise(libc::SIGSTOP);
+                // Interval timers survive the exec, the target gets a SIGALRM on timeout
+                libc::setitimer(libc::ITIMER_REAL, &raw const timer, ptr::null_mut());
+                libc::execve(self.program.as_ptr(), argv_ptrs.as_ptr(), env_ptrs.as_ptr());
+                libc::_exit(127);
+            },
+        }
+    }
+}
+
+/// Waits for the stopped child to `exec` the target, with syscall tracing enabled
+fn start_tracing(child: Pid, program: &CStr) -> Result<(), Error> {
+    match waitpid(child, None)? {
+        WaitStatus::Stopped(pid, Signal::SIGSTOP) if pid == child => {}
+        status => {
+            return Err(Error::unknown(format!(
+                "Unexpected state of child process {status:?} (while waiting for SIGSTOP)"
+            )));
+        }
+    }
+
+    let options = ptrace::Options::PTRACE_O_TRACESYSGOOD
+        | ptrace::Options::PTRACE_O_TRACEEXEC
+        | ptrace::Options::PTRACE_O_EXITKILL;
+    ptrace::setoptions(child, options)?;
+    ptrace::cont(child, None)?;
+
+    match waitpid(child, None)? {
+        WaitStatus::PtraceEvent(pid, Signal::SIGTRAP, event)
+            if pid == child && event == ptrace::Event::PTRACE_EVENT_EXEC as i32 =>
+        {
+            Ok(())
+        }
+        status => Err(Error::illegal_state(format!(
+            "Could not execute {program:?}, child process state {status:?}"
+        ))),
+    }
+}
+
+/// Runs the child until it exits, recording its syscalls and injecting `faults`
+#[expect(clippy::cast_sign_loss, clippy::cast_possible_wrap)] // registers hold negative errnos
+fn trace_syscalls(child: Pid, faults: &[SyscallFault]) -> Result<Trace, Error> {
+    let mut records = Vec::new();
+    let mut invocations: HashMap<u64, u32> = HashMap::new();
+    let mut pending: Option<PendingSyscall> = None;
+    let mut interrupted: Option<SyscallRecord> = None;
+    // The first syscall stop is the return from the `execve` we already waited for
+    let mut in_execve = true;
+    let mut signal = None;
+
+    let exit_kind = loop {
+        ptrace::syscall(child, signal.take())?;
+        match waitpid(child, None)? {
+            WaitStatus::PtraceSyscall(_) if in_execve => in_execve = false,
+            WaitStatus::PtraceSyscall(_) => {
+                let mut regs = ptrace::getregs(child)?;
+                if let Some(syscall) = pending.take() {
+                    // Syscall exit
+                    if let Some(errno) = syscall.errno {
+                        regs.rax = -i64::from(errno) as u64;
+                    }
+                    let mut set_regs = syscall.errno.is_some();
+                    for saved in syscall.saved_counts {
+                        match saved {
+                            SavedCount::Register(count) => {
+                                regs.rdx = count;
+                                set_regs = true;
+                            }
+                            SavedCount::Iovec(addr, len) => {
+                                ptrace::write(child, addr as ptrace::AddressType, len as i64)?;
+                            }
+                        }
+                    }
+                    if set_regs {
+                        ptrace::setregs(child, regs)?;
+                    }
+                    let record = SyscallRecord {
+                        ret: regs.rax as i64,
+                        ..syscall.record
+                    };
+                    interrupted = RESTART_ERRNOS.contains(&-record.ret).then_some(record);
+                    records.push(record);
+                } else {
+                    // Syscall entry
+                    interrupted = None;
+                    let nr = regs.orig_rax;
+                    let nth = invocations.entry(nr).or_default();
+                    let fault = faults
+                        .iter()
+                        .find(|fault| fault.target == nr && fault.nth == *nth);
+                    *nth += 1;
+
+                    let mut syscall = PendingSyscall {
+                        record: SyscallRecord {
+                            nr,
+                            args: [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9],
+                            ret: 0,
+                            // Short reads only apply to the reading syscalls
+                            faulted: fault.is_some_and(|fault| {
+                                matches!(fault.kind, FaultKind::Errno(_)) || nr.reads()
+                            }),
+                        },
+                        errno: None,
+                        saved_counts: Vec::new(),
+                    };
+                    match fault.map(|fault| fault.kind) {
+                        Some(FaultKind::Errno(errno)) => {
+                            syscall.errno = Some(errno);
+                            regs.orig_rax = SKIPPED_SYSCALL;
+                            ptrace::setregs(child, regs)?;
+                        }
+                        Some(FaultKind::ShortRead(max)) if nr.reads() => {
+                            syscall.saved_counts = cap_read_count(child, &mut regs, max)?;
+                            if syscall
+                                .saved_counts
+                                .iter()
+                                .any(|saved| matches!(saved, SavedCount::Register(_)))
+                            {
+                                ptrace::setregs(child, regs)?;
+                            }
+                        }
+                        _ => {}
+                    }
+                    pending = Some(syscall);
+                }
+            }
+            WaitStatus::Stopped(_, Signal::SIGALRM) => {
+                let blocked = pending.map(|syscall| syscall.record).or(interrupted);
+                // The child is still traced, it dies on the `SIGKILL` without further stops
+                kill(child, Signal::SIGKILL)?;
+                waitpid(child, None)?;
+                return Ok(Trace {
+                    exit_kind: ExitKind::Timeout,
+                    records,
+                    blocked,
+                });
+            }
+            // Forward all other signals to the target
+            WaitStatus::Stopped(_, sig) => signal = Some(sig),
+            WaitStatus::PtraceEvent(..) => {}
+            // Exiting with an error is how targets handle injected faults properly
+            WaitStatus::Exited(..) => break ExitKind::Ok,
+            // Killed from the outside, as the timeout does
+            WaitStatus::Signaled(_, Signal::SIGKILL, _) => break ExitKind::Timeout,
+            WaitStatus::Signaled(..) => break ExitKind::Crash,
+            status => {
+                return Err(Error::unsupported(format!(
+                    "Target program returned an unexpected state when waiting on it. {status:?} (waiting for pid {child})"
+                )));
+            }
+        }
+    };
+
+    Ok(Trace {
+        exit_kind,
+        records,
+        blocked: None,
+    })
+}
+
+impl<EM, I, OT, S, Z> Executor<EM, I, S, Z> for PtraceExecutor<I, OT, S>
+where
+    I: HasTargetBytes + HasFaults<u64>,
+    OT: MatchName + ObserversTuple<I, S>,
+    S: HasExecutions,
+{
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut S,
+        _mgr: &mut EM,
+        input: &I,
+    ) -> Result<ExitKind, Error> {
+        *state.executions_mut() += 1;
+        self.observers.pre_exec_child_all(state, input)?;
+
+        let child = self.spawn_child(input)?;
+        if let Err(err) = start_tracing(child, &self.program) {
+            let _ = kill(child, Signal::SIGKILL);
+            let _ = waitpid(child, None);
+            return Err(err);
+        }
+        let trace = trace_syscalls(child, input.faults())?;
+
+        if let Some(handle) = self.syscall_observer.clone() {
+            let mut observers = self.observers_mut();
+            let observer = &mut observers[&handle];
+            for record in trace.records {
+                observer.record(record);
+            }
+            observer.set_blocked_syscall(trace.blocked);
+        }
+
+        self.observers
+            .post_exec_child_all(state, input, &trace.exit_kind)?;
+        Ok(trace.exit_kind)
+    }
+}
+
+impl<I, OT, S> HasTimeout for PtraceExecutor<I, OT, S> {
+    #[inline]
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    #[inline]
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+}
+
+impl<I, OT, S> HasObservers for PtraceExecutor<I, OT, S>
+where
+    OT: ObserversTuple<I, S>,
+{
+    type Observers = OT;
+
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        RefIndexable::from(&self.observers)
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        RefIndexable::from(&mut self.observers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use libafl_bolts::tuples::{Handled, MatchNameRef, tuple_list};
+
+    use super::PtraceExecutor;
+    use crate::{
+        events::NopEventManager,
+        executors::{Executor, ExitKind, command::InputLocation},
+        fuzzer::NopFuzzer,
+        inputs::{BytesInput, FaultKind, HasFaults, SyscallFault, SyscallFaultInput},
+        observers::SyscallObserver,
+        state::NopState,
+    };
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_ptrace_executor_faults() {
+        let observer = SyscallObserver::new("syscalls");
+        let handle = observer.handle();
+        let mut executor = PtraceExecutor::new(
+            "/bin/cat",
+            &["-"],
+            InputLocation::StdIn,
+            Duration::from_secs(5),
+            tuple_list!(observer),
+        )
+        .unwrap()
+        .with_syscall_observer(handle.clone());
+        let mut state: NopState<SyscallFaultInput<BytesInput>> = NopState::new();
+        let mut fuzzer = NopFuzzer::new();
+        let mut mgr = NopEventManager::new();
+
+        let mut input = SyscallFaultInput::new(BytesInput::new(b"hello".to_vec()));
+        let exit_kind = executor
+            .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+            .unwrap();
+        assert_eq!(exit_kind, ExitKind::Ok);
+        let records = executor.observers.get(&handle).unwrap().records().to_vec();
+        let read = records
+            .iter()
+            .find(|record| {
+                record.nr == u64::try_from(libc::SYS_read).unwrap() && record.args[0] == 0
+            })
+            .unwrap();
+        assert_eq!(read.ret, 5);
+
+        // Let the first read of `cat` fail
+        let nth = records
+            .iter()
+            .take_while(|record| !core::ptr::eq(*record, read))
+            .filter(|record| record.nr == read.nr)
+            .count();
+        input.faults_mut().push(SyscallFault::new(
+            read.nr,
+            nth.try_into().unwrap(),
+            FaultKind::Errno(libc::EIO),
+        ));
+        let exit_kind = executor
+            .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+            .unwrap();
+        // `cat` handles the error, and exits with a failure
+        assert_eq!(exit_kind, ExitKind::Ok);
+        let observer = executor.observers.get(&handle).unwrap();
+        assert!(
+            observer
+                .records()
+                .iter()
+                .any(|record| record.faulted && record.ret == -i64::from(libc::EIO))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_ptrace_executor_short_reads() {
+        let path =
+            std::env::temp_dir().join(format!("libafl_ptrace_short_reads_{}", std::process::id()));
+        let observer = SyscallObserver::new("syscalls");
+        let handle = observer.handle();
+        let mut executor = PtraceExecutor::new(
+            "/bin/tee",
+            &[path.as_os_str()],
+            InputLocation::StdIn,
+            Duration::from_secs(5),
+            tuple_list!(observer),
+        )
+        .unwrap()
+        .with_syscall_observer(handle.clone());
+        let mut state: NopState<SyscallFaultInput<BytesInput>> = NopState::new();
+        let mut fuzzer = NopFuzzer::new();
+        let mut mgr = NopEventManager::new();
+
+        // Short reads on `openat` leave its flags alone, `tee` creates its file
+        let openat = u64::try_from(libc::SYS_openat).unwrap();
+        let mut input = SyscallFaultInput::with_faults(
+            BytesInput::new(b"hello".to_vec()),
+            (0..16)
+                .map(|nth| SyscallFault::new(openat, nth, FaultKind::ShortRead(0)))
+                .collect(),
+        );
+        let exit_kind = executor
+            .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+            .unwrap();
+        assert_eq!(exit_kind, ExitKind::Ok);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+        let records = executor.observers.get(&handle).unwrap().records().to_vec();
+        assert!(records.iter().all(|record| !record.faulted));
+
+        // Let the first read of the input return two bytes, `tee` reads the rest afterwards
+        let read = records
+            .iter()
+            .find(|record| {
+                record.nr == u64::try_from(libc::SYS_read).unwrap() && record.args[0] == 0
+            })
+            .unwrap();
+        let nth = records
+            .iter()
+            .take_while(|record| !core::ptr::eq(*record, read))
+            .filter(|record| record.nr == read.nr)
+            .count();
+        input.faults_mut().push(SyscallFault::new(
+            read.nr,
+            nth.try_into().unwrap(),
+            FaultKind::ShortRead(2),
+        ));
+        std::fs::remove_file(&path).unwrap();
+        let exit_kind = executor
+            .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+            .unwrap();
+        assert_eq!(exit_kind, ExitKind::Ok);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+        let observer = executor.observers.get(&handle).unwrap();
+        assert!(
+            observer
+                .records()
+                .iter()
+                .any(|record| record.faulted && record.nr == read.nr && record.ret == 2)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
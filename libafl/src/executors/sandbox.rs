@@ -0,0 +1,422 @@
+//! A sandbox for the child processes of [`crate::executors::CommandExecutor`] and
+//! [`crate::executors::ForkserverExecutor`] on Linux, based on cgroup v2 and namespaces.
+//!
+//! A [`Sandbox`] owns a cgroup limiting the memory, the number of processes and the cpu time of
+//! everything the target spawns, and can put the target into fresh mount and network namespaces.
+//! Wrapping the executor in a [`SandboxExecutor`] reports runs that exhausted the memory or the
+//! process limit as [`ExitKind::Oom`], which can be used as objective with
+//! [`crate::feedbacks::OomFeedback`].
+//!
+//! The cgroup is created below a parent cgroup that has to be delegated to the fuzzer, with the
+//! `memory`, `pids` and `cpu` controllers available. Namespaces need `CAP_SYS_ADMIN`.
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+};
+use core::{
+    ptr,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    os::{fd::AsRawFd, unix::process::CommandExt},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use libafl_bolts::tuples::RefIndexable;
+
+use crate::{
+    Error,
+    executors::{Executor, ExitKind, HasObservers},
+};
+
+/// The default mount point of the cgroup v2 hierarchy
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// The default period for the cpu quota
+const CPU_PERIOD: Duration = Duration::from_millis(100);
+
+/// A counter to give each [`Sandbox`] of this process a unique cgroup
+static SANDBOX_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Reads the counter `key` from the content of a cgroup `*.events` file
+fn parse_event(events: &str, key: &str) -> Option<u64> {
+    events.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        if name == key {
+            value.trim().parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// The builder for a [`Sandbox`]
+#[derive(Debug, Clone, Default)]
+pub struct SandboxBuilder {
+    parent: Option<PathBuf>,
+    memory_max: Option<u64>,
+    pids_max: Option<u64>,
+    cpu_quota: Option<f64>,
+    max_open_files: Option<u64>,
+    mount_namespace: bool,
+    network_namespace: bool,
+}
+
+impl SandboxBuilder {
+    /// Creates the cgroup of the [`Sandbox`] below `parent`, instead of [`CGROUP_ROOT`]
+    #[must_use]
+    pub fn parent<P: AsRef<Path>>(mut self, parent: P) -> Self {
+        self.parent = Some(parent.as_ref().to_path_buf());
+        self
+    }
+
+    /// Limits the memory of all processes in the sandbox to `bytes`, swap is disabled.
+    #[must_use]
+    pub fn memory_max(mut self, bytes: u64) -> Self {
+        self.memory_max = Some(bytes);
+        self
+    }
+
+    /// Limits the number of processes and threads in the sandbox
+    #[must_use]
+    pub fn pids_max(mut self, pids: u64) -> Self {
+        self.pids_max = Some(pids);
+        self
+    }
+
+    /// Limits the cpu time of the sandbox to a share of one cpu, `2.0` meaning two full cpus
+    #[must_use]
+    pub fn cpu_quota(mut self, cpus: f64) -> Self {
+        self.cpu_quota = Some(cpus);
+        self
+    }
+
+    /// Limits the number of file descriptors each process may open, using `RLIMIT_NOFILE`
+    #[must_use]
+    pub fn max_open_files(mut self, max_open_files: u64) -> Self {
+        self.max_open_files = Some(max_open_files);
+        self
+    }
+
+    /// Runs the target in a new mount namespace, so its mounts do not affect the host
+    #[must_use]
+    pub fn mount_namespace(mut self, mount_namespace: bool) -> Self {
+        self.mount_namespace = mount_namespace;
+        self
+    }
+
+    /// Runs the target in a new network namespace, with only an unconfigured loopback device
+    #[must_use]
+    pub fn network_namespace(mut self, network_namespace: bool) -> Self {
+        self.network_namespace = network_namespace;
+        self
+    }
+
+    /// Creates the cgroup and applies the limits
+    pub fn build(self) -> Result<Sandbox, Error> {
+        let parent = self.parent.unwrap_or_else(|| PathBuf::from(CGROUP_ROOT));
+        let mut controllers = String::new();
+        if self.memory_max.is_some() {
+            controllers.push_str(" +memory");
+        }
+        if self.pids_max.is_some() {
+            controllers.push_str(" +pids");
+        }
+        if self.cpu_quota.is_some() {
+            controllers.push_str(" +cpu");
+        }
+        if !controllers.is_empty() {
+            // Fails if the controllers are already enabled, or not delegated to us
+            if let Err(err) = fs::write(parent.join("cgroup.subtree_control"), controllers.trim()) {
+                log::debug!("Could not enable cgroup controllers{controllers}: {err}");
+            }
+        }
+
+        let path = parent.join(format!(
+            "libafl-{}-{}",
+            std::process::id(),
+            SANDBOX_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir(&path).map_err(|err| {
+            Error::os_error(
+                err,
+                format!("Could not create the cgroup {}", path.display()),
+            )
+        })?;
+
+        let inner = SandboxInner {
+            procs: OpenOptions::new()
+                .write(true)
+                .open(path.join("cgroup.procs"))?,
+            path,
+            max_open_files: self.max_open_files,
+            mount_namespace: self.mount_namespace,
+            network_namespace: self.network_namespace,
+            oom_kills: AtomicU64::new(0),
+            pids_max_events: AtomicU64::new(0),
+        };
+        if let Some(bytes) = self.memory_max {
+            inner.write_control("memory.max", &bytes.to_string())?;
+            // Without swap, the target is killed instead of slowing down the whole machine
+            if inner.path.join("memory.swap.max").exists() {
+                inner.write_control("memory.swap.max", "0")?;
+            }
+        }
+        if let Some(pids) = self.pids_max {
+            inner.write_control("pids.max", &pids.to_string())?;
+        }
+        if let Some(cpus) = self.cpu_quota {
+            let period = CPU_PERIOD.as_micros();
+            #[expect(clippy::cast_precision_loss, clippy::cast_sign_loss)]
+            let quota = ((period as f64 * cpus) as u128).max(1000);
+            inner.write_control("cpu.max", &format!("{quota} {period}"))?;
+        }
+
+        Ok(Sandbox {
+            inner: Arc::new(inner),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct SandboxInner {
+    path: PathBuf,
+    /// `cgroup.procs`, written by the child to join the cgroup
+    procs: File,
+    max_open_files: Option<u64>,
+    mount_namespace: bool,
+    network_namespace: bool,
+    /// The last seen `oom_kill` counter of `memory.events`
+    oom_kills: AtomicU64,
+    /// The last seen `max` counter of `pids.events`
+    pids_max_events: AtomicU64,
+}
+
+impl SandboxInner {
+    fn write_control(&self, file: &str, value: &str) -> Result<(), Error> {
+        fs::write(self.path.join(file), value).map_err(|err| {
+            Error::os_error(
+                err,
+                format!("Could not set {file} of cgroup {}", self.path.display()),
+            )
+        })
+    }
+
+    /// Reads the `key` counter of the events `file`, returns if it increased since the last call
+    fn event_increased(&self, file: &str, key: &str, last: &AtomicU64) -> bool {
+        let Some(count) = fs::read_to_string(self.path.join(file))
+            .ok()
+            .and_then(|events| parse_event(&events, key))
+        else {
+            return false;
+        };
+        last.swap(count, Ordering::Relaxed) < count
+    }
+}
+
+impl Drop for SandboxInner {
+    fn drop(&mut self) {
+        // `cgroup.kill` needs Linux 5.14, older kernels keep leftover processes alive
+        let _ = fs::write(self.path.join("cgroup.kill"), "1");
+        for _ in 0..100 {
+            if fs::remove_dir(&self.path).is_ok() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        log::warn!("Could not remove the cgroup {}", self.path.display());
+    }
+}
+
+/// A cgroup v2 (and namespace) sandbox for the processes spawned by an executor.
+/// The cgroup is removed once the last clone of the [`Sandbox`] is dropped.
+///
+/// Use [`SandboxBuilder`] to create it, and [`Sandbox::apply`], or the `sandbox` option of the
+/// command and forkserver executor builders, to spawn the target in it.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    inner: Arc<SandboxInner>,
+}
+
+impl Sandbox {
+    /// Creates a builder for a new [`Sandbox`]
+    #[must_use]
+    pub fn builder() -> SandboxBuilder {
+        SandboxBuilder::default()
+    }
+
+    /// The path of the cgroup
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// Configures `command` to spawn its process in this sandbox
+    pub fn apply<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        let procs = self.inner.procs.as_raw_fd();
+        let max_open_files = self.inner.max_open_files;
+        let mut unshare_flags = 0;
+        if self.inner.mount_namespace {
+            unshare_flags |= libc::CLONE_NEWNS;
+        }
+        if self.inner.network_namespace {
+            unshare_flags |= libc::CLONE_NEWNET;
+        }
+        let mount_namespace = self.inner.mount_namespace;
+
+        // # Safety
+        // Only calls async-signal-safe libc functions in the child, and does not allocate.
+        let func = move || {
+            // Writing 0 moves the writing process
+            if unsafe { libc::write(procs, b"0".as_ptr().cast(), 1) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if let Some(max_open_files) = max_open_files {
+                let r = libc::rlimit {
+                    rlim_cur: max_open_files,
+                    rlim_max: max_open_files,
+                };
+                if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &raw const r) } < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if unshare_flags != 0 && unsafe { libc::unshare(unshare_flags) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Keep mount events from propagating back to the host
+            if mount_namespace
+                && unsafe {
+                    libc::mount(
+                        ptr::null(),
+                        c"/".as_ptr(),
+                        ptr::null(),
+                        libc::MS_REC | libc::MS_PRIVATE,
+                        ptr::null(),
+                    )
+                } < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        };
+        unsafe { command.pre_exec(func) }
+    }
+
+    /// Moves the running process `pid` into the cgroup. Namespaces are not applied.
+    pub fn add_process(&self, pid: u32) -> Result<(), Error> {
+        self.inner.write_control("cgroup.procs", &pid.to_string())
+    }
+
+    /// Kills all processes in the sandbox
+    pub fn kill_all(&self) -> Result<(), Error> {
+        self.inner.write_control("cgroup.kill", "1")
+    }
+
+    /// Returns `true` if a process in the sandbox was killed for exceeding the memory limit, or
+    /// failed to fork because of the process limit, since the last call
+    #[must_use]
+    pub fn resources_exhausted(&self) -> bool {
+        // Evaluate both, to update both counters
+        let oom = self
+            .inner
+            .event_increased("memory.events", "oom_kill", &self.inner.oom_kills);
+        let pids = self
+            .inner
+            .event_increased("pids.events", "max", &self.inner.pids_max_events);
+        oom || pids
+    }
+}
+
+/// Wraps an executor spawning its target in a [`Sandbox`], and reports runs that exhausted
+/// the resources of the sandbox as [`ExitKind::Oom`]
+#[derive(Debug)]
+pub struct SandboxExecutor<E> {
+    executor: E,
+    sandbox: Sandbox,
+    kill_after_run: bool,
+}
+
+impl<E> SandboxExecutor<E> {
+    /// Wraps `executor`, which has to spawn its target in `sandbox`
+    pub fn new(executor: E, sandbox: Sandbox) -> Self {
+        Self {
+            executor,
+            sandbox,
+            kill_after_run: false,
+        }
+    }
+
+    /// Kills all processes left in the sandbox after each run, such as daemons spawned by the
+    /// target. Must not be used with a forkserver, which lives in the sandbox itself.
+    #[must_use]
+    pub fn with_kill_after_run(mut self, kill_after_run: bool) -> Self {
+        self.kill_after_run = kill_after_run;
+        self
+    }
+
+    /// The [`Sandbox`]
+    pub fn sandbox(&self) -> &Sandbox {
+        &self.sandbox
+    }
+
+    /// The wrapped executor
+    pub fn inner(&mut self) -> &mut E {
+        &mut self.executor
+    }
+}
+
+impl<E, EM, I, S, Z> Executor<EM, I, S, Z> for SandboxExecutor<E>
+where
+    E: Executor<EM, I, S, Z>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut S,
+        mgr: &mut EM,
+        input: &I,
+    ) -> Result<ExitKind, Error> {
+        let exit_kind = self.executor.run_target(fuzzer, state, mgr, input)?;
+        if self.kill_after_run {
+            self.sandbox.kill_all()?;
+        }
+        if self.sandbox.resources_exhausted() {
+            return Ok(ExitKind::Oom);
+        }
+        Ok(exit_kind)
+    }
+}
+
+impl<E> HasObservers for SandboxExecutor<E>
+where
+    E: HasObservers,
+{
+    type Observers = E::Observers;
+
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        self.executor.observers()
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        self.executor.observers_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_event;
+
+    #[test]
+    fn test_parse_event() {
+        let events = "low 0\nhigh 0\nmax 12\noom 3\noom_kill 2\noom_group_kill 0\n";
+        assert_eq!(parse_event(events, "oom_kill"), Some(2));
+        assert_eq!(parse_event(events, "max"), Some(12));
+        assert_eq!(parse_event(events, "oom"), Some(3));
+        assert_eq!(parse_event(events, "missing"), None);
+    }
+}
@@ -0,0 +1,369 @@
+//! Feedbacks for algorithmic complexity fuzzing, in the style of `PerfFuzz` and `SlowFuzz`.
+//!
+//! The [`MaxHitcountFeedback`] keeps inputs raising the hitcount of any edge to a new maximum,
+//! and records the hitcounts and the cost of each input for the `MaxCostScheduler`.
+//! The [`CostBudgetFeedback`] is an objective, firing for inputs whose cost exceeds a budget.
+
+use alloc::{borrow::Cow, format, vec::Vec};
+use core::{fmt::Debug, marker::PhantomData};
+
+use libafl_bolts::{
+    Error, Named, impl_serdeany,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    HasMetadata,
+    corpus::Testcase,
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle, MaxMapFeedback, StateInitializer},
+    observers::{CanTrack, CostCounter, CostMapObserver, MapObserver},
+};
+
+/// The cost of a testcase, as measured by a [`CostMapObserver`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct CostMetadata {
+    /// The total hitcount of the map in the run of this testcase
+    pub cost: u64,
+}
+impl_serdeany!(CostMetadata);
+
+/// The hitcounts of the map entries a testcase hit, sorted by index
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MaxHitcountsMetadata {
+    /// The map index and hitcount of each entry hit
+    pub hitcounts: Vec<(usize, u64)>,
+}
+impl_serdeany!(MaxHitcountsMetadata);
+
+/// A [`MaxMapFeedback`] on a [`CostMapObserver`], finding inputs which hit any map entry more
+/// often than all inputs before. The hitcounts and the cost of the run are attached to each new
+/// testcase as [`MaxHitcountsMetadata`] and [`CostMetadata`].
+///
+/// Use it on a map with the raw hitcounts in [`CostCounter`]s, without buckets, so every increase
+/// counts.
+#[derive(Debug)]
+pub struct MaxHitcountFeedback<C, M> {
+    inner: MaxMapFeedback<C, CostMapObserver<M>>,
+}
+
+impl<C, M> MaxHitcountFeedback<C, M>
+where
+    C: CanTrack + AsRef<CostMapObserver<M>> + Named,
+{
+    /// Creates a new [`MaxHitcountFeedback`] for the `map_observer`
+    #[must_use]
+    pub fn new(map_observer: &C) -> Self {
+        Self {
+            inner: MaxMapFeedback::new(map_observer),
+        }
+    }
+}
+
+impl<C, M> Named for MaxHitcountFeedback<C, M> {
+    fn name(&self) -> &Cow<'static, str> {
+        self.inner.name()
+    }
+}
+
+impl<C, M, S> StateInitializer<S> for MaxHitcountFeedback<C, M>
+where
+    MaxMapFeedback<C, CostMapObserver<M>>: StateInitializer<S>,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.inner.init_state(state)
+    }
+}
+
+impl<C, EM, I, M, OT, S> Feedback<EM, I, OT, S> for MaxHitcountFeedback<C, M>
+where
+    MaxMapFeedback<C, CostMapObserver<M>>: Feedback<EM, I, OT, S>,
+    C: AsRef<CostMapObserver<M>>,
+    M: MapObserver,
+    M::Entry: CostCounter,
+    OT: MatchName,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        self.inner
+            .is_interesting(state, manager, input, observers, exit_kind)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.inner.last_result()
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        self.inner
+            .append_metadata(state, manager, observers, testcase)?;
+
+        let observer = observers
+            .get(self.inner.observer_handle())
+            .ok_or_else(|| Error::key_not_found("CostMapObserver not found"))?
+            .as_ref();
+        let initial = observer.initial();
+        let hitcounts = (0..observer.usable_count())
+            .filter_map(|idx| {
+                let count = observer.get(idx);
+                (count != initial).then(|| (idx, count.into()))
+            })
+            .collect();
+        testcase.add_metadata(MaxHitcountsMetadata { hitcounts });
+        testcase.add_metadata(CostMetadata {
+            cost: observer.cost(),
+        });
+        Ok(())
+    }
+}
+
+/// An objective firing for runs whose cost, as measured by a [`CostMapObserver`], exceeds the
+/// budget. The cost is attached to each solution as [`CostMetadata`].
+#[derive(Debug)]
+pub struct CostBudgetFeedback<C, M> {
+    name: Cow<'static, str>,
+    observer_handle: Handle<C>,
+    budget: u64,
+    last_cost: u64,
+    #[cfg(feature = "track_hit_feedbacks")]
+    last_result: Option<bool>,
+    phantom: PhantomData<M>,
+}
+
+impl<C, M> CostBudgetFeedback<C, M>
+where
+    C: Named,
+{
+    /// Creates a new [`CostBudgetFeedback`], firing for runs costing more than `budget`
+    #[must_use]
+    pub fn new(observer: &C, budget: u64) -> Self {
+        Self {
+            name: Cow::Owned(format!("cost_budget_{}", observer.name())),
+            observer_handle: observer.handle(),
+            budget,
+            last_cost: 0,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The budget a run may cost
+    #[must_use]
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+}
+
+impl<C, M> Named for CostBudgetFeedback<C, M> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<C, M> HasObserverHandle for CostBudgetFeedback<C, M> {
+    type Observer = C;
+
+    fn observer_handle(&self) -> &Handle<C> {
+        &self.observer_handle
+    }
+}
+
+impl<C, M, S> StateInitializer<S> for CostBudgetFeedback<C, M> {}
+
+impl<C, EM, I, M, OT, S> Feedback<EM, I, OT, S> for CostBudgetFeedback<C, M>
+where
+    C: AsRef<CostMapObserver<M>>,
+    OT: MatchName,
+{
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = observers
+            .get(&self.observer_handle)
+            .ok_or_else(|| Error::key_not_found("CostMapObserver not found"))?;
+        self.last_cost = observer.as_ref().cost();
+        let res = self.last_cost > self.budget;
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or_else(|| {
+            Error::illegal_state(
+                "No last result set in `CostBudgetFeedback`. Either `is_interesting` has never been called or the fuzzer restarted in the meantime.",
+            )
+        })
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        testcase.add_metadata(CostMetadata {
+            cost: self.last_cost,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use libafl_bolts::{
+        rands::StdRand,
+        tuples::{tuple_list, tuple_list_type},
+    };
+
+    use super::{CostBudgetFeedback, CostMetadata, MaxHitcountFeedback, MaxHitcountsMetadata};
+    use crate::{
+        HasMetadata,
+        corpus::{InMemoryCorpus, Testcase},
+        events::NopEventManager,
+        executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer},
+        inputs::BytesInput,
+        observers::{CostMapObserver, MapObserver, Observer, OwnedMapObserver},
+        state::StdState,
+    };
+
+    type TestState =
+        StdState<InMemoryCorpus<BytesInput>, BytesInput, StdRand, InMemoryCorpus<BytesInput>>;
+    type TestObservers = tuple_list_type!(CostMapObserver<OwnedMapObserver<u32>>);
+
+    fn state() -> TestState {
+        StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::new(),
+            InMemoryCorpus::new(),
+            &mut (),
+            &mut (),
+        )
+        .unwrap()
+    }
+
+    fn observers() -> TestObservers {
+        tuple_list!(CostMapObserver::new(OwnedMapObserver::new(
+            "edges",
+            vec![0_u32; 4]
+        )))
+    }
+
+    /// Runs the observer as if the target hit the map entries with these `hitcounts`
+    fn run(observers: &mut TestObservers, hitcounts: &[(usize, u32)]) {
+        let observer = &mut observers.0;
+        observer.pre_exec(&mut (), &()).unwrap();
+        observer.reset_map().unwrap();
+        for (idx, count) in hitcounts {
+            observer.set(*idx, *count);
+        }
+        observer.post_exec(&mut (), &(), &ExitKind::Ok).unwrap();
+    }
+
+    #[test]
+    fn test_max_hitcount_feedback() {
+        let mut state = state();
+        let mut mgr = NopEventManager::new();
+        let input = BytesInput::new(vec![0]);
+        let mut observers = observers();
+        let mut feedback = MaxHitcountFeedback::new(&observers.0);
+        feedback.init_state(&mut state).unwrap();
+
+        run(&mut observers, &[(0, 3), (1, 5)]);
+        assert!(
+            feedback
+                .is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok)
+                .unwrap()
+        );
+        let mut testcase = Testcase::new(input.clone());
+        feedback
+            .append_metadata(&mut state, &mut mgr, &observers, &mut testcase)
+            .unwrap();
+        assert_eq!(
+            testcase
+                .metadata::<MaxHitcountsMetadata>()
+                .unwrap()
+                .hitcounts,
+            [(0, 3), (1, 5)]
+        );
+        assert_eq!(testcase.metadata::<CostMetadata>().unwrap().cost, 8);
+
+        // No entry is hit more often than before
+        run(&mut observers, &[(0, 3), (1, 4)]);
+        assert!(
+            !feedback
+                .is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok)
+                .unwrap()
+        );
+
+        // A new maximum, past what a `u8` counter holds
+        run(&mut observers, &[(0, 300)]);
+        assert!(
+            feedback
+                .is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_cost_budget_feedback() {
+        let mut state = state();
+        let mut mgr = NopEventManager::new();
+        let input = BytesInput::new(vec![0]);
+        let mut observers = observers();
+        let mut feedback = CostBudgetFeedback::new(&observers.0, 100);
+
+        run(&mut observers, &[(0, 60), (1, 40)]);
+        assert!(
+            !feedback
+                .is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok)
+                .unwrap()
+        );
+
+        run(&mut observers, &[(0, 60), (1, 41)]);
+        assert!(
+            feedback
+                .is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok)
+                .unwrap()
+        );
+        let mut testcase = Testcase::new(input);
+        feedback
+            .append_metadata(&mut state, &mut mgr, &observers, &mut testcase)
+            .unwrap();
+        assert_eq!(testcase.metadata::<CostMetadata>().unwrap().cost, 101);
+    }
+}
@@ -35,6 +35,8 @@ pub use bool::BoolValueFeedback;
 
 #[cfg(feature = "std")]
 pub mod concolic;
+pub mod cost;
+pub use cost::{CostBudgetFeedback, CostMetadata, MaxHitcountFeedback, MaxHitcountsMetadata};
 #[cfg(feature = "std")]
 /// The module for `CustomFilenameToTestcaseFeedback`
 pub mod custom_filename;
@@ -794,6 +796,21 @@ impl ExitKindLogic for TimeoutLogic {
     }
 }
 
+/// Name used by `OomFeedback`
+pub const OOM_FEEDBACK_NAME: &str = "OomFeedback";
+
+/// Logic which finds all [`ExitKind::Oom`] exits interesting
+#[derive(Debug, Copy, Clone)]
+pub struct OomLogic;
+
+impl ExitKindLogic for OomLogic {
+    const NAME: Cow<'static, str> = Cow::Borrowed(OOM_FEEDBACK_NAME);
+
+    fn check_exit_kind(kind: &ExitKind) -> Result<bool, Error> {
+        Ok(matches!(kind, ExitKind::Oom))
+    }
+}
+
 /// Logic which finds all [`ExitKind::Diff`] exits interesting
 #[derive(Debug, Copy, Clone)]
 pub struct GenericDiffLogic;
@@ -806,7 +823,7 @@ impl ExitKindLogic for GenericDiffLogic {
     }
 }
 
-/// A generic exit type checking feedback. Use [`CrashFeedback`], [`TimeoutFeedback`], or
+/// A generic exit type checking feedback. Use [`CrashFeedback`], [`TimeoutFeedback`], [`OomFeedback`], or
 /// [`DiffExitKindFeedback`] directly instead.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ExitKindFeedback<L> {
@@ -890,6 +907,8 @@ where
 pub type CrashFeedback = ExitKindFeedback<CrashLogic>;
 /// A [`TimeoutFeedback`] reduces the timeout value of a run.
 pub type TimeoutFeedback = ExitKindFeedback<TimeoutLogic>;
+/// An [`OomFeedback`] reports as interesting if the target ran out of memory or another resource.
+pub type OomFeedback = ExitKindFeedback<OomLogic>;
 /// A [`DiffExitKindFeedback`] checks if there is a difference in the [`ExitKind`]s in a [`crate::executors::DiffExecutor`].
 pub type DiffExitKindFeedback = ExitKindFeedback<GenericDiffLogic>;
 
@@ -0,0 +1,176 @@
+//! Inputs carrying a schedule of faults next to the actual input.
+//!
+//! Each fault makes one call of a syscall or library function of the target fail. Executors
+//! tracing the syscalls of the target, such as the `PtraceExecutor` on Linux, or runtimes
+//! interposing library functions, such as the libc fault runtime of `libafl_targets`, inject the
+//! scheduled faults. This exercises the error handling paths of the target, which are almost never
+//! reached otherwise.
+
+use alloc::{format, string::String, vec::Vec};
+use core::{fmt::Debug, hash::Hash};
+
+use libafl_bolts::{generic_hash_std, ownedref::OwnedSlice};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    corpus::CorpusId,
+    inputs::{HasTargetBytes, Input},
+};
+
+/// The syscalls or library functions faults are injected into
+pub trait FaultTarget: Copy + PartialEq {
+    /// The number identifying this target, e.g. in the `FaultCallsMetadata`
+    fn id(self) -> u64;
+
+    /// The `errno`s this target commonly fails with, or an empty slice to use the `errno`s
+    /// configured in the `FaultMutator`
+    fn errnos(self) -> &'static [i32] {
+        &[]
+    }
+
+    /// If this target reads data, so that it can return a short read
+    fn reads(self) -> bool;
+}
+
+/// Syscall numbers, as used by the `PtraceExecutor`
+impl FaultTarget for u64 {
+    fn id(self) -> u64 {
+        self
+    }
+
+    /// The syscalls reading into a buffer, whose byte count the `PtraceExecutor` caps
+    fn reads(self) -> bool {
+        #[cfg(target_os = "linux")]
+        {
+            [
+                libc::SYS_read,
+                libc::SYS_pread64,
+                libc::SYS_readv,
+                libc::SYS_preadv,
+                libc::SYS_recvfrom,
+                libc::SYS_recvmsg,
+            ]
+            .into_iter()
+            .any(|nr| u64::try_from(nr) == Ok(self))
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            false
+        }
+    }
+}
+
+/// How an injected fault changes the call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FaultKind {
+    /// The call is not executed and fails with this `errno`, e.g. `ENOMEM` or `EINTR`
+    Errno(i32),
+    /// The byte count of a `read`-like call is capped to this value, resulting in a short read
+    ShortRead(u64),
+}
+
+/// A failure of one call of a syscall or library function `T`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fault<T> {
+    /// The syscall or function to fail
+    pub target: T,
+    /// Which call of the target to fail, starting at `0`
+    pub nth: u32,
+    /// How the call fails
+    pub kind: FaultKind,
+}
+
+impl<T> Fault<T> {
+    /// Creates a new [`Fault`] making the `nth` call of `target` fail
+    #[must_use]
+    pub fn new(target: T, nth: u32, kind: FaultKind) -> Self {
+        Self { target, nth, kind }
+    }
+}
+
+/// A failure of one invocation of the syscall with this number
+pub type SyscallFault = Fault<u64>;
+
+/// Inputs with a schedule of faults of the targets `T` to inject during their execution
+pub trait HasFaults<T> {
+    /// The faults to inject
+    fn faults(&self) -> &[Fault<T>];
+
+    /// The faults to inject, mutable
+    fn faults_mut(&mut self) -> &mut Vec<Fault<T>>;
+}
+
+/// An input extended by a schedule of [`Fault`]s
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FaultInput<I, T> {
+    input: I,
+    faults: Vec<Fault<T>>,
+}
+
+/// An input extended by a schedule of [`SyscallFault`]s
+pub type SyscallFaultInput<I> = FaultInput<I, u64>;
+
+impl<I, T> FaultInput<I, T> {
+    /// Creates a new [`FaultInput`] without any faults
+    #[must_use]
+    pub fn new(input: I) -> Self {
+        Self {
+            input,
+            faults: Vec::new(),
+        }
+    }
+
+    /// Creates a new [`FaultInput`] with the given faults
+    #[must_use]
+    pub fn with_faults(input: I, faults: Vec<Fault<T>>) -> Self {
+        Self { input, faults }
+    }
+
+    /// The wrapped input
+    #[must_use]
+    pub fn input(&self) -> &I {
+        &self.input
+    }
+
+    /// The wrapped input, mutable, e.g. to use it with a [`crate::mutators::MappingMutator`]
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+}
+
+impl<I, T> HasFaults<T> for FaultInput<I, T> {
+    fn faults(&self) -> &[Fault<T>] {
+        &self.faults
+    }
+
+    fn faults_mut(&mut self) -> &mut Vec<Fault<T>> {
+        &mut self.faults
+    }
+}
+
+impl<I, T> HasTargetBytes for FaultInput<I, T>
+where
+    I: HasTargetBytes,
+{
+    fn target_bytes(&self) -> OwnedSlice<'_, u8> {
+        self.input.target_bytes()
+    }
+}
+
+impl<I, T> Input for FaultInput<I, T>
+where
+    I: Input,
+    T: Clone + Debug + Hash + Serialize + DeserializeOwned,
+{
+    fn generate_name(&self, id: Option<CorpusId>) -> String {
+        if self.faults.is_empty() {
+            self.input.generate_name(id)
+        } else {
+            format!(
+                "{}-faults:{:016x}",
+                self.input.generate_name(id),
+                generic_hash_std(&self.faults)
+            )
+        }
+    }
+}
@@ -18,6 +18,11 @@ pub use generalized::*;
 pub mod bytessub;
 pub use bytessub::BytesSubInput;
 
+pub mod fault;
+pub use fault::{
+    Fault, FaultInput, FaultKind, FaultTarget, HasFaults, SyscallFault, SyscallFaultInput,
+};
+
 #[cfg(feature = "multipart_inputs")]
 pub mod multi;
 #[cfg(feature = "multipart_inputs")]
@@ -0,0 +1,275 @@
+//! Mutations of the fault schedule of inputs implementing [`HasFaults`]
+
+use alloc::{borrow::Cow, vec::Vec};
+
+use hashbrown::HashMap;
+use libafl_bolts::{Named, impl_serdeany, rands::Rand};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    inputs::{Fault, FaultKind, FaultTarget, HasFaults},
+    mutators::{MutationResult, Mutator},
+    state::{HasCurrentTestcase, HasRand},
+};
+
+/// The default maximum number of faults scheduled for one input
+pub const DEFAULT_MAX_FAULTS: usize = 4;
+
+/// The default highest call of a target a fault is scheduled for
+pub const DEFAULT_MAX_FAULT_NTH: u32 = 64;
+
+/// The highest byte count of an injected short read
+const MAX_SHORT_READ: usize = 64;
+
+/// The number of calls of each fault target during the execution of a testcase, by
+/// [`FaultTarget::id`]. The [`FaultMutator`] schedules new faults for calls that happen.
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FaultCallsMetadata {
+    /// The calls, by target
+    pub calls: HashMap<u64, u32>,
+}
+
+impl_serdeany!(FaultCallsMetadata);
+
+/// Adds, removes and changes the [`Fault`]s of an input
+///
+/// New faults fail a call of one of the given targets that happened for the current testcase,
+/// according to its [`FaultCallsMetadata`]. Without metadata, any of the first calls is picked.
+/// Faults fail with one of the [`FaultTarget::errnos`], or else the `errnos` of the mutator. One in
+/// four new faults of targets reading data is a short read instead, with a byte count from
+/// `0..=64`.
+#[derive(Debug, Clone)]
+pub struct FaultMutator<T> {
+    targets: Vec<T>,
+    errnos: Vec<i32>,
+    max_faults: usize,
+    max_nth: u32,
+}
+
+/// Adds, removes and changes the [`crate::inputs::SyscallFault`]s of an input
+pub type SyscallFaultMutator = FaultMutator<u64>;
+
+impl<T> FaultMutator<T>
+where
+    T: FaultTarget,
+{
+    /// Creates a new [`FaultMutator`] failing the given `targets` with the given `errnos`
+    #[must_use]
+    pub fn new(targets: Vec<T>, errnos: Vec<i32>) -> Self {
+        Self {
+            targets,
+            errnos,
+            max_faults: DEFAULT_MAX_FAULTS,
+            max_nth: DEFAULT_MAX_FAULT_NTH,
+        }
+    }
+
+    /// Sets the maximum number of faults scheduled for one input
+    #[must_use]
+    pub fn with_max_faults(mut self, max_faults: usize) -> Self {
+        self.max_faults = max_faults;
+        self
+    }
+
+    /// Sets the highest call of a target a fault is scheduled for
+    #[must_use]
+    pub fn with_max_nth(mut self, max_nth: u32) -> Self {
+        self.max_nth = max_nth;
+        self
+    }
+
+    fn random_kind<R: Rand>(&self, rand: &mut R, target: T) -> Option<FaultKind> {
+        let errnos = match target.errnos() {
+            [] => &self.errnos[..],
+            errnos => errnos,
+        };
+        match rand.choose(errnos) {
+            Some(errno) if !target.reads() || rand.coinflip(0.75) => Some(FaultKind::Errno(*errno)),
+            _ if target.reads() => {
+                Some(FaultKind::ShortRead(rand.between(0, MAX_SHORT_READ) as u64))
+            }
+            _ => None,
+        }
+    }
+
+    /// Picks a call of a target, one that happened if the `calls` are known
+    fn random_nth<R: Rand>(
+        &self,
+        rand: &mut R,
+        target: T,
+        calls: Option<&HashMap<u64, u32>>,
+    ) -> u32 {
+        let max = calls.map_or(self.max_nth, |calls| {
+            calls.get(&target.id()).copied().unwrap_or_default()
+        });
+        rand.below_or_zero(max.min(self.max_nth) as usize) as u32
+    }
+
+    fn random_target<R: Rand>(&self, rand: &mut R, calls: Option<&HashMap<u64, u32>>) -> Option<T> {
+        match calls {
+            Some(calls) => rand
+                .choose(
+                    self.targets
+                        .iter()
+                        .filter(|target| calls.get(&target.id()).is_some_and(|calls| *calls > 0)),
+                )
+                .copied(),
+            None => rand.choose(&self.targets).copied(),
+        }
+    }
+}
+
+impl<I, S, T> Mutator<I, S> for FaultMutator<T>
+where
+    I: HasFaults<T>,
+    S: HasRand + HasCurrentTestcase<I>,
+    T: FaultTarget,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let calls = state.current_testcase().ok().and_then(|testcase| {
+            testcase
+                .metadata::<FaultCallsMetadata>()
+                .ok()
+                .map(|meta| meta.calls.clone())
+        });
+        let calls = calls.as_ref();
+        let rand = state.rand_mut();
+        let faults_len = input.faults().len();
+
+        match rand.between(0, 3) {
+            // Schedule a new fault
+            0 | 1 if faults_len < self.max_faults => {
+                let Some(target) = self.random_target(rand, calls) else {
+                    return Ok(MutationResult::Skipped);
+                };
+                let nth = self.random_nth(rand, target, calls);
+                if input
+                    .faults()
+                    .iter()
+                    .any(|fault| fault.target == target && fault.nth == nth)
+                {
+                    return Ok(MutationResult::Skipped);
+                }
+                let Some(kind) = self.random_kind(rand, target) else {
+                    return Ok(MutationResult::Skipped);
+                };
+                input.faults_mut().push(Fault::new(target, nth, kind));
+            }
+            // Remove a fault
+            2 if faults_len > 0 => {
+                let idx = rand.below_or_zero(faults_len);
+                input.faults_mut().swap_remove(idx);
+            }
+            // Move a fault to another call of the same target, or change how it fails
+            _ if faults_len > 0 => {
+                let idx = rand.below_or_zero(faults_len);
+                let target = input.faults()[idx].target;
+                if rand.coinflip(0.5) {
+                    input.faults_mut()[idx].nth = self.random_nth(rand, target, calls);
+                } else if let Some(kind) = self.random_kind(rand, target) {
+                    input.faults_mut()[idx].kind = kind;
+                }
+            }
+            _ => return Ok(MutationResult::Skipped),
+        }
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<T> Named for FaultMutator<T> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("FaultMutator");
+        &NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashMap;
+    use libafl_bolts::rands::StdRand;
+
+    use super::{FaultCallsMetadata, SyscallFaultMutator};
+    use crate::{
+        HasMetadata,
+        corpus::{Corpus, HasCurrentCorpusId, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, FaultKind, HasFaults, SyscallFaultInput},
+        mutators::{MutationResult, Mutator},
+        state::StdState,
+    };
+
+    type TestInput = SyscallFaultInput<BytesInput>;
+    type TestState =
+        StdState<InMemoryCorpus<TestInput>, TestInput, StdRand, InMemoryCorpus<TestInput>>;
+
+    fn state_with_calls(calls: Option<HashMap<u64, u32>>) -> TestState {
+        let mut corpus = InMemoryCorpus::new();
+        let mut testcase = Testcase::new(SyscallFaultInput::new(BytesInput::new(vec![1, 2, 3])));
+        if let Some(calls) = calls {
+            testcase.add_metadata(FaultCallsMetadata { calls });
+        }
+        let id = corpus.add(testcase).unwrap();
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            corpus,
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        state.set_corpus_id(id).unwrap();
+        state
+    }
+
+    #[test]
+    fn test_syscall_fault_mutator() {
+        let mut state = state_with_calls(None);
+        let mut mutator = SyscallFaultMutator::new(vec![0, 2], vec![12, 4]).with_max_faults(2);
+        let mut input = SyscallFaultInput::new(BytesInput::new(vec![1, 2, 3]));
+
+        let mut mutated = 0;
+        for _ in 0..100 {
+            if mutator.mutate(&mut state, &mut input).unwrap() == MutationResult::Mutated {
+                mutated += 1;
+            }
+            let faults = input.faults();
+            assert!(faults.len() <= 2);
+            assert!(
+                faults
+                    .iter()
+                    .all(|fault| fault.target == 0 || fault.target == 2)
+            );
+            assert!(faults.iter().all(|fault| match fault.kind {
+                FaultKind::Errno(errno) => errno == 12 || errno == 4,
+                FaultKind::ShortRead(max) => max <= 64,
+            }));
+        }
+        assert!(mutated > 0);
+    }
+
+    #[test]
+    fn test_fault_mutator_calls() {
+        // Only syscall 2 was called, three times
+        let mut state = state_with_calls(Some(HashMap::from([(0, 0), (2, 3)])));
+        let mut mutator = SyscallFaultMutator::new(vec![0, 2], vec![12]).with_max_faults(3);
+        let mut input = SyscallFaultInput::new(BytesInput::new(vec![1, 2, 3]));
+
+        let mut scheduled = false;
+        for _ in 0..100 {
+            mutator.mutate(&mut state, &mut input).unwrap();
+            assert!(
+                input
+                    .faults()
+                    .iter()
+                    .all(|fault| fault.target == 2 && fault.nth < 3)
+            );
+            scheduled |= !input.faults().is_empty();
+        }
+        assert!(scheduled);
+    }
+}
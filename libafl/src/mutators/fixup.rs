@@ -0,0 +1,676 @@
+//! Declarative post-mutation repair of structured binary formats.
+//!
+//! Many formats carry length fields, checksums or compressed sections that the target validates
+//! early and rejects on mismatch. Instead of writing a [`crate::stages::mutational::MutatedTransformPost`]
+//! by hand for every format, the fields can be described with [`FixupField`]s and collected in
+//! [`FormatFixups`]. The [`FormatFixupMutator`] wraps any other [`Mutator`] and repairs the input after
+//! each mutation.
+//!
+//! Fixups discovered at runtime (for example by the [`crate::stages::ChecksumDetectionStage`]) are
+//! stored as [`FormatFixupMetadata`] on the testcase and applied by the [`FormatFixupMutator`], too.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::ops::Range;
+
+use libafl_bolts::{HasLen, Named, impl_serdeany};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::CorpusId,
+    inputs::{HasMutatorBytes, ResizableMutator},
+    mutators::{MutationResult, Mutator},
+    state::HasCurrentTestcase,
+};
+
+/// The maximum size a zlib-compressed region may expand to during mutation.
+#[cfg(feature = "gzip")]
+pub const MAX_ZLIB_EXPANDED_LEN: usize = 1 << 20;
+
+/// The byte order of a numeric field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FixupEndian {
+    /// Least significant byte first
+    Little,
+    /// Most significant byte first
+    Big,
+}
+
+/// A position in the input, either from the start or from the end of the input.
+///
+/// Positions relative to the end stay valid when mutations change the size of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FixupOffset {
+    /// `n` bytes after the start of the input
+    Start(usize),
+    /// `n` bytes before the end of the input
+    End(usize),
+}
+
+impl FixupOffset {
+    /// Resolves this offset for an input of the given length
+    #[must_use]
+    pub fn resolve(&self, len: usize) -> Option<usize> {
+        match self {
+            Self::Start(off) => (*off <= len).then_some(*off),
+            Self::End(off) => len.checked_sub(*off),
+        }
+    }
+}
+
+/// A range of bytes in the input, delimited by two [`FixupOffset`]s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FixupRange {
+    /// The (inclusive) start of the range
+    pub start: FixupOffset,
+    /// The (exclusive) end of the range
+    pub end: FixupOffset,
+}
+
+impl FixupRange {
+    /// Creates a new [`FixupRange`]
+    #[must_use]
+    pub fn new(start: FixupOffset, end: FixupOffset) -> Self {
+        Self { start, end }
+    }
+
+    /// A range covering the whole input
+    #[must_use]
+    pub fn all() -> Self {
+        Self::new(FixupOffset::Start(0), FixupOffset::End(0))
+    }
+
+    /// Resolves this range for an input of the given length.
+    ///
+    /// Returns `None` if the range does not fit into the input.
+    #[must_use]
+    pub fn resolve(&self, len: usize) -> Option<Range<usize>> {
+        let start = self.start.resolve(len)?;
+        let end = self.end.resolve(len)?;
+        (start <= end).then_some(start..end)
+    }
+}
+
+/// A single field of a format that has to be repaired after mutation
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FixupField {
+    /// An integer field holding the length of `range`, plus `adjust`
+    Length {
+        /// Where the field is stored
+        at: FixupOffset,
+        /// The width of the field in bytes, `1..=8`
+        width: usize,
+        /// The byte order of the field
+        endian: FixupEndian,
+        /// The range the length is computed for
+        range: FixupRange,
+        /// A constant added to the length before it is stored
+        adjust: i64,
+    },
+    /// A 4 byte CRC-32 (as used by zlib, PNG, ...) of `range`
+    Crc32 {
+        /// Where the field is stored
+        at: FixupOffset,
+        /// The byte order of the field
+        endian: FixupEndian,
+        /// The range the checksum is computed over
+        range: FixupRange,
+    },
+    /// A 4 byte Adler-32 of `range`
+    Adler32 {
+        /// Where the field is stored
+        at: FixupOffset,
+        /// The byte order of the field
+        endian: FixupEndian,
+        /// The range the checksum is computed over
+        range: FixupRange,
+    },
+    /// A 16 byte MD5 digest of `range`
+    Md5 {
+        /// Where the digest is stored
+        at: FixupOffset,
+        /// The range the digest is computed over
+        range: FixupRange,
+    },
+    /// A region holding a zlib stream.
+    ///
+    /// The [`FormatFixupMutator`] decompresses the region before mutation, so that the inner
+    /// mutators operate on the plain data, and compresses it again afterwards.
+    /// If the data following the region has to stay intact, its end should be given relative to
+    /// the end of the input.
+    #[cfg(feature = "gzip")]
+    Zlib {
+        /// The compressed region
+        range: FixupRange,
+    },
+}
+
+fn write_uint(bytes: &mut [u8], at: usize, width: usize, endian: FixupEndian, value: u64) {
+    if width == 0 || width > 8 || at + width > bytes.len() {
+        return;
+    }
+    let field = &mut bytes[at..at + width];
+    match endian {
+        FixupEndian::Little => field.copy_from_slice(&value.to_le_bytes()[..width]),
+        FixupEndian::Big => field.copy_from_slice(&value.to_be_bytes()[8 - width..]),
+    }
+}
+
+impl FixupField {
+    /// Repairs this field in the given bytes.
+    ///
+    /// Fields that do not fit into the input are left untouched.
+    pub fn apply<I>(&self, input: &mut I)
+    where
+        I: HasMutatorBytes + ResizableMutator<u8>,
+    {
+        let len = input.len();
+        match self {
+            Self::Length {
+                at,
+                width,
+                endian,
+                range,
+                adjust,
+            } => {
+                if let (Some(at), Some(range)) = (at.resolve(len), range.resolve(len)) {
+                    let value = (range.len() as u64).wrapping_add_signed(*adjust);
+                    write_uint(input.mutator_bytes_mut(), at, *width, *endian, value);
+                }
+            }
+            Self::Crc32 { at, endian, range } => {
+                if let (Some(at), Some(range)) = (at.resolve(len), range.resolve(len)) {
+                    let value = crc32(&input.mutator_bytes()[range]);
+                    write_uint(input.mutator_bytes_mut(), at, 4, *endian, value.into());
+                }
+            }
+            Self::Adler32 { at, endian, range } => {
+                if let (Some(at), Some(range)) = (at.resolve(len), range.resolve(len)) {
+                    let value = adler32(&input.mutator_bytes()[range]);
+                    write_uint(input.mutator_bytes_mut(), at, 4, *endian, value.into());
+                }
+            }
+            Self::Md5 { at, range } => {
+                if let (Some(at), Some(range)) = (at.resolve(len), range.resolve(len)) {
+                    if at + 16 <= len {
+                        let digest = md5(&input.mutator_bytes()[range]);
+                        input.mutator_bytes_mut()[at..at + 16].copy_from_slice(&digest);
+                    }
+                }
+            }
+            #[cfg(feature = "gzip")]
+            Self::Zlib { range } => {
+                if let Some(range) = range.resolve(len) {
+                    let compressed = miniz_oxide::deflate::compress_to_vec_zlib(
+                        &input.mutator_bytes()[range.clone()],
+                        miniz_oxide::deflate::CompressionLevel::BestSpeed as u8,
+                    );
+                    input.splice(range, compressed);
+                }
+            }
+        }
+    }
+
+    /// Undoes the parts of this fixup that must not be seen by mutators.
+    ///
+    /// Currently, this decompresses [`FixupField::Zlib`] regions, all other fields are left as-is.
+    /// Regions that do not hold a valid zlib stream are treated as plain data.
+    pub fn expand<I>(&self, input: &mut I)
+    where
+        I: HasMutatorBytes + ResizableMutator<u8>,
+    {
+        #[cfg(feature = "gzip")]
+        if let Self::Zlib { range } = self {
+            if let Some(range) = range.resolve(input.len()) {
+                if let Ok(plain) = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(
+                    &input.mutator_bytes()[range.clone()],
+                    MAX_ZLIB_EXPANDED_LEN,
+                ) {
+                    input.splice(range, plain);
+                }
+            }
+        }
+        #[cfg(not(feature = "gzip"))]
+        let _ = input;
+    }
+}
+
+/// A declarative description of all fields of a format that have to be repaired after mutation.
+///
+/// Fields are repaired in the order they were added, so compressed regions should come first,
+/// then lengths, and checksums last.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FormatFixups {
+    fields: Vec<FixupField>,
+}
+
+impl FormatFixups {
+    /// Creates a new, empty, [`FormatFixups`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self { fields: Vec::new() }
+    }
+
+    /// Adds a field, builder-style
+    #[must_use]
+    pub fn with_field(mut self, field: FixupField) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    /// Adds a field, if it is not already part of this description
+    pub fn add_field(&mut self, field: FixupField) {
+        if !self.fields.contains(&field) {
+            self.fields.push(field);
+        }
+    }
+
+    /// Adds the fields of `other` that are not already part of this description
+    pub fn merge(&mut self, other: &Self) {
+        for field in &other.fields {
+            self.add_field(field.clone());
+        }
+    }
+
+    /// The fields of this format
+    #[must_use]
+    pub fn fields(&self) -> &[FixupField] {
+        &self.fields
+    }
+
+    /// Prepares the input for mutation, see [`FixupField::expand`]
+    pub fn expand<I>(&self, input: &mut I)
+    where
+        I: HasMutatorBytes + ResizableMutator<u8>,
+    {
+        for field in self.fields.iter().rev() {
+            field.expand(input);
+        }
+    }
+
+    /// Repairs all fields of the input, in order
+    pub fn apply<I>(&self, input: &mut I)
+    where
+        I: HasMutatorBytes + ResizableMutator<u8>,
+    {
+        for field in &self.fields {
+            field.apply(input);
+        }
+    }
+}
+
+impl From<Vec<FixupField>> for FormatFixups {
+    fn from(fields: Vec<FixupField>) -> Self {
+        Self { fields }
+    }
+}
+
+impl HasLen for FormatFixups {
+    fn len(&self) -> usize {
+        self.fields.len()
+    }
+}
+
+/// Testcase metadata holding the fixups discovered for this testcase, for example by the
+/// [`crate::stages::ChecksumDetectionStage`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FormatFixupMetadata {
+    /// The fixups to apply to inputs derived from this testcase
+    pub fixups: FormatFixups,
+}
+
+impl_serdeany!(FormatFixupMetadata);
+
+impl FormatFixupMetadata {
+    /// Creates a new [`struct@FormatFixupMetadata`]
+    #[must_use]
+    pub fn new(fixups: FormatFixups) -> Self {
+        Self { fixups }
+    }
+}
+
+/// A [`Mutator`] wrapper that repairs the input according to a [`FormatFixups`] description
+/// after the inner mutator ran.
+///
+/// Fixups stored in the [`struct@FormatFixupMetadata`] of the current testcase are applied after
+/// the static ones.
+#[derive(Debug)]
+pub struct FormatFixupMutator<M> {
+    inner: M,
+    fixups: FormatFixups,
+    name: Cow<'static, str>,
+}
+
+impl<M> FormatFixupMutator<M>
+where
+    M: Named,
+{
+    /// Creates a new [`FormatFixupMutator`], repairing `fixups` after `inner` mutated.
+    pub fn new(inner: M, fixups: FormatFixups) -> Self {
+        let name = Cow::Owned(format!("FormatFixupMutator<{}>", inner.name()));
+        Self {
+            inner,
+            fixups,
+            name,
+        }
+    }
+
+    /// The static fixups of this mutator
+    #[must_use]
+    pub fn fixups(&self) -> &FormatFixups {
+        &self.fixups
+    }
+
+    /// The static fixups of this mutator (mutable)
+    pub fn fixups_mut(&mut self) -> &mut FormatFixups {
+        &mut self.fixups
+    }
+}
+
+impl<I, M, S> Mutator<I, S> for FormatFixupMutator<M>
+where
+    I: HasMutatorBytes + ResizableMutator<u8>,
+    M: Mutator<I, S>,
+    S: HasCurrentTestcase<I>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let discovered = state.current_testcase().ok().and_then(|tc| {
+            tc.metadata::<FormatFixupMetadata>()
+                .ok()
+                .map(|meta| meta.fixups.clone())
+        });
+        let fixups = match discovered {
+            Some(discovered) if !discovered.is_empty() => {
+                let mut merged = self.fixups.clone();
+                merged.merge(&discovered);
+                Cow::Owned(merged)
+            }
+            _ => Cow::Borrowed(&self.fixups),
+        };
+
+        fixups.expand(input);
+        let res = self.inner.mutate(state, input)?;
+        fixups.apply(input);
+
+        Ok(res)
+    }
+
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        self.inner.post_exec(state, new_corpus_id)
+    }
+}
+
+impl<M> Named for FormatFixupMutator<M> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0_u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Updates a (pre- and post-inverted) CRC-32 state with the given bytes
+#[inline]
+pub(crate) fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc = CRC32_TABLE[((crc ^ u32::from(*byte)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// Computes the CRC-32 (IEEE 802.3, as used by zlib and PNG) of the given bytes
+#[must_use]
+pub fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(!0, data)
+}
+
+const ADLER32_MOD: u32 = 65521;
+
+/// Updates an Adler-32 state with the given bytes
+#[inline]
+pub(crate) fn adler32_update(adler: u32, data: &[u8]) -> u32 {
+    let mut a = adler & 0xffff;
+    let mut b = adler >> 16;
+    for byte in data {
+        a = (a + u32::from(*byte)) % ADLER32_MOD;
+        b = (b + a) % ADLER32_MOD;
+    }
+    (b << 16) | a
+}
+
+/// Computes the Adler-32 checksum of the given bytes
+#[must_use]
+pub fn adler32(data: &[u8]) -> u32 {
+    adler32_update(1, data)
+}
+
+const MD5_SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+const MD5_CONSTANTS: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// Computes the MD5 digest of the given bytes
+#[must_use]
+#[expect(clippy::many_single_char_names)]
+pub fn md5(data: &[u8]) -> [u8; 16] {
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    let mut message = data.to_vec();
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&bit_len.to_le_bytes());
+
+    for chunk in message.chunks_exact(64) {
+        let mut words = [0_u32; 16];
+        for (word, bytes) in words.iter_mut().zip(chunk.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(MD5_CONSTANTS[i])
+                .wrapping_add(words[g])
+                .rotate_left(MD5_SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut digest = [0; 16];
+    for (out, word) in digest.chunks_exact_mut(4).zip(state) {
+        out.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::{
+        FixupEndian, FixupField, FixupOffset, FixupRange, FormatFixupMetadata, FormatFixupMutator,
+        FormatFixups, adler32, crc32, md5,
+    };
+    use crate::{
+        HasMetadata,
+        corpus::{Corpus, HasCurrentCorpusId, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::{BytesSetMutator, MutationResult, Mutator},
+        state::{NopState, StdState},
+    };
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(
+            md5(b"The quick brown fox jumps over the lazy dog"),
+            [
+                0x9e, 0x10, 0x7d, 0x9d, 0x37, 0x2b, 0xb6, 0x82, 0x6b, 0xd8, 0x1d, 0x35, 0x42, 0xa4,
+                0x19, 0xd6
+            ]
+        );
+    }
+
+    #[test]
+    fn test_length_and_crc() {
+        // [len: u16 be][payload ...][crc32 le over payload]
+        let fixups = FormatFixups::new()
+            .with_field(FixupField::Length {
+                at: FixupOffset::Start(0),
+                width: 2,
+                endian: FixupEndian::Big,
+                range: FixupRange::new(FixupOffset::Start(2), FixupOffset::End(4)),
+                adjust: 0,
+            })
+            .with_field(FixupField::Crc32 {
+                at: FixupOffset::End(4),
+                endian: FixupEndian::Little,
+                range: FixupRange::new(FixupOffset::Start(2), FixupOffset::End(4)),
+            });
+
+        let mut input: Vec<u8> = vec![0, 0, b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9'];
+        input.extend_from_slice(&[0; 4]);
+        fixups.apply(&mut input);
+
+        assert_eq!(&input[..2], &[0, 9]);
+        assert_eq!(&input[11..], &0xcbf4_3926_u32.to_le_bytes());
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_zlib_roundtrip() {
+        let fixups = FormatFixups::new().with_field(FixupField::Zlib {
+            range: FixupRange::new(FixupOffset::Start(1), FixupOffset::End(0)),
+        });
+
+        let mut input: Vec<u8> = vec![0xaa, 1, 2, 3, 4];
+        fixups.apply(&mut input);
+        assert_ne!(&input[1..], &[1, 2, 3, 4]);
+
+        fixups.expand(&mut input);
+        assert_eq!(input, vec![0xaa, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_fixup_mutator() {
+        let mut state: NopState<BytesInput> = NopState::new();
+        let fixups = FormatFixups::new().with_field(FixupField::Adler32 {
+            at: FixupOffset::Start(0),
+            endian: FixupEndian::Big,
+            range: FixupRange::new(FixupOffset::Start(4), FixupOffset::End(0)),
+        });
+        let mut mutator = FormatFixupMutator::new(BytesSetMutator::new(), fixups);
+
+        let mut input = BytesInput::new(vec![0; 16]);
+        assert_eq!(
+            MutationResult::Mutated,
+            mutator.mutate(&mut state, &mut input).unwrap()
+        );
+        let bytes = input.mutator_bytes();
+        assert_eq!(&bytes[..4], &adler32(&bytes[4..]).to_be_bytes());
+    }
+
+    #[test]
+    fn test_merge() {
+        let crc = FixupField::Crc32 {
+            at: FixupOffset::End(4),
+            endian: FixupEndian::Little,
+            range: FixupRange::new(FixupOffset::Start(0), FixupOffset::End(4)),
+        };
+        let adler = FixupField::Adler32 {
+            at: FixupOffset::Start(0),
+            endian: FixupEndian::Big,
+            range: FixupRange::new(FixupOffset::Start(4), FixupOffset::End(0)),
+        };
+        let mut fixups = FormatFixups::new().with_field(crc.clone());
+        fixups.merge(
+            &FormatFixups::new()
+                .with_field(adler.clone())
+                .with_field(crc.clone()),
+        );
+        assert_eq!(fixups.fields(), &[crc, adler]);
+    }
+
+    #[test]
+    fn test_fixup_mutator_discovered() {
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut corpus = InMemoryCorpus::new();
+        let mut testcase = Testcase::new(BytesInput::new(vec![0; 16]));
+        testcase.add_metadata(FormatFixupMetadata::new(FormatFixups::new().with_field(
+            FixupField::Crc32 {
+                at: FixupOffset::End(4),
+                endian: FixupEndian::Big,
+                range: FixupRange::new(FixupOffset::Start(0), FixupOffset::End(4)),
+            },
+        )));
+        let id = corpus.add(testcase).unwrap();
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            corpus,
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        state.set_corpus_id(id).unwrap();
+
+        // No static fixups, the discovered checksum is repaired anyway
+        let mut mutator = FormatFixupMutator::new(BytesSetMutator::new(), FormatFixups::new());
+        let mut input = BytesInput::new(vec![0; 16]);
+        mutator.mutate(&mut state, &mut input).unwrap();
+        let bytes = input.mutator_bytes();
+        assert_eq!(&bytes[12..], &crc32(&bytes[..12]).to_be_bytes());
+    }
+}
@@ -27,6 +27,12 @@ pub mod mapping;
 pub use mapping::*;
 pub mod tuneable;
 pub use tuneable::*;
+pub mod fixup;
+pub use fixup::*;
+pub mod structure;
+pub use structure::*;
+pub mod fault;
+pub use fault::{FaultCallsMetadata, FaultMutator, SyscallFaultMutator};
 
 #[cfg(feature = "std")]
 pub mod hash;
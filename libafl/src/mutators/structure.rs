@@ -0,0 +1,556 @@
+//! Mutations respecting the coarse structure of an input.
+//!
+//! The structure is inferred once per testcase by the [`crate::stages::StructureIdentificationStage`]
+//! and stored as [`InputStructureMetadata`]. The [`StructureAwareScheduledMutator`] prefers the
+//! mutations of this module for testcases carrying this metadata, and falls back to the usual
+//! uniform schedule for all others.
+
+use alloc::{borrow::Cow, string::ToString, vec::Vec};
+use core::{num::NonZero, ops::Range};
+
+use libafl_bolts::{
+    HasLen, Named, impl_serdeany,
+    rands::Rand,
+    tuples::{HasConstLen, Merge, NamedTuple, tuple_list, tuple_list_type},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    inputs::{HasMutatorBytes, ResizableMutator},
+    mutators::{
+        ComposedByMutations, MutationId, MutationResult, Mutator, MutatorsTuple, ScheduledMutator,
+        Tokens,
+    },
+    state::{HasCurrentTestcase, HasMaxSize, HasRand},
+};
+
+/// The maximum value added to or subtracted from a field, as in afl
+const ARITH_MAX: usize = 35;
+
+/// The default probability to schedule a structure-aware mutation for a testcase with structure
+pub const DEFAULT_STRUCTURE_BIAS: f64 = 0.5;
+
+/// The coarse kind of an input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputKind {
+    /// Mostly printable characters
+    Text,
+    /// Anything else
+    Binary,
+}
+
+/// The coarse structure of a testcase, as found by the
+/// [`crate::stages::StructureIdentificationStage`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputStructureMetadata {
+    kind: InputKind,
+    boundaries: Vec<usize>,
+    chunks: Vec<Range<usize>>,
+}
+
+impl_serdeany!(InputStructureMetadata);
+
+impl InputStructureMetadata {
+    /// Creates new [`InputStructureMetadata`] from the start offsets of the fields and the ranges
+    /// of repeated chunks in the input
+    #[must_use]
+    pub fn new(kind: InputKind, mut boundaries: Vec<usize>, chunks: Vec<Range<usize>>) -> Self {
+        boundaries.push(0);
+        boundaries.sort_unstable();
+        boundaries.dedup();
+        Self {
+            kind,
+            boundaries,
+            chunks,
+        }
+    }
+
+    /// The kind of the input
+    #[must_use]
+    pub fn kind(&self) -> InputKind {
+        self.kind
+    }
+
+    /// The sorted start offsets of the fields, always starting with `0`
+    #[must_use]
+    pub fn boundaries(&self) -> &[usize] {
+        &self.boundaries
+    }
+
+    /// The ranges of chunks occurring more than once in the input
+    #[must_use]
+    pub fn chunks(&self) -> &[Range<usize>] {
+        &self.chunks
+    }
+
+    /// The fields of a (mutated) input of `len` bytes.
+    ///
+    /// Boundaries past the end of the input are dropped, the last field extends to its end.
+    #[must_use]
+    pub fn fields(&self, len: usize) -> Vec<Range<usize>> {
+        let starts = self
+            .boundaries
+            .iter()
+            .copied()
+            .take_while(|start| *start < len)
+            .collect::<Vec<_>>();
+        starts
+            .iter()
+            .zip(starts.iter().skip(1).chain(Some(&len)))
+            .map(|(start, end)| *start..*end)
+            .collect()
+    }
+}
+
+/// Runs `f` on the [`InputStructureMetadata`] of the current testcase, if there is any
+fn with_structure<I, S, R>(state: &S, f: impl FnOnce(&InputStructureMetadata) -> R) -> Option<R>
+where
+    S: HasCurrentTestcase<I>,
+{
+    let testcase = state.current_testcase().ok()?;
+    testcase.metadata::<InputStructureMetadata>().ok().map(f)
+}
+
+/// Swaps the non-overlapping ranges `first` and `second` in place, `first` has to come first
+fn swap_ranges(bytes: &mut [u8], first: &Range<usize>, second: &Range<usize>) {
+    let gap = second.start - first.end;
+    let span = &mut bytes[first.start..second.end];
+    // [first][gap][second] -> [gap][second][first] -> [second][gap][first]
+    span.rotate_left(first.len());
+    span[..gap + second.len()].rotate_left(gap);
+}
+
+/// Swaps two fields, or two repeated chunks, of the input.
+#[derive(Debug, Default)]
+pub struct FieldSwapMutator;
+
+impl<I, S> Mutator<I, S> for FieldSwapMutator
+where
+    S: HasRand + HasCurrentTestcase<I>,
+    I: HasMutatorBytes,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let len = input.mutator_bytes().len();
+        let Some(ranges) = with_structure(state, |meta| {
+            let mut ranges = meta.fields(len);
+            ranges.extend(meta.chunks().iter().filter(|c| c.end <= len).cloned());
+            ranges
+        }) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some(count) = NonZero::new(ranges.len()) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let a = &ranges[state.rand_mut().below(count)];
+        let b = &ranges[state.rand_mut().below(count)];
+        let (first, second) = if a.start <= b.start { (a, b) } else { (b, a) };
+        if first.end > second.start || first.is_empty() || second.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let bytes = input.mutator_bytes();
+        if bytes[first.clone()] == bytes[second.clone()] {
+            return Ok(MutationResult::Skipped);
+        }
+        swap_ranges(input.mutator_bytes_mut(), first, second);
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for FieldSwapMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("FieldSwapMutator");
+        &NAME
+    }
+}
+
+impl FieldSwapMutator {
+    /// Creates a new [`FieldSwapMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Adds or subtracts a small value to a field, at the width of the field.
+///
+/// Fields of binary inputs are treated as little or big endian integers of up to 8 bytes, while
+/// for text inputs the first decimal number in the field is changed.
+#[derive(Debug, Default)]
+pub struct FieldArithMutator;
+
+impl FieldArithMutator {
+    /// Creates a new [`FieldArithMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn mutate_binary(bytes: &mut [u8], delta: u64, sub: bool, big_endian: bool) {
+        let width = [8, 4, 2, 1]
+            .into_iter()
+            .find(|width| *width <= bytes.len())
+            .unwrap_or(0);
+        let bytes = &mut bytes[..width];
+
+        let mut buf = [0_u8; 8];
+        let value = if big_endian {
+            buf[8 - width..].copy_from_slice(bytes);
+            u64::from_be_bytes(buf)
+        } else {
+            buf[..width].copy_from_slice(bytes);
+            u64::from_le_bytes(buf)
+        };
+        let value = if sub {
+            value.wrapping_sub(delta)
+        } else {
+            value.wrapping_add(delta)
+        };
+        if big_endian {
+            bytes.copy_from_slice(&value.to_be_bytes()[8 - width..]);
+        } else {
+            bytes.copy_from_slice(&value.to_le_bytes()[..width]);
+        }
+    }
+
+    /// Returns the range of the first decimal number in `field` and its mutated representation
+    fn mutate_text(field: &[u8], delta: u64, sub: bool) -> Option<(Range<usize>, Vec<u8>)> {
+        let mut start = field.iter().position(u8::is_ascii_digit)?;
+        let end = field[start..]
+            .iter()
+            .position(|b| !b.is_ascii_digit())
+            .map_or(field.len(), |end| start + end);
+        if start > 0 && field[start - 1] == b'-' {
+            start -= 1;
+        }
+
+        let value = core::str::from_utf8(&field[start..end])
+            .ok()?
+            .parse::<i64>()
+            .ok()?;
+        let delta = i64::try_from(delta).ok()?;
+        let value = if sub {
+            value.checked_sub(delta)?
+        } else {
+            value.checked_add(delta)?
+        };
+        Some((start..end, value.to_string().into_bytes()))
+    }
+}
+
+impl<I, S> Mutator<I, S> for FieldArithMutator
+where
+    S: HasRand + HasMaxSize + HasCurrentTestcase<I>,
+    I: HasMutatorBytes + ResizableMutator<u8>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let len = input.mutator_bytes().len();
+        let Some((kind, fields)) = with_structure(state, |meta| (meta.kind(), meta.fields(len)))
+        else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some(count) = NonZero::new(fields.len()) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let field = fields[state.rand_mut().below(count)].clone();
+        let delta = state.rand_mut().between(1, ARITH_MAX) as u64;
+        let sub = state.rand_mut().coinflip(0.5);
+
+        match kind {
+            InputKind::Binary => {
+                let big_endian = state.rand_mut().coinflip(0.5);
+                Self::mutate_binary(
+                    &mut input.mutator_bytes_mut()[field],
+                    delta,
+                    sub,
+                    big_endian,
+                );
+            }
+            InputKind::Text => {
+                let Some((range, replacement)) =
+                    Self::mutate_text(&input.mutator_bytes()[field.clone()], delta, sub)
+                else {
+                    return Ok(MutationResult::Skipped);
+                };
+                if len - range.len() + replacement.len() > state.max_size() {
+                    return Ok(MutationResult::Skipped);
+                }
+                let range = field.start + range.start..field.start + range.end;
+                input.splice(range, replacement);
+            }
+        }
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for FieldArithMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("FieldArithMutator");
+        &NAME
+    }
+}
+
+/// Inserts a token, or a copy of a repeated chunk, at a field boundary.
+#[derive(Debug, Default)]
+pub struct BoundaryInsertMutator;
+
+impl<I, S> Mutator<I, S> for BoundaryInsertMutator
+where
+    S: HasRand + HasMetadata + HasMaxSize + HasCurrentTestcase<I>,
+    I: HasMutatorBytes + ResizableMutator<u8>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let size = input.mutator_bytes().len();
+        let Some((boundaries, chunks)) = with_structure(state, |meta| {
+            let boundaries = meta
+                .boundaries()
+                .iter()
+                .copied()
+                .take_while(|boundary| *boundary < size)
+                .chain(Some(size))
+                .collect::<Vec<_>>();
+            let chunks = meta
+                .chunks()
+                .iter()
+                .filter(|c| c.end <= size)
+                .cloned()
+                .collect::<Vec<_>>();
+            (boundaries, chunks)
+        }) else {
+            return Ok(MutationResult::Skipped);
+        };
+        // Safety: the boundaries always contain the end of the input
+        let off = boundaries[state
+            .rand_mut()
+            .below(unsafe { NonZero::new(boundaries.len()).unwrap_unchecked() })];
+
+        let tokens_len = state
+            .metadata_map()
+            .get::<Tokens>()
+            .map_or(0, |tokens| tokens.tokens().len());
+
+        let insert = if let Some(tokens_len) = NonZero::new(tokens_len)
+            && (chunks.is_empty() || state.rand_mut().coinflip(0.5))
+        {
+            let token_idx = state.rand_mut().below(tokens_len);
+            state.metadata_map().get::<Tokens>().unwrap().tokens()[token_idx].clone()
+        } else if let Some(chunks_len) = NonZero::new(chunks.len()) {
+            let chunk = chunks[state.rand_mut().below(chunks_len)].clone();
+            input.mutator_bytes()[chunk].to_vec()
+        } else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        if insert.is_empty() || size + insert.len() > state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+        input.splice(off..off, insert);
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for BoundaryInsertMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("BoundaryInsertMutator");
+        &NAME
+    }
+}
+
+impl BoundaryInsertMutator {
+    /// Creates a new [`BoundaryInsertMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Get the mutations respecting the [`InputStructureMetadata`] of the testcase
+#[must_use]
+pub fn structure_mutations()
+-> tuple_list_type!(FieldSwapMutator, FieldArithMutator, BoundaryInsertMutator) {
+    tuple_list!(
+        FieldSwapMutator::new(),
+        FieldArithMutator::new(),
+        BoundaryInsertMutator::new()
+    )
+}
+
+/// The maximum stack power for an input of `len` bytes: short inputs get fewer stacked mutations
+fn stack_pow_for_len(len: usize, max_stack_pow: usize) -> usize {
+    let len_pow = len.checked_ilog2().map_or(0, |pow| pow as usize);
+    max_stack_pow.min(len_pow)
+}
+
+/// A [`ScheduledMutator`] biased toward structure-aware mutations, and aware of the input length.
+///
+/// For testcases with [`InputStructureMetadata`], one of the structure mutations is picked with
+/// probability `bias`, and any mutation otherwise. For all other testcases, mutations are picked
+/// uniformly, like the [`crate::mutators::StdScheduledMutator`] does.
+///
+/// The number of stacked mutations grows with the length of the input: up to `2^max_stack_pow`
+/// mutations are stacked, but no more than about one per byte of the input, so that short inputs
+/// are not overwritten entirely.
+#[derive(Debug)]
+pub struct StructureAwareScheduledMutator<MT> {
+    name: Cow<'static, str>,
+    mutations: MT,
+    structure_mutations_len: usize,
+    bias: f64,
+    max_stack_pow: usize,
+}
+
+impl<MT> Named for StructureAwareScheduledMutator<MT> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, MT, S> Mutator<I, S> for StructureAwareScheduledMutator<MT>
+where
+    I: HasLen,
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasCurrentTestcase<I>,
+{
+    #[inline]
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        self.scheduled_mutate(state, input)
+    }
+}
+
+impl<MT> ComposedByMutations for StructureAwareScheduledMutator<MT> {
+    type Mutations = MT;
+    /// Get the mutations
+    #[inline]
+    fn mutations(&self) -> &MT {
+        &self.mutations
+    }
+
+    // Get the mutations (mutable)
+    #[inline]
+    fn mutations_mut(&mut self) -> &mut MT {
+        &mut self.mutations
+    }
+}
+
+impl<I, MT, S> ScheduledMutator<I, S> for StructureAwareScheduledMutator<MT>
+where
+    I: HasLen,
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasCurrentTestcase<I>,
+{
+    /// Compute the number of iterations used to apply stacked mutations
+    fn iterations(&self, state: &mut S, input: &I) -> u64 {
+        let max_stack_pow = stack_pow_for_len(input.len(), self.max_stack_pow);
+        1 << (1 + state.rand_mut().below_or_zero(max_stack_pow))
+    }
+
+    /// Get the next mutation to apply
+    fn schedule(&self, state: &mut S, _: &I) -> MutationId {
+        let has_structure = self.structure_mutations_len > 0
+            && state
+                .current_testcase()
+                .is_ok_and(|tc| tc.has_metadata::<InputStructureMetadata>());
+
+        let len = if has_structure && state.rand_mut().coinflip(self.bias) {
+            self.structure_mutations_len
+        } else {
+            self.mutations.len()
+        };
+        state
+            .rand_mut()
+            .below(NonZero::new(len).expect("No mutations provided!"))
+            .into()
+    }
+}
+
+impl<MT> StructureAwareScheduledMutator<MT>
+where
+    MT: NamedTuple,
+{
+    /// Create a new [`StructureAwareScheduledMutator`], preferring `structure_mutations` over the
+    /// other `mutations` for testcases with [`InputStructureMetadata`]
+    pub fn new<SMT, OMT>(structure_mutations: SMT, mutations: OMT) -> Self
+    where
+        SMT: Merge<OMT, MergeResult = MT> + HasConstLen,
+    {
+        let mutations = structure_mutations.merge(mutations);
+        Self {
+            name: Cow::from(format!(
+                "StructureAwareScheduledMutator[{}]",
+                mutations.names().join(", ")
+            )),
+            mutations,
+            structure_mutations_len: SMT::LEN,
+            bias: DEFAULT_STRUCTURE_BIAS,
+            max_stack_pow: 7,
+        }
+    }
+
+    /// Sets the probability to pick one of the structure mutations, for testcases with structure
+    #[must_use]
+    pub fn with_bias(mut self, bias: f64) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    /// Sets the maximum number of stacked mutations, as a power of two
+    #[must_use]
+    pub fn with_max_stack_pow(mut self, max_stack_pow: usize) -> Self {
+        self.max_stack_pow = max_stack_pow;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::{
+        FieldArithMutator, InputKind, InputStructureMetadata, stack_pow_for_len, swap_ranges,
+    };
+
+    #[test]
+    fn test_fields() {
+        let meta = InputStructureMetadata::new(InputKind::Binary, vec![8, 4, 12], vec![]);
+        assert_eq!(meta.boundaries(), &[0, 4, 8, 12]);
+        assert_eq!(meta.fields(16), vec![0..4, 4..8, 8..12, 12..16]);
+        assert_eq!(meta.fields(10), vec![0..4, 4..8, 8..10]);
+        assert!(meta.fields(0).is_empty());
+    }
+
+    #[test]
+    fn test_swap_ranges() {
+        let mut bytes = *b"aaXbbbYc";
+        swap_ranges(&mut bytes, &(0..2), &(3..6));
+        assert_eq!(&bytes, b"bbbXaaYc");
+    }
+
+    #[test]
+    fn test_field_arith() {
+        let mut bytes = [0xff, 0x00, 0x12];
+        FieldArithMutator::mutate_binary(&mut bytes, 1, false, false);
+        assert_eq!(bytes, [0x00, 0x01, 0x12]);
+        FieldArithMutator::mutate_binary(&mut bytes, 2, true, true);
+        assert_eq!(bytes, [0xff, 0xff, 0x12]);
+
+        let (range, replacement) = FieldArithMutator::mutate_text(b"len=-3;", 5, false).unwrap();
+        assert_eq!(range, 4..6);
+        assert_eq!(replacement, b"2");
+        assert!(FieldArithMutator::mutate_text(b"none", 5, false).is_none());
+    }
+
+    #[test]
+    fn test_stack_pow_for_len() {
+        assert_eq!(stack_pow_for_len(0, 7), 0);
+        assert_eq!(stack_pow_for_len(1, 7), 0);
+        assert_eq!(stack_pow_for_len(16, 7), 4);
+        assert_eq!(stack_pow_for_len(17, 7), 4);
+        assert_eq!(stack_pow_for_len(4096, 7), 7);
+    }
+}
@@ -1,10 +1,10 @@
 //! Tokens are what AFL calls extras or dictionaries.
 //! They may be inserted as part of mutations during fuzzing.
-use alloc::{borrow::Cow, vec::Vec};
+use alloc::{borrow::Cow, string::String, vec::Vec};
 #[cfg(any(target_os = "linux", target_vendor = "apple"))]
 use core::slice::from_raw_parts;
 use core::{
-    fmt::Debug,
+    fmt::{Debug, Display, LowerExp},
     mem::size_of,
     num::NonZero,
     ops::{Add, AddAssign, Deref},
@@ -429,8 +429,195 @@ impl TokenReplace {
     }
 }
 
+/// The encodings under which a floating point operand may appear in the input:
+/// its raw bits in little and big endian, and, for finite values, its textual representations.
+fn float_encodings<F>(le_bytes: &[u8], be_bytes: &[u8], value: F, is_finite: bool) -> Vec<Vec<u8>>
+where
+    F: Display + Debug + LowerExp,
+{
+    let mut encodings = vec![le_bytes.to_vec(), be_bytes.to_vec()];
+    if is_finite {
+        encodings.push(format!("{value}").into_bytes());
+        encodings.push(format!("{value:?}").into_bytes());
+        encodings.push(format!("{value:e}").into_bytes());
+    }
+    encodings
+}
+
+fn f32_encodings(bits: u32) -> Vec<Vec<u8>> {
+    let value = f32::from_bits(bits);
+    float_encodings(
+        &bits.to_le_bytes(),
+        &bits.to_be_bytes(),
+        value,
+        value.is_finite(),
+    )
+}
+
+fn f64_encodings(bits: u64) -> Vec<Vec<u8>> {
+    let value = f64::from_bits(bits);
+    float_encodings(
+        &bits.to_le_bytes(),
+        &bits.to_be_bytes(),
+        value,
+        value.is_finite(),
+    )
+}
+
+/// The encodings under which a wide string, logged with `width` bytes per character
+/// in native byte order, may appear in the input: UTF-16 and UTF-32 in both byte orders, and UTF-8.
+/// The string ends at the first null character.
+///
+/// Returns `None` if the logged string is not valid.
+fn wide_string_encodings(bytes: &[u8], width: u8) -> Option<Vec<Vec<u8>>> {
+    let mut string = String::new();
+    match width {
+        2 => {
+            let units = bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+                .take_while(|unit| *unit != 0);
+            for c in char::decode_utf16(units) {
+                string.push(c.ok()?);
+            }
+        }
+        4 => {
+            let units = bytes
+                .chunks_exact(4)
+                .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+                .take_while(|unit| *unit != 0);
+            for unit in units {
+                string.push(char::from_u32(unit)?);
+            }
+        }
+        _ => return None,
+    }
+
+    let utf16: Vec<u16> = string.encode_utf16().collect();
+    Some(vec![
+        utf16.iter().flat_map(|unit| unit.to_le_bytes()).collect(),
+        utf16.iter().flat_map(|unit| unit.to_be_bytes()).collect(),
+        string
+            .chars()
+            .flat_map(|c| u32::from(c).to_le_bytes())
+            .collect(),
+        string
+            .chars()
+            .flat_map(|c| u32::from(c).to_be_bytes())
+            .collect(),
+        string.into_bytes(),
+    ])
+}
+
+/// Pairs up the encodings of both sides of a comparison, each side to be replaced with the other.
+/// The first side is not used as pattern if it is a constant.
+fn i2s_encoding_pairs(
+    v1: &[Vec<u8>],
+    v2: &[Vec<u8>],
+    v1_is_const: bool,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let v1_to_v2 = v1.iter().zip(v2).filter(|_| !v1_is_const);
+    let v2_to_v1 = v2.iter().zip(v1);
+    v1_to_v2
+        .chain(v2_to_v1)
+        .map(|(pattern, repl)| (pattern.clone(), repl.clone()))
+        .collect()
+}
+
+/// Replaces the first occurrence of a pattern at or after `off` with its paired replacement.
+/// The input is resized if the two differ in length, as long as it stays within `max_size`.
+fn i2s_replace_encoded<I>(
+    input: &mut I,
+    off: usize,
+    max_size: usize,
+    pairs: &[(Vec<u8>, Vec<u8>)],
+) -> MutationResult
+where
+    I: ResizableMutator<u8> + HasMutatorBytes,
+{
+    let len = input.mutator_bytes().len();
+    for i in off..len {
+        for (pattern, repl) in pairs {
+            if pattern.is_empty()
+                || pattern == repl
+                || !input.mutator_bytes()[i..].starts_with(pattern)
+            {
+                continue;
+            }
+            if pattern.len() == repl.len() {
+                input.mutator_bytes_mut()[i..i + repl.len()].copy_from_slice(repl);
+            } else if len - pattern.len() + repl.len() <= max_size {
+                input.splice(i..i + pattern.len(), repl.iter().copied());
+            } else {
+                continue;
+            }
+            return MutationResult::Mutated;
+        }
+    }
+    MutationResult::Skipped
+}
+
+/// Replaces the longest prefix of either side found at or after `off` with the other side.
+fn i2s_replace_bytes<I>(input: &mut I, off: usize, v1: &[u8], v2: &[u8]) -> MutationResult
+where
+    I: HasMutatorBytes,
+{
+    let len = input.mutator_bytes().len();
+    for i in off..len {
+        for (pattern, repl) in [(v1, v2), (v2, v1)] {
+            let mut size = core::cmp::min(core::cmp::min(pattern.len(), repl.len()), len - i);
+            while size != 0 {
+                if pattern[0..size] == input.mutator_bytes()[i..i + size] {
+                    unsafe {
+                        buffer_copy(input.mutator_bytes_mut(), repl, 0, i, size);
+                    }
+                    return MutationResult::Mutated;
+                }
+                size -= 1;
+            }
+        }
+    }
+    MutationResult::Skipped
+}
+
+/// Replaces the operand of a float, wide string or long memory comparison, trying each of its encodings.
+/// Integer and short byte comparisons are skipped, the mutators match them directly.
+fn i2s_replace_extended<I>(
+    input: &mut I,
+    off: usize,
+    max_size: usize,
+    cmp_values: &CmpValues,
+) -> MutationResult
+where
+    I: ResizableMutator<u8> + HasMutatorBytes,
+{
+    let pairs = match cmp_values {
+        CmpValues::F32((v1, v2, v1_is_const)) => {
+            i2s_encoding_pairs(&f32_encodings(*v1), &f32_encodings(*v2), *v1_is_const)
+        }
+        CmpValues::F64((v1, v2, v1_is_const)) => {
+            i2s_encoding_pairs(&f64_encodings(*v1), &f64_encodings(*v2), *v1_is_const)
+        }
+        CmpValues::WideBytes((v1, v2, width)) => {
+            let (Some(v1), Some(v2)) = (
+                wide_string_encodings(v1.as_slice(), *width),
+                wide_string_encodings(v2.as_slice(), *width),
+            ) else {
+                return MutationResult::Skipped;
+            };
+            i2s_encoding_pairs(&v1, &v2, false)
+        }
+        CmpValues::LongBytes((v1, v2)) => return i2s_replace_bytes(input, off, v1, v2),
+        _ => return MutationResult::Skipped,
+    };
+    i2s_replace_encoded(input, off, max_size, &pairs)
+}
+
 /// A `I2SRandReplace` [`Mutator`] replaces a random matching input-2-state comparison operand with the other.
 /// It needs a valid [`CmpValuesMetadata`] in the state.
+///
+/// Floating point operands are also looked up by their textual representations,
+/// and wide strings in all of their UTF encodings.
 #[derive(Debug, Default)]
 pub struct I2SRandReplace;
 
@@ -462,6 +649,7 @@ where
 
         let off = state.rand_mut().below(size);
         let len = input.mutator_bytes().len();
+        let max_size = state.max_size();
         let bytes = input.mutator_bytes_mut();
 
         let meta = state.metadata_map().get::<CmpValuesMetadata>().unwrap();
@@ -570,30 +758,10 @@ where
                 }
             }
             CmpValues::Bytes(v) => {
-                'outer: for i in off..len {
-                    let mut size = core::cmp::min(v.0.len(), len - i);
-                    while size != 0 {
-                        if v.0.as_slice()[0..size] == input.mutator_bytes()[i..i + size] {
-                            unsafe {
-                                buffer_copy(input.mutator_bytes_mut(), v.1.as_slice(), 0, i, size);
-                            }
-                            result = MutationResult::Mutated;
-                            break 'outer;
-                        }
-                        size -= 1;
-                    }
-                    size = core::cmp::min(v.1.len(), len - i);
-                    while size != 0 {
-                        if v.1.as_slice()[0..size] == input.mutator_bytes()[i..i + size] {
-                            unsafe {
-                                buffer_copy(input.mutator_bytes_mut(), v.0.as_slice(), 0, i, size);
-                            }
-                            result = MutationResult::Mutated;
-                            break 'outer;
-                        }
-                        size -= 1;
-                    }
-                }
+                result = i2s_replace_bytes(input, off, v.0.as_slice(), v.1.as_slice());
+            }
+            extended => {
+                result = i2s_replace_extended(input, off, max_size, extended);
             }
         }
 
@@ -657,6 +825,7 @@ where
 
         let off = state.rand_mut().below(size);
         let len = input.mutator_bytes().len();
+        let max_size = state.max_size();
         let bytes = input.mutator_bytes_mut();
 
         let meta = state.metadata_map().get::<CmpValuesMetadata>().unwrap();
@@ -778,30 +947,10 @@ where
                 }
             }
             CmpValues::Bytes(v) => {
-                'outer: for i in off..len {
-                    let mut size = core::cmp::min(v.0.len(), len - i);
-                    while size != 0 {
-                        if v.0.as_slice()[0..size] == input.mutator_bytes()[i..i + size] {
-                            unsafe {
-                                buffer_copy(input.mutator_bytes_mut(), v.1.as_slice(), 0, i, size);
-                            }
-                            result = MutationResult::Mutated;
-                            break 'outer;
-                        }
-                        size -= 1;
-                    }
-                    size = core::cmp::min(v.1.len(), len - i);
-                    while size != 0 {
-                        if v.1.as_slice()[0..size] == input.mutator_bytes()[i..i + size] {
-                            unsafe {
-                                buffer_copy(input.mutator_bytes_mut(), v.0.as_slice(), 0, i, size);
-                            }
-                            result = MutationResult::Mutated;
-                            break 'outer;
-                        }
-                        size -= 1;
-                    }
-                }
+                result = i2s_replace_bytes(input, off, v.0.as_slice(), v.1.as_slice());
+            }
+            extended => {
+                result = i2s_replace_extended(input, off, max_size, &extended);
             }
         }
 
@@ -853,8 +1002,7 @@ impl AFLppRedQueen {
         clippy::cast_sign_loss,
         clippy::too_many_arguments,
         clippy::too_many_lines,
-        clippy::cast_possible_wrap,
-        clippy::cast_precision_loss
+        clippy::cast_possible_wrap
     )]
     pub fn cmp_extend_encoding(
         &self,
@@ -1134,14 +1282,13 @@ impl AFLppRedQueen {
                 let repl_new: u64;
 
                 if attr & CMP_ATTRIBUTE_IS_GREATER != 0 {
+                    // the operands are the raw bits of the floats
                     if hshape == 4 && its_len >= 4 {
-                        let mut g = repl as f32;
-                        g += 1.0;
-                        repl_new = u64::from(g as u32);
+                        let g = f32::from_bits(repl as u32) + 1.0;
+                        repl_new = u64::from(g.to_bits());
                     } else if hshape == 8 && its_len >= 8 {
-                        let mut g = repl as f64;
-                        g += 1.0;
-                        repl_new = g as u64;
+                        let g = f64::from_bits(repl) + 1.0;
+                        repl_new = g.to_bits();
                     } else {
                         return Ok(false);
                     }
@@ -1164,14 +1311,13 @@ impl AFLppRedQueen {
                         return Ok(true);
                     }
                 } else {
+                    // the operands are the raw bits of the floats
                     if hshape == 4 && its_len >= 4 {
-                        let mut g = repl as f32;
-                        g -= 1.0;
-                        repl_new = u64::from(g as u32);
+                        let g = f32::from_bits(repl as u32) - 1.0;
+                        repl_new = u64::from(g.to_bits());
                     } else if hshape == 8 && its_len >= 8 {
-                        let mut g = repl as f64;
-                        g -= 1.0;
-                        repl_new = g as u64;
+                        let g = f64::from_bits(repl) - 1.0;
+                        repl_new = g.to_bits();
                     } else {
                         return Ok(false);
                     }
@@ -1864,6 +2010,86 @@ where
                                 );
                             }
                         }
+                        (CmpValues::LongBytes(orig), CmpValues::LongBytes(new)) => {
+                            let (orig_v0, orig_v1, new_v0, new_v1) =
+                                (&orig.0, &orig.1, &new.0, &new.1);
+                            let hshape = core::cmp::max(orig_v0.len(), orig_v1.len());
+                            // Compare v0 against v1
+                            self.rtn_extend_encoding(
+                                orig_v0,
+                                orig_v1,
+                                new_v0,
+                                new_v1,
+                                new_bytes,
+                                orig_bytes,
+                                cmp_buf_idx,
+                                taint_len,
+                                input_len,
+                                hshape,
+                                &mut ret,
+                            );
+
+                            // Compare v1 against v0
+                            self.rtn_extend_encoding(
+                                orig_v1,
+                                orig_v0,
+                                new_v1,
+                                new_v0,
+                                new_bytes,
+                                orig_bytes,
+                                cmp_buf_idx,
+                                taint_len,
+                                input_len,
+                                hshape,
+                                &mut ret,
+                            );
+                        }
+                        (CmpValues::WideBytes(orig), CmpValues::WideBytes(new)) => {
+                            let (Some(orig_v0), Some(orig_v1), Some(new_v0), Some(new_v1)) = (
+                                wide_string_encodings(orig.0.as_slice(), orig.2),
+                                wide_string_encodings(orig.1.as_slice(), orig.2),
+                                wide_string_encodings(new.0.as_slice(), new.2),
+                                wide_string_encodings(new.1.as_slice(), new.2),
+                            ) else {
+                                continue;
+                            };
+
+                            // The input may hold the strings in any of their encodings
+                            for (((orig_v0, orig_v1), new_v0), new_v1) in
+                                orig_v0.iter().zip(&orig_v1).zip(&new_v0).zip(&new_v1)
+                            {
+                                let hshape = core::cmp::max(orig_v0.len(), orig_v1.len());
+                                // Compare v0 against v1
+                                self.rtn_extend_encoding(
+                                    orig_v0,
+                                    orig_v1,
+                                    new_v0,
+                                    new_v1,
+                                    new_bytes,
+                                    orig_bytes,
+                                    cmp_buf_idx,
+                                    taint_len,
+                                    input_len,
+                                    hshape,
+                                    &mut ret,
+                                );
+
+                                // Compare v1 against v0
+                                self.rtn_extend_encoding(
+                                    orig_v1,
+                                    orig_v0,
+                                    new_v1,
+                                    new_v0,
+                                    new_bytes,
+                                    orig_bytes,
+                                    cmp_buf_idx,
+                                    taint_len,
+                                    input_len,
+                                    hshape,
+                                    &mut ret,
+                                );
+                            }
+                        }
                         (_, _) => {
                             // not gonna happen
                         }
@@ -2094,8 +2320,83 @@ mod tests {
     #[cfg(feature = "std")]
     use std::fs;
 
+    use alloc::vec::Vec;
+
+    use super::I2SRandReplace;
     #[cfg(feature = "std")]
     use super::{AFLppRedQueen, Tokens};
+    use crate::{
+        HasMetadata,
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::{MutationResult, Mutator},
+        observers::cmp::{CmpValues, CmpValuesMetadata, CmplogBytes},
+        state::NopState,
+    };
+
+    /// Runs the mutator on fresh copies of `input` until the random offset allows a replacement
+    fn i2s_mutate(cmp_values: CmpValues, input: &[u8]) -> Vec<u8> {
+        let mut state: NopState<BytesInput> = NopState::new();
+        let mut meta = CmpValuesMetadata::new();
+        meta.list.push(cmp_values);
+        state.add_metadata(meta);
+
+        let mut mutator = I2SRandReplace::new();
+        for _ in 0..1000 {
+            let mut mutated = BytesInput::new(input.to_vec());
+            if mutator.mutate(&mut state, &mut mutated).unwrap() == MutationResult::Mutated {
+                return mutated.mutator_bytes().to_vec();
+            }
+        }
+        panic!("I2SRandReplace never mutated the input");
+    }
+
+    #[test]
+    fn test_i2s_float() {
+        let cmp = CmpValues::F64((1.5_f64.to_bits(), 2.25_f64.to_bits(), false));
+        assert_eq!(i2s_mutate(cmp.clone(), b"1.5"), b"2.25");
+        assert_eq!(
+            i2s_mutate(cmp, &1.5_f64.to_be_bytes()),
+            2.25_f64.to_be_bytes()
+        );
+
+        let cmp = CmpValues::F32((0.5_f32.to_bits(), 8.0_f32.to_bits(), false));
+        assert_eq!(
+            i2s_mutate(cmp, &0.5_f32.to_le_bytes()),
+            8.0_f32.to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn test_i2s_wide_string() {
+        let mut ab = [0; 32];
+        let mut xyz = [0; 32];
+        for (i, c) in "ab".encode_utf16().enumerate() {
+            ab[i * 2..i * 2 + 2].copy_from_slice(&c.to_ne_bytes());
+        }
+        for (i, c) in "xyz".encode_utf16().enumerate() {
+            xyz[i * 2..i * 2 + 2].copy_from_slice(&c.to_ne_bytes());
+        }
+        let (ab, xyz) = (
+            CmplogBytes::from_buf_and_len(ab, 32),
+            CmplogBytes::from_buf_and_len(xyz, 32),
+        );
+
+        let cmp = CmpValues::WideBytes((ab, xyz, 2));
+        // the input holds the narrow version of the wide string
+        assert_eq!(i2s_mutate(cmp.clone(), b"ab"), b"xyz");
+        assert_eq!(
+            i2s_mutate(cmp, &[0, b'a', 0, b'b']),
+            [0, b'x', 0, b'y', 0, b'z']
+        );
+    }
+
+    #[test]
+    fn test_i2s_long_bytes() {
+        let v0: Vec<u8> = (0..100).collect();
+        let v1: Vec<u8> = (100..200).collect();
+        let cmp = CmpValues::LongBytes((v0.clone(), v1.clone()));
+        assert_eq!(i2s_mutate(cmp, &v0), v1);
+    }
 
     #[cfg(feature = "std")]
     #[test]
@@ -2130,7 +2431,7 @@ token2="B"
         let taint_len = 0;
         let input_len = 0;
         let hshape = 0;
-        let mut vec = alloc::vec::Vec::new();
+        let mut vec = Vec::new();
 
         let _res = rq.cmp_extend_encoding(
             pattern,
@@ -59,6 +59,14 @@ pub enum CmpValues {
     U64((u64, u64, bool)),
     /// Two vecs of u8 values/byte
     Bytes((CmplogBytes, CmplogBytes)),
+    /// A `f32` comparison, as raw bits: (side 1, side 2, side 1 value is const)
+    F32((u32, u32, bool)),
+    /// A `f64` comparison, as raw bits: (side 1, side 2, side 1 value is const)
+    F64((u64, u64, bool)),
+    /// Two wide strings in target-native byte order, and the width of a single character (2 or 4)
+    WideBytes((CmplogBytes, CmplogBytes, u8)),
+    /// Two byte buffers from a memory comparison longer than [`CmplogBytes`] can hold
+    LongBytes((Vec<u8>, Vec<u8>)),
 }
 
 impl CmpValues {
@@ -67,7 +75,12 @@ impl CmpValues {
     pub fn is_numeric(&self) -> bool {
         matches!(
             self,
-            CmpValues::U8(_) | CmpValues::U16(_) | CmpValues::U32(_) | CmpValues::U64(_)
+            CmpValues::U8(_)
+                | CmpValues::U16(_)
+                | CmpValues::U32(_)
+                | CmpValues::U64(_)
+                | CmpValues::F32(_)
+                | CmpValues::F64(_)
         )
     }
 
@@ -77,9 +90,9 @@ impl CmpValues {
         match self {
             CmpValues::U8(t) => Some((u64::from(t.0), u64::from(t.1), t.2)),
             CmpValues::U16(t) => Some((u64::from(t.0), u64::from(t.1), t.2)),
-            CmpValues::U32(t) => Some((u64::from(t.0), u64::from(t.1), t.2)),
-            CmpValues::U64(t) => Some(*t),
-            CmpValues::Bytes(_) => None,
+            CmpValues::U32(t) | CmpValues::F32(t) => Some((u64::from(t.0), u64::from(t.1), t.2)),
+            CmpValues::U64(t) | CmpValues::F64(t) => Some(*t),
+            CmpValues::Bytes(_) | CmpValues::WideBytes(_) | CmpValues::LongBytes(_) => None,
         }
     }
 }
@@ -0,0 +1,273 @@
+//! The [`CostMapObserver`] measures the cost of a run as the total hitcount of a map.
+//!
+//! With a map of wide edge counters, this is the number of executed edges, the cost metric of
+//! `PerfFuzz` and `SlowFuzz`, used to find inputs with bad algorithmic complexity. The usual `u8`
+//! edge counters wrap at 256 and would hide the very blowups looked for, so the map entries have to
+//! be [`CostCounter`]s.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::{
+    fmt::Debug,
+    hash::Hash,
+    ops::{Deref, DerefMut},
+};
+
+use libafl_bolts::{AsSlice, AsSliceMut, HasLen, Named, Truncate};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error,
+    executors::ExitKind,
+    observers::{ConstLenMapObserver, Observer, VarLenMapObserver, map::MapObserver},
+};
+
+/// The map entries counting without wrapping for a [`CostMapObserver`], `u32` or `u64`
+pub trait CostCounter: Copy + Into<u64> {}
+
+impl CostCounter for u32 {}
+
+impl CostCounter for u64 {}
+
+/// Map observer summing up the entries of its map into the cost of the run.
+///
+/// The map holds the raw hitcounts in [`CostCounter`]s, for instance an [`OwnedMapObserver`]
+/// of `u32`s filled by the edge hooks. The cost is computed before the `base` observer
+/// postprocesses its map.
+///
+/// [`OwnedMapObserver`]: crate::observers::OwnedMapObserver
+#[derive(Serialize, Deserialize, Clone, Debug, Hash)]
+pub struct CostMapObserver<M> {
+    base: M,
+    cost: u64,
+}
+
+impl<M> Deref for CostMapObserver<M> {
+    type Target = M;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl<M> DerefMut for CostMapObserver<M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+impl<I, S, M> Observer<I, S> for CostMapObserver<M>
+where
+    M: MapObserver + Observer<I, S>,
+    M::Entry: CostCounter,
+{
+    #[inline]
+    fn pre_exec(&mut self, state: &mut S, input: &I) -> Result<(), Error> {
+        self.cost = 0;
+        self.base.pre_exec(state, input)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, state: &mut S, input: &I, exit_kind: &ExitKind) -> Result<(), Error> {
+        self.cost = (0..self.base.usable_count())
+            .map(|idx| self.base.get(idx).into())
+            .fold(0, u64::saturating_add);
+        self.base.post_exec(state, input, exit_kind)
+    }
+}
+
+impl<M> Named for CostMapObserver<M>
+where
+    M: Named,
+{
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        self.base.name()
+    }
+}
+
+impl<M> CostMapObserver<M> {
+    /// Creates a new [`CostMapObserver`] measuring the total hitcount of the map of `base`
+    pub fn new(base: M) -> Self {
+        Self { base, cost: 0 }
+    }
+
+    /// The cost of the last run: the sum of all map entries
+    #[must_use]
+    pub fn cost(&self) -> u64 {
+        self.cost
+    }
+}
+
+impl<M> HasLen for CostMapObserver<M>
+where
+    M: HasLen,
+{
+    #[inline]
+    fn len(&self) -> usize {
+        self.base.len()
+    }
+}
+
+impl<M> AsRef<Self> for CostMapObserver<M> {
+    fn as_ref(&self) -> &Self {
+        self
+    }
+}
+
+impl<M> AsMut<Self> for CostMapObserver<M> {
+    fn as_mut(&mut self) -> &mut Self {
+        self
+    }
+}
+
+impl<M> MapObserver for CostMapObserver<M>
+where
+    M: MapObserver,
+{
+    type Entry = M::Entry;
+
+    #[inline]
+    fn initial(&self) -> M::Entry {
+        self.base.initial()
+    }
+
+    #[inline]
+    fn usable_count(&self) -> usize {
+        self.base.usable_count()
+    }
+
+    #[inline]
+    fn get(&self, idx: usize) -> M::Entry {
+        self.base.get(idx)
+    }
+
+    #[inline]
+    fn set(&mut self, idx: usize, val: M::Entry) {
+        self.base.set(idx, val);
+    }
+
+    fn count_bytes(&self) -> u64 {
+        self.base.count_bytes()
+    }
+
+    #[inline]
+    fn reset_map(&mut self) -> Result<(), Error> {
+        self.base.reset_map()
+    }
+
+    fn to_vec(&self) -> Vec<M::Entry> {
+        self.base.to_vec()
+    }
+
+    fn how_many_set(&self, indexes: &[usize]) -> usize {
+        self.base.how_many_set(indexes)
+    }
+}
+
+impl<M, const N: usize> ConstLenMapObserver<N> for CostMapObserver<M>
+where
+    M: ConstLenMapObserver<N>,
+{
+    fn map_slice(&self) -> &[Self::Entry; N] {
+        self.base.map_slice()
+    }
+
+    fn map_slice_mut(&mut self) -> &mut [Self::Entry; N] {
+        self.base.map_slice_mut()
+    }
+}
+
+impl<M> VarLenMapObserver for CostMapObserver<M>
+where
+    M: VarLenMapObserver,
+{
+    fn map_slice(&self) -> &[Self::Entry] {
+        self.base.map_slice()
+    }
+
+    fn map_slice_mut(&mut self) -> &mut [Self::Entry] {
+        self.base.map_slice_mut()
+    }
+
+    fn size(&self) -> &usize {
+        self.base.size()
+    }
+
+    fn size_mut(&mut self) -> &mut usize {
+        self.base.size_mut()
+    }
+}
+
+impl<M> Truncate for CostMapObserver<M>
+where
+    M: Truncate,
+{
+    fn truncate(&mut self, new_len: usize) {
+        self.base.truncate(new_len);
+    }
+}
+
+impl<'a, M> AsSlice<'a> for CostMapObserver<M>
+where
+    M: AsSlice<'a>,
+{
+    type Entry = <M as AsSlice<'a>>::Entry;
+    type SliceRef = <M as AsSlice<'a>>::SliceRef;
+
+    #[inline]
+    fn as_slice(&'a self) -> Self::SliceRef {
+        self.base.as_slice()
+    }
+}
+
+impl<'a, M> AsSliceMut<'a> for CostMapObserver<M>
+where
+    M: AsSliceMut<'a>,
+{
+    type SliceRefMut = <M as AsSliceMut<'a>>::SliceRefMut;
+    #[inline]
+    fn as_slice_mut(&'a mut self) -> Self::SliceRefMut {
+        self.base.as_slice_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::CostMapObserver;
+    use crate::{
+        executors::ExitKind,
+        observers::{MapObserver, Observer, OwnedMapObserver},
+    };
+
+    #[test]
+    fn test_cost_map_observer() {
+        let mut observer = CostMapObserver::new(OwnedMapObserver::new("edges", vec![0_u32; 8]));
+        let mut state = ();
+
+        observer.pre_exec(&mut state, &()).unwrap();
+        observer.set(0, 3);
+        observer.set(5, 200);
+        observer.post_exec(&mut state, &(), &ExitKind::Ok).unwrap();
+        assert_eq!(observer.cost(), 203);
+
+        // A quadratic loop, hitting its edges far more often than a `u8` counter could hold
+        observer.pre_exec(&mut state, &()).unwrap();
+        observer.reset_map().unwrap();
+        observer.set(1, 256);
+        observer.set(2, 1 << 20);
+        observer.post_exec(&mut state, &(), &ExitKind::Ok).unwrap();
+        assert_eq!(observer.cost(), 256 + (1 << 20));
+    }
+
+    #[test]
+    fn test_cost_map_observer_saturates() {
+        let mut observer = CostMapObserver::new(OwnedMapObserver::new("edges", vec![u64::MAX; 2]));
+        let mut state = ();
+
+        observer.pre_exec(&mut state, &()).unwrap();
+        observer.post_exec(&mut state, &(), &ExitKind::Ok).unwrap();
+        assert_eq!(observer.cost(), u64::MAX);
+    }
+}
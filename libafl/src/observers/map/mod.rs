@@ -25,6 +25,9 @@ pub use variable_map::*;
 pub mod hitcount_map;
 pub use hitcount_map::*;
 
+pub mod cost_map;
+pub use cost_map::*;
+
 pub mod multi_map;
 pub use multi_map::*;
 
@@ -20,6 +20,9 @@ pub use map::*;
 
 pub mod value;
 
+pub mod syscall;
+pub use syscall::{SyscallObserver, SyscallRecord};
+
 /// List observer
 pub mod list;
 use core::{fmt::Debug, time::Duration};
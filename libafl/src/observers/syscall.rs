@@ -0,0 +1,178 @@
+//! The [`SyscallObserver`] records the syscalls of a run of the target.
+//!
+//! The executor must explicitly support this observer, such as the `PtraceExecutor` on Linux.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::hash::{BuildHasher, Hash, Hasher};
+
+use ahash::RandomState;
+use libafl_bolts::Named;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error,
+    observers::{Observer, ObserverWithHashField},
+};
+
+/// The default maximum number of syscalls recorded for one run
+pub const DEFAULT_MAX_SYSCALL_RECORDS: usize = 1 << 16;
+
+/// One syscall made by the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SyscallRecord {
+    /// The syscall number
+    pub nr: u64,
+    /// The arguments, as passed by the target
+    pub args: [u64; 6],
+    /// The return value, negative `errno`s for failed syscalls
+    pub ret: i64,
+    /// If the executor injected a fault into this syscall
+    pub faulted: bool,
+}
+
+/// An observer recording the syscalls of the last run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyscallObserver {
+    name: Cow<'static, str>,
+    records: Vec<SyscallRecord>,
+    blocked: Option<SyscallRecord>,
+    max_records: usize,
+    truncated: bool,
+}
+
+impl SyscallObserver {
+    /// Creates a new [`SyscallObserver`] with the given name
+    #[must_use]
+    pub fn new<S>(name: S) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        Self {
+            name: name.into(),
+            records: Vec::new(),
+            blocked: None,
+            max_records: DEFAULT_MAX_SYSCALL_RECORDS,
+            truncated: false,
+        }
+    }
+
+    /// Sets the maximum number of syscalls recorded for one run, later syscalls are dropped
+    #[must_use]
+    pub fn with_max_records(mut self, max_records: usize) -> Self {
+        self.max_records = max_records;
+        self
+    }
+
+    /// The syscalls of the last run, in order
+    #[must_use]
+    pub fn records(&self) -> &[SyscallRecord] {
+        &self.records
+    }
+
+    /// The syscall the target was blocked in when the last run timed out, if any.
+    ///
+    /// The return value of this record is meaningless, as the syscall never returned.
+    #[must_use]
+    pub fn blocked_syscall(&self) -> Option<&SyscallRecord> {
+        self.blocked.as_ref()
+    }
+
+    /// Returns `true` if the last run made more syscalls than were recorded
+    #[must_use]
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Records a syscall of the current run, called by the executor
+    pub fn record(&mut self, record: SyscallRecord) {
+        if self.records.len() < self.max_records {
+            self.records.push(record);
+        } else {
+            self.truncated = true;
+        }
+    }
+
+    /// Records the syscall the target is blocked in, called by the executor on timeouts
+    pub fn set_blocked_syscall(&mut self, record: Option<SyscallRecord>) {
+        self.blocked = record;
+    }
+
+    fn reset(&mut self) {
+        self.records.clear();
+        self.blocked = None;
+        self.truncated = false;
+    }
+}
+
+impl Named for SyscallObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Observer<I, S> for SyscallObserver {
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.reset();
+        Ok(())
+    }
+
+    fn pre_exec_child(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.reset();
+        Ok(())
+    }
+}
+
+/// Hashes the sequence of syscall numbers and whether each syscall failed, ignoring arguments
+/// and exact return values which change from run to run (pointers, file descriptors).
+impl ObserverWithHashField for SyscallObserver {
+    fn hash(&self) -> Option<u64> {
+        if self.records.is_empty() {
+            return None;
+        }
+        let mut hasher = RandomState::with_seeds(1, 2, 3, 4).build_hasher();
+        for record in &self.records {
+            record.nr.hash(&mut hasher);
+            (record.ret < 0).hash(&mut hasher);
+        }
+        Some(hasher.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SyscallObserver, SyscallRecord};
+    use crate::observers::ObserverWithHashField;
+
+    fn record(nr: u64, ret: i64) -> SyscallRecord {
+        SyscallRecord {
+            nr,
+            args: [nr; 6],
+            ret,
+            faulted: false,
+        }
+    }
+
+    #[test]
+    fn test_syscall_observer_hash() {
+        let mut first = SyscallObserver::new("syscalls").with_max_records(2);
+        let mut second = SyscallObserver::new("syscalls");
+        assert_eq!(first.hash(), None);
+
+        first.record(record(0, 10));
+        first.record(record(1, 3));
+        first.record(record(2, 0));
+        assert!(first.truncated());
+        assert_eq!(first.records().len(), 2);
+
+        // Different arguments and return values, same behaviour
+        second.record(SyscallRecord {
+            args: [0; 6],
+            ..record(0, 12)
+        });
+        second.record(record(1, 3));
+        assert_eq!(first.hash(), second.hash());
+
+        second.record(record(1, -12));
+        assert_ne!(first.hash(), second.hash());
+    }
+}
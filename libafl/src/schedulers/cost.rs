@@ -0,0 +1,306 @@
+//! The [`MaxCostScheduler`] feeds the fuzzer with the testcases maximizing the hitcount of some
+//! map entry, for algorithmic complexity fuzzing in the style of `PerfFuzz`.
+
+use alloc::{format, vec::Vec};
+use core::marker::PhantomData;
+
+use hashbrown::HashMap;
+use libafl_bolts::{HasLen, rands::Rand, tuples::MatchName};
+use serde::{Deserialize, Serialize};
+
+use super::HasQueueCycles;
+use crate::{
+    Error, HasMetadata,
+    corpus::{Corpus, CorpusId, Testcase},
+    feedbacks::{CostMetadata, MaxHitcountsMetadata},
+    schedulers::{RemovableScheduler, Scheduler, minimizer::DEFAULT_SKIP_NON_FAVORED_PROB},
+    state::{HasCorpus, HasRand},
+};
+
+/// The testcase hitting a map entry most often
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct MaxHitcountTopRated {
+    /// The highest hitcount of the map entry
+    pub hitcount: u64,
+    /// The cost per input byte of the testcase, breaking ties
+    pub cost_per_byte: f64,
+    /// The testcase
+    pub id: CorpusId,
+}
+
+/// A state metadata holding the testcase with the highest hitcount for each map entry
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct MaxHitcountTopRatedsMetadata {
+    /// map index -> top rated testcase
+    pub map: HashMap<usize, MaxHitcountTopRated>,
+    /// corpus index -> number of map entries it is top rated for
+    pub favored: HashMap<CorpusId, usize>,
+}
+
+libafl_bolts::impl_serdeany!(MaxHitcountTopRatedsMetadata);
+
+impl MaxHitcountTopRatedsMetadata {
+    /// Creates a new [`struct@MaxHitcountTopRatedsMetadata`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if the testcase has the highest hitcount of any map entry
+    #[must_use]
+    pub fn is_favored(&self, id: CorpusId) -> bool {
+        self.favored.contains_key(&id)
+    }
+
+    fn unfavor(&mut self, id: CorpusId) {
+        if let Some(count) = self.favored.get_mut(&id) {
+            *count -= 1;
+            if *count == 0 {
+                self.favored.remove(&id);
+            }
+        }
+    }
+}
+
+/// The [`MaxCostScheduler`] favors, for each map entry, the testcase hitting it most often, as
+/// recorded by a `MaxHitcountFeedback`. Ties go to the testcase with the higher cost per input
+/// byte, so small inputs with a large cost are preferred. Non-favored testcases are mostly
+/// skipped, as in the `MinimizerScheduler`.
+#[derive(Debug, Clone)]
+pub struct MaxCostScheduler<CS, I> {
+    base: CS,
+    skip_non_favored_prob: f64,
+    phantom: PhantomData<I>,
+}
+
+impl<CS, I, S> RemovableScheduler<I, S> for MaxCostScheduler<CS, I>
+where
+    CS: RemovableScheduler<I, S>,
+    I: HasLen,
+    S: HasCorpus<I> + HasMetadata,
+{
+    fn on_replace(
+        &mut self,
+        state: &mut S,
+        id: CorpusId,
+        testcase: &Testcase<I>,
+    ) -> Result<(), Error> {
+        self.base.on_replace(state, id, testcase)?;
+        self.update_score(state, id)
+    }
+
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        id: CorpusId,
+        testcase: &Option<Testcase<I>>,
+    ) -> Result<(), Error> {
+        self.base.on_remove(state, id, testcase)?;
+        // The map entries of the removed testcase lose their top rated testcase, until a new
+        // testcase hits them
+        if let Ok(meta) = state.metadata_mut::<MaxHitcountTopRatedsMetadata>() {
+            meta.map.retain(|_, top| top.id != id);
+            meta.favored.remove(&id);
+        }
+        Ok(())
+    }
+}
+
+impl<CS, I, S> Scheduler<I, S> for MaxCostScheduler<CS, I>
+where
+    CS: Scheduler<I, S>,
+    I: HasLen,
+    S: HasCorpus<I> + HasMetadata + HasRand,
+{
+    fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+        self.base.on_add(state, id)?;
+        self.update_score(state, id)
+    }
+
+    fn on_evaluation<OT>(&mut self, state: &mut S, input: &I, observers: &OT) -> Result<(), Error>
+    where
+        OT: MatchName,
+    {
+        self.base.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        let mut id = self.base.next(state)?;
+        while !state
+            .metadata::<MaxHitcountTopRatedsMetadata>()
+            .is_ok_and(|meta| meta.is_favored(id))
+            && state.rand_mut().coinflip(self.skip_non_favored_prob)
+        {
+            id = self.base.next(state)?;
+        }
+        Ok(id)
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        _state: &mut S,
+        _next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        // We do nothing here, the inner scheduler will take care of it
+        Ok(())
+    }
+}
+
+impl<CS, I> HasQueueCycles for MaxCostScheduler<CS, I>
+where
+    CS: HasQueueCycles,
+{
+    fn queue_cycles(&self) -> u64 {
+        self.base.queue_cycles()
+    }
+}
+
+impl<CS, I> MaxCostScheduler<CS, I> {
+    /// Creates a new [`MaxCostScheduler`] that wraps a `base` [`Scheduler`], skipping non-favored
+    /// testcases with a probability of [`DEFAULT_SKIP_NON_FAVORED_PROB`]
+    pub fn new(base: CS) -> Self {
+        Self::with_skip_prob(base, DEFAULT_SKIP_NON_FAVORED_PROB)
+    }
+
+    /// Creates a new [`MaxCostScheduler`] that wraps a `base` [`Scheduler`], skipping non-favored
+    /// testcases with a probability of `skip_non_favored_prob`
+    pub fn with_skip_prob(base: CS, skip_non_favored_prob: f64) -> Self {
+        Self {
+            base,
+            skip_non_favored_prob,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Get a reference to the base scheduler
+    pub fn base(&self) -> &CS {
+        &self.base
+    }
+
+    /// Get a reference to the base scheduler (mut)
+    pub fn base_mut(&mut self) -> &mut CS {
+        &mut self.base
+    }
+
+    /// Makes the testcase `id` the top rated testcase of the map entries it hits most often
+    #[expect(clippy::cast_precision_loss)]
+    pub fn update_score<S>(&self, state: &mut S, id: CorpusId) -> Result<(), Error>
+    where
+        I: HasLen,
+        S: HasCorpus<I> + HasMetadata,
+    {
+        let (hitcounts, cost_per_byte) = {
+            let mut testcase = state.corpus().get(id)?.borrow_mut();
+            let Some(hitcounts) = testcase
+                .metadata_map()
+                .get::<MaxHitcountsMetadata>()
+                .map(|meta| meta.hitcounts.clone())
+            else {
+                return Err(Error::key_not_found(format!(
+                    "MaxHitcountsMetadata needed for MaxCostScheduler not found in testcase #{id}"
+                )));
+            };
+            let cost = testcase
+                .metadata_map()
+                .get::<CostMetadata>()
+                .map_or(0, |meta| meta.cost);
+            let len = testcase.load_len(state.corpus())?.max(1);
+            (hitcounts, cost as f64 / len as f64)
+        };
+
+        let top_rateds = state.metadata_or_insert_with(MaxHitcountTopRatedsMetadata::new);
+        let mut replaced = Vec::new();
+        for (idx, hitcount) in hitcounts {
+            let candidate = MaxHitcountTopRated {
+                hitcount,
+                cost_per_byte,
+                id,
+            };
+            match top_rateds.map.get_mut(&idx) {
+                Some(top) if top.id == id => *top = candidate,
+                Some(top)
+                    if hitcount < top.hitcount
+                        || (hitcount == top.hitcount && cost_per_byte <= top.cost_per_byte) => {}
+                Some(top) => {
+                    replaced.push(top.id);
+                    *top = candidate;
+                    *top_rateds.favored.entry(id).or_default() += 1;
+                }
+                None => {
+                    top_rateds.map.insert(idx, candidate);
+                    *top_rateds.favored.entry(id).or_default() += 1;
+                }
+            }
+        }
+        for old_id in replaced {
+            top_rateds.unfavor(old_id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::{MaxCostScheduler, MaxHitcountTopRatedsMetadata};
+    use crate::{
+        HasMetadata,
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        feedbacks::{CostMetadata, MaxHitcountsMetadata},
+        inputs::BytesInput,
+        schedulers::{QueueScheduler, Scheduler},
+        state::{HasCorpus, StdState},
+    };
+
+    fn testcase(len: usize, cost: u64, hitcounts: &[(usize, u64)]) -> Testcase<BytesInput> {
+        let mut testcase = Testcase::new(BytesInput::new(vec![0; len]));
+        testcase.add_metadata(MaxHitcountsMetadata {
+            hitcounts: hitcounts.to_vec(),
+        });
+        testcase.add_metadata(CostMetadata { cost });
+        testcase
+    }
+
+    #[test]
+    fn test_max_cost_scheduler() {
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::new(),
+            InMemoryCorpus::new(),
+            &mut (),
+            &mut (),
+        )
+        .unwrap();
+        let mut scheduler = MaxCostScheduler::new(QueueScheduler::new());
+
+        let first = state
+            .corpus_mut()
+            .add(testcase(4, 40, &[(0, 10), (1, 5)]))
+            .unwrap();
+        scheduler.on_add(&mut state, first).unwrap();
+        // Hits entry 1 more often, and entry 0 as often but with a higher cost per byte
+        let second = state
+            .corpus_mut()
+            .add(testcase(2, 40, &[(0, 10), (1, 6)]))
+            .unwrap();
+        scheduler.on_add(&mut state, second).unwrap();
+        // Hits entry 2 for the first time
+        let third = state.corpus_mut().add(testcase(100, 1, &[(2, 1)])).unwrap();
+        scheduler.on_add(&mut state, third).unwrap();
+
+        let meta = state.metadata::<MaxHitcountTopRatedsMetadata>().unwrap();
+        assert_eq!(meta.map[&0].id, second);
+        assert_eq!(meta.map[&1].id, second);
+        assert_eq!(meta.map[&2].id, third);
+        assert!(!meta.is_favored(first));
+        assert!(meta.is_favored(second));
+        assert!(meta.is_favored(third));
+    }
+}
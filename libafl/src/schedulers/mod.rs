@@ -23,6 +23,9 @@ pub use probabilistic_sampling::ProbabilitySamplingScheduler;
 pub mod accounting;
 pub use accounting::CoverageAccountingScheduler;
 
+pub mod cost;
+pub use cost::{MaxCostScheduler, MaxHitcountTopRatedsMetadata};
+
 pub mod weighted;
 pub use weighted::{StdWeightedScheduler, WeightedScheduler};
 
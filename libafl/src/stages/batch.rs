@@ -0,0 +1,358 @@
+//! A mutational stage running its mutants in batches, using a [`BatchExecutor`].
+//!
+//! The batch is evaluated one input at a time afterwards: the coverage of each input is copied
+//! back into the map observer, so that feedbacks attribute novelty to the right input.
+
+use alloc::{
+    borrow::{Cow, ToOwned},
+    vec::Vec,
+};
+use core::{marker::PhantomData, num::NonZeroUsize};
+
+use libafl_bolts::{
+    AsSliceMut, Named,
+    rands::Rand,
+    tuples::{Handle, Handled},
+};
+
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::{CorpusId, HasCurrentCorpusId},
+    executors::{BatchExecutor, ExitKind, HasObservers},
+    fuzzer::ExecutionProcessor,
+    mutators::{MutationResult, Mutator},
+    nonzero,
+    observers::ObserversTuple,
+    stages::{Restartable, RetryCountRestartHelper, Stage},
+    state::{HasCurrentTestcase, HasRand},
+};
+
+/// The default name of the [`BatchMutationalStage`]
+pub const BATCH_MUTATIONAL_STAGE_NAME: &str = "batch_mutational";
+
+/// The default maximum number of mutants per testcase
+pub const DEFAULT_BATCH_MAX_ITERATIONS: usize = 128;
+
+/// A mutational stage creating all mutants of a testcase first, and running them in batches
+/// with a [`BatchExecutor`].
+#[derive(Debug)]
+pub struct BatchMutationalStage<C, E, EM, I, M, O, S, Z> {
+    name: Cow<'static, str>,
+    map_observer_handle: Handle<C>,
+    mutator: M,
+    max_iterations: NonZeroUsize,
+    /// Reused buffer for the coverage of one input
+    coverage: Vec<u8>,
+    phantom: PhantomData<(E, EM, I, O, S, Z)>,
+}
+
+impl<C, E, EM, I, M, O, S, Z> Named for BatchMutationalStage<C, E, EM, I, M, O, S, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<C, E, EM, I, M, O, S, Z> Stage<E, EM, S, Z> for BatchMutationalStage<C, E, EM, I, M, O, S, Z>
+where
+    C: AsMut<O>,
+    E: BatchExecutor<I, S> + HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    I: Clone,
+    M: Mutator<I, S>,
+    O: for<'a> AsSliceMut<'a, Entry = u8>,
+    S: HasRand + HasCurrentTestcase<I> + HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+    Z: ExecutionProcessor<EM, I, E::Observers, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let num = 1 + state.rand_mut().below(self.max_iterations);
+        let input = state.current_input_cloned()?;
+
+        let mut mutants = Vec::with_capacity(num);
+        for _ in 0..num {
+            let mut mutant = input.clone();
+            if self.mutator.mutate(state, &mut mutant)? == MutationResult::Mutated {
+                mutants.push(mutant);
+            }
+        }
+
+        let mut start = 0;
+        while start < mutants.len() {
+            executor
+                .observers_mut()
+                .pre_exec_all(state, &mutants[start])?;
+            let exit_kinds = executor.run_batch(state, &mutants[start..])?;
+            if exit_kinds.is_empty() {
+                return Err(Error::illegal_state("The executor did not run any input"));
+            }
+
+            // The input ending the batch early has its coverage in the regular map
+            let last = exit_kinds.len() - 1;
+            let mut last_coverage = None;
+            if executor.batch_coverage(last).is_none() {
+                let mut observers = executor.observers_mut();
+                let map = observers[&self.map_observer_handle].as_mut();
+                last_coverage = Some(map.as_slice_mut().to_vec());
+            }
+
+            for (i, exit_kind) in exit_kinds.iter().enumerate() {
+                let mutant = &mutants[start + i];
+                self.coverage.clear();
+                match executor.batch_coverage(i) {
+                    Some(coverage) => self.coverage.extend_from_slice(coverage),
+                    None => self
+                        .coverage
+                        .extend_from_slice(last_coverage.as_deref().unwrap_or_default()),
+                }
+                let corpus_id =
+                    self.evaluate(fuzzer, executor, state, manager, mutant, *exit_kind)?;
+                self.mutator.post_exec(state, corpus_id)?;
+            }
+            start += exit_kinds.len();
+        }
+
+        Ok(())
+    }
+}
+
+impl<C, E, EM, I, M, O, S, Z> Restartable<S> for BatchMutationalStage<C, E, EM, I, M, O, S, Z>
+where
+    S: HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        RetryCountRestartHelper::should_restart(state, &self.name, 3)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+impl<C, E, EM, I, M, O, S, Z> BatchMutationalStage<C, E, EM, I, M, O, S, Z>
+where
+    C: AsMut<O>,
+    E: BatchExecutor<I, S> + HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    O: for<'a> AsSliceMut<'a, Entry = u8>,
+    Z: ExecutionProcessor<EM, I, E::Observers, S>,
+{
+    /// Creates a new [`BatchMutationalStage`], restoring the coverage of each input of a batch into
+    /// `map_observer`
+    pub fn new(mutator: M, map_observer: &C) -> Self
+    where
+        C: Named,
+    {
+        Self {
+            name: Cow::Owned(
+                BATCH_MUTATIONAL_STAGE_NAME.to_owned() + ":" + map_observer.name().as_ref(),
+            ),
+            map_observer_handle: map_observer.handle(),
+            mutator,
+            max_iterations: nonzero!(DEFAULT_BATCH_MAX_ITERATIONS),
+            coverage: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of mutants per testcase
+    #[must_use]
+    pub fn with_max_iterations(mut self, max_iterations: NonZeroUsize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Replays the observations of one input of the batch, and evaluates them
+    fn evaluate(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+        exit_kind: ExitKind,
+    ) -> Result<Option<CorpusId>, Error> {
+        let mut observers = executor.observers_mut();
+        observers.pre_exec_all(state, input)?;
+        {
+            let mut map = observers[&self.map_observer_handle].as_mut().as_slice_mut();
+            let len = map.len().min(self.coverage.len());
+            map[..len].copy_from_slice(&self.coverage[..len]);
+        }
+        observers.post_exec_all(state, input, &exit_kind)?;
+
+        let (_, corpus_id) = fuzzer.evaluate_execution(
+            state,
+            manager,
+            input,
+            &*executor.observers(),
+            &exit_kind,
+            true,
+        )?;
+        Ok(corpus_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::Cow, vec, vec::Vec};
+    use core::num::NonZeroUsize;
+
+    use libafl_bolts::{
+        AsSliceMut, Named,
+        rands::StdRand,
+        tuples::{RefIndexable, tuple_list, tuple_list_type},
+    };
+
+    use super::BatchMutationalStage;
+    use crate::{
+        Error, StdFuzzer,
+        corpus::{Corpus, HasCurrentCorpusId, InMemoryCorpus, Testcase},
+        events::NopEventManager,
+        executors::{BatchExecutor, ExitKind, HasObservers},
+        feedbacks::{CrashFeedback, MaxMapFeedback},
+        inputs::BytesInput,
+        mutators::{MutationResult, Mutator},
+        observers::StdMapObserver,
+        schedulers::QueueScheduler,
+        stages::Stage,
+        state::{HasCorpus, HasExecutions, HasSolutions, StdState},
+    };
+
+    type TestObservers = tuple_list_type!(StdMapObserver<'static, u8, false>);
+
+    /// Sets each mutant to the next byte value
+    struct CountingMutator(u8);
+
+    impl Named for CountingMutator {
+        fn name(&self) -> &Cow<'static, str> {
+            &Cow::Borrowed("CountingMutator")
+        }
+    }
+
+    impl<S> Mutator<BytesInput, S> for CountingMutator {
+        fn mutate(
+            &mut self,
+            _state: &mut S,
+            input: &mut BytesInput,
+        ) -> Result<MutationResult, Error> {
+            self.0 += 1;
+            *input = BytesInput::new(vec![self.0]);
+            Ok(MutationResult::Mutated)
+        }
+    }
+
+    /// Covers the map entry of the first byte of each input, and crashes on `crash_on`
+    struct TestBatchExecutor {
+        observers: TestObservers,
+        crash_on: u8,
+        coverage: Vec<Vec<u8>>,
+        batches: Vec<Vec<u8>>,
+    }
+
+    impl HasObservers for TestBatchExecutor {
+        type Observers = TestObservers;
+
+        fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+            RefIndexable::from(&self.observers)
+        }
+
+        fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+            RefIndexable::from(&mut self.observers)
+        }
+    }
+
+    impl<S> BatchExecutor<BytesInput, S> for TestBatchExecutor
+    where
+        S: HasExecutions,
+    {
+        fn batch_size(&self) -> usize {
+            2
+        }
+
+        fn run_batch(
+            &mut self,
+            state: &mut S,
+            inputs: &[BytesInput],
+        ) -> Result<Vec<ExitKind>, Error> {
+            self.coverage.clear();
+            let mut batch = vec![];
+            let mut exit_kinds = vec![];
+            for input in inputs.iter().take(2) {
+                let value = input.as_ref()[0];
+                batch.push(value);
+                if value == self.crash_on {
+                    // The crashing input keeps its coverage in the regular map
+                    self.observers.0.as_slice_mut()[usize::from(value)] = 1;
+                    exit_kinds.push(ExitKind::Crash);
+                    break;
+                }
+                let mut coverage = vec![0; 8];
+                coverage[usize::from(value)] = 1;
+                self.coverage.push(coverage);
+                exit_kinds.push(ExitKind::Ok);
+            }
+            self.batches.push(batch);
+            *state.executions_mut() += exit_kinds.len() as u64;
+            Ok(exit_kinds)
+        }
+
+        fn batch_coverage(&self, index: usize) -> Option<&[u8]> {
+            self.coverage.get(index).map(Vec::as_slice)
+        }
+    }
+
+    #[test]
+    fn test_batch_stage() {
+        let observer = StdMapObserver::owned("map", vec![0_u8; 8]);
+        let mut feedback = MaxMapFeedback::new(&observer);
+        let mut objective = CrashFeedback::new();
+
+        let mut corpus = InMemoryCorpus::new();
+        let id = corpus.add(Testcase::new(BytesInput::new(vec![0]))).unwrap();
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            corpus,
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        state.set_corpus_id(id).unwrap();
+
+        let mut stage = BatchMutationalStage::new(CountingMutator(0), &observer)
+            .with_max_iterations(NonZeroUsize::new(6).unwrap());
+        let mut executor = TestBatchExecutor {
+            observers: tuple_list!(observer),
+            crash_on: 2,
+            coverage: vec![],
+            batches: vec![],
+        };
+        let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+        let mut mgr = NopEventManager::new();
+
+        while executor.batches.iter().flatten().count() < 3 {
+            stage
+                .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+                .unwrap();
+        }
+
+        // The crash ends its batch, the next batch starts after it
+        let executed: Vec<u8> = executor.batches.iter().flatten().copied().collect();
+        assert_eq!(executed, (1..=executed.len() as u8).collect::<Vec<_>>());
+        assert!(
+            executor
+                .batches
+                .iter()
+                .any(|batch| batch.last() == Some(&2))
+        );
+        assert_eq!(*state.executions(), executed.len() as u64);
+        // Every other input has novel coverage of its own
+        assert_eq!(state.corpus().count(), executed.len());
+        assert_eq!(state.solutions().count(), 1);
+    }
+}
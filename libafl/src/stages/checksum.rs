@@ -0,0 +1,542 @@
+//! A `TaintScope`-style stage detecting checksum fields in the input.
+//!
+//! The stage traces the current testcase with a `CmpLog` executor and looks for comparisons where one
+//! operand is read directly from the input, while the other one changes whenever unrelated input
+//! bytes change. Such a comparison is very likely a checksum check. If the checksum algorithm is
+//! recognized, the field is added to the [`FormatFixupMetadata`] of the testcase, so that the
+//! [`crate::mutators::FormatFixupMutator`] repairs it after each mutation. Checksums of unknown
+//! algorithms are bypassed once, by writing the value the target expects into the input and
+//! evaluating it.
+
+use alloc::{
+    borrow::{Cow, ToOwned},
+    string::ToString,
+    vec::Vec,
+};
+use core::{fmt::Debug, marker::PhantomData, ops::Range};
+
+use libafl_bolts::{AsSlice, HasLen, Named};
+
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::HasCurrentCorpusId,
+    fuzzer::{Evaluator, ExecutesInput},
+    inputs::{HasMutatorBytes, Input, ResizableMutator},
+    mutators::fixup::{
+        FixupEndian, FixupField, FixupOffset, FixupRange, FormatFixupMetadata, FormatFixups,
+        adler32_update, crc32_update, md5,
+    },
+    observers::{CmpValues, CmpValuesMetadata},
+    stages::{Restartable, RetryCountRestartHelper, Stage},
+    state::{HasCorpus, HasCurrentTestcase, HasExecutions},
+};
+
+/// The name for the checksum detection stage
+pub static CHECKSUM_DETECTION_STAGE_NAME: &str = "checksum_detection";
+
+/// Inputs up to this size are searched exhaustively for the checksummed range by default
+pub const DEFAULT_MAX_SCAN_LEN: usize = 1024;
+
+/// The maximum number of candidate checksum comparisons checked per testcase
+const MAX_CANDIDATES: usize = 16;
+
+/// A comparison that may be a checksum check
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ChecksumCandidate {
+    /// The position of the stored checksum in the input
+    at: usize,
+    /// The byte order of the stored checksum
+    endian: FixupEndian,
+    /// The stored checksum, as read from the input
+    stored: Vec<u8>,
+    /// The value computed by the target
+    computed: Vec<u8>,
+}
+
+impl ChecksumCandidate {
+    fn field(&self) -> Range<usize> {
+        self.at..self.at + self.stored.len()
+    }
+
+    fn computed_u32(&self) -> Option<u32> {
+        let bytes: [u8; 4] = self.computed.as_slice().try_into().ok()?;
+        Some(match self.endian {
+            FixupEndian::Little => u32::from_le_bytes(bytes),
+            FixupEndian::Big => u32::from_be_bytes(bytes),
+        })
+    }
+}
+
+/// Find all occurrences of `needle` in `haystack`
+fn find_all(haystack: &[u8], needle: &[u8]) -> Vec<usize> {
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return Vec::new();
+    }
+    haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter_map(|(idx, window)| (window == needle).then_some(idx))
+        .collect()
+}
+
+/// Collect the comparisons where one operand is stored in the input
+fn find_candidates(bytes: &[u8], cmps: &[CmpValues]) -> Vec<ChecksumCandidate> {
+    let mut candidates = Vec::new();
+    for cmp in cmps {
+        let pairs: Vec<(Vec<u8>, Vec<u8>, FixupEndian)> = match cmp {
+            CmpValues::U32((v0, v1, _)) => [(*v0, *v1), (*v1, *v0)]
+                .into_iter()
+                .flat_map(|(stored, computed)| {
+                    [
+                        (
+                            stored.to_le_bytes().to_vec(),
+                            computed.to_le_bytes().to_vec(),
+                            FixupEndian::Little,
+                        ),
+                        (
+                            stored.to_be_bytes().to_vec(),
+                            computed.to_be_bytes().to_vec(),
+                            FixupEndian::Big,
+                        ),
+                    ]
+                })
+                .collect(),
+            CmpValues::Bytes((v0, v1)) if v0.len() == 16 && v1.len() == 16 => vec![
+                (
+                    v0.as_slice().to_vec(),
+                    v1.as_slice().to_vec(),
+                    FixupEndian::Little,
+                ),
+                (
+                    v1.as_slice().to_vec(),
+                    v0.as_slice().to_vec(),
+                    FixupEndian::Little,
+                ),
+            ],
+            _ => continue,
+        };
+
+        for (stored, computed, endian) in pairs {
+            // Constants like magic values and all-zero fields are no interesting checksums
+            if stored.iter().all(|b| *b == stored[0]) {
+                continue;
+            }
+            for at in find_all(bytes, &stored) {
+                let candidate = ChecksumCandidate {
+                    at,
+                    endian,
+                    stored: stored.clone(),
+                    computed: computed.clone(),
+                };
+                if !candidates.contains(&candidate) {
+                    candidates.push(candidate);
+                }
+                if candidates.len() >= MAX_CANDIDATES {
+                    return candidates;
+                }
+            }
+        }
+    }
+    candidates
+}
+
+/// Express a position in the input in a way that survives size changes as good as possible:
+/// positions in the second half of the input are stored relative to its end.
+fn offset_for(pos: usize, len: usize) -> FixupOffset {
+    if pos >= len / 2 {
+        FixupOffset::End(len - pos)
+    } else {
+        FixupOffset::Start(pos)
+    }
+}
+
+/// Search for a range not overlapping `field` whose checksum matches `expected`.
+///
+/// Ranges directly adjacent to the field are always checked, while arbitrary ranges are only
+/// searched in inputs up to `max_scan_len` bytes.
+fn find_checksummed_range<F>(
+    bytes: &[u8],
+    field: &Range<usize>,
+    max_scan_len: usize,
+    init: u32,
+    update: F,
+    finalize: fn(u32) -> u32,
+    expected: u32,
+) -> Option<Range<usize>>
+where
+    F: Fn(u32, &[u8]) -> u32,
+{
+    let len = bytes.len();
+
+    // Ranges ending at the field
+    let starts = if len <= max_scan_len {
+        0..field.start
+    } else {
+        0..field.start.min(1)
+    };
+    for start in starts {
+        if finalize(update(init, &bytes[start..field.start])) == expected {
+            return Some(start..field.start);
+        }
+    }
+
+    // Ranges starting after the field, growing incrementally
+    let mut state = init;
+    for end in field.end..len {
+        state = update(state, &bytes[end..=end]);
+        if finalize(state) == expected {
+            return Some(field.end..end + 1);
+        }
+    }
+
+    None
+}
+
+/// Try to identify the algorithm of a confirmed checksum, returning the matching [`FixupField`]
+fn identify_checksum(
+    bytes: &[u8],
+    candidate: &ChecksumCandidate,
+    max_scan_len: usize,
+) -> Option<FixupField> {
+    let len = bytes.len();
+    let field = candidate.field();
+    let at = offset_for(candidate.at, len);
+    let to_range = |range: Range<usize>| {
+        FixupRange::new(offset_for(range.start, len), offset_for(range.end, len))
+    };
+
+    if let Some(expected) = candidate.computed_u32() {
+        if let Some(range) = find_checksummed_range(
+            bytes,
+            &field,
+            max_scan_len,
+            !0,
+            crc32_update,
+            |crc| !crc,
+            expected,
+        ) {
+            return Some(FixupField::Crc32 {
+                at,
+                endian: candidate.endian,
+                range: to_range(range),
+            });
+        }
+        if let Some(range) = find_checksummed_range(
+            bytes,
+            &field,
+            max_scan_len,
+            1,
+            adler32_update,
+            |adler| adler,
+            expected,
+        ) {
+            return Some(FixupField::Adler32 {
+                at,
+                endian: candidate.endian,
+                range: to_range(range),
+            });
+        }
+    } else if candidate.computed.len() == 16 {
+        for range in [0..field.start, field.end..len] {
+            if md5(&bytes[range.clone()]).as_slice() == candidate.computed.as_slice() {
+                return Some(FixupField::Md5 {
+                    at,
+                    range: to_range(range),
+                });
+            }
+        }
+    }
+
+    None
+}
+
+/// A stage detecting checksum fields in the current testcase, `TaintScope`-style.
+///
+/// `tracer_executor` has to run the target with `CmpLog` instrumentation, and an observer adding the
+/// [`CmpValuesMetadata`] to the state.
+/// Detected checksums are stored as [`FormatFixupMetadata`] in the testcase, to be repaired by
+/// the [`crate::mutators::FormatFixupMutator`].
+#[derive(Clone, Debug)]
+pub struct ChecksumDetectionStage<EM, I, TE, S, Z> {
+    name: Cow<'static, str>,
+    tracer_executor: TE,
+    max_scan_len: usize,
+    phantom: PhantomData<(EM, I, S, Z)>,
+}
+
+impl<EM, I, TE, S, Z> Named for ChecksumDetectionStage<EM, I, TE, S, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+/// The counter for giving this stage unique id
+static mut CHECKSUM_DETECTION_STAGE_ID: usize = 0;
+
+impl<EM, I, TE, S, Z> ChecksumDetectionStage<EM, I, TE, S, Z> {
+    /// Creates a new [`ChecksumDetectionStage`], tracing comparisons with the given executor
+    pub fn new(tracer_executor: TE) -> Self {
+        // unsafe but impossible that you create two threads both instantiating this instance
+        let stage_id = unsafe {
+            let ret = CHECKSUM_DETECTION_STAGE_ID;
+            CHECKSUM_DETECTION_STAGE_ID += 1;
+            ret
+        };
+
+        Self {
+            name: Cow::Owned(
+                CHECKSUM_DETECTION_STAGE_NAME.to_owned() + ":" + stage_id.to_string().as_ref(),
+            ),
+            tracer_executor,
+            max_scan_len: DEFAULT_MAX_SCAN_LEN,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the maximum input length for which arbitrary checksummed ranges are searched.
+    /// The search is quadratic in the input length.
+    #[must_use]
+    pub fn with_max_scan_len(mut self, max_scan_len: usize) -> Self {
+        self.max_scan_len = max_scan_len;
+        self
+    }
+
+    /// Gets the underlying tracer executor
+    pub fn executor(&self) -> &TE {
+        &self.tracer_executor
+    }
+
+    /// Gets the underlying tracer executor (mut)
+    pub fn executor_mut(&mut self) -> &mut TE {
+        &mut self.tracer_executor
+    }
+}
+
+impl<EM, I, TE, S, Z> ChecksumDetectionStage<EM, I, TE, S, Z>
+where
+    S: HasMetadata,
+    Z: ExecutesInput<TE, EM, I, S>,
+{
+    /// Run the tracer and return the logged comparisons
+    fn trace_cmps(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+    ) -> Result<Vec<CmpValues>, Error> {
+        if let Ok(meta) = state.metadata_mut::<CmpValuesMetadata>() {
+            meta.list.clear();
+        }
+
+        fuzzer.execute_input(state, &mut self.tracer_executor, manager, input)?;
+
+        Ok(state
+            .metadata::<CmpValuesMetadata>()
+            .map(|meta| meta.list.clone())
+            .unwrap_or_default())
+    }
+}
+
+impl<E, EM, I, TE, S, Z> Stage<E, EM, S, Z> for ChecksumDetectionStage<EM, I, TE, S, Z>
+where
+    I: HasMutatorBytes + ResizableMutator<u8> + Input,
+    S: HasCorpus<I>
+        + HasCurrentTestcase<I>
+        + HasMetadata
+        + HasNamedMetadata
+        + HasExecutions
+        + HasCurrentCorpusId,
+    Z: Evaluator<E, EM, I, S> + ExecutesInput<TE, EM, I, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        if state
+            .current_testcase()?
+            .has_metadata::<FormatFixupMetadata>()
+        {
+            return Ok(());
+        }
+
+        let input = state.current_input_cloned()?;
+        let bytes = input.mutator_bytes().to_vec();
+
+        let cmps = self.trace_cmps(fuzzer, state, manager, &input)?;
+        let candidates = find_candidates(&bytes, &cmps);
+
+        let mut fixups = FormatFixups::new();
+        for candidate in candidates {
+            // Flip a byte next to the stored value. If the other operand changes, it has been
+            // computed from the input, which is what a checksum check looks like.
+            let field = candidate.field();
+            let flip_idx = if field.start > 0 {
+                field.start - 1
+            } else if field.end < bytes.len() {
+                field.end
+            } else {
+                continue;
+            };
+            let mut flipped = input.clone();
+            flipped.mutator_bytes_mut()[flip_idx] ^= 0xff;
+
+            let flipped_cmps = self.trace_cmps(fuzzer, state, manager, &flipped)?;
+            let depends_on_input = find_candidates(flipped.mutator_bytes(), &flipped_cmps)
+                .iter()
+                .any(|other| {
+                    other.at == candidate.at
+                        && other.stored == candidate.stored
+                        && other.computed != candidate.computed
+                });
+            if !depends_on_input {
+                continue;
+            }
+
+            if let Some(fixup) = identify_checksum(&bytes, &candidate, self.max_scan_len) {
+                log::debug!("Found checksum field: {fixup:?}");
+                fixups.add_field(fixup);
+            } else if candidate.stored != candidate.computed {
+                // Unknown algorithm: bypass the check for this input by storing the expected value.
+                log::debug!(
+                    "Found checksum of unknown algorithm at {}, bypassing it",
+                    candidate.at
+                );
+                let mut patched = input.clone();
+                patched.mutator_bytes_mut()[field].copy_from_slice(&candidate.computed);
+                fuzzer.evaluate_input(state, executor, manager, &patched)?;
+            }
+        }
+
+        state
+            .current_testcase_mut()?
+            .add_metadata(FormatFixupMetadata::new(fixups));
+
+        Ok(())
+    }
+}
+
+impl<EM, I, TE, S, Z> Restartable<S> for ChecksumDetectionStage<EM, I, TE, S, Z>
+where
+    S: HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        // This is a deterministic stage
+        // Once it failed, then don't retry,
+        // It will just fail again
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ChecksumCandidate, DEFAULT_MAX_SCAN_LEN, find_candidates, identify_checksum, offset_for,
+    };
+    use crate::{
+        mutators::fixup::{FixupEndian, FixupField, FixupOffset, FixupRange, adler32, crc32, md5},
+        observers::{CmpValues, CmplogBytes},
+    };
+
+    #[test]
+    fn test_identify_crc32() {
+        // [magic][payload][crc32 be over payload]
+        let mut bytes = b"MAGIhello world".to_vec();
+        let crc = crc32(&bytes[4..]);
+        bytes.extend_from_slice(&crc.to_be_bytes());
+
+        // The target read the (correct) stored value and computed the same value
+        let cmps = [CmpValues::U32((crc, crc, false))];
+        let candidates = find_candidates(&bytes, &cmps);
+        let candidate = candidates
+            .iter()
+            .find(|c: &&ChecksumCandidate| c.endian == FixupEndian::Big)
+            .unwrap();
+        assert_eq!(candidate.at, 15);
+
+        let field = identify_checksum(&bytes, candidate, DEFAULT_MAX_SCAN_LEN).unwrap();
+        assert_eq!(
+            field,
+            FixupField::Crc32 {
+                at: FixupOffset::End(4),
+                endian: FixupEndian::Big,
+                range: FixupRange::new(FixupOffset::Start(4), FixupOffset::End(4)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_identify_adler32() {
+        // [adler32 le over payload][payload]
+        let payload = b"some payload bytes";
+        let adler = adler32(payload);
+        let mut bytes = adler.to_le_bytes().to_vec();
+        bytes.extend_from_slice(payload);
+
+        let cmps = [CmpValues::U32((adler, adler, false))];
+        let mut candidate = find_candidates(&bytes, &cmps)
+            .into_iter()
+            .find(|c| c.endian == FixupEndian::Little)
+            .unwrap();
+        assert_eq!(candidate.at, 0);
+
+        let field = identify_checksum(&bytes, &candidate, DEFAULT_MAX_SCAN_LEN).unwrap();
+        assert_eq!(
+            field,
+            FixupField::Adler32 {
+                at: FixupOffset::Start(0),
+                endian: FixupEndian::Little,
+                range: FixupRange::new(FixupOffset::Start(4), FixupOffset::End(0)),
+            }
+        );
+
+        // A value not matching any range is not identified
+        candidate.computed = adler32(b"other payload").to_le_bytes().to_vec();
+        assert!(identify_checksum(&bytes, &candidate, DEFAULT_MAX_SCAN_LEN).is_none());
+    }
+
+    #[test]
+    fn test_identify_md5() {
+        let payload = b"hash me";
+        let digest = md5(payload);
+        let mut bytes = payload.to_vec();
+        bytes.extend_from_slice(&digest);
+
+        let mut buf = [0; 32];
+        buf[..16].copy_from_slice(&digest);
+        let logged = CmplogBytes::from_buf_and_len(buf, 16);
+        let cmps = [CmpValues::Bytes((logged, logged))];
+        let candidates = find_candidates(&bytes, &cmps);
+        assert_eq!(candidates.len(), 1);
+
+        let field = identify_checksum(&bytes, &candidates[0], DEFAULT_MAX_SCAN_LEN).unwrap();
+        assert_eq!(
+            field,
+            FixupField::Md5 {
+                at: FixupOffset::Start(7),
+                range: FixupRange::new(FixupOffset::Start(0), FixupOffset::Start(7)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_candidates_skip_constants() {
+        let bytes = [0_u8; 32];
+        let cmps = [CmpValues::U32((0, 0x1234_5678, false))];
+        assert!(find_candidates(&bytes, &cmps).is_empty());
+    }
+
+    #[test]
+    fn test_offset_for() {
+        assert_eq!(offset_for(2, 10), FixupOffset::Start(2));
+        assert_eq!(offset_for(5, 10), FixupOffset::End(5));
+        assert_eq!(offset_for(10, 10), FixupOffset::End(0));
+    }
+}
@@ -58,6 +58,37 @@ impl Ord for Earlier {
     }
 }
 
+/// Run the target and get the map hash before the `post_exec` of hitcounts is used
+pub(crate) fn get_raw_map_hash_run<C, E, EM, I, O, S, Z>(
+    fuzzer: &mut Z,
+    executor: &mut E,
+    state: &mut S,
+    manager: &mut EM,
+    input: &I,
+    observer_handle: &Handle<C>,
+) -> Result<usize, Error>
+where
+    E: HasObservers + Executor<EM, I, S, Z>,
+    E::Observers: ObserversTuple<I, S>,
+    O: Hash,
+    C: AsRef<O>,
+{
+    executor.observers_mut().pre_exec_all(state, input)?;
+
+    let exit_kind = executor.run_target(fuzzer, state, manager, input)?;
+
+    let observers = executor.observers();
+    let observer = observers[observer_handle].as_ref();
+
+    let hash = generic_hash_std(observer) as usize;
+
+    executor
+        .observers_mut()
+        .post_exec_all(state, input, &exit_kind)?;
+
+    Ok(hash)
+}
+
 /// Default name for `ColorizationStage`; derived from ALF++
 pub const COLORIZATION_STAGE_NAME: &str = "colorization";
 /// The mutational stage using power schedules
@@ -182,7 +213,7 @@ where
 
         // Idea: No need to do this every time
         let orig_hash =
-            Self::get_raw_map_hash_run(fuzzer, executor, state, manager, &input, observer_handle)?;
+            get_raw_map_hash_run(fuzzer, executor, state, manager, &input, observer_handle)?;
         let changed_bytes = changed.mutator_bytes_mut();
         let input_len = changed_bytes.len();
 
@@ -220,7 +251,7 @@ where
                     );
                 }
 
-                let changed_hash = Self::get_raw_map_hash_run(
+                let changed_hash = get_raw_map_hash_run(
                     fuzzer,
                     executor,
                     state,
@@ -306,34 +337,6 @@ where
         }
     }
 
-    // Run the target and get map hash but before hitcounts's post_exec is used
-    fn get_raw_map_hash_run(
-        fuzzer: &mut Z,
-        executor: &mut E,
-        state: &mut S,
-        manager: &mut EM,
-        input: &I,
-        observer_handle: &Handle<C>,
-    ) -> Result<usize, Error> {
-        executor.observers_mut().pre_exec_all(state, input)?;
-
-        let exit_kind = executor.run_target(fuzzer, state, manager, input)?;
-
-        let observers = executor.observers();
-        let observer = observers[observer_handle].as_ref();
-
-        let hash = generic_hash_std(observer) as usize;
-
-        executor
-            .observers_mut()
-            .post_exec_all(state, input, &exit_kind)?;
-
-        // let observers = executor.observers();
-        // fuzzer.process_execution(state, manager, input, observers, &exit_kind, true)?;
-
-        Ok(hash)
-    }
-
     /// Replace bytes with random values but following certain rules
     #[expect(clippy::needless_range_loop)]
     fn type_replace(bytes: &mut [u8], state: &mut S) {
@@ -2,14 +2,31 @@
 //! and use the results for fuzzer input and mutations.
 use alloc::borrow::{Cow, ToOwned};
 #[cfg(feature = "concolic_mutation")]
-use alloc::{string::ToString, vec::Vec};
+use alloc::{rc::Rc, string::ToString, vec::Vec};
 #[cfg(feature = "concolic_mutation")]
-use core::marker::PhantomData;
+use core::{
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    time::Duration,
+};
 
+#[cfg(feature = "concolic_mutation")]
+use ahash::RandomState;
+#[cfg(feature = "concolic_mutation")]
+use hashbrown::{HashMap, HashSet};
 use libafl_bolts::{
     Named,
     tuples::{Handle, MatchNameRef},
 };
+#[cfg(feature = "concolic_mutation")]
+use libafl_bolts::{current_time, impl_serdeany};
+#[cfg(feature = "concolic_mutation")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "concolic_mutation")]
+use z3::{
+    Config, Context, Model, Params, SatResult, Solver, Symbol,
+    ast::{Ast, BV, Bool, Dynamic},
+};
 
 #[cfg(all(feature = "concolic_mutation", feature = "introspection"))]
 use crate::monitors::stats::PerfFeature;
@@ -113,62 +130,52 @@ impl<'a, EM, I, TE, S, Z> ConcolicTracingStage<'a, EM, I, TE, S, Z> {
 }
 
 #[cfg(feature = "concolic_mutation")]
-#[expect(clippy::too_many_lines)]
-fn generate_mutations(iter: impl Iterator<Item = (SymExprRef, SymExpr)>) -> Vec<Vec<(usize, u8)>> {
-    use hashbrown::HashMap;
-    use z3::{
-        Config, Context, Solver, Symbol,
-        ast::{Ast, BV, Bool, Dynamic},
-    };
-    fn build_extract<'ctx>(
-        bv: &BV<'ctx>,
-        offset: u64,
-        length: u64,
-        little_endian: bool,
-    ) -> BV<'ctx> {
-        let size = u64::from(bv.get_size());
-        assert_eq!(
-            size % 8,
-            0,
-            "can't extract on byte-boundary on BV that is not byte-sized"
-        );
+fn build_extract<'ctx>(bv: &BV<'ctx>, offset: u64, length: u64, little_endian: bool) -> BV<'ctx> {
+    let size = u64::from(bv.get_size());
+    assert_eq!(
+        size % 8,
+        0,
+        "can't extract on byte-boundary on BV that is not byte-sized"
+    );
 
-        if little_endian {
-            (0..length)
-                .map(|i| {
-                    bv.extract(
-                        (size - (offset + i) * 8 - 1).try_into().unwrap(),
-                        (size - (offset + i + 1) * 8).try_into().unwrap(),
-                    )
-                })
-                .reduce(|acc, next| next.concat(&acc))
-                .unwrap()
-        } else {
-            bv.extract(
-                (size - offset * 8 - 1).try_into().unwrap(),
-                (size - (offset + length) * 8).try_into().unwrap(),
-            )
-        }
+    if little_endian {
+        (0..length)
+            .map(|i| {
+                bv.extract(
+                    (size - (offset + i) * 8 - 1).try_into().unwrap(),
+                    (size - (offset + i + 1) * 8).try_into().unwrap(),
+                )
+            })
+            .reduce(|acc, next| next.concat(&acc))
+            .unwrap()
+    } else {
+        bv.extract(
+            (size - offset * 8 - 1).try_into().unwrap(),
+            (size - (offset + length) * 8).try_into().unwrap(),
+        )
     }
+}
 
-    let mut res = Vec::new();
-
-    let mut cfg = Config::new();
-    cfg.set_timeout_msec(10_000);
-    let ctx = Context::new(&cfg);
-    let solver = Solver::new(&ctx);
-
-    let mut translation = HashMap::<SymExprRef, Dynamic>::new();
-
+/// Translates a single [`SymExpr`] into a z3 expression, looking up its operands in `translation`.
+///
+/// Returns `None` for messages that are not expressions, such as path constraints, and for
+/// expressions that can't be translated or depend on one that couldn't.
+#[cfg(feature = "concolic_mutation")]
+#[expect(clippy::too_many_lines)]
+fn translate_expr<'ctx>(
+    ctx: &'ctx Context,
+    translation: &HashMap<SymExprRef, Dynamic<'ctx>>,
+    msg: &SymExpr,
+) -> Option<Dynamic<'ctx>> {
     macro_rules! bool {
         ($op:ident) => {
-            translation[&$op].as_bool().unwrap()
+            translation.get(&$op)?.as_bool().unwrap()
         };
     }
 
     macro_rules! bv {
         ($op:ident) => {
-            translation[&$op].as_bv().unwrap()
+            translation.get(&$op)?.as_bv().unwrap()
         };
     }
 
@@ -178,128 +185,164 @@ fn generate_mutations(iter: impl Iterator<Item = (SymExprRef, SymExpr)>) -> Vec<
         };
     }
 
-    for (id, msg) in iter {
-        let z3_expr: Option<Dynamic> = match msg {
-            SymExpr::InputByte { offset, .. } => {
-                Some(BV::new_const(&ctx, Symbol::Int(offset as u32), 8).into())
-            }
-            SymExpr::Integer { value, bits } => {
-                Some(BV::from_u64(&ctx, value, u32::from(bits)).into())
-            }
-            SymExpr::Integer128 { high: _, low: _ } => todo!(),
-            SymExpr::IntegerFromBuffer {} => todo!(),
-            SymExpr::NullPointer => Some(BV::from_u64(&ctx, 0, usize::BITS).into()),
-            SymExpr::True => Some(Bool::from_bool(&ctx, true).into()),
-            SymExpr::False => Some(Bool::from_bool(&ctx, false).into()),
-            SymExpr::Bool { value } => Some(Bool::from_bool(&ctx, value).into()),
-            SymExpr::Neg { op } => Some(bv!(op).bvneg().into()),
-            SymExpr::Add { a, b } => bv_binop!(a bvadd b),
-            SymExpr::Sub { a, b } => bv_binop!(a bvsub b),
-            SymExpr::Mul { a, b } => bv_binop!(a bvmul b),
-            SymExpr::UnsignedDiv { a, b } => bv_binop!(a bvudiv b),
-            SymExpr::SignedDiv { a, b } => bv_binop!(a bvsdiv b),
-            SymExpr::UnsignedRem { a, b } => bv_binop!(a bvurem b),
-            SymExpr::SignedRem { a, b } => bv_binop!(a bvsrem b),
-            SymExpr::ShiftLeft { a, b } => bv_binop!(a bvshl b),
-            SymExpr::LogicalShiftRight { a, b } => bv_binop!(a bvlshr b),
-            SymExpr::ArithmeticShiftRight { a, b } => bv_binop!(a bvashr b),
-            SymExpr::SignedLessThan { a, b } => bv_binop!(a bvslt b),
-            SymExpr::SignedLessEqual { a, b } => bv_binop!(a bvsle b),
-            SymExpr::SignedGreaterThan { a, b } => bv_binop!(a bvsgt b),
-            SymExpr::SignedGreaterEqual { a, b } => bv_binop!(a bvsge b),
-            SymExpr::UnsignedLessThan { a, b } => bv_binop!(a bvult b),
-            SymExpr::UnsignedLessEqual { a, b } => bv_binop!(a bvule b),
-            SymExpr::UnsignedGreaterThan { a, b } => bv_binop!(a bvugt b),
-            SymExpr::UnsignedGreaterEqual { a, b } => bv_binop!(a bvuge b),
-            SymExpr::Not { op } => {
-                let translated = &translation[&op];
-                Some(if let Some(bv) = translated.as_bv() {
-                    bv.bvnot().into()
-                } else if let Some(bool) = translated.as_bool() {
-                    bool.not().into()
-                } else {
-                    panic!(
-                        "unexpected z3 expr of type {:?} when applying not operation",
-                        translated.kind()
-                    )
-                })
-            }
-            SymExpr::Equal { a, b } => Some(translation[&a]._eq(&translation[&b]).into()),
-            SymExpr::NotEqual { a, b } => Some(translation[&a]._eq(&translation[&b]).not().into()),
-            SymExpr::BoolAnd { a, b } => Some(Bool::and(&ctx, &[&bool!(a), &bool!(b)]).into()),
-            SymExpr::BoolOr { a, b } => Some(Bool::or(&ctx, &[&bool!(a), &bool!(b)]).into()),
-            SymExpr::BoolXor { a, b } => Some(bool!(a).xor(&bool!(b)).into()),
-            SymExpr::And { a, b } => bv_binop!(a bvand b),
-            SymExpr::Or { a, b } => bv_binop!(a bvor b),
-            SymExpr::Xor { a, b } => bv_binop!(a bvxor b),
-            SymExpr::Sext { op, bits } => Some(bv!(op).sign_ext(u32::from(bits)).into()),
-            SymExpr::Zext { op, bits } => Some(bv!(op).zero_ext(u32::from(bits)).into()),
-            SymExpr::Trunc { op, bits } => Some(bv!(op).extract(u32::from(bits - 1), 0).into()),
-            SymExpr::BoolToBit { op } => Some(
-                bool!(op)
-                    .ite(&BV::from_u64(&ctx, 1, 1), &BV::from_u64(&ctx, 0, 1))
-                    .into(),
-            ),
-            SymExpr::Concat { a, b } => bv_binop!(a concat b),
-            SymExpr::Extract {
-                op,
-                first_bit,
-                last_bit,
-            } => Some(bv!(op).extract(first_bit as u32, last_bit as u32).into()),
-            SymExpr::Insert {
-                target,
-                to_insert,
-                offset,
-                little_endian,
-            } => {
-                let target = bv!(target);
-                let to_insert = bv!(to_insert);
-                let bits_to_insert = u64::from(to_insert.get_size());
-                assert_eq!(bits_to_insert % 8, 0, "can only insert full bytes");
-                let after_len = (u64::from(target.get_size()) / 8) - offset - (bits_to_insert / 8);
-                Some(
-                    [
-                        if offset == 0 {
-                            None
-                        } else {
-                            Some(build_extract(&target, 0, offset, false))
-                        },
-                        Some(if little_endian {
-                            build_extract(&to_insert, 0, bits_to_insert / 8, true)
-                        } else {
-                            to_insert
-                        }),
-                        if after_len == 0 {
-                            None
-                        } else {
-                            Some(build_extract(
-                                &target,
-                                offset + (bits_to_insert / 8),
-                                after_len,
-                                false,
-                            ))
-                        },
-                    ]
-                    .into_iter()
-                    .reduce(|acc: Option<BV>, val: Option<BV>| match (acc, val) {
-                        (Some(prev), Some(next)) => Some(prev.concat(&next)),
-                        (Some(prev), None) => Some(prev),
-                        (None, next) => next,
-                    })
-                    .unwrap()
-                    .unwrap()
-                    .into(),
+    match *msg {
+        SymExpr::InputByte { offset, .. } => {
+            Some(BV::new_const(ctx, Symbol::Int(offset as u32), 8).into())
+        }
+        SymExpr::Integer { value, bits } => Some(BV::from_u64(ctx, value, u32::from(bits)).into()),
+        SymExpr::Integer128 { .. } | SymExpr::IntegerFromBuffer {} => None,
+        SymExpr::NullPointer => Some(BV::from_u64(ctx, 0, usize::BITS).into()),
+        SymExpr::True => Some(Bool::from_bool(ctx, true).into()),
+        SymExpr::False => Some(Bool::from_bool(ctx, false).into()),
+        SymExpr::Bool { value } => Some(Bool::from_bool(ctx, value).into()),
+        SymExpr::Neg { op } => Some(bv!(op).bvneg().into()),
+        SymExpr::Add { a, b } => bv_binop!(a bvadd b),
+        SymExpr::Sub { a, b } => bv_binop!(a bvsub b),
+        SymExpr::Mul { a, b } => bv_binop!(a bvmul b),
+        SymExpr::UnsignedDiv { a, b } => bv_binop!(a bvudiv b),
+        SymExpr::SignedDiv { a, b } => bv_binop!(a bvsdiv b),
+        SymExpr::UnsignedRem { a, b } => bv_binop!(a bvurem b),
+        SymExpr::SignedRem { a, b } => bv_binop!(a bvsrem b),
+        SymExpr::ShiftLeft { a, b } => bv_binop!(a bvshl b),
+        SymExpr::LogicalShiftRight { a, b } => bv_binop!(a bvlshr b),
+        SymExpr::ArithmeticShiftRight { a, b } => bv_binop!(a bvashr b),
+        SymExpr::SignedLessThan { a, b } => bv_binop!(a bvslt b),
+        SymExpr::SignedLessEqual { a, b } => bv_binop!(a bvsle b),
+        SymExpr::SignedGreaterThan { a, b } => bv_binop!(a bvsgt b),
+        SymExpr::SignedGreaterEqual { a, b } => bv_binop!(a bvsge b),
+        SymExpr::UnsignedLessThan { a, b } => bv_binop!(a bvult b),
+        SymExpr::UnsignedLessEqual { a, b } => bv_binop!(a bvule b),
+        SymExpr::UnsignedGreaterThan { a, b } => bv_binop!(a bvugt b),
+        SymExpr::UnsignedGreaterEqual { a, b } => bv_binop!(a bvuge b),
+        SymExpr::Not { op } => {
+            let translated = translation.get(&op)?;
+            Some(if let Some(bv) = translated.as_bv() {
+                bv.bvnot().into()
+            } else if let Some(bool) = translated.as_bool() {
+                bool.not().into()
+            } else {
+                panic!(
+                    "unexpected z3 expr of type {:?} when applying not operation",
+                    translated.kind()
                 )
-            }
-            _ => None,
-        };
-        if let Some(expr) = z3_expr {
+            })
+        }
+        SymExpr::Equal { a, b } => Some(translation.get(&a)?._eq(translation.get(&b)?).into()),
+        SymExpr::NotEqual { a, b } => {
+            Some(translation.get(&a)?._eq(translation.get(&b)?).not().into())
+        }
+        SymExpr::BoolAnd { a, b } => Some(Bool::and(ctx, &[&bool!(a), &bool!(b)]).into()),
+        SymExpr::BoolOr { a, b } => Some(Bool::or(ctx, &[&bool!(a), &bool!(b)]).into()),
+        SymExpr::BoolXor { a, b } => Some(bool!(a).xor(&bool!(b)).into()),
+        SymExpr::And { a, b } => bv_binop!(a bvand b),
+        SymExpr::Or { a, b } => bv_binop!(a bvor b),
+        SymExpr::Xor { a, b } => bv_binop!(a bvxor b),
+        SymExpr::Sext { op, bits } => Some(bv!(op).sign_ext(u32::from(bits)).into()),
+        SymExpr::Zext { op, bits } => Some(bv!(op).zero_ext(u32::from(bits)).into()),
+        SymExpr::Trunc { op, bits } => Some(bv!(op).extract(u32::from(bits - 1), 0).into()),
+        SymExpr::BoolToBit { op } => Some(
+            bool!(op)
+                .ite(&BV::from_u64(ctx, 1, 1), &BV::from_u64(ctx, 0, 1))
+                .into(),
+        ),
+        SymExpr::Concat { a, b } => bv_binop!(a concat b),
+        SymExpr::Extract {
+            op,
+            first_bit,
+            last_bit,
+        } => Some(bv!(op).extract(first_bit as u32, last_bit as u32).into()),
+        SymExpr::Insert {
+            target,
+            to_insert,
+            offset,
+            little_endian,
+        } => {
+            let target = bv!(target);
+            let to_insert = bv!(to_insert);
+            let bits_to_insert = u64::from(to_insert.get_size());
+            assert_eq!(bits_to_insert % 8, 0, "can only insert full bytes");
+            let after_len = (u64::from(target.get_size()) / 8) - offset - (bits_to_insert / 8);
+            Some(
+                [
+                    if offset == 0 {
+                        None
+                    } else {
+                        Some(build_extract(&target, 0, offset, false))
+                    },
+                    Some(if little_endian {
+                        build_extract(&to_insert, 0, bits_to_insert / 8, true)
+                    } else {
+                        to_insert
+                    }),
+                    if after_len == 0 {
+                        None
+                    } else {
+                        Some(build_extract(
+                            &target,
+                            offset + (bits_to_insert / 8),
+                            after_len,
+                            false,
+                        ))
+                    },
+                ]
+                .into_iter()
+                .reduce(|acc: Option<BV>, val: Option<BV>| match (acc, val) {
+                    (Some(prev), Some(next)) => Some(prev.concat(&next)),
+                    (Some(prev), None) => Some(prev),
+                    (None, next) => next,
+                })
+                .unwrap()
+                .unwrap()
+                .into(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Reads the input byte replacements out of a satisfying model.
+#[cfg(feature = "concolic_mutation")]
+fn model_replacements(model: &Model) -> Vec<(usize, u8)> {
+    let model_string = model.to_string();
+    let mut replacements = Vec::new();
+    for l in model_string.lines() {
+        if let [offset_str, value_str] = l.split(" -> ").collect::<Vec<_>>().as_slice() {
+            let offset = offset_str
+                .trim_start_matches("k!")
+                .parse::<usize>()
+                .unwrap();
+            let value = u8::from_str_radix(value_str.trim_start_matches("#x"), 16).unwrap();
+            replacements.push((offset, value));
+        } else {
+            panic!();
+        }
+    }
+    replacements
+}
+
+#[cfg(feature = "concolic_mutation")]
+fn generate_mutations(iter: impl Iterator<Item = (SymExprRef, SymExpr)>) -> Vec<Vec<(usize, u8)>> {
+    let mut res = Vec::new();
+
+    let mut cfg = Config::new();
+    cfg.set_timeout_msec(10_000);
+    let ctx = Context::new(&cfg);
+    let solver = Solver::new(&ctx);
+
+    let mut translation = HashMap::<SymExprRef, Dynamic>::new();
+
+    for (id, msg) in iter {
+        if let Some(expr) = translate_expr(&ctx, &translation, &msg) {
             translation.insert(id, expr);
         } else if let SymExpr::PathConstraint {
             constraint, taken, ..
         } = msg
         {
-            let op = translation[&constraint].as_bool().unwrap();
+            let Some(op) = translation.get(&constraint) else {
+                // the constraint depends on an expression that can't be translated
+                continue;
+            };
+            let op = op.as_bool().unwrap();
             let op = if taken { op } else { op.not() }.simplify();
             if op.as_bool().is_some() {
                 // this constraint is useless, as it is always sat or unsat
@@ -308,44 +351,23 @@ fn generate_mutations(iter: impl Iterator<Item = (SymExprRef, SymExpr)>) -> Vec<
                 solver.push();
                 solver.assert(&negated_constraint);
                 match solver.check() {
-                    z3::SatResult::Unsat => {
+                    SatResult::Unsat => {
                         // negation is unsat => no mutation
                         solver.pop(1);
                         // check that out path is ever still sat, otherwise, we can stop trying
-                        if matches!(
-                            solver.check(),
-                            z3::SatResult::Unknown | z3::SatResult::Unsat
-                        ) {
+                        if matches!(solver.check(), SatResult::Unknown | SatResult::Unsat) {
                             return res;
                         }
                     }
-                    z3::SatResult::Unknown => {
+                    SatResult::Unknown => {
                         // we've got a problem. ignore
                     }
-                    z3::SatResult::Sat => {
+                    SatResult::Sat => {
                         let model = solver.get_model().unwrap();
-                        let model_string = model.to_string();
-                        let mut replacements = Vec::new();
-                        for l in model_string.lines() {
-                            if let [offset_str, value_str] =
-                                l.split(" -> ").collect::<Vec<_>>().as_slice()
-                            {
-                                let offset = offset_str
-                                    .trim_start_matches("k!")
-                                    .parse::<usize>()
-                                    .unwrap();
-                                let value =
-                                    u8::from_str_radix(value_str.trim_start_matches("#x"), 16)
-                                        .unwrap();
-                                replacements.push((offset, value));
-                            } else {
-                                panic!();
-                            }
-                        }
-                        res.push(replacements);
+                        res.push(model_replacements(&model));
                         solver.pop(1);
                     }
-                };
+                }
                 // assert the path constraint
                 solver.assert(&op);
             }
@@ -463,3 +485,595 @@ impl<I, Z> SimpleConcolicMutationalStage<I, Z> {
         }
     }
 }
+
+/// Global knowledge about the branch directions that concolic mutation has already reached,
+/// shared by all testcases through the state metadata.
+///
+/// Branch directions are identified by the `(location, taken)` pair of a
+/// [`SymExpr::PathConstraint`].
+#[cfg(feature = "concolic_mutation")]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConcolicBranchMetadata {
+    /// Branch directions observed in any trace processed so far
+    seen: HashSet<(usize, bool)>,
+    /// Branch directions the solver generated an input for
+    solved: HashSet<(usize, bool)>,
+    /// How often the solver was queried for each branch direction
+    attempts: HashMap<(usize, bool), usize>,
+}
+
+#[cfg(feature = "concolic_mutation")]
+impl_serdeany!(ConcolicBranchMetadata);
+
+#[cfg(feature = "concolic_mutation")]
+impl ConcolicBranchMetadata {
+    /// Creates an empty [`ConcolicBranchMetadata`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if this branch direction was observed in a trace or already solved for
+    #[must_use]
+    pub fn is_flipped(&self, location: usize, taken: bool) -> bool {
+        self.seen.contains(&(location, taken)) || self.solved.contains(&(location, taken))
+    }
+
+    /// The number of solver queries that targeted this branch direction
+    #[must_use]
+    pub fn attempts(&self, location: usize, taken: bool) -> usize {
+        self.attempts
+            .get(&(location, taken))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The number of distinct branch directions observed in traces
+    #[must_use]
+    pub fn seen_len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// The number of distinct branch directions the solver generated an input for
+    #[must_use]
+    pub fn solved_len(&self) -> usize {
+        self.solved.len()
+    }
+}
+
+/// A z3 solver kept alive across testcases.
+///
+/// Every asserted path constraint lives in its own solver scope, so a new trace only pops the
+/// scopes that differ from the previous one and keeps what z3 learned about the shared prefix.
+#[cfg(feature = "concolic_mutation")]
+struct IncrementalSolver {
+    // The fields are dropped in order, everything referencing the context has to come first.
+    solver: Solver<'static>,
+    asserted: Vec<Bool<'static>>,
+    ctx: Rc<Context>,
+}
+
+#[cfg(feature = "concolic_mutation")]
+impl Debug for IncrementalSolver {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IncrementalSolver")
+            .field("asserted", &self.asserted.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "concolic_mutation")]
+impl IncrementalSolver {
+    fn new() -> Self {
+        // Expressions are hash-consed per context, so it must be shared by all traces for
+        // path prefixes to compare equal.
+        let ctx = Rc::new(Context::new(&Config::new()));
+        // Safety: the context does not move with `Self`, and it is dropped last.
+        let solver = Solver::new(unsafe { &*Rc::as_ptr(&ctx) });
+        Self {
+            solver,
+            asserted: Vec::new(),
+            ctx,
+        }
+    }
+
+    /// Runs `f` on a [`SolverSession`], to build and solve the queries of one trace.
+    ///
+    /// The session is valid for any lifetime `'ctx` picked by this function, so the expressions
+    /// built from its context can't escape `f`, except into the assertions of this solver.
+    fn session<R>(&mut self, f: impl for<'ctx> FnOnce(&mut SolverSession<'ctx>) -> R) -> R {
+        // Safety: `f` only gets the context for `'ctx`, and every expression it asserts stems from
+        // this context, which outlives `asserted` and `solver`.
+        let mut session = unsafe {
+            SolverSession {
+                ctx: &*Rc::as_ptr(&self.ctx),
+                solver: &*(&raw const self.solver).cast::<Solver<'_>>(),
+                asserted: &mut *(&raw mut self.asserted).cast::<Vec<Bool<'_>>>(),
+            }
+        };
+        f(&mut session)
+    }
+}
+
+/// The outcome of a single solver query
+#[cfg(feature = "concolic_mutation")]
+#[derive(Debug)]
+enum QueryResult {
+    /// The query is sat, with the input bytes of its model
+    Sat(Vec<(usize, u8)>),
+    /// The query is unsat
+    Unsat,
+    /// The solver gave up, i.e. on a timeout
+    Unknown,
+}
+
+/// The view of an [`IncrementalSolver`] handed out by [`IncrementalSolver::session`]
+#[cfg(feature = "concolic_mutation")]
+struct SolverSession<'ctx> {
+    ctx: &'ctx Context,
+    solver: &'ctx Solver<'ctx>,
+    asserted: &'ctx mut Vec<Bool<'ctx>>,
+}
+
+#[cfg(feature = "concolic_mutation")]
+impl<'ctx> SolverSession<'ctx> {
+    /// The context of the solver, for the expressions of the queries
+    fn ctx(&self) -> &'ctx Context {
+        self.ctx
+    }
+
+    /// Brings the solver's assertions in line with `prefix`, reusing the common scopes.
+    fn sync_prefix(&mut self, prefix: &[Bool<'ctx>]) {
+        let common = self
+            .asserted
+            .iter()
+            .zip(prefix)
+            .take_while(|(asserted, constraint)| asserted == constraint)
+            .count();
+        let stale = self.asserted.len() - common;
+        if stale > 0 {
+            self.solver.pop(u32::try_from(stale).unwrap());
+            self.asserted.truncate(common);
+        }
+        for constraint in &prefix[common..] {
+            self.solver.push();
+            self.solver.assert(constraint);
+            self.asserted.push(constraint.clone());
+        }
+    }
+
+    /// Solves `prefix && negated` within `timeout`.
+    fn solve(
+        &mut self,
+        prefix: &[Bool<'ctx>],
+        negated: &Bool<'ctx>,
+        timeout: Duration,
+    ) -> QueryResult {
+        self.sync_prefix(prefix);
+
+        let mut params = Params::new(self.ctx);
+        params.set_u32(
+            "timeout",
+            u32::try_from(timeout.as_millis())
+                .unwrap_or(u32::MAX)
+                .max(1),
+        );
+        self.solver.set_params(&params);
+
+        self.solver.push();
+        self.solver.assert(negated);
+        let res = match self.solver.check() {
+            SatResult::Sat => self
+                .solver
+                .get_model()
+                .map_or(QueryResult::Unknown, |model| {
+                    QueryResult::Sat(model_replacements(&model))
+                }),
+            SatResult::Unsat => QueryResult::Unsat,
+            SatResult::Unknown => QueryResult::Unknown,
+        };
+        self.solver.pop(1);
+        res
+    }
+}
+
+/// The default timeout for a single solver query
+#[cfg(feature = "concolic_mutation")]
+pub const DEFAULT_CONCOLIC_QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The default time budget for solving the constraints of one testcase
+#[cfg(feature = "concolic_mutation")]
+pub const DEFAULT_CONCOLIC_STAGE_BUDGET: Duration = Duration::from_secs(10);
+
+/// The number of tried queries remembered before the cache is reset
+#[cfg(feature = "concolic_mutation")]
+const MAX_CACHED_QUERIES: usize = 1 << 20;
+
+/// A concolic mutational stage that keeps one incremental z3 solver across testcases.
+///
+/// Unlike [`SimpleConcolicMutationalStage`], it
+/// - reuses the solver scopes of the path prefix shared with the previously solved trace,
+/// - skips queries whose prefix and negated constraint were already tried,
+/// - tries branches whose other direction was never reached, as tracked in the
+///   [`ConcolicBranchMetadata`], before all others,
+/// - bounds every query by a timeout and all queries for one testcase by a time budget.
+#[cfg(feature = "concolic_mutation")]
+pub struct IncrementalConcolicMutationalStage<I, Z> {
+    name: Cow<'static, str>,
+    solver: Option<IncrementalSolver>,
+    tried: HashSet<u64>,
+    query_timeout: Duration,
+    stage_budget: Duration,
+    phantom: PhantomData<(I, Z)>,
+}
+
+#[cfg(feature = "concolic_mutation")]
+impl<I, Z> Debug for IncrementalConcolicMutationalStage<I, Z> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IncrementalConcolicMutationalStage")
+            .field("name", &self.name)
+            .field("solver", &self.solver)
+            .field("tried", &self.tried.len())
+            .field("query_timeout", &self.query_timeout)
+            .field("stage_budget", &self.stage_budget)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "concolic_mutation")]
+/// The unique id for this stage
+static mut INCREMENTAL_CONCOLIC_MUTATIONAL_ID: usize = 0;
+
+#[cfg(feature = "concolic_mutation")]
+/// The name for the incremental concolic mutation stage
+pub const INCREMENTAL_CONCOLIC_MUTATIONAL_NAME: &str = "incrementalconcolicmutation";
+
+#[cfg(feature = "concolic_mutation")]
+impl<I, Z> Named for IncrementalConcolicMutationalStage<I, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+#[cfg(feature = "concolic_mutation")]
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for IncrementalConcolicMutationalStage<I, Z>
+where
+    Z: Evaluator<E, EM, I, S>,
+    I: HasMutatorBytes + Clone,
+    S: HasExecutions
+        + HasCorpus<I>
+        + HasMetadata
+        + HasNamedMetadata
+        + HasCurrentTestcase<I>
+        + MaybeHasClientPerfMonitor
+        + HasCurrentCorpusId,
+{
+    #[inline]
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        {
+            start_timer!(state);
+            mark_feature_time!(state, PerfFeature::GetInputFromCorpus);
+        }
+        let testcase = state.current_testcase()?.clone();
+        let Ok(meta) = testcase.metadata::<ConcolicMetadata>() else {
+            return Ok(());
+        };
+
+        start_timer!(state);
+        let mutations = {
+            let branches = state.metadata_or_insert_with(ConcolicBranchMetadata::new);
+            self.generate_mutations(branches, meta.iter_messages())
+        };
+        mark_feature_time!(state, PerfFeature::Mutate);
+
+        for mutation in mutations {
+            let mut input_copy = state.current_input_cloned()?;
+            let bytes = input_copy.mutator_bytes_mut();
+            for (index, new_byte) in mutation {
+                if let Some(byte) = bytes.get_mut(index) {
+                    *byte = new_byte;
+                }
+            }
+            fuzzer.evaluate_filtered(state, executor, manager, &input_copy)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "concolic_mutation")]
+impl<I, S, Z> Restartable<S> for IncrementalConcolicMutationalStage<I, Z>
+where
+    S: HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+{
+    #[inline]
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        // A timeout in the solver is bounded by the budget, a crash here will just happen again
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+#[cfg(feature = "concolic_mutation")]
+impl<I, Z> Default for IncrementalConcolicMutationalStage<I, Z> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "concolic_mutation")]
+impl<I, Z> IncrementalConcolicMutationalStage<I, Z> {
+    /// Construct this stage with the default query timeout and stage budget
+    #[must_use]
+    pub fn new() -> Self {
+        // unsafe but impossible that you create two threads both instantiating this instance
+        let stage_id = unsafe {
+            let ret = INCREMENTAL_CONCOLIC_MUTATIONAL_ID;
+            INCREMENTAL_CONCOLIC_MUTATIONAL_ID += 1;
+            ret
+        };
+        Self {
+            name: Cow::Owned(
+                INCREMENTAL_CONCOLIC_MUTATIONAL_NAME.to_owned()
+                    + ":"
+                    + stage_id.to_string().as_str(),
+            ),
+            solver: None,
+            tried: HashSet::new(),
+            query_timeout: DEFAULT_CONCOLIC_QUERY_TIMEOUT,
+            stage_budget: DEFAULT_CONCOLIC_STAGE_BUDGET,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the timeout for a single solver query
+    #[must_use]
+    pub fn with_query_timeout(mut self, query_timeout: Duration) -> Self {
+        self.query_timeout = query_timeout;
+        self
+    }
+
+    /// Sets the time budget for all solver queries of one testcase
+    #[must_use]
+    pub fn with_stage_budget(mut self, stage_budget: Duration) -> Self {
+        self.stage_budget = stage_budget;
+        self
+    }
+
+    /// Solves the negations of the path constraints in `iter`, most promising branches first,
+    /// until the stage budget is used up.
+    fn generate_mutations(
+        &mut self,
+        branches: &mut ConcolicBranchMetadata,
+        iter: impl Iterator<Item = (SymExprRef, SymExpr)>,
+    ) -> Vec<Vec<(usize, u8)>> {
+        let deadline = current_time() + self.stage_budget;
+        let solver = self.solver.get_or_insert_with(IncrementalSolver::new);
+        solver.session(|session| {
+            let mut translation = HashMap::<SymExprRef, Dynamic>::new();
+            let mut path = Vec::new();
+            let mut directions = Vec::new();
+            for (id, msg) in iter {
+                if let Some(expr) = translate_expr(session.ctx(), &translation, &msg) {
+                    translation.insert(id, expr);
+                } else if let SymExpr::PathConstraint {
+                    constraint,
+                    taken,
+                    location,
+                } = msg
+                {
+                    let location = usize::from(location);
+                    branches.seen.insert((location, taken));
+                    let Some(op) = translation.get(&constraint) else {
+                        // the constraint depends on an expression that can't be translated
+                        continue;
+                    };
+                    let op = op.as_bool().unwrap();
+                    let op = if taken { op } else { op.not() }.simplify();
+                    // constraints that are always sat or unsat can't be flipped
+                    if op.as_bool().is_none() {
+                        path.push(op);
+                        directions.push((location, !taken));
+                    }
+                }
+            }
+
+            // hash of every path prefix, identifying the solver state a query runs in
+            let mut prefix_hashes = Vec::with_capacity(path.len());
+            let mut prefix_hash = 0_u64;
+            for constraint in &path {
+                prefix_hashes.push(prefix_hash);
+                prefix_hash =
+                    RandomState::with_seeds(0, 0, 0, 0).hash_one((prefix_hash, constraint));
+            }
+
+            // Branches never flipped go first. Both groups are solved in path order, so that every
+            // query only adds one scope to the prefix of the previous one.
+            let mut order = (0..path.len()).collect::<Vec<_>>();
+            order.sort_by_key(|&i| {
+                let (location, taken) = directions[i];
+                branches.is_flipped(location, taken)
+            });
+
+            let mut res = Vec::new();
+            let mut solved_now = HashSet::new();
+            for i in order {
+                let remaining = deadline.saturating_sub(current_time());
+                if remaining.is_zero() {
+                    break;
+                }
+                let target = directions[i];
+                if solved_now.contains(&target) {
+                    // another occurrence of this branch, i.e. in a loop, was flipped already
+                    continue;
+                }
+
+                let negated = path[i].not().simplify();
+                let query =
+                    RandomState::with_seeds(0, 0, 0, 0).hash_one((prefix_hashes[i], &negated));
+                if self.tried.contains(&query) {
+                    // the same query was already solved for an earlier testcase
+                    continue;
+                }
+
+                *branches.attempts.entry(target).or_default() += 1;
+                let timeout = self.query_timeout.min(remaining);
+                match session.solve(&path[..i], &negated, timeout) {
+                    QueryResult::Sat(replacements) => {
+                        branches.solved.insert(target);
+                        solved_now.insert(target);
+                        res.push(replacements);
+                    }
+                    QueryResult::Unsat => {}
+                    // the solver gave up, it may succeed with more time for a later testcase
+                    QueryResult::Unknown => continue,
+                }
+                if self.tried.len() >= MAX_CACHED_QUERIES {
+                    self.tried.clear();
+                }
+                self.tried.insert(query);
+            }
+            res
+        })
+    }
+}
+
+#[cfg(all(test, feature = "concolic_mutation"))]
+mod tests {
+    use alloc::vec::Vec;
+    use core::num::NonZeroUsize;
+
+    use super::{ConcolicBranchMetadata, IncrementalConcolicMutationalStage};
+    use crate::{
+        inputs::BytesInput,
+        observers::concolic::{SymExpr, SymExprRef},
+    };
+
+    fn id(id: usize) -> SymExprRef {
+        NonZeroUsize::new(id).unwrap()
+    }
+
+    /// A trace comparing the input byte at `offset` to `value`, taking the `!=` direction
+    fn trace(offset: usize, value: u64, location: usize) -> Vec<(SymExprRef, SymExpr)> {
+        vec![
+            (id(1), SymExpr::InputByte { offset, value: 0 }),
+            (id(2), SymExpr::Integer { value, bits: 8 }),
+            (id(3), SymExpr::Equal { a: id(1), b: id(2) }),
+            (
+                id(4),
+                SymExpr::PathConstraint {
+                    constraint: id(3),
+                    taken: false,
+                    location: location.into(),
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_incremental_mutations() {
+        let mut stage = IncrementalConcolicMutationalStage::<BytesInput, ()>::new();
+        let mut branches = ConcolicBranchMetadata::new();
+
+        let mutations = stage.generate_mutations(&mut branches, trace(1, 0x42, 7).into_iter());
+        assert_eq!(mutations, vec![vec![(1, 0x42)]]);
+        assert!(branches.is_flipped(7, false));
+        assert!(branches.is_flipped(7, true));
+        assert_eq!(branches.attempts(7, true), 1);
+
+        // The same query is not solved twice
+        let mutations = stage.generate_mutations(&mut branches, trace(1, 0x42, 7).into_iter());
+        assert!(mutations.is_empty());
+        assert_eq!(branches.attempts(7, true), 1);
+    }
+
+    #[test]
+    fn test_prefix_reuse() {
+        let mut stage = IncrementalConcolicMutationalStage::<BytesInput, ()>::new();
+        let mut branches = ConcolicBranchMetadata::new();
+
+        let mut messages = trace(0, 0x41, 1);
+        messages.extend(trace(1, 0x42, 2).into_iter().map(|(i, msg)| {
+            let shift = |r: SymExprRef| id(r.get() + 4);
+            let msg = match msg {
+                SymExpr::Equal { a, b } => SymExpr::Equal {
+                    a: shift(a),
+                    b: shift(b),
+                },
+                SymExpr::PathConstraint {
+                    constraint,
+                    taken,
+                    location,
+                } => SymExpr::PathConstraint {
+                    constraint: shift(constraint),
+                    taken,
+                    location,
+                },
+                msg => msg,
+            };
+            (shift(i), msg)
+        }));
+
+        let mutations = stage.generate_mutations(&mut branches, messages.into_iter());
+        assert_eq!(mutations.len(), 2);
+        assert_eq!(mutations[0], vec![(0, 0x41)]);
+        assert!(mutations[1].contains(&(1, 0x42)));
+        // The second query ran on top of the first constraint, which is still asserted
+        assert_eq!(stage.solver.as_ref().unwrap().asserted.len(), 1);
+    }
+
+    #[test]
+    fn test_untranslatable_constraint() {
+        let mut stage = IncrementalConcolicMutationalStage::<BytesInput, ()>::new();
+        let mut branches = ConcolicBranchMetadata::new();
+
+        // A constraint on a 128 bit integer is skipped, the rest of the trace is still solved
+        let mut messages = vec![
+            (id(10), SymExpr::Integer128 { high: 0, low: 1 }),
+            (
+                id(11),
+                SymExpr::InputByte {
+                    offset: 0,
+                    value: 0,
+                },
+            ),
+            (
+                id(12),
+                SymExpr::Zext {
+                    op: id(11),
+                    bits: 120,
+                },
+            ),
+            (
+                id(13),
+                SymExpr::Equal {
+                    a: id(10),
+                    b: id(12),
+                },
+            ),
+            (
+                id(14),
+                SymExpr::PathConstraint {
+                    constraint: id(13),
+                    taken: false,
+                    location: 3_usize.into(),
+                },
+            ),
+        ];
+        messages.extend(trace(1, 0x42, 7));
+
+        let mutations = stage.generate_mutations(&mut branches, messages.into_iter());
+        assert_eq!(mutations, vec![vec![(1, 0x42)]]);
+        assert!(branches.is_flipped(3, false));
+        assert!(!branches.is_flipped(3, true));
+    }
+}
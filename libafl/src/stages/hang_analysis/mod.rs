@@ -0,0 +1,498 @@
+//! The hang analysis stage finds out *why* a confirmed timeout hangs.
+//!
+//! Each new timeout in the solutions is re-run on a separate analysis executor, while a
+//! [`HangSampler`] periodically samples the call stack of the target. From the samples, the hang is
+//! classified as an infinite loop, cycling through the same few functions under the same callers,
+//! or as slow algorithmic behaviour that still makes progress. A symbolized report of the hottest
+//! locations is attached to the solution as [`HangReportMetadata`].
+//!
+//! Samplers exist for forking in-process executors ([`SignalHangSampler`]) and for the
+//! [`crate::executors::ForkserverExecutor`] ([`PtraceHangSampler`]). `libafl_qemu` provides the
+//! `CallStackHangSampler` on top of its `CallTracerModule`.
+
+use alloc::{borrow::Cow, format, string::String, vec::Vec};
+use core::{fmt::Debug, marker::PhantomData, time::Duration};
+#[cfg(feature = "std")]
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+use hashbrown::HashMap;
+use libafl_bolts::{Named, impl_serdeany};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::{Corpus, CorpusId, HasCurrentCorpusId},
+    executors::{Executor, ExitKind, HasObservers, HasTimeout},
+    observers::ObserversTuple,
+    stages::{Restartable, RetryCountRestartHelper, Stage},
+    state::HasSolutions,
+};
+
+#[cfg(all(
+    feature = "std",
+    feature = "fork",
+    target_os = "linux",
+    target_arch = "x86_64"
+))]
+pub mod ptrace;
+#[cfg(all(
+    feature = "std",
+    feature = "fork",
+    target_os = "linux",
+    target_arch = "x86_64"
+))]
+pub use ptrace::PtraceHangSampler;
+
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+pub mod signal;
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+pub use signal::{SignalHangSampler, SignalHangSamplerHook};
+
+/// Default name for [`HangAnalysisStage`]
+pub const HANG_ANALYSIS_STAGE_NAME: &str = "hang_analysis";
+
+/// The number of stack samples taken during one timeout, the sampling interval is derived from it
+const SAMPLES_PER_TIMEOUT: u32 = 64;
+/// Fewer samples than this are not enough to classify a hang
+const MIN_SAMPLES: usize = 8;
+/// An infinite loop cycles through at most this many distinct functions
+const MAX_LOOP_STACKS: usize = 16;
+/// The default number of hotspots in a report
+const DEFAULT_MAX_HOTSPOTS: usize = 8;
+
+/// One sample of the call stack of the target: the program counter, followed by the return
+/// addresses of the calling frames, innermost first
+pub type StackSample = Vec<usize>;
+
+/// Samples the call stack of the target periodically, while the executor `E` runs it
+pub trait HangSampler<E> {
+    /// Starts sampling every `interval` for the next run of `executor`
+    fn start(&mut self, executor: &mut E, interval: Duration) -> Result<(), Error>;
+
+    /// Stops sampling after the run, returning the samples in the order they were taken
+    fn stop(&mut self, executor: &mut E) -> Result<Vec<StackSample>, Error>;
+}
+
+/// What kind of hang a timeout is
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HangKind {
+    /// The target cycles through the same few functions under the same callers
+    InfiniteLoop,
+    /// The target still makes progress, e.g. through an algorithm with bad complexity
+    SlowAlgorithm,
+    /// Too few samples were taken to tell, e.g. because the target blocked in a syscall
+    Unknown,
+}
+
+/// A code location the target was sampled at
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Hotspot {
+    /// The address in the target
+    pub address: usize,
+    /// The symbolized location, if a symbolizer was set
+    pub symbol: Option<String>,
+    /// The number of samples hitting this location
+    pub hits: usize,
+}
+
+/// The findings of the [`HangAnalysisStage`] for one timeout
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HangReportMetadata {
+    kind: HangKind,
+    samples: usize,
+    hotspots: Vec<Hotspot>,
+    stack: Vec<Hotspot>,
+}
+impl_serdeany!(HangReportMetadata);
+
+impl HangReportMetadata {
+    /// What kind of hang this is
+    #[must_use]
+    pub fn kind(&self) -> HangKind {
+        self.kind
+    }
+
+    /// The number of stack samples taken
+    #[must_use]
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// The locations the target was sampled at most often, hottest first
+    #[must_use]
+    pub fn hotspots(&self) -> &[Hotspot] {
+        &self.hotspots
+    }
+
+    /// The most frequently sampled call stack, innermost first, with the number of samples
+    /// containing each of its frames
+    #[must_use]
+    pub fn stack(&self) -> &[Hotspot] {
+        &self.stack
+    }
+}
+
+/// The function a sample is in, as the return addresses of its callers. The program counter itself
+/// moves all over the body of a loop. Samples without callers keep their program counter.
+fn function_of(sample: &StackSample) -> &[usize] {
+    match sample.as_slice() {
+        [_, callers @ ..] if !callers.is_empty() => callers,
+        pc => pc,
+    }
+}
+
+/// Classifies a hang from its stack samples.
+///
+/// Only the second half of the samples is considered, once the target settled into the hang.
+/// In an infinite loop the outermost frames stay the same and the few distinct functions sampled
+/// in the last quarter all recur from the third quarter, as the loop cycles through them and the
+/// functions it calls. A slow algorithm keeps reaching new functions, e.g. recursing deeper.
+fn classify(samples: &[StackSample]) -> HangKind {
+    if samples.len() < MIN_SAMPLES {
+        return HangKind::Unknown;
+    }
+    let tail = &samples[samples.len() / 2..];
+    let (earlier, later) = tail.split_at(tail.len() / 2);
+
+    // The callers the loop runs in must not change
+    let Some(shortest) = tail.iter().map(Vec::len).min() else {
+        return HangKind::Unknown;
+    };
+    let outer = |sample: &StackSample| sample[sample.len() - shortest.saturating_sub(1)..].to_vec();
+    let callers_constant = tail
+        .windows(2)
+        .all(|pair| outer(&pair[0]) == outer(&pair[1]));
+
+    let mut functions: Vec<&[usize]> = tail.iter().map(function_of).collect();
+    functions.sort_unstable();
+    functions.dedup();
+    let cycles = later.iter().all(|sample| {
+        earlier
+            .iter()
+            .any(|other| function_of(other) == function_of(sample))
+    });
+
+    if callers_constant && cycles && functions.len() <= MAX_LOOP_STACKS {
+        HangKind::InfiniteLoop
+    } else {
+        HangKind::SlowAlgorithm
+    }
+}
+
+/// Counts how many samples hit each innermost location, hottest first
+fn hotspots(samples: &[StackSample]) -> Vec<(usize, usize)> {
+    let mut hits: HashMap<usize, usize> = HashMap::new();
+    for address in samples.iter().filter_map(|sample| sample.first()) {
+        *hits.entry(*address).or_default() += 1;
+    }
+    let mut hits: Vec<(usize, usize)> = hits.into_iter().collect();
+    hits.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    hits
+}
+
+/// Returns the most frequently sampled stack, the lowest one of equally frequent stacks
+fn hottest_stack(samples: &[StackSample]) -> Option<&StackSample> {
+    let mut counts: HashMap<&StackSample, usize> = HashMap::new();
+    for sample in samples {
+        *counts.entry(sample).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+        .map(|(stack, _)| stack)
+}
+
+/// The hang analysis stage re-runs each new timeout in the solutions on the `analysis_executor`,
+/// sampling the call stack of the target with a [`HangSampler`]. Timeouts are classified as
+/// infinite loops or slow algorithms, and a [`HangReportMetadata`] with the hottest locations is
+/// attached to the solution.
+///
+/// Use it together with the `VerifyTimeoutsStage`, so only confirmed timeouts end up in the
+/// solutions. Solutions not timing out again on the analysis executor are not reported.
+#[derive(Debug)]
+pub struct HangAnalysisStage<AE, E, HS, I, S> {
+    name: Cow<'static, str>,
+    analysis_executor: AE,
+    sampler: HS,
+    analyzed: usize,
+    max_hotspots: usize,
+    symbolizer: Option<fn(usize) -> Option<String>>,
+    #[cfg(feature = "std")]
+    report_path: Option<PathBuf>,
+    phantom: PhantomData<(E, I, S)>,
+}
+
+impl<AE, E, EM, HS, I, S, Z> Stage<E, EM, S, Z> for HangAnalysisStage<AE, E, HS, I, S>
+where
+    AE: Executor<EM, I, S, Z> + HasObservers + HasTimeout,
+    AE::Observers: ObserversTuple<I, S>,
+    HS: HangSampler<AE>,
+    S: HasSolutions<I> + HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+    I: Clone,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        mgr: &mut EM,
+    ) -> Result<(), Error> {
+        let new_solutions: Vec<CorpusId> = state.solutions().ids().skip(self.analyzed).collect();
+        for id in new_solutions {
+            self.analyzed += 1;
+            if state
+                .solutions()
+                .get(id)?
+                .borrow()
+                .has_metadata::<HangReportMetadata>()
+            {
+                continue;
+            }
+            let input = state.solutions().cloned_input_for_id(id)?;
+
+            let interval = self.analysis_executor.timeout() / SAMPLES_PER_TIMEOUT;
+            self.sampler.start(&mut self.analysis_executor, interval)?;
+            self.analysis_executor
+                .observers_mut()
+                .pre_exec_all(state, &input)?;
+            let exit_kind = self
+                .analysis_executor
+                .run_target(fuzzer, state, mgr, &input)?;
+            self.analysis_executor
+                .observers_mut()
+                .post_exec_all(state, &input, &exit_kind)?;
+            let samples = self.sampler.stop(&mut self.analysis_executor)?;
+            if exit_kind != ExitKind::Timeout {
+                continue;
+            }
+
+            let meta = self.analyze(&samples);
+            log::info!(
+                "Solution {id} hangs ({:?}, {} samples){}",
+                meta.kind,
+                meta.samples,
+                meta.hotspots
+                    .first()
+                    .map(|hotspot| format!(" at {}", Self::describe(hotspot)))
+                    .unwrap_or_default()
+            );
+            #[cfg(feature = "std")]
+            self.report(id, &meta)?;
+            state.solutions().get(id)?.borrow_mut().add_metadata(meta);
+        }
+        Ok(())
+    }
+}
+
+impl<AE, E, HS, I, S> Restartable<S> for HangAnalysisStage<AE, E, HS, I, S>
+where
+    S: HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        // The solutions may crash the target, don't retry
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+impl<AE, E, HS, I, S> HangAnalysisStage<AE, E, HS, I, S> {
+    /// Create a new [`HangAnalysisStage`].
+    /// The `analysis_executor` must survive timeouts of the target and report them as
+    /// [`ExitKind::Timeout`], for example a forking executor, and be supported by the `sampler`.
+    /// Its timeout should be longer than the one of the fuzzing executor.
+    #[must_use]
+    pub fn new(analysis_executor: AE, sampler: HS) -> Self {
+        Self {
+            name: Cow::Borrowed(HANG_ANALYSIS_STAGE_NAME),
+            analysis_executor,
+            sampler,
+            analyzed: 0,
+            max_hotspots: DEFAULT_MAX_HOTSPOTS,
+            symbolizer: None,
+            #[cfg(feature = "std")]
+            report_path: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the number of hotspots in each report
+    #[must_use]
+    pub fn with_max_hotspots(mut self, max_hotspots: usize) -> Self {
+        self.max_hotspots = max_hotspots;
+        self
+    }
+
+    /// Resolves sampled addresses to source locations in the report
+    #[must_use]
+    pub fn with_symbolizer(mut self, symbolizer: fn(usize) -> Option<String>) -> Self {
+        self.symbolizer = Some(symbolizer);
+        self
+    }
+
+    /// Appends a report of each analyzed hang to the file at `path`
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn with_report<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.report_path = Some(path.into());
+        self
+    }
+
+    /// The executor used to re-run timeouts
+    pub fn analysis_executor(&self) -> &AE {
+        &self.analysis_executor
+    }
+
+    /// The sampler of the call stacks
+    pub fn sampler(&self) -> &HS {
+        &self.sampler
+    }
+
+    fn hotspot(&self, address: usize, hits: usize) -> Hotspot {
+        Hotspot {
+            address,
+            symbol: self.symbolizer.and_then(|symbolize| symbolize(address)),
+            hits,
+        }
+    }
+
+    fn analyze(&self, samples: &[StackSample]) -> HangReportMetadata {
+        let hotspots = hotspots(samples)
+            .into_iter()
+            .take(self.max_hotspots)
+            .map(|(address, hits)| self.hotspot(address, hits))
+            .collect();
+        let stack = hottest_stack(samples)
+            .map(|stack| {
+                stack
+                    .iter()
+                    .map(|address| {
+                        let hits = samples
+                            .iter()
+                            .filter(|sample| sample.contains(address))
+                            .count();
+                        self.hotspot(*address, hits)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        HangReportMetadata {
+            kind: classify(samples),
+            samples: samples.len(),
+            hotspots,
+            stack,
+        }
+    }
+
+    fn describe(hotspot: &Hotspot) -> String {
+        match &hotspot.symbol {
+            Some(symbol) => format!("{:#x} ({symbol})", hotspot.address),
+            None => format!("{:#x}", hotspot.address),
+        }
+    }
+
+    /// Appends the findings for one solution to the report
+    #[cfg(feature = "std")]
+    fn report(&self, id: CorpusId, meta: &HangReportMetadata) -> Result<(), Error> {
+        let Some(path) = &self.report_path else {
+            return Ok(());
+        };
+
+        let mut report = Vec::new();
+        writeln!(
+            report,
+            "solution {id}: {:?} ({} samples)",
+            meta.kind, meta.samples
+        )?;
+        for hotspot in &meta.hotspots {
+            writeln!(
+                report,
+                "  hotspot {}: {} samples",
+                Self::describe(hotspot),
+                hotspot.hits
+            )?;
+        }
+        for (depth, frame) in meta.stack.iter().enumerate() {
+            writeln!(
+                report,
+                "  #{depth} {}: in {} samples",
+                Self::describe(frame),
+                frame.hits
+            )?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(&report)?;
+        Ok(())
+    }
+}
+
+impl<AE, E, HS, I, S> Named for HangAnalysisStage<AE, E, HS, I, S> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::{HangKind, StackSample, classify, hotspots, hottest_stack};
+
+    #[test]
+    fn test_classify_hangs() {
+        // Too few samples
+        assert_eq!(classify(&[vec![0x10, 0x100]]), HangKind::Unknown);
+
+        // Cycling through a loop body under the same callers
+        let looping: Vec<StackSample> = (0..32)
+            .map(|i| vec![0x10 + (i % 3), 0x100, 0x200])
+            .collect();
+        assert_eq!(classify(&looping), HangKind::InfiniteLoop);
+
+        // Calling a helper from within the loop
+        let calling: Vec<StackSample> = (0..32)
+            .map(|i| {
+                if i % 2 == 0 {
+                    vec![0x10, 0x100, 0x200]
+                } else {
+                    vec![0x50, 0x14, 0x100, 0x200]
+                }
+            })
+            .collect();
+        assert_eq!(classify(&calling), HangKind::InfiniteLoop);
+
+        // A loop with a large body, never sampled twice at the same location
+        let large_body: Vec<StackSample> =
+            (0..32).map(|i| vec![0x1000 + i, 0x100, 0x200]).collect();
+        assert_eq!(classify(&large_body), HangKind::InfiniteLoop);
+
+        // Progressing through ever new functions, recursing deeper
+        let progressing: Vec<StackSample> = (0..32)
+            .map(|i| {
+                let mut sample = vec![0x10];
+                sample.extend(vec![0x50; i / 4 + 1]);
+                sample.extend([0x100, 0x200]);
+                sample
+            })
+            .collect();
+        assert_eq!(classify(&progressing), HangKind::SlowAlgorithm);
+
+        // The callers change
+        let changing: Vec<StackSample> = (0..32).map(|i| vec![0x10, 0x100 + (i % 2)]).collect();
+        assert_eq!(classify(&changing), HangKind::SlowAlgorithm);
+
+        let hot = hotspots(&looping);
+        assert_eq!(hot[0], (0x10, 11));
+        assert_eq!(hot.len(), 3);
+        assert_eq!(hottest_stack(&looping), Some(&vec![0x10, 0x100, 0x200]));
+        assert_eq!(hottest_stack(&calling), Some(&vec![0x10, 0x100, 0x200]));
+        assert_eq!(hottest_stack(&[]), None);
+    }
+}
@@ -0,0 +1,165 @@
+//! The [`PtraceHangSampler`] samples the call stack of a forkserver target with `ptrace`.
+//!
+//! While the [`ForkserverExecutor`] runs an input, a sampler thread periodically attaches to the
+//! current child of the forkserver, interrupts it, and walks the frame pointer chain from its
+//! registers. Targets must be built with frame pointers for full stacks, otherwise only the
+//! program counter is sampled reliably.
+
+use alloc::{format, sync::Arc, vec::Vec};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use std::{
+    fs,
+    thread::{self, JoinHandle},
+};
+
+use libafl_bolts::shmem::ShMem;
+use nix::{
+    sys::{
+        ptrace::{self, AddressType, Options},
+        wait::{WaitPidFlag, WaitStatus, waitpid},
+    },
+    unistd::Pid,
+};
+
+use crate::{
+    Error,
+    executors::ForkserverExecutor,
+    inputs::TargetBytesConverter,
+    observers::ObserversTuple,
+    stages::hang_analysis::{HangSampler, StackSample},
+};
+
+/// The maximum number of samples of one run
+const MAX_SAMPLES: usize = 256;
+/// The maximum number of frames of one sample
+const MAX_DEPTH: usize = 32;
+
+/// Samples the call stack of the children of a [`ForkserverExecutor`] with `ptrace`, from a
+/// separate thread. The fuzzer needs the permission to trace the target, which, with the Yama
+/// `ptrace_scope` of most distributions, it has as an ancestor of the target.
+#[derive(Debug, Default)]
+pub struct PtraceHangSampler {
+    stop: Arc<AtomicBool>,
+    sampler: Option<JoinHandle<Vec<StackSample>>>,
+}
+
+impl PtraceHangSampler {
+    /// Creates a new [`PtraceHangSampler`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<I, OT, S, SHM, TC> HangSampler<ForkserverExecutor<I, OT, S, SHM, TC>> for PtraceHangSampler
+where
+    OT: ObserversTuple<I, S>,
+    TC: TargetBytesConverter<I>,
+    SHM: ShMem,
+{
+    fn start(
+        &mut self,
+        executor: &mut ForkserverExecutor<I, OT, S, SHM, TC>,
+        interval: Duration,
+    ) -> Result<(), Error> {
+        if self.sampler.is_some() {
+            return Err(Error::illegal_state("The hang sampler is already running"));
+        }
+        let forkserver = executor.forkserver().forkserver_pid();
+        self.stop = Arc::new(AtomicBool::new(false));
+        let stop = self.stop.clone();
+        self.sampler = Some(
+            thread::Builder::new()
+                .name("hang_sampler".into())
+                .spawn(move || sample_children(forkserver, interval, &stop))?,
+        );
+        Ok(())
+    }
+
+    fn stop(
+        &mut self,
+        _executor: &mut ForkserverExecutor<I, OT, S, SHM, TC>,
+    ) -> Result<Vec<StackSample>, Error> {
+        let Some(sampler) = self.sampler.take() else {
+            return Ok(Vec::new());
+        };
+        self.stop.store(true, Ordering::Release);
+        sampler
+            .join()
+            .map_err(|_| Error::unknown("The hang sampler thread panicked"))
+    }
+}
+
+/// Samples the current child of the `forkserver` every `interval`, until told to `stop`
+fn sample_children(forkserver: Pid, interval: Duration, stop: &AtomicBool) -> Vec<StackSample> {
+    let mut samples = Vec::new();
+    while !stop.load(Ordering::Acquire) && samples.len() < MAX_SAMPLES {
+        thread::sleep(interval);
+        if let Some(sample) = current_child(forkserver).and_then(sample_stack) {
+            samples.push(sample);
+        }
+    }
+    samples
+}
+
+/// The child the forkserver currently runs, if any
+fn current_child(forkserver: Pid) -> Option<Pid> {
+    let children =
+        fs::read_to_string(format!("/proc/{forkserver}/task/{forkserver}/children")).ok()?;
+    children
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+        .map(Pid::from_raw)
+}
+
+/// Attaches to `pid` and samples its stack. The child may exit at any time, which ends sampling.
+fn sample_stack(pid: Pid) -> Option<StackSample> {
+    ptrace::seize(pid, Options::empty()).ok()?;
+    let sample = interrupt_and_walk(pid);
+    let _ = ptrace::detach(pid, None);
+    sample
+}
+
+fn interrupt_and_walk(pid: Pid) -> Option<StackSample> {
+    ptrace::interrupt(pid).ok()?;
+    loop {
+        match waitpid(pid, Some(WaitPidFlag::__WALL)).ok()? {
+            WaitStatus::PtraceEvent(..) => break,
+            // A signal arrived first, pass it on, the interrupt stop follows
+            WaitStatus::Stopped(_, signal) => ptrace::cont(pid, signal).ok()?,
+            WaitStatus::Exited(..) | WaitStatus::Signaled(..) => return None,
+            _ => {}
+        }
+    }
+
+    let regs = ptrace::getregs(pid).ok()?;
+    let mut sample = Vec::with_capacity(MAX_DEPTH);
+    sample.push(usize::try_from(regs.rip).ok()?);
+    let mut fp = usize::try_from(regs.rbp).ok()?;
+    while sample.len() < MAX_DEPTH && fp != 0 && fp % 8 == 0 {
+        let Some(ret) = read_word(pid, fp + size_of::<usize>()) else {
+            break;
+        };
+        if ret == 0 {
+            break;
+        }
+        sample.push(ret);
+        match read_word(pid, fp) {
+            // The stack grows down, callers are at higher addresses
+            Some(next) if next > fp => fp = next,
+            _ => break,
+        }
+    }
+    Some(sample)
+}
+
+/// Reads a word of the memory of the stopped `pid`
+fn read_word(pid: Pid, address: usize) -> Option<usize> {
+    let word = ptrace::read(pid, address as AddressType).ok()?;
+    Some(usize::from_ne_bytes(word.to_ne_bytes()))
+}
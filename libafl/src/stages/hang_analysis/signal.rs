@@ -0,0 +1,264 @@
+//! The [`SignalHangSampler`] samples the call stack of a forked in-process target with `SIGPROF`.
+//!
+//! The [`SignalHangSamplerHook`] must be one of the hooks of the analysis executor, e.g. an
+//! `InProcessForkExecutor`. In the forked child, it arms an `ITIMER_PROF` timer, and the signal
+//! handler walks the frame pointer chain of the interrupted code into a buffer shared with the
+//! fuzzer. Targets must be built with frame pointers for full stacks, otherwise only the program
+//! counter is sampled reliably. Only CPU time is sampled, targets blocked in a syscall yield no
+//! samples.
+
+use alloc::{rc::Rc, vec::Vec};
+use core::{
+    ffi::{c_int, c_void},
+    mem::{self, MaybeUninit},
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use std::io;
+
+use libc::{siginfo_t, ucontext_t};
+
+use crate::{
+    Error,
+    executors::hooks::ExecutorHook,
+    stages::hang_analysis::{HangSampler, StackSample},
+};
+
+/// The maximum number of samples of one run
+const MAX_SAMPLES: usize = 256;
+/// The maximum number of frames of one sample
+const MAX_DEPTH: usize = 32;
+
+/// The samples, shared between the fuzzer and the forked child
+#[repr(C)]
+struct SampleBuffer {
+    interval_us: u64,
+    len: AtomicUsize,
+    // Zero-terminated stacks
+    samples: [[usize; MAX_DEPTH]; MAX_SAMPLES],
+}
+
+/// Owns the shared mapping of the [`SampleBuffer`]
+#[derive(Debug)]
+struct SharedSampleBuffer {
+    buffer: *mut SampleBuffer,
+}
+
+impl SharedSampleBuffer {
+    fn new() -> Result<Self, Error> {
+        // # Safety
+        // A fresh anonymous mapping, zeroed by the kernel, which is a valid `SampleBuffer`
+        let buffer = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size_of::<SampleBuffer>(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if buffer == libc::MAP_FAILED {
+            return Err(Error::os_error(
+                io::Error::last_os_error(),
+                "Failed to map the hang sample buffer",
+            ));
+        }
+        Ok(Self {
+            buffer: buffer.cast(),
+        })
+    }
+}
+
+impl Drop for SharedSampleBuffer {
+    fn drop(&mut self) {
+        // # Safety
+        // The mapping was created in `new`, and is only used through this struct
+        unsafe {
+            libc::munmap(self.buffer.cast(), size_of::<SampleBuffer>());
+        }
+    }
+}
+
+/// The buffer the signal handler writes to, in the forked child
+static mut SAMPLE_BUFFER: *mut SampleBuffer = ptr::null_mut();
+/// The bounds of the stack of the forked child, frame pointers outside are not followed
+static mut STACK_BOUNDS: (usize, usize) = (0, 0);
+
+/// Records one sample of the interrupted code
+#[expect(clippy::cast_sign_loss)] // registers are stored as signed integers
+unsafe extern "C" fn handle_sigprof(_sig: c_int, _info: *mut siginfo_t, context: *mut c_void) {
+    unsafe {
+        let buffer = (&raw const SAMPLE_BUFFER).read();
+        if buffer.is_null() || context.is_null() {
+            return;
+        }
+        let idx = (*buffer).len.load(Ordering::Relaxed);
+        if idx >= MAX_SAMPLES {
+            return;
+        }
+        let gregs = &(*context.cast::<ucontext_t>()).uc_mcontext.gregs;
+        let sample = (&raw mut (*buffer).samples)
+            .cast::<usize>()
+            .add(idx * MAX_DEPTH);
+        sample.write(gregs[libc::REG_RIP as usize] as usize);
+
+        let (low, high) = (&raw const STACK_BOUNDS).read();
+        let mut fp = gregs[libc::REG_RBP as usize] as usize;
+        let mut depth = 1;
+        while depth < MAX_DEPTH && fp >= low && fp + 2 * size_of::<usize>() <= high && fp % 8 == 0 {
+            let frame = fp as *const usize;
+            let ret = frame.add(1).read();
+            if ret == 0 {
+                break;
+            }
+            sample.add(depth).write(ret);
+            depth += 1;
+            let next = frame.read();
+            // The stack grows down, callers are at higher addresses
+            if next <= fp {
+                break;
+            }
+            fp = next;
+        }
+        if depth < MAX_DEPTH {
+            sample.add(depth).write(0);
+        }
+        (*buffer).len.store(idx + 1, Ordering::Release);
+    }
+}
+
+/// Returns the bounds of the stack of the current thread
+fn stack_bounds() -> Option<(usize, usize)> {
+    // # Safety
+    // `pthread_getattr_np` initializes the attributes on success, which are destroyed after use
+    unsafe {
+        let mut attr = MaybeUninit::<libc::pthread_attr_t>::uninit();
+        if libc::pthread_getattr_np(libc::pthread_self(), attr.as_mut_ptr()) != 0 {
+            return None;
+        }
+        let mut addr = ptr::null_mut();
+        let mut size = 0;
+        let res = libc::pthread_attr_getstack(attr.as_ptr(), &raw mut addr, &raw mut size);
+        libc::pthread_attr_destroy(attr.as_mut_ptr());
+        (res == 0).then(|| (addr as usize, addr as usize + size))
+    }
+}
+
+/// Samples the call stack of a target run by a forking in-process executor, with `SIGPROF`.
+/// Add its [`SignalHangSampler::hook`] to the hooks of the analysis executor.
+#[derive(Debug)]
+pub struct SignalHangSampler {
+    shared: Rc<SharedSampleBuffer>,
+}
+
+impl SignalHangSampler {
+    /// Creates a new [`SignalHangSampler`]
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {
+            shared: Rc::new(SharedSampleBuffer::new()?),
+        })
+    }
+
+    /// The hook arming the sampling in the forked child, for the hooks of the analysis executor
+    #[must_use]
+    pub fn hook(&self) -> SignalHangSamplerHook {
+        SignalHangSamplerHook {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<E> HangSampler<E> for SignalHangSampler {
+    fn start(&mut self, _executor: &mut E, interval: Duration) -> Result<(), Error> {
+        let buffer = self.shared.buffer;
+        // # Safety
+        // No child is running, the buffer is not accessed concurrently
+        unsafe {
+            (&raw mut (*buffer).interval_us).write(
+                u64::try_from(interval.as_micros())
+                    .unwrap_or(u64::MAX)
+                    .max(1),
+            );
+            (*buffer).len.store(0, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self, _executor: &mut E) -> Result<Vec<StackSample>, Error> {
+        let buffer = self.shared.buffer;
+        // # Safety
+        // The child exited, the buffer is not accessed concurrently anymore
+        unsafe {
+            let len = (*buffer).len.load(Ordering::Acquire).min(MAX_SAMPLES);
+            (&raw mut (*buffer).interval_us).write(0);
+            Ok((0..len)
+                .map(|idx| {
+                    (*buffer).samples[idx]
+                        .iter()
+                        .copied()
+                        .take_while(|address| *address != 0)
+                        .collect()
+                })
+                .collect())
+        }
+    }
+}
+
+/// The [`ExecutorHook`] of a [`SignalHangSampler`], arming `SIGPROF` in the forked child
+#[derive(Debug)]
+pub struct SignalHangSamplerHook {
+    shared: Rc<SharedSampleBuffer>,
+}
+
+impl<I, S> ExecutorHook<I, S> for SignalHangSamplerHook {
+    fn init(&mut self, _state: &mut S) {}
+
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) {
+        let buffer = self.shared.buffer;
+        // # Safety
+        // This runs in the forked child before the target, no handler is running yet
+        unsafe {
+            let interval_us = (&raw const (*buffer).interval_us).read();
+            if interval_us == 0 {
+                return;
+            }
+            (&raw mut STACK_BOUNDS).write(stack_bounds().unwrap_or((0, 0)));
+            (&raw mut SAMPLE_BUFFER).write(buffer);
+
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handle_sigprof as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+            libc::sigemptyset(&raw mut action.sa_mask);
+            if libc::sigaction(libc::SIGPROF, &raw const action, ptr::null_mut()) != 0 {
+                log::error!("Failed to install the SIGPROF handler for hang sampling");
+                return;
+            }
+
+            #[expect(clippy::cast_possible_wrap)] // the interval is far below the limits
+            let interval = libc::timeval {
+                tv_sec: (interval_us / 1_000_000) as libc::time_t,
+                tv_usec: (interval_us % 1_000_000) as libc::suseconds_t,
+            };
+            let timer = libc::itimerval {
+                it_interval: interval,
+                it_value: interval,
+            };
+            libc::setitimer(libc::ITIMER_PROF, &raw const timer, ptr::null_mut());
+        }
+    }
+
+    fn post_exec(&mut self, _state: &mut S, _input: &I) {
+        // # Safety
+        // Disarms the timer, the handler stays installed but finds no buffer
+        unsafe {
+            if (&raw const SAMPLE_BUFFER).read().is_null() {
+                return;
+            }
+            let timer: libc::itimerval = mem::zeroed();
+            libc::setitimer(libc::ITIMER_PROF, &raw const timer, ptr::null_mut());
+            (&raw mut SAMPLE_BUFFER).write(ptr::null_mut());
+        }
+    }
+}
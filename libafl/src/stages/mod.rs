@@ -14,15 +14,20 @@ use core::{fmt, marker::PhantomData};
 
 #[cfg(feature = "std")]
 pub use afl_stats::{AflStatsStage, CalibrationTime, FuzzTime, SyncTime};
+pub use batch::BatchMutationalStage;
 pub use calibrate::CalibrationStage;
+pub use checksum::ChecksumDetectionStage;
 pub use colorization::*;
 #[cfg(all(feature = "std", unix))]
 pub use concolic::ConcolicTracingStage;
 #[cfg(all(feature = "std", feature = "concolic_mutation", unix))]
-pub use concolic::SimpleConcolicMutationalStage;
+pub use concolic::{
+    ConcolicBranchMetadata, IncrementalConcolicMutationalStage, SimpleConcolicMutationalStage,
+};
 #[cfg(feature = "std")]
 pub use dump::*;
 pub use generalization::GeneralizationStage;
+pub use hang_analysis::{HangAnalysisStage, HangKind, HangReportMetadata, HangSampler};
 use hashbrown::HashSet;
 use libafl_bolts::{
     Named, impl_serdeany,
@@ -30,8 +35,10 @@ use libafl_bolts::{
 };
 pub use logics::*;
 pub use mutational::{MutationalStage, StdMutationalStage};
+pub use nondeterminism::NondeterminismStage;
 pub use power::{PowerMutationalStage, StdPowerMutationalStage};
 use serde::{Deserialize, Serialize};
+pub use structure::StructureIdentificationStage;
 #[cfg(feature = "std")]
 pub use sync::*;
 #[cfg(feature = "std")]
@@ -65,7 +72,9 @@ pub use replay::*;
 
 #[cfg(feature = "std")]
 pub mod afl_stats;
+pub mod batch;
 pub mod calibrate;
+pub mod checksum;
 pub mod colorization;
 #[cfg(all(feature = "std", unix))]
 pub mod concolic;
@@ -73,8 +82,11 @@ pub mod concolic;
 pub mod dump;
 pub mod generalization;
 pub mod generation;
+pub mod hang_analysis;
 pub mod logics;
+pub mod nondeterminism;
 pub mod power;
+pub mod structure;
 #[cfg(feature = "std")]
 pub mod sync;
 #[cfg(feature = "std")]
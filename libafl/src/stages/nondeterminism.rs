@@ -0,0 +1,691 @@
+//! The nondeterminism stage replays corpus entries to find out *why* a target is unstable.
+//!
+//! Each corpus entry is run in a fresh process, back-to-back in the regular executor, and after
+//! other corpus entries. Map entries changing between back-to-back runs are flaky, while entries
+//! only changing with the executions before them point to leftover state, e.g. in persistent mode.
+
+#[cfg(feature = "std")]
+use alloc::string::ToString;
+use alloc::{
+    borrow::{Cow, ToOwned},
+    format,
+    string::String,
+    vec::Vec,
+};
+use core::{fmt::Debug, marker::PhantomData};
+#[cfg(feature = "std")]
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+use hashbrown::HashMap;
+use libafl_bolts::{Named, impl_serdeany, rands::Rand, tuples::Handle};
+use num_traits::Bounded;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::{Corpus, CorpusId, HasCurrentCorpusId},
+    executors::{Executor, ExitKind, HasObservers},
+    feedbacks::{HasObserverHandle, map::MapFeedbackMetadata},
+    observers::{MapObserver, ObserversTuple},
+    stages::{Restartable, RetryCountRestartHelper, Stage},
+    state::{HasCorpus, HasCurrentTestcase, HasRand},
+};
+
+/// Default name for [`NondeterminismStage`]
+pub const NONDETERMINISM_STAGE_NAME: &str = "nondeterminism";
+
+/// The default number of back-to-back runs of each corpus entry
+const DEFAULT_REPEATS: usize = 4;
+/// The default number of corpus entries to run before each corpus entry
+const DEFAULT_ORDERINGS: usize = 8;
+
+/// How an unstable map entry behaves
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instability {
+    /// The entry changes between back-to-back runs of the same input
+    Flaky,
+    /// The entry is stable for back-to-back runs, but depends on the previous executions
+    StateDependent,
+}
+
+/// The unstable map entries found by the [`NondeterminismStage`], for all corpus entries
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NondeterminismMetadata {
+    entries: HashMap<usize, Instability>,
+    state_dependent_inputs: Vec<CorpusId>,
+}
+impl_serdeany!(NondeterminismMetadata);
+
+impl NondeterminismMetadata {
+    /// Create a new [`struct@NondeterminismMetadata`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The unstable map entries, and how they behave
+    #[must_use]
+    pub fn entries(&self) -> &HashMap<usize, Instability> {
+        &self.entries
+    }
+
+    /// The corpus entries behaving differently depending on the previous executions
+    #[must_use]
+    pub fn state_dependent_inputs(&self) -> &[CorpusId] {
+        &self.state_dependent_inputs
+    }
+
+    fn insert(&mut self, idx: usize, instability: Instability) {
+        // Flaky entries stay flaky, whatever the other inputs showed
+        let entry = self.entries.entry(idx).or_insert(instability);
+        if instability == Instability::Flaky {
+            *entry = Instability::Flaky;
+        }
+    }
+}
+
+/// The findings of the [`NondeterminismStage`] for one testcase
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NondeterminismTestcaseMetadata {
+    flaky: Vec<usize>,
+    state_dependent: Vec<usize>,
+    predecessors: Vec<CorpusId>,
+    fresh_process_differs: bool,
+}
+impl_serdeany!(NondeterminismTestcaseMetadata);
+
+impl NondeterminismTestcaseMetadata {
+    /// The map entries changing between back-to-back runs of this testcase
+    #[must_use]
+    pub fn flaky(&self) -> &[usize] {
+        &self.flaky
+    }
+
+    /// The map entries depending on the previous executions
+    #[must_use]
+    pub fn state_dependent(&self) -> &[usize] {
+        &self.state_dependent
+    }
+
+    /// The corpus entries changing the behavior of this testcase when run before it
+    #[must_use]
+    pub fn predecessors(&self) -> &[CorpusId] {
+        &self.predecessors
+    }
+
+    /// If this testcase behaves differently in a fresh process
+    #[must_use]
+    pub fn fresh_process_differs(&self) -> bool {
+        self.fresh_process_differs
+    }
+
+    /// If the behavior of this testcase depends on the previous executions
+    #[must_use]
+    pub fn is_state_dependent(&self) -> bool {
+        !self.state_dependent.is_empty()
+    }
+}
+
+/// Appends the indices of the entries differing between `a` and `b`, which are not in `skip`
+fn push_differences<T: PartialEq>(a: &[T], b: &[T], skip: &[usize], out: &mut Vec<usize>) {
+    for (idx, (x, y)) in a.iter().zip(b).enumerate() {
+        if x != y && !skip.contains(&idx) && !out.contains(&idx) {
+            out.push(idx);
+        }
+    }
+}
+
+/// Splits the entries changing between back-to-back runs into flaky and state dependent ones.
+/// The first run follows some other execution, all later runs follow a run of the same input.
+fn classify<T: PartialEq>(runs: &[Vec<T>]) -> (Vec<usize>, Vec<usize>) {
+    let mut flaky = vec![];
+    let mut state_dependent = vec![];
+    if let Some((baseline, warm)) = runs.split_last() {
+        for run in warm.iter().skip(1) {
+            push_differences(baseline, run, &[], &mut flaky);
+        }
+        if let Some(cold) = warm.first() {
+            push_differences(baseline, cold, &flaky, &mut state_dependent);
+        }
+    }
+    (flaky, state_dependent)
+}
+
+/// Runs `input` and returns the map, or `None` if the run did not exit normally
+fn run_and_observe<C, EM, I, O, S, X, Z>(
+    fuzzer: &mut Z,
+    executor: &mut X,
+    state: &mut S,
+    mgr: &mut EM,
+    input: &I,
+    handle: &Handle<C>,
+) -> Result<Option<Vec<O::Entry>>, Error>
+where
+    C: AsRef<O>,
+    O: MapObserver,
+    X: Executor<EM, I, S, Z> + HasObservers,
+    X::Observers: ObserversTuple<I, S>,
+{
+    executor.observers_mut().pre_exec_all(state, input)?;
+    let exit_kind = executor.run_target(fuzzer, state, mgr, input)?;
+    executor
+        .observers_mut()
+        .post_exec_all(state, input, &exit_kind)?;
+    if exit_kind != ExitKind::Ok {
+        return Ok(None);
+    }
+    Ok(Some(executor.observers()[handle].as_ref().to_vec()))
+}
+
+/// Marks the unstable entries in the history map, the same way the `CalibrationStage` does
+fn mask_entries<O, S>(
+    state: &mut S,
+    map_name: &str,
+    meta: &NondeterminismTestcaseMetadata,
+) -> Result<(), Error>
+where
+    O: MapObserver,
+    for<'de> <O as MapObserver>::Entry:
+        Serialize + Deserialize<'de> + 'static + Default + Debug + Bounded,
+    S: HasNamedMetadata,
+{
+    let map_state = state
+        .named_metadata_map_mut()
+        .get_mut::<MapFeedbackMetadata<O::Entry>>(map_name)
+        .ok_or_else(|| Error::key_not_found(format!("MapFeedbackMetadata {map_name}")))?;
+    for idx in meta.flaky.iter().chain(&meta.state_dependent) {
+        if map_state.history_map.len() <= *idx {
+            map_state.history_map.resize(*idx + 1, O::Entry::default());
+        }
+        let history = &mut map_state.history_map[*idx];
+        if *history != O::Entry::max_value() {
+            // Keep `num_covered_map_indexes` in sync, like the `CalibrationStage`
+            map_state.num_covered_map_indexes += usize::from(*history == O::Entry::default());
+            *history = O::Entry::max_value();
+        }
+    }
+    Ok(())
+}
+
+/// The nondeterminism stage diagnoses unstable targets. It replays each corpus entry once,
+/// in a fresh process using the `fresh_executor`, back-to-back, and after random other corpus entries,
+/// and diffs the map observations. It reports flaky map entries, and map entries and inputs depending
+/// on the previous executions. Optionally, unstable map entries are masked from the `MapFeedback`.
+#[derive(Clone, Debug)]
+pub struct NondeterminismStage<C, E, FE, I, O, OT, S> {
+    map_observer_handle: Handle<C>,
+    map_name: Cow<'static, str>,
+    name: Cow<'static, str>,
+    fresh_executor: FE,
+    repeats: usize,
+    orderings: usize,
+    mask: bool,
+    symbolizer: Option<fn(usize) -> Option<String>>,
+    #[cfg(feature = "std")]
+    report_path: Option<PathBuf>,
+    phantom: PhantomData<(E, I, O, OT, S)>,
+}
+
+impl<C, E, EM, FE, I, O, OT, S, Z> Stage<E, EM, S, Z> for NondeterminismStage<C, E, FE, I, O, OT, S>
+where
+    E: Executor<EM, I, S, Z> + HasObservers<Observers = OT>,
+    FE: Executor<EM, I, S, Z> + HasObservers,
+    FE::Observers: ObserversTuple<I, S>,
+    O: MapObserver,
+    C: AsRef<O>,
+    for<'de> <O as MapObserver>::Entry:
+        Serialize + Deserialize<'de> + 'static + Default + Debug + Bounded,
+    OT: ObserversTuple<I, S>,
+    S: HasCorpus<I>
+        + HasMetadata
+        + HasNamedMetadata
+        + HasRand
+        + HasCurrentTestcase<I>
+        + HasCurrentCorpusId,
+    I: Clone,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        mgr: &mut EM,
+    ) -> Result<(), Error> {
+        // Diagnose each corpus entry only once
+        if state
+            .current_testcase()?
+            .has_metadata::<NondeterminismTestcaseMetadata>()
+        {
+            return Ok(());
+        }
+        let Some(corpus_id) = state.current_corpus_id()? else {
+            return Err(Error::illegal_state(
+                "state is not currently processing a corpus index",
+            ));
+        };
+        let input = state.current_input_cloned()?;
+
+        let mut meta = NondeterminismTestcaseMetadata::default();
+
+        // Back-to-back runs, the first one follows whatever ran before
+        let mut runs = Vec::with_capacity(self.repeats);
+        for _ in 0..self.repeats {
+            if let Some(map) = run_and_observe::<C, EM, I, O, S, _, Z>(
+                fuzzer,
+                executor,
+                state,
+                mgr,
+                &input,
+                &self.map_observer_handle,
+            )? {
+                runs.push(map);
+            }
+        }
+        let Some(baseline) = runs.last().cloned() else {
+            log::warn!("Corpus entry {corpus_id} errored on every execution, not diagnosing it");
+            state
+                .current_testcase_mut()?
+                .add_metadata(NondeterminismTestcaseMetadata::default());
+            return Ok(());
+        };
+        (meta.flaky, meta.state_dependent) = classify(&runs);
+
+        if let Some(fresh) = run_and_observe::<C, EM, I, O, S, _, Z>(
+            fuzzer,
+            &mut self.fresh_executor,
+            state,
+            mgr,
+            &input,
+            &self.map_observer_handle,
+        )? {
+            let before = meta.state_dependent.len();
+            push_differences(&baseline, &fresh, &meta.flaky, &mut meta.state_dependent);
+            meta.fresh_process_differs = meta.state_dependent.len() > before;
+        }
+
+        let count = state.corpus().count();
+        for _ in 0..self.orderings.min(count.saturating_sub(1)) {
+            let nth = state.rand_mut().below_or_zero(count);
+            let predecessor_id = state.corpus().nth(nth);
+            if predecessor_id == corpus_id {
+                continue;
+            }
+            let predecessor = state.corpus().cloned_input_for_id(predecessor_id)?;
+            run_and_observe::<C, EM, I, O, S, _, Z>(
+                fuzzer,
+                executor,
+                state,
+                mgr,
+                &predecessor,
+                &self.map_observer_handle,
+            )?;
+            if let Some(map) = run_and_observe::<C, EM, I, O, S, _, Z>(
+                fuzzer,
+                executor,
+                state,
+                mgr,
+                &input,
+                &self.map_observer_handle,
+            )? {
+                let before = meta.state_dependent.len();
+                push_differences(&baseline, &map, &meta.flaky, &mut meta.state_dependent);
+                if meta.state_dependent.len() > before
+                    && !meta.predecessors.contains(&predecessor_id)
+                {
+                    meta.predecessors.push(predecessor_id);
+                }
+            }
+        }
+
+        let global = state.metadata_or_insert_with(NondeterminismMetadata::new);
+        for idx in &meta.flaky {
+            global.insert(*idx, Instability::Flaky);
+        }
+        for idx in &meta.state_dependent {
+            global.insert(*idx, Instability::StateDependent);
+        }
+        if meta.is_state_dependent() {
+            global.state_dependent_inputs.push(corpus_id);
+        }
+
+        if self.mask {
+            mask_entries::<O, S>(state, &self.map_name, &meta)?;
+        }
+
+        if !meta.flaky.is_empty() || meta.is_state_dependent() {
+            log::info!(
+                "Corpus entry {corpus_id}: {} flaky, {} state dependent map entries",
+                meta.flaky.len(),
+                meta.state_dependent.len()
+            );
+        }
+        #[cfg(feature = "std")]
+        self.report(corpus_id, &meta)?;
+
+        state.current_testcase_mut()?.add_metadata(meta);
+        Ok(())
+    }
+}
+
+impl<C, E, FE, I, O, OT, S> Restartable<S> for NondeterminismStage<C, E, FE, I, O, OT, S>
+where
+    S: HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        // Replaying the other corpus entries may crash the target again, don't retry
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+impl<C, E, FE, I, O, OT, S> NondeterminismStage<C, E, FE, I, O, OT, S>
+where
+    C: AsRef<O>,
+    O: MapObserver,
+{
+    /// Create a new [`NondeterminismStage`] for the map of `map_feedback`.
+    /// The `fresh_executor` runs each input in a fresh process, for example a non-persistent
+    /// [`crate::executors::ForkserverExecutor`], and must observe the same map under the same name.
+    #[must_use]
+    pub fn new<F>(map_feedback: &F, fresh_executor: FE) -> Self
+    where
+        F: HasObserverHandle<Observer = C> + Named,
+    {
+        let map_name = map_feedback.name().clone();
+        Self {
+            map_observer_handle: map_feedback.observer_handle().clone(),
+            map_name: map_name.clone(),
+            name: Cow::Owned(
+                NONDETERMINISM_STAGE_NAME.to_owned() + ":" + map_name.into_owned().as_str(),
+            ),
+            fresh_executor,
+            repeats: DEFAULT_REPEATS,
+            orderings: DEFAULT_ORDERINGS,
+            mask: false,
+            symbolizer: None,
+            #[cfg(feature = "std")]
+            report_path: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the number of back-to-back runs of each corpus entry, at least 3
+    #[must_use]
+    pub fn with_repeats(mut self, repeats: usize) -> Self {
+        self.repeats = repeats.max(3);
+        self
+    }
+
+    /// Sets the number of random corpus entries to run before each corpus entry
+    #[must_use]
+    pub fn with_orderings(mut self, orderings: usize) -> Self {
+        self.orderings = orderings;
+        self
+    }
+
+    /// Masks the unstable map entries from the `MapFeedback`, so they are never novel again
+    #[must_use]
+    pub fn with_masking(mut self) -> Self {
+        self.mask = true;
+        self
+    }
+
+    /// Resolves map indices to source locations in the report, e.g. with the
+    /// `sanitizer_cov_symbolize_edge` function of `libafl_targets`
+    #[must_use]
+    pub fn with_symbolizer(mut self, symbolizer: fn(usize) -> Option<String>) -> Self {
+        self.symbolizer = Some(symbolizer);
+        self
+    }
+
+    /// Appends a report of the unstable map entries of each corpus entry to the file at `path`
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn with_report<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.report_path = Some(path.into());
+        self
+    }
+
+    /// The executor used for runs in a fresh process
+    pub fn fresh_executor(&self) -> &FE {
+        &self.fresh_executor
+    }
+
+    /// Appends the findings for one corpus entry to the report
+    #[cfg(feature = "std")]
+    fn report(
+        &self,
+        corpus_id: CorpusId,
+        meta: &NondeterminismTestcaseMetadata,
+    ) -> Result<(), Error> {
+        let Some(path) = &self.report_path else {
+            return Ok(());
+        };
+        if meta.flaky.is_empty() && !meta.is_state_dependent() {
+            return Ok(());
+        }
+
+        // Write the whole section at once, reports of several clients may share the file
+        let mut report = Vec::new();
+        writeln!(report, "corpus entry {corpus_id}:")?;
+        if meta.fresh_process_differs {
+            writeln!(report, "  behaves differently in a fresh process")?;
+        }
+        if !meta.predecessors.is_empty() {
+            let predecessors: Vec<String> =
+                meta.predecessors.iter().map(ToString::to_string).collect();
+            writeln!(
+                report,
+                "  behaves differently after corpus entries {}",
+                predecessors.join(", ")
+            )?;
+        }
+        for (kind, entries) in [
+            ("flaky", &meta.flaky),
+            ("state dependent", &meta.state_dependent),
+        ] {
+            for idx in entries {
+                write!(report, "  {kind} map entry {idx}")?;
+                if let Some(location) = self.symbolizer.and_then(|symbolize| symbolize(*idx)) {
+                    write!(report, " at {location}")?;
+                }
+                writeln!(report)?;
+            }
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(&report)?;
+        Ok(())
+    }
+}
+
+impl<C, E, FE, I, O, OT, S> Named for NondeterminismStage<C, E, FE, I, O, OT, S> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use libafl_bolts::{
+        AsSliceMut,
+        rands::StdRand,
+        tuples::{RefIndexable, tuple_list, tuple_list_type},
+    };
+
+    use super::{
+        NondeterminismMetadata, NondeterminismStage, NondeterminismTestcaseMetadata, classify,
+    };
+    use crate::{
+        Error, HasMetadata, HasNamedMetadata,
+        corpus::{Corpus, HasCurrentCorpusId, InMemoryCorpus, Testcase},
+        events::NopEventManager,
+        executors::{Executor, ExitKind, HasObservers},
+        feedbacks::{MaxMapFeedback, map::MapFeedbackMetadata},
+        fuzzer::NopFuzzer,
+        inputs::BytesInput,
+        observers::StdMapObserver,
+        stages::Stage,
+        state::{HasCurrentTestcase, HasExecutions, StdState},
+    };
+
+    #[test]
+    fn test_classify() {
+        // Entry 1 is flaky, entry 2 differs only after another execution
+        let runs = vec![
+            vec![1_u8, 0, 5, 1],
+            vec![1, 1, 3, 1],
+            vec![1, 0, 3, 1],
+            vec![1, 1, 3, 1],
+        ];
+        let (flaky, state_dependent) = classify(&runs);
+        assert_eq!(flaky, vec![1]);
+        assert_eq!(state_dependent, vec![2]);
+
+        let (flaky, state_dependent) = classify(&runs[..1]);
+        assert!(flaky.is_empty() && state_dependent.is_empty());
+    }
+
+    type TestObservers = tuple_list_type!(StdMapObserver<'static, u8, false>);
+
+    /// Covers entry 0 on every run, entry 1 on every other run, entry 2 after another input, and
+    /// entry 3 in a fresh process
+    struct UnstableExecutor {
+        observers: TestObservers,
+        fresh: bool,
+        runs: usize,
+        previous: Option<BytesInput>,
+    }
+
+    impl UnstableExecutor {
+        fn new(fresh: bool) -> Self {
+            Self {
+                observers: tuple_list!(StdMapObserver::owned("map", vec![0; 4])),
+                fresh,
+                runs: 0,
+                previous: None,
+            }
+        }
+    }
+
+    impl HasObservers for UnstableExecutor {
+        type Observers = TestObservers;
+
+        fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+            RefIndexable::from(&self.observers)
+        }
+
+        fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+            RefIndexable::from(&mut self.observers)
+        }
+    }
+
+    impl<EM, S, Z> Executor<EM, BytesInput, S, Z> for UnstableExecutor
+    where
+        S: HasExecutions,
+    {
+        fn run_target(
+            &mut self,
+            _fuzzer: &mut Z,
+            state: &mut S,
+            _mgr: &mut EM,
+            input: &BytesInput,
+        ) -> Result<ExitKind, Error> {
+            *state.executions_mut() += 1;
+            let after_other = self
+                .previous
+                .as_ref()
+                .is_some_and(|previous| previous != input);
+            let map = self.observers.0.as_slice_mut();
+            map[0] = 1;
+            map[1] = u8::from(self.runs % 2 == 0);
+            map[2] = u8::from(after_other);
+            map[3] = u8::from(self.fresh);
+            self.runs += 1;
+            if !self.fresh {
+                self.previous = Some(input.clone());
+            }
+            Ok(ExitKind::Ok)
+        }
+    }
+
+    #[test]
+    fn test_nondeterminism_stage() {
+        let observer = StdMapObserver::owned("map", vec![0_u8; 4]);
+        let mut feedback = MaxMapFeedback::new(&observer);
+        let mut objective = ();
+
+        let mut corpus = InMemoryCorpus::new();
+        let id = corpus.add(Testcase::new(BytesInput::new(vec![0]))).unwrap();
+        let mut others = Vec::new();
+        for i in 1..4 {
+            others.push(corpus.add(Testcase::new(BytesInput::new(vec![i]))).unwrap());
+        }
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            corpus,
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        state.set_corpus_id(id).unwrap();
+
+        let mut stage = NondeterminismStage::new(&feedback, UnstableExecutor::new(true))
+            .with_orderings(8)
+            .with_masking();
+        let mut executor = UnstableExecutor::new(false);
+        let mut fuzzer = NopFuzzer::new();
+        let mut mgr = NopEventManager::new();
+
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+
+        let meta = state
+            .current_testcase()
+            .unwrap()
+            .metadata::<NondeterminismTestcaseMetadata>()
+            .unwrap()
+            .clone();
+        assert_eq!(meta.flaky(), [1]);
+        assert!(meta.fresh_process_differs());
+        let mut state_dependent = meta.state_dependent().to_vec();
+        state_dependent.sort_unstable();
+        assert_eq!(state_dependent, [2, 3]);
+        assert!(!meta.predecessors().is_empty());
+        assert!(meta.predecessors().iter().all(|id| others.contains(id)));
+
+        let global = state.metadata::<NondeterminismMetadata>().unwrap();
+        assert_eq!(global.entries().len(), 3);
+        assert_eq!(global.state_dependent_inputs(), [id]);
+
+        // The unstable entries are masked from the feedback
+        let history = &state
+            .named_metadata::<MapFeedbackMetadata<u8>>("map")
+            .unwrap()
+            .history_map;
+        assert_eq!(history[1..], [u8::MAX; 3]);
+        assert_ne!(history[0], u8::MAX);
+
+        // Each corpus entry is diagnosed once
+        let executions = *state.executions();
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(*state.executions(), executions);
+    }
+}
@@ -0,0 +1,308 @@
+//! A stage inferring the coarse structure of an input.
+//!
+//! The [`StructureIdentificationStage`] classifies the current testcase as text or binary, finds
+//! field boundaries from an effector map, and collects chunks occurring more than once.
+//! The result is stored as [`InputStructureMetadata`] in the testcase, which is used by the
+//! [`crate::mutators::StructureAwareScheduledMutator`].
+//!
+//! The effector map is built afl-style, by flipping each byte (or block of bytes, for long inputs)
+//! and hashing the resulting coverage map. Neighbouring bytes with the same effect on the coverage
+//! are considered to belong to the same field.
+
+use alloc::{
+    borrow::{Cow, ToOwned},
+    vec::Vec,
+};
+use core::{fmt::Debug, hash::Hash, marker::PhantomData, ops::Range};
+
+use hashbrown::HashMap;
+use libafl_bolts::{
+    Named,
+    tuples::{Handle, Handled},
+};
+
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::HasCurrentCorpusId,
+    executors::{Executor, HasObservers},
+    inputs::HasMutatorBytes,
+    mutators::structure::{InputKind, InputStructureMetadata},
+    observers::ObserversTuple,
+    stages::{Restartable, RetryCountRestartHelper, Stage, colorization::get_raw_map_hash_run},
+    state::{HasCorpus, HasCurrentTestcase},
+};
+
+/// Default name for the [`StructureIdentificationStage`]
+pub const STRUCTURE_IDENTIFICATION_STAGE_NAME: &str = "structure_identification";
+
+/// The default maximum number of target runs to build the effector map of one testcase
+pub const DEFAULT_MAX_EFFECTOR_RUNS: usize = 1024;
+
+/// The minimum length of a repeated chunk
+const MIN_CHUNK_LEN: usize = 4;
+
+/// The maximum number of repeated chunks stored per testcase
+const MAX_CHUNKS: usize = 64;
+
+/// Inputs with at least this share of printable bytes, in percent, are considered text
+const TEXT_THRESHOLD_PERCENT: usize = 90;
+
+/// `true` if (most of) the input consists of printable characters
+pub(crate) fn is_text(bytes: &[u8]) -> bool {
+    if bytes.is_empty() {
+        return false;
+    }
+    let printable = bytes
+        .iter()
+        .filter(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+        .count();
+    printable * 100 >= bytes.len() * TEXT_THRESHOLD_PERCENT
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Word,
+    Space,
+    Punct,
+    Other,
+}
+
+impl CharClass {
+    fn of(byte: u8) -> Self {
+        if byte.is_ascii_alphanumeric() || byte == b'_' || byte >= 0x80 {
+            Self::Word
+        } else if byte.is_ascii_whitespace() {
+            Self::Space
+        } else if byte.is_ascii_punctuation() {
+            Self::Punct
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// Boundaries of a text input: words, runs of whitespace, and every punctuation character
+pub(crate) fn text_boundaries(bytes: &[u8]) -> Vec<usize> {
+    (1..bytes.len())
+        .filter(|i| {
+            let (prev, cur) = (CharClass::of(bytes[i - 1]), CharClass::of(bytes[*i]));
+            prev != cur || cur == CharClass::Punct
+        })
+        .collect()
+}
+
+/// Boundaries between blocks of `block_len` bytes with a different effect on the coverage
+pub(crate) fn effector_boundaries(effects: &[usize], block_len: usize) -> Vec<usize> {
+    (1..effects.len())
+        .filter(|i| effects[i - 1] != effects[*i])
+        .map(|i| i * block_len)
+        .collect()
+}
+
+/// Chunks of at least [`MIN_CHUNK_LEN`] bytes that occur more than once, without overlap.
+/// Runs of a single repeated byte, such as padding, are ignored.
+pub(crate) fn repeated_chunks(bytes: &[u8]) -> Vec<Range<usize>> {
+    let mut seen = HashMap::new();
+    let mut chunks: Vec<Range<usize>> = Vec::new();
+
+    let mut i = 0;
+    while i + MIN_CHUNK_LEN <= bytes.len() && chunks.len() < MAX_CHUNKS {
+        let gram = &bytes[i..i + MIN_CHUNK_LEN];
+        if gram.iter().all(|b| *b == gram[0]) {
+            i += 1;
+            continue;
+        }
+        match seen.get(gram).copied() {
+            Some(first) if first + MIN_CHUNK_LEN <= i => {
+                let mut len = MIN_CHUNK_LEN;
+                while i + len < bytes.len()
+                    && first + len < i
+                    && bytes[first + len] == bytes[i + len]
+                {
+                    len += 1;
+                }
+                if !chunks.iter().any(|c| c.start == first) {
+                    chunks.push(first..first + len);
+                }
+                chunks.push(i..i + len);
+                i += len;
+                continue;
+            }
+            Some(_) => {}
+            None => {
+                seen.insert(gram, i);
+            }
+        }
+        i += 1;
+    }
+
+    chunks.truncate(MAX_CHUNKS);
+    chunks
+}
+
+/// A stage inferring the coarse structure of the current testcase, storing it as
+/// [`InputStructureMetadata`].
+///
+/// Each testcase is only analyzed once. The effector map costs up to `max_effector_runs` runs of
+/// the target per testcase.
+#[derive(Clone, Debug)]
+pub struct StructureIdentificationStage<C, E, EM, I, O, S, Z> {
+    map_observer_handle: Handle<C>,
+    name: Cow<'static, str>,
+    max_effector_runs: usize,
+    phantom: PhantomData<(E, EM, I, O, S, Z)>,
+}
+
+impl<C, E, EM, I, O, S, Z> Named for StructureIdentificationStage<C, E, EM, I, O, S, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<C, E, EM, I, O, S, Z> Stage<E, EM, S, Z> for StructureIdentificationStage<C, E, EM, I, O, S, Z>
+where
+    E: HasObservers + Executor<EM, I, S, Z>,
+    E::Observers: ObserversTuple<I, S>,
+    S: HasCorpus<I> + HasCurrentTestcase<I> + HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+    I: HasMutatorBytes + Clone,
+    O: Hash,
+    C: AsRef<O> + Named,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        if state
+            .current_testcase()?
+            .has_metadata::<InputStructureMetadata>()
+        {
+            return Ok(());
+        }
+
+        let input = state.current_input_cloned()?;
+        let bytes = input.mutator_bytes().to_vec();
+
+        let kind = if is_text(&bytes) {
+            InputKind::Text
+        } else {
+            InputKind::Binary
+        };
+        let mut boundaries = match kind {
+            InputKind::Text => text_boundaries(&bytes),
+            InputKind::Binary => Vec::new(),
+        };
+
+        if !bytes.is_empty() {
+            let block_len = bytes.len().div_ceil(self.max_effector_runs.max(1));
+            let mut effects = Vec::with_capacity(bytes.len().div_ceil(block_len));
+            for start in (0..bytes.len()).step_by(block_len) {
+                let mut flipped = input.clone();
+                let end = (start + block_len).min(bytes.len());
+                for byte in &mut flipped.mutator_bytes_mut()[start..end] {
+                    *byte ^= 0xff;
+                }
+                effects.push(get_raw_map_hash_run(
+                    fuzzer,
+                    executor,
+                    state,
+                    manager,
+                    &flipped,
+                    &self.map_observer_handle,
+                )?);
+            }
+            boundaries.extend(effector_boundaries(&effects, block_len));
+        }
+
+        let chunks = repeated_chunks(&bytes);
+        log::debug!(
+            "Identified {kind:?} input with {} boundaries and {} repeated chunks",
+            boundaries.len(),
+            chunks.len()
+        );
+        state
+            .current_testcase_mut()?
+            .add_metadata(InputStructureMetadata::new(kind, boundaries, chunks));
+
+        Ok(())
+    }
+}
+
+impl<C, E, EM, I, O, S, Z> Restartable<S> for StructureIdentificationStage<C, E, EM, I, O, S, Z>
+where
+    S: HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        // This is a deterministic stage, it would fail the same way again
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress::<S>(state, &self.name)
+    }
+}
+
+impl<C, E, EM, I, O, S, Z> StructureIdentificationStage<C, E, EM, I, O, S, Z>
+where
+    E: HasObservers + Executor<EM, I, S, Z>,
+    E::Observers: ObserversTuple<I, S>,
+    O: Hash,
+    C: AsRef<O> + Named,
+{
+    /// Creates a new [`StructureIdentificationStage`], building effector maps from `map_observer`
+    #[must_use]
+    pub fn new(map_observer: &C) -> Self {
+        let obs_name = map_observer.name().clone().into_owned();
+        Self {
+            map_observer_handle: map_observer.handle(),
+            name: Cow::Owned(
+                STRUCTURE_IDENTIFICATION_STAGE_NAME.to_owned() + ":" + obs_name.as_str(),
+            ),
+            max_effector_runs: DEFAULT_MAX_EFFECTOR_RUNS,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of target runs for the effector map of one testcase.
+    /// Longer inputs are flipped in blocks, so their boundaries are only found at block granularity.
+    #[must_use]
+    pub fn with_max_effector_runs(mut self, max_effector_runs: usize) -> Self {
+        self.max_effector_runs = max_effector_runs;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::{effector_boundaries, is_text, repeated_chunks, text_boundaries};
+
+    #[test]
+    fn test_is_text() {
+        assert!(is_text(b"GET /index.html HTTP/1.1\r\n"));
+        assert!(!is_text(b"\x7fELF\x02\x01\x01\x00\x00\x00"));
+        assert!(!is_text(b""));
+    }
+
+    #[test]
+    fn test_text_boundaries() {
+        assert_eq!(text_boundaries(b"key=value;"), vec![3, 4, 9]);
+        assert_eq!(text_boundaries(b"a  b"), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_effector_boundaries() {
+        assert_eq!(effector_boundaries(&[1, 1, 2, 2, 2, 3], 1), vec![2, 5]);
+        assert_eq!(effector_boundaries(&[1, 2], 8), vec![8]);
+    }
+
+    #[test]
+    fn test_repeated_chunks() {
+        assert_eq!(repeated_chunks(b"abcdefXXabcdeg"), vec![0..5, 8..13]);
+        assert!(repeated_chunks(b"\0\0\0\0\0\0\0\0\0\0").is_empty());
+        assert!(repeated_chunks(b"abcdefgh").is_empty());
+    }
+}
@@ -1,12 +1,15 @@
-//! The [`SyncFromDiskStage`] is a stage that imports inputs from disk for e.g. sync with AFL
+//! The [`SyncFromDiskStage`] is a stage that imports inputs from disk for e.g. sync with AFL.
+//! The [`SharedCorpusSyncStage`] syncs the corpora of fuzzing threads in the same process.
 
 use alloc::{
     borrow::{Cow, ToOwned},
+    format,
     vec::Vec,
 };
 use core::{marker::PhantomData, time::Duration};
 use std::path::{Path, PathBuf};
 
+use hashbrown::HashSet;
 use libafl_bolts::{
     Named, current_time,
     fs::find_new_files_rec,
@@ -16,7 +19,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     Error, HasMetadata, HasNamedMetadata,
-    corpus::{Corpus, CorpusId, HasCurrentCorpusId},
+    corpus::{Corpus, CorpusId, HasCurrentCorpusId, SharedCorpus},
     events::{Event, EventConfig, EventFirer, llmp::LlmpEventConverter},
     executors::{Executor, ExitKind, HasObservers},
     fuzzer::{Evaluator, EvaluatorObservers, ExecutionProcessor, HasObjective},
@@ -328,3 +331,166 @@ impl<I, IC, ICB, S, SHM, SP> SyncFromBrokerStage<I, IC, ICB, S, SHM, SP> {
         Self { client }
     }
 }
+
+/// Default name for [`SharedCorpusSyncStage`]
+pub const SHARED_CORPUS_SYNC_STAGE_NAME: &str = "shared_corpus_sync";
+
+/// A stage syncing the corpus of this fuzzing thread with the other threads of the process,
+/// through a [`SharedCorpus`]. New local corpus entries are published, the entries of the other
+/// threads are evaluated, and added to the local corpus if interesting.
+#[derive(Debug)]
+pub struct SharedCorpusSyncStage<I> {
+    name: Cow<'static, str>,
+    thread_id: usize,
+    shared: SharedCorpus<I>,
+    /// The position in the shared corpus up to which we imported
+    cursor: usize,
+    /// The last local corpus entry we published
+    last_published: Option<CorpusId>,
+    /// The local corpus entries imported from other threads, never published again
+    imported: HashSet<CorpusId>,
+}
+
+impl<I> Named for SharedCorpusSyncStage<I> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for SharedCorpusSyncStage<I>
+where
+    I: Clone,
+    S: HasCorpus<I>,
+    Z: Evaluator<E, EM, I, S>,
+{
+    #[inline]
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let mut next = match self.last_published {
+            Some(id) => state.corpus().next(id),
+            None => state.corpus().first(),
+        };
+        while let Some(id) = next {
+            if !self.imported.contains(&id) {
+                let input = state.corpus().cloned_input_for_id(id)?;
+                self.shared.publish(self.thread_id, input)?;
+            }
+            self.last_published = Some(id);
+            next = state.corpus().next(id);
+        }
+
+        let (inputs, cursor) = self.shared.fetch_since(self.thread_id, self.cursor)?;
+        self.cursor = cursor;
+        for input in inputs {
+            if let (_, Some(id)) = fuzzer.evaluate_input(state, executor, manager, &input)? {
+                self.imported.insert(id);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I, S> Restartable<S> for SharedCorpusSyncStage<I>
+where
+    S: HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+{
+    #[inline]
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        // An imported testcase may crash, don't get stuck on it
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+impl<I> SharedCorpusSyncStage<I> {
+    /// Creates a new [`SharedCorpusSyncStage`] for the fuzzing thread `thread_id`
+    #[must_use]
+    pub fn new(thread_id: usize, shared: SharedCorpus<I>) -> Self {
+        Self {
+            name: Cow::Owned(format!("{SHARED_CORPUS_SYNC_STAGE_NAME}:{thread_id}")),
+            thread_id,
+            shared,
+            cursor: 0,
+            last_published: None,
+            imported: HashSet::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::rands::StdRand;
+
+    use super::SharedCorpusSyncStage;
+    use crate::{
+        StdFuzzer,
+        corpus::{Corpus, InMemoryCorpus, SharedCorpus, Testcase},
+        events::NopEventManager,
+        executors::{WithObservers, test::NopExecutor},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        schedulers::QueueScheduler,
+        stages::Stage,
+        state::{HasCorpus, StdState},
+    };
+
+    #[test]
+    fn test_shared_corpus_sync() {
+        let shared = SharedCorpus::new();
+        let mut feedback = ConstFeedback::new(true);
+        let mut objective = ConstFeedback::new(false);
+        let mut states = [0_u8, 1].map(|_| {
+            StdState::new(
+                StdRand::with_seed(0),
+                InMemoryCorpus::<BytesInput>::new(),
+                InMemoryCorpus::new(),
+                &mut feedback,
+                &mut objective,
+            )
+            .unwrap()
+        });
+        let mut stages =
+            [0, 1].map(|thread_id| SharedCorpusSyncStage::new(thread_id, shared.clone()));
+        let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+        let mut executor = WithObservers::new(NopExecutor::new(), ());
+        let mut mgr = NopEventManager::new();
+
+        let mut sync = |states: &mut [StdState<_, _, _, _>; 2], thread_id: usize| {
+            stages[thread_id]
+                .perform(&mut fuzzer, &mut executor, &mut states[thread_id], &mut mgr)
+                .unwrap();
+        };
+
+        states[0]
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![1])))
+            .unwrap();
+        sync(&mut states, 0);
+        sync(&mut states, 1);
+        assert_eq!(states[1].corpus().count(), 1);
+        let id = states[1].corpus().first().unwrap();
+        assert_eq!(
+            states[1].corpus().cloned_input_for_id(id).unwrap(),
+            BytesInput::new(vec![1])
+        );
+
+        // The imported testcase is not published back, only the new one of the second thread
+        states[1]
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![2])))
+            .unwrap();
+        sync(&mut states, 1);
+        sync(&mut states, 0);
+        assert_eq!(states[0].corpus().count(), 2);
+        assert_eq!(shared.fetch_since(2, 0).unwrap().0.len(), 2);
+    }
+}
@@ -144,6 +144,10 @@ bool CmpLogInstructions::hookInstrs(Module &M) {
   FunctionCallee cmplogHookIns16;
   FunctionCallee cmplogHookInsN;
 #endif
+  // float and double compares get their own hooks, unless the extended
+  // (AFL++) map is used, which marks them with an attribute instead
+  FunctionCallee cmplogHookFp4;
+  FunctionCallee cmplogHookFp8;
   if (CmplogExtended) {
     cmplogHookIns1 = M.getOrInsertFunction("__cmplog_ins_hook1_extended",
                                            VoidTy, Int8Ty, Int8Ty, Int8Ty);
@@ -194,6 +198,13 @@ bool CmpLogInstructions::hookInstrs(Module &M) {
   }
 #endif
 
+  if (!CmplogExtended) {
+    cmplogHookFp4 =
+        M.getOrInsertFunction("__cmplog_ins_hook_fp4", VoidTy, Int32Ty, Int32Ty);
+    cmplogHookFp8 =
+        M.getOrInsertFunction("__cmplog_ins_hook_fp8", VoidTy, Int64Ty, Int64Ty);
+  }
+
   Constant *Null = Constant::getNullValue(PointerType::get(Int8Ty, 0));
 
   /* iterate over all functions, bbs and instruction and add suitable calls */
@@ -479,30 +490,39 @@ bool CmpLogInstructions::hookInstrs(Module &M) {
           // fprintf(stderr, "_ExtInt(%u) castTo %u with attr %u didcast %u\n",
           //         max_size, cast_size, attr);
 
-          switch (cast_size) {
-            case 8:
-              IRB.CreateCall(cmplogHookIns1, args);
-              break;
-            case 16:
-              IRB.CreateCall(cmplogHookIns2, args);
-              break;
-            case 32:
-              IRB.CreateCall(cmplogHookIns4, args);
-              break;
-            case 64:
-              IRB.CreateCall(cmplogHookIns8, args);
-              break;
+          if (is_fp && !CmplogExtended && (max_size == 32 || max_size == 64)) {
+            // log the raw bits, so the values can be told apart from integers
+            if (max_size == 32) {
+              IRB.CreateCall(cmplogHookFp4, args);
+            } else {
+              IRB.CreateCall(cmplogHookFp8, args);
+            }
+          } else {
+            switch (cast_size) {
+              case 8:
+                IRB.CreateCall(cmplogHookIns1, args);
+                break;
+              case 16:
+                IRB.CreateCall(cmplogHookIns2, args);
+                break;
+              case 32:
+                IRB.CreateCall(cmplogHookIns4, args);
+                break;
+              case 64:
+                IRB.CreateCall(cmplogHookIns8, args);
+                break;
 #ifndef _WIN32
-            case 128:
-              if (max_size == 128) {
-                IRB.CreateCall(cmplogHookIns16, args);
+              case 128:
+                if (max_size == 128) {
+                  IRB.CreateCall(cmplogHookIns16, args);
 
-              } else {
-                IRB.CreateCall(cmplogHookInsN, args);
-              }
+                } else {
+                  IRB.CreateCall(cmplogHookInsN, args);
+                }
 
-              break;
+                break;
 #endif
+            }
           }
         }
 
@@ -111,6 +111,8 @@ char CmpLogRoutines::ID = 0;
 bool CmpLogRoutines::hookRtns(Module &M) {
   std::vector<CallInst *> calls, llvmStdStd, llvmStdC, gccStdStd, gccStdC,
       Memcmp, Strcmp, Strncmp;
+  // wide string compares, with the size of a character in bytes
+  std::vector<std::pair<CallInst *, unsigned>> WStrcmp, WStrncmp;
   LLVMContext &C = M.getContext();
 
   Type *VoidTy = Type::getVoidTy(C);
@@ -128,6 +130,15 @@ bool CmpLogRoutines::hookRtns(Module &M) {
   FunctionCallee cmplogHookFnN;
   FunctionCallee cmplogHookFnStrN;
   FunctionCallee cmplogHookFnStr;
+  FunctionCallee cmplogHookFnWStr;
+  FunctionCallee cmplogHookFnWStrN;
+
+  // the size of wchar_t is recorded by clang in the module flags
+  unsigned WcharSize = 4;
+  if (auto *WcharSizeFlag =
+          mdconst::extract_or_null<ConstantInt>(M.getModuleFlag("wchar_size"))) {
+    WcharSize = WcharSizeFlag->getZExtValue();
+  }
 
   if (CmplogExtended) {
     cmplogHookFn = M.getOrInsertFunction("__cmplog_rtn_hook_extended", VoidTy,
@@ -197,6 +208,14 @@ bool CmpLogRoutines::hookRtns(Module &M) {
                                             i8PtrTy, i8PtrTy);
   }
 
+  // wide strings have no counterpart in the extended (AFL++) map
+  if (!CmplogExtended) {
+    cmplogHookFnWStr = M.getOrInsertFunction("__cmplog_rtn_hook_wstr", VoidTy,
+                                             i8PtrTy, i8PtrTy, Int8Ty);
+    cmplogHookFnWStrN = M.getOrInsertFunction(
+        "__cmplog_rtn_hook_wstrn", VoidTy, i8PtrTy, i8PtrTy, Int64Ty, Int8Ty);
+  }
+
   /* iterate over all functions, bbs and instruction and add suitable calls */
   for (auto &F : M) {
     if (isIgnoreFunction(&F)) { continue; }
@@ -293,6 +312,40 @@ bool CmpLogRoutines::hookRtns(Module &M) {
                   IntegerType::getInt8Ty(M.getContext())->getPointerTo(0) &&
               FT->getParamType(2)->isIntegerTy();
 
+          bool isWStrcmp =
+              (!FuncName.compare("wcscmp") || !FuncName.compare("wcscasecmp") ||
+               !FuncName.compare("wcscoll") || !FuncName.compare("wcsstr") ||
+               !FuncName.compare("_wcsicmp") || !FuncName.compare("lstrcmpW") ||
+               !FuncName.compare("lstrcmpiW") ||
+               !FuncName.compare("u_strcmp") ||
+               !FuncName.compare("u_strstr"));
+          isWStrcmp &= !CmplogExtended && FT->getNumParams() == 2 &&
+                       !FT->getReturnType()->isVoidTy() &&
+                       FT->getParamType(0) == FT->getParamType(1) &&
+                       FT->getParamType(0)->isPointerTy();
+
+          bool isWStrncmp =
+              (!FuncName.compare("wcsncmp") ||
+               !FuncName.compare("wcsncasecmp") ||
+               !FuncName.compare("_wcsnicmp") ||
+               !FuncName.compare("wmemcmp") ||
+               !FuncName.compare("u_strncmp") ||
+               !FuncName.compare("u_memcmp"));
+          isWStrncmp &= !CmplogExtended && FT->getNumParams() == 3 &&
+                        FT->getReturnType()->isIntegerTy(32) &&
+                        FT->getParamType(0) == FT->getParamType(1) &&
+                        FT->getParamType(0)->isPointerTy() &&
+                        FT->getParamType(2)->isIntegerTy();
+
+          // ICU's UChar and the Windows APIs are always UTF-16
+          unsigned WidthOfChar = WcharSize;
+          if (!FuncName.compare(0, 2, "u_") || !FuncName.compare("_wcsicmp") ||
+              !FuncName.compare("_wcsnicmp") ||
+              !FuncName.compare("lstrcmpW") ||
+              !FuncName.compare("lstrcmpiW")) {
+            WidthOfChar = 2;
+          }
+
           bool isGccStdStringStdString =
               Callee->getName().find("__is_charIT_EE7__value") !=
                   std::string::npos &&
@@ -341,7 +394,7 @@ bool CmpLogRoutines::hookRtns(Module &M) {
 
           if (isGccStdStringCString || isGccStdStringStdString ||
               isLlvmStdStringStdString || isLlvmStdStringCString || isMemcmp ||
-              isStrcmp || isStrncmp) {
+              isStrcmp || isStrncmp || isWStrcmp || isWStrncmp) {
             isPtrRtnN = isPtrRtn = false;
           }
 
@@ -351,6 +404,8 @@ bool CmpLogRoutines::hookRtns(Module &M) {
           if (isMemcmp || isPtrRtnN) { Memcmp.push_back(callInst); }
           if (isStrcmp) { Strcmp.push_back(callInst); }
           if (isStrncmp) { Strncmp.push_back(callInst); }
+          if (isWStrcmp) { WStrcmp.push_back({callInst, WidthOfChar}); }
+          if (isWStrncmp) { WStrncmp.push_back({callInst, WidthOfChar}); }
           if (isGccStdStringStdString) { gccStdStd.push_back(callInst); }
           if (isGccStdStringCString) { gccStdC.push_back(callInst); }
           if (isLlvmStdStringStdString) { llvmStdStd.push_back(callInst); }
@@ -362,7 +417,7 @@ bool CmpLogRoutines::hookRtns(Module &M) {
 
   if (!calls.size() && !gccStdStd.size() && !gccStdC.size() &&
       !llvmStdStd.size() && !llvmStdC.size() && !Memcmp.size() &&
-      Strcmp.size() && Strncmp.size())
+      Strcmp.size() && Strncmp.size() && !WStrcmp.size() && !WStrncmp.size())
     return false;
 
   for (auto &callInst : calls) {
@@ -443,6 +498,40 @@ bool CmpLogRoutines::hookRtns(Module &M) {
     // errs() << callInst->getCalledFunction()->getName() << "\n";
   }
 
+  for (auto &[callInst, width] : WStrcmp) {
+    Value *v1P = callInst->getArgOperand(0), *v2P = callInst->getArgOperand(1);
+
+    IRBuilder<> IRB(callInst->getParent());
+    IRB.SetInsertPoint(callInst);
+    std::vector<Value *> args;
+    Value               *v1Pcasted = IRB.CreatePointerCast(v1P, i8PtrTy);
+    Value               *v2Pcasted = IRB.CreatePointerCast(v2P, i8PtrTy);
+    args.push_back(v1Pcasted);
+    args.push_back(v2Pcasted);
+    args.push_back(ConstantInt::get(Int8Ty, width));
+
+    IRB.CreateCall(cmplogHookFnWStr, args);
+  }
+
+  for (auto &[callInst, width] : WStrncmp) {
+    Value *v1P = callInst->getArgOperand(0), *v2P = callInst->getArgOperand(1),
+          *v3P = callInst->getArgOperand(2);
+
+    IRBuilder<> IRB(callInst->getParent());
+    IRB.SetInsertPoint(callInst);
+    std::vector<Value *> args;
+    Value               *v1Pcasted = IRB.CreatePointerCast(v1P, i8PtrTy);
+    Value               *v2Pcasted = IRB.CreatePointerCast(v2P, i8PtrTy);
+    Value               *v3Pcasted =
+        IRB.CreateIntCast(v3P, IntegerType::get(C, 64), false);
+    args.push_back(v1Pcasted);
+    args.push_back(v2Pcasted);
+    args.push_back(v3Pcasted);
+    args.push_back(ConstantInt::get(Int8Ty, width));
+
+    IRB.CreateCall(cmplogHookFnWStrN, args);
+  }
+
   for (auto &callInst : gccStdStd) {
     Value *v1P = callInst->getArgOperand(0), *v2P = callInst->getArgOperand(1);
 
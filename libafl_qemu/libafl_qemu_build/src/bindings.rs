@@ -55,6 +55,7 @@ const WRAPPER_HEADER: &str = r#"
 
 #include "migration/vmstate.h"
 #include "migration/savevm.h"
+#include "migration/snapshot.h"
 #include "hw/core/sysemu-cpu-ops.h"
 #include "exec/address-spaces.h"
 #include "sysemu/tcg.h"
@@ -155,6 +156,12 @@ pub fn generate(
         .allowlist_type("Syx.*")
         .allowlist_type("libafl_mapinfo")
         .allowlist_type("IntervalTreeRoot")
+        .allowlist_type("MemoryRegionOps")
+        .allowlist_type("ReplayMode")
+        .allowlist_var("replay_mode")
+        .allowlist_var("CF_COUNT_MASK")
+        .allowlist_var("CF_NO_GOTO_TB")
+        .allowlist_var("CF_NO_GOTO_PTR")
         .allowlist_function("qemu_system_debug_request")
         .allowlist_function("target_mmap")
         .allowlist_function("target_mprotect")
@@ -170,6 +177,7 @@ pub fn generate(
         .allowlist_function("qemu_plugin_get_hwaddr")
         .allowlist_function("qemu_target_page_size")
         .allowlist_function("syx_.*")
+        .allowlist_function("delete_snapshot")
         .allowlist_function("device_list_all")
         .allowlist_function("libafl_.*")
         .allowlist_function("read_self_maps")
@@ -178,6 +186,10 @@ pub fn generate(
         .allowlist_function("vm_start")
         .allowlist_function("qemu_main_loop")
         .allowlist_function("qemu_cleanup")
+        .allowlist_function("get_system_memory")
+        .allowlist_function("memory_region_init_io")
+        .allowlist_function("memory_region_add_subregion_overlap")
+        .allowlist_function("replay_get_current_icount")
         .blocklist_function("main_loop_wait") // bindgen issue #1313
         .blocklist_type("siginfo_t")
         .raw_line("use libc::siginfo_t;")
@@ -192,6 +204,7 @@ pub fn generate(
         bindings
             .allowlist_type("ARMCPU")
             .allowlist_type("ARMv7MState")
+            .allowlist_function("armv7m_nvic_set_pending")
     } else if cpu_target == "riscv32" || cpu_target == "riscv64" {
         bindings
             .allowlist_type("RISCVCPU")
@@ -167,6 +167,9 @@ impl<T> ::core::fmt::Debug for __IncompleteArrayField<T> {
         fmt.write_str("__IncompleteArrayField")
     }
 }
+pub const CF_COUNT_MASK: u32 = 511;
+pub const CF_NO_GOTO_TB: u32 = 512;
+pub const CF_NO_GOTO_PTR: u32 = 1024;
 pub type __uint32_t = ::std::os::raw::c_uint;
 pub type __uid_t = ::std::os::raw::c_uint;
 pub type __off_t = ::std::os::raw::c_long;
@@ -16,7 +16,7 @@ use num_enum::TryFromPrimitive;
 use paste::paste;
 
 use crate::{
-    CPU, Emulator, EmulatorDriverError, EmulatorDriverResult, GuestReg, InputLocation,
+    CPU, Emulator, EmulatorDriverError, EmulatorDriverResult, GdbAttach, GuestReg, InputLocation,
     IsSnapshotManager, Qemu, QemuMemoryChunk, QemuRWError, Regs, StdEmulatorDriver,
     command::parser::{
         EndCommandParser, InputPhysCommandParser, InputVirtCommandParser, LoadCommandParser,
@@ -300,6 +300,8 @@ where
             .set_snapshot_id(snapshot_id)
             .map_err(|_| EmulatorDriverError::MultipleSnapshotDefinition)?;
 
+        emu.driver_mut().request_gdb(GdbAttach::Save);
+
         Ok(None)
     }
 }
@@ -336,6 +338,8 @@ where
         #[cfg(feature = "paranoid_debug")]
         emu.snapshot_manager_mut().check(qemu, &snapshot_id)?;
 
+        emu.driver_mut().request_gdb(GdbAttach::Load);
+
         Ok(None)
     }
 }
@@ -492,6 +496,10 @@ where
             ));
         }
 
+        if self.exit_kind == Some(ExitKind::Crash) {
+            emu.serve_gdb_on_crash();
+        }
+
         let snapshot_id = emu
             .driver_mut()
             .snapshot_id()
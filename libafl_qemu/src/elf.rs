@@ -53,20 +53,8 @@ impl<'a> EasyElf<'a> {
                 if sym_name == name {
                     return if sym.st_value == 0 {
                         None
-                    } else if self.is_pic() {
-                        #[cfg(cpu_target = "arm")]
-                        // Required because of arm interworking addresses aka bit(0) for thumb mode
-                        let addr = (sym.st_value as GuestAddr + load_addr) & !(0x1 as GuestAddr);
-                        #[cfg(not(cpu_target = "arm"))]
-                        let addr = sym.st_value as GuestAddr + load_addr;
-                        Some(addr)
                     } else {
-                        #[cfg(cpu_target = "arm")]
-                        // Required because of arm interworking addresses aka bit(0) for thumb mode
-                        let addr = (sym.st_value as GuestAddr) & !(0x1 as GuestAddr);
-                        #[cfg(not(cpu_target = "arm"))]
-                        let addr = sym.st_value as GuestAddr;
-                        Some(addr)
+                        Some(self.symbol_addr(sym.st_value, load_addr))
                     };
                 }
             }
@@ -74,6 +62,33 @@ impl<'a> EasyElf<'a> {
         None
     }
 
+    /// Resolves a symbol exported by the dynamic symbol table, the only one left in stripped
+    /// shared libraries
+    #[must_use]
+    pub fn resolve_export(&self, name: &str, load_addr: GuestAddr) -> Option<GuestAddr> {
+        self.elf
+            .dynsyms
+            .iter()
+            // The imported symbols are undefined
+            .filter(|sym| sym.st_value != 0 && sym.st_shndx != 0)
+            .find(|sym| self.elf.dynstrtab.get_at(sym.st_name) == Some(name))
+            .map(|sym| self.symbol_addr(sym.st_value, load_addr))
+    }
+
+    fn symbol_addr(&self, value: u64, load_addr: GuestAddr) -> GuestAddr {
+        let addr = if self.is_pic() {
+            value as GuestAddr + load_addr
+        } else {
+            value as GuestAddr
+        };
+
+        #[cfg(cpu_target = "arm")]
+        // Required because of arm interworking addresses aka bit(0) for thumb mode
+        return addr & !(0x1 as GuestAddr);
+        #[cfg(not(cpu_target = "arm"))]
+        return addr;
+    }
+
     #[must_use]
     pub fn get_section(&self, name: &str, load_addr: GuestAddr) -> Option<Range<GuestAddr>> {
         for section in &self.elf.section_headers {
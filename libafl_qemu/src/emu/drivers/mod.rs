@@ -8,8 +8,9 @@ use libafl_bolts::os::{CTRL_C_EXIT, unix_signals::Signal};
 use typed_builder::TypedBuilder;
 
 use crate::{
-    Emulator, EmulatorExitError, EmulatorExitResult, InputLocation, IsSnapshotManager, QemuError,
-    QemuShutdownCause, Regs, SnapshotId, SnapshotManagerCheckError, SnapshotManagerError,
+    Emulator, EmulatorExitError, EmulatorExitResult, GdbAttach, GdbStub, InputLocation,
+    IsSnapshotManager, QemuError, QemuShutdownCause, Regs, SnapshotId, SnapshotManagerCheckError,
+    SnapshotManagerError,
     command::{CommandError, CommandManager, InputCommand, IsCommand},
     modules::EmulatorModuleTuple,
 };
@@ -139,6 +140,11 @@ pub struct StdEmulatorDriver {
     process_only: bool,
     #[builder(default = false)]
     print_commands: bool,
+    /// The GDB stub attaching at the points it was configured for
+    #[builder(default, setter(strip_option))]
+    gdb_stub: Option<GdbStub>,
+    #[builder(default, setter(skip))]
+    gdb_request: Option<GdbAttach>,
 }
 
 impl StdEmulatorDriver {
@@ -170,6 +176,46 @@ impl StdEmulatorDriver {
     pub fn is_process_only(&self) -> bool {
         self.process_only
     }
+
+    /// The GDB stub of this driver, if one was set in its builder
+    #[must_use]
+    pub fn gdb_stub(&self) -> Option<&GdbStub> {
+        self.gdb_stub.as_ref()
+    }
+
+    /// Asks for the GDB stub to attach once the current command has run, if it attaches on
+    /// `attach`
+    pub fn request_gdb(&mut self, attach: GdbAttach) {
+        if self
+            .gdb_stub
+            .as_ref()
+            .is_some_and(|stub| stub.attaches_on(attach))
+        {
+            self.gdb_request = Some(attach);
+        }
+    }
+}
+
+impl<C, CM, ET, I, S, SM> Emulator<C, CM, StdEmulatorDriver, ET, I, S, SM>
+where
+    SM: IsSnapshotManager,
+{
+    /// Hands the guest over to the GDB stub of the driver on a crash, if it attaches on crashes.
+    /// The run is over, so the guest is not resumed after the session. The crashes of usermode
+    /// targets are handled by `GdbStub::serve_crash` instead.
+    pub fn serve_gdb_on_crash(&mut self) {
+        let Some(stub) = self.driver.gdb_stub.clone() else {
+            return;
+        };
+        if !stub.attaches_on(GdbAttach::Crash) {
+            return;
+        }
+        match self.serve_gdb(&stub, GdbAttach::Crash) {
+            Ok(Some(exit)) => log::warn!("The crashed guest was resumed until {exit:?}"),
+            Ok(None) => {}
+            Err(err) => log::error!("The gdb session failed: {err}"),
+        }
+    }
 }
 
 // TODO: replace handlers with generics to permit compile-time customization of handlers
@@ -183,6 +229,18 @@ where
     SM: IsSnapshotManager,
 {
     fn first_harness_exec(emulator: &mut Emulator<C, CM, Self, ET, I, S, SM>, state: &mut S) {
+        // Crashes are signals handled by QEMU in usermode, the stub attaches from the crash hooks
+        #[cfg(feature = "usermode")]
+        if let Some(stub) = emulator.driver.gdb_stub.clone()
+            && stub.attaches_on(GdbAttach::Crash)
+        {
+            unsafe {
+                emulator.modules_mut().crash_closure(Box::new(
+                    move |qemu, _emulator_modules, target_sig| stub.serve_crash(qemu, target_sig),
+                ));
+            }
+        }
+
         if !emulator.driver.hooks_locked {
             emulator.modules.first_exec_all(emulator.qemu, state);
         }
@@ -257,6 +315,7 @@ where
                     return Err(EmulatorDriverError::UnhandledSignal(*signal));
                 }
                 QemuShutdownCause::GuestPanic => {
+                    emulator.serve_gdb_on_crash();
                     return Ok(Some(EmulatorDriverResult::EndOfRun(ExitKind::Crash)));
                 }
                 QemuShutdownCause::GuestShutdown | QemuShutdownCause::HostQmpQuit => {
@@ -280,7 +339,24 @@ where
             if emulator.driver.print_commands {
                 println!("Received command: {cmd:?}");
             }
-            cmd.run(emulator, state, input, ret_reg)
+            let result = cmd.run(emulator, state, input, ret_reg)?;
+
+            // Hand the guest over to the debugger if the command asked for it
+            if result.is_none()
+                && let Some(attach) = emulator.driver.gdb_request.take()
+                && let Some(stub) = emulator.driver.gdb_stub.clone()
+            {
+                match emulator.serve_gdb(&stub, attach) {
+                    Ok(Some(exit)) => {
+                        let mut exit_reason = emulator.exit_result(exit);
+                        return Self::post_qemu_exec(emulator, state, &mut exit_reason, input);
+                    }
+                    Ok(None) => {}
+                    Err(err) => log::error!("The gdb session failed: {err}"),
+                }
+            }
+
+            Ok(result)
         } else {
             Ok(Some(EmulatorDriverResult::ReturnToHarness(
                 exit_reason.clone(),
@@ -0,0 +1,704 @@
+//! A GDB remote stub, to debug the guest in the exact state it is fuzzed in.
+//!
+//! Unlike the gdbstub of QEMU, the [`GdbStub`] is served by LibAFL itself: the guest only stops
+//! for the debugger when the [`Emulator`] hands it over, at a snapshot or on a crash, and the
+//! session ends when the debugger detaches, or when the guest stops for a reason only LibAFL can
+//! handle, like a command. The [`StdEmulatorDriver`](crate::StdEmulatorDriver) attaches its stub
+//! on the points selected when creating it, while [`Emulator::serve_gdb`] attaches anywhere else.
+//!
+//! In usermode, a crash of the guest is a signal handled by QEMU: the stub attaches from the crash
+//! hooks, where the guest can be inspected but not resumed.
+//!
+//! The stub implements the subset of the GDB Remote Serial Protocol needed to debug a crash:
+//!
+//! - Registers, with the numbering and the width of [`Regs`](crate::Regs). The `g` packets hold
+//!   the registers up to the program counter, the others are accessed one by one.
+//! - Memory, through the current CPU.
+//! - Software and hardware breakpoints, both implemented with QEMU breakpoints.
+//! - Stepping, one instruction at a time, with translation blocks of a single instruction as the
+//!   single-step of QEMU.
+//! - Reverse stepping and continuing, by restoring the snapshots taken each time the guest is
+//!   resumed. The memory is restored by the snapshot manager of the [`Emulator`], so in usermode
+//!   only the registers go back in time unless a snapshot manager is used. The snapshots are
+//!   dropped when the session ends.
+//!
+//! The guest cannot be interrupted while it runs, breakpoints must be set before continuing.
+
+use std::{
+    io::{BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use hashbrown::HashSet;
+use libafl::Error;
+use libafl_qemu_sys::{CF_COUNT_MASK, CF_NO_GOTO_PTR, CF_NO_GOTO_TB, CPUArchState, GuestAddr};
+
+#[cfg(feature = "usermode")]
+use crate::NopSnapshotManager;
+use crate::{
+    CPU, Emulator, GuestReg, HookId, IsSnapshotManager, Qemu, QemuExitError, QemuExitReason, Regs,
+    SnapshotId,
+};
+
+/// The signal reported to the debugger when the guest stops for it
+const SIGTRAP: u8 = 5;
+/// The signal reported to the debugger when it attaches on a crash
+const SIGSEGV: u8 = 11;
+
+/// The `tcg_cflags` of the translation blocks of a step, as in `curr_cflags` of QEMU with
+/// single-step enabled: one instruction per block (a count of 1 in `CF_COUNT_MASK`), no direct
+/// jump to the next block (`CF_NO_GOTO_TB` and `CF_NO_GOTO_PTR`), so the block hook runs before
+/// each instruction
+const STEP_CFLAGS: u32 = CF_NO_GOTO_TB | CF_NO_GOTO_PTR | 1;
+
+/// Set when the guest is resumed for a step, to let the first block run
+static STEP_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// The points of the run a [`GdbStub`] attaches on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GdbAttach {
+    /// Right after a snapshot is saved by a `SaveCommand`
+    Save,
+    /// Right after a snapshot is restored by a `LoadCommand`
+    Load,
+    /// When the guest reports a crash, before the snapshot is restored
+    Crash,
+}
+
+/// A GDB Remote Serial Protocol server, see the [module-level documentation](self).
+#[derive(Debug, Clone)]
+pub struct GdbStub {
+    addr: SocketAddr,
+    on_save: bool,
+    on_load: bool,
+    on_crash: bool,
+}
+
+impl GdbStub {
+    /// Creates a new [`GdbStub`] waiting for the debugger on `addr`, attaching on crashes only
+    #[must_use]
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            on_save: false,
+            on_load: false,
+            on_crash: true,
+        }
+    }
+
+    /// Whether to attach right after a snapshot is saved
+    #[must_use]
+    pub fn attach_on_save(mut self, on_save: bool) -> Self {
+        self.on_save = on_save;
+        self
+    }
+
+    /// Whether to attach right after a snapshot is restored
+    #[must_use]
+    pub fn attach_on_load(mut self, on_load: bool) -> Self {
+        self.on_load = on_load;
+        self
+    }
+
+    /// Whether to attach when the guest reports a crash
+    #[must_use]
+    pub fn attach_on_crash(mut self, on_crash: bool) -> Self {
+        self.on_crash = on_crash;
+        self
+    }
+
+    /// The address the debugger connects to
+    #[must_use]
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Whether the stub attaches on `attach`
+    #[must_use]
+    pub fn attaches_on(&self, attach: GdbAttach) -> bool {
+        match attach {
+            GdbAttach::Save => self.on_save,
+            GdbAttach::Load => self.on_load,
+            GdbAttach::Crash => self.on_crash,
+        }
+    }
+
+    /// Stops the crashed guest for the debugger, waiting for it to connect. Called from the crash
+    /// hooks with the signal `target_sig` of the guest, still in the signal handler of QEMU: the
+    /// guest can be inspected, but not resumed.
+    #[cfg(feature = "usermode")]
+    pub fn serve_crash(&self, qemu: Qemu, target_sig: i32) {
+        let mut snapshot_manager = NopSnapshotManager;
+        let result = GdbSession::accept(self, GdbAttach::Crash, qemu, &mut snapshot_manager)
+            .and_then(|mut session| {
+                session.resumable = false;
+                session.run(u8::try_from(target_sig).unwrap_or(SIGSEGV))
+            });
+        if let Err(err) = result {
+            log::error!("The gdb session failed: {err}");
+        }
+    }
+}
+
+/// The state of the guest before it was resumed by the debugger
+struct HistoryEntry {
+    snapshot_id: SnapshotId,
+    cpu_state: CPUArchState,
+    pc: GuestAddr,
+}
+
+/// How a resumed guest stopped
+enum Resumed {
+    /// For the debugger
+    Stopped,
+    /// For a reason the debugger cannot handle
+    Exited(Result<QemuExitReason, QemuExitError>),
+}
+
+/// A connection to a debugger
+struct GdbSession<'a, SM> {
+    qemu: Qemu,
+    cpu: CPU,
+    snapshot_manager: &'a mut SM,
+    /// The breakpoints of the [`Emulator`], left in place when the debugger removes its own
+    emulator_breakpoints: HashSet<GuestAddr>,
+    breakpoints: HashSet<GuestAddr>,
+    history: Vec<HistoryEntry>,
+    /// Whether the guest can be resumed, or only inspected
+    resumable: bool,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    no_ack: bool,
+}
+
+impl<C, CM, ED, ET, I, S, SM> Emulator<C, CM, ED, ET, I, S, SM>
+where
+    SM: IsSnapshotManager,
+{
+    /// Stops the guest for the debugger of `stub`, waiting for it to connect.
+    ///
+    /// Returns the exit of QEMU which ended the session, if the debugger resumed the guest until
+    /// it stopped for a reason only LibAFL can handle. The caller is responsible for handling it.
+    /// Returns `None` if the debugger detached, with the guest stopped where it left it.
+    pub fn serve_gdb(
+        &mut self,
+        stub: &GdbStub,
+        attach: GdbAttach,
+    ) -> Result<Option<Result<QemuExitReason, QemuExitError>>, Error> {
+        let emulator_breakpoints = self.breakpoints_by_addr.borrow().keys().copied().collect();
+        let mut session = GdbSession::accept(stub, attach, self.qemu, &mut self.snapshot_manager)?;
+        session.emulator_breakpoints = emulator_breakpoints;
+        let signal = if attach == GdbAttach::Crash {
+            SIGSEGV
+        } else {
+            SIGTRAP
+        };
+        session.run(signal)
+    }
+}
+
+impl<'a, SM> GdbSession<'a, SM>
+where
+    SM: IsSnapshotManager,
+{
+    /// Waits for the debugger of `stub` to connect
+    fn accept(
+        stub: &GdbStub,
+        attach: GdbAttach,
+        qemu: Qemu,
+        snapshot_manager: &'a mut SM,
+    ) -> Result<Self, Error> {
+        let listener = TcpListener::bind(stub.addr)?;
+        log::info!(
+            "Attached on {attach:?}, waiting for gdb on {}...",
+            stub.addr
+        );
+        let (stream, peer) = listener.accept()?;
+        log::info!("gdb connected from {peer}");
+
+        Ok(Self {
+            qemu,
+            cpu: qemu.current_cpu().unwrap_or_else(|| qemu.cpu_from_index(0)),
+            snapshot_manager,
+            emulator_breakpoints: HashSet::new(),
+            breakpoints: HashSet::new(),
+            history: Vec::new(),
+            resumable: true,
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            no_ack: false,
+        })
+    }
+
+    /// Serves the debugger, then removes its breakpoints and drops the snapshots of the session
+    fn run(mut self, signal: u8) -> Result<Option<Result<QemuExitReason, QemuExitError>>, Error> {
+        let result = self.serve(signal);
+        self.clear_breakpoints();
+        self.clear_history();
+        result
+    }
+
+    fn serve(
+        &mut self,
+        signal: u8,
+    ) -> Result<Option<Result<QemuExitReason, QemuExitError>>, Error> {
+        loop {
+            let Some(packet) = self.read_packet()? else {
+                log::info!("gdb disconnected");
+                return Ok(None);
+            };
+            let packet = String::from_utf8_lossy(&packet).into_owned();
+            let reply = match packet.as_bytes().first() {
+                Some(b'?') => format!("S{signal:02x}"),
+                Some(b'q') => Self::query(&packet[1..]),
+                Some(b'Q') if packet == "QStartNoAckMode" => {
+                    self.write_packet("OK")?;
+                    self.no_ack = true;
+                    continue;
+                }
+                Some(b'H' | b'T') => "OK".to_string(),
+                Some(b'g') => self.read_registers(),
+                Some(b'G') => self.write_registers(&packet[1..]),
+                Some(b'p') => self.read_register(&packet[1..]),
+                Some(b'P') => self.write_register(&packet[1..]),
+                Some(b'm') => self.read_memory(&packet[1..]),
+                Some(b'M') => self.write_memory(&packet[1..]),
+                Some(b'Z') => self.insert_breakpoint(&packet[1..]),
+                Some(b'z') => self.remove_breakpoint(&packet[1..]),
+                Some(b'c' | b's' | b'b') if !self.resumable => {
+                    self.write_packet(&format!(
+                        "O{}",
+                        to_hex(b"The crashed guest cannot be resumed\n")
+                    ))?;
+                    "E01".to_string()
+                }
+                Some(b'c' | b's') => {
+                    if let Some(addr) = parse_hex(&packet[1..]) {
+                        self.set_pc(addr);
+                    }
+                    match self.resume(packet.starts_with('s'))? {
+                        Resumed::Stopped => format!("S{SIGTRAP:02x}"),
+                        Resumed::Exited(exit) => {
+                            self.write_packet(&format!(
+                                "O{}",
+                                to_hex(b"The guest stopped for LibAFL, ending the session\n")
+                            ))?;
+                            self.write_packet("W00")?;
+                            return Ok(Some(exit));
+                        }
+                    }
+                }
+                Some(b'b') if packet == "bs" || packet == "bc" => self.reverse(packet == "bc")?,
+                Some(b'D') => {
+                    self.write_packet("OK")?;
+                    return Ok(None);
+                }
+                Some(b'k') => return Ok(None),
+                _ => String::new(),
+            };
+            self.write_packet(&reply)?;
+        }
+    }
+
+    /// The reply to the query `query`, without the leading `q`
+    fn query(query: &str) -> String {
+        if query.starts_with("Supported") {
+            "PacketSize=4000;QStartNoAckMode+;ReverseStep+;ReverseContinue+".to_string()
+        } else {
+            match query {
+                "Attached" => "1".to_string(),
+                "C" => "QC1".to_string(),
+                "fThreadInfo" => "m1".to_string(),
+                "sThreadInfo" => "l".to_string(),
+                _ => String::new(),
+            }
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        let mut reply = String::new();
+        for reg in 0..=i32::from(Regs::Pc) {
+            match self.cpu.read_reg(reg) {
+                Ok(value) => reply.push_str(&reg_to_hex(value)),
+                Err(_) => break,
+            }
+        }
+        reply
+    }
+
+    fn write_registers(&self, values: &str) -> String {
+        let width = 2 * size_of::<GuestReg>();
+        for (reg, value) in (0..=i32::from(Regs::Pc)).zip(values.as_bytes().chunks(width)) {
+            let Some(value) = reg_from_hex(value) else {
+                return "E16".to_string();
+            };
+            if self.cpu.write_reg(reg, value).is_err() {
+                return "E16".to_string();
+            }
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&self, reg: &str) -> String {
+        let Some(reg) = parse_reg(reg) else {
+            return "E16".to_string();
+        };
+        self.cpu
+            .read_reg(reg)
+            .map_or_else(|_| "E16".to_string(), reg_to_hex)
+    }
+
+    fn write_register(&self, args: &str) -> String {
+        let Some((reg, value)) = args.split_once('=') else {
+            return "E16".to_string();
+        };
+        let (Some(reg), Some(value)) = (parse_reg(reg), reg_from_hex(value.as_bytes())) else {
+            return "E16".to_string();
+        };
+        match self.cpu.write_reg(reg, value) {
+            Ok(()) => "OK".to_string(),
+            Err(_) => "E16".to_string(),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let Some((addr, len)) = parse_addr_len(args) else {
+            return "E16".to_string();
+        };
+        let mut buf = vec![0; len];
+        match self.cpu.read_mem(addr, &mut buf) {
+            Ok(()) => to_hex(&buf),
+            Err(_) => "E0e".to_string(),
+        }
+    }
+
+    fn write_memory(&self, args: &str) -> String {
+        let Some((location, data)) = args.split_once(':') else {
+            return "E16".to_string();
+        };
+        let (Some((addr, len)), Some(data)) = (parse_addr_len(location), from_hex(data)) else {
+            return "E16".to_string();
+        };
+        if data.len() != len {
+            return "E16".to_string();
+        }
+        match self.cpu.write_mem(addr, &data) {
+            Ok(()) => "OK".to_string(),
+            Err(_) => "E0e".to_string(),
+        }
+    }
+
+    fn insert_breakpoint(&mut self, args: &str) -> String {
+        let Some(addr) = parse_breakpoint(args) else {
+            return String::new();
+        };
+        if self.breakpoints.insert(addr) && !self.emulator_breakpoints.contains(&addr) {
+            self.qemu.set_breakpoint(addr);
+        }
+        "OK".to_string()
+    }
+
+    fn remove_breakpoint(&mut self, args: &str) -> String {
+        let Some(addr) = parse_breakpoint(args) else {
+            return String::new();
+        };
+        if self.breakpoints.remove(&addr) && !self.emulator_breakpoints.contains(&addr) {
+            self.qemu.remove_breakpoint(addr);
+        }
+        "OK".to_string()
+    }
+
+    fn clear_breakpoints(&mut self) {
+        for addr in self.breakpoints.drain() {
+            if !self.emulator_breakpoints.contains(&addr) {
+                self.qemu.remove_breakpoint(addr);
+            }
+        }
+    }
+
+    /// Drops the snapshots taken when resuming the guest
+    fn clear_history(&mut self) {
+        for entry in self.history.drain(..) {
+            self.snapshot_manager.remove(self.qemu, &entry.snapshot_id);
+        }
+    }
+
+    fn pc(&self) -> GuestAddr {
+        self.cpu.read_reg(Regs::Pc).unwrap_or_default()
+    }
+
+    fn set_pc(&self, pc: GuestAddr) {
+        if let Err(err) = self.cpu.write_reg(Regs::Pc, pc) {
+            log::warn!("Could not set the program counter: {err:?}");
+        }
+    }
+
+    /// Resumes the guest for one instruction if `step` is set, or until it hits a breakpoint of
+    /// the debugger otherwise
+    fn resume(&mut self, step: bool) -> Result<Resumed, Error> {
+        let pc = self.pc();
+        self.history.push(HistoryEntry {
+            snapshot_id: self.snapshot_manager.save(self.qemu),
+            cpu_state: self.cpu.save_state(),
+            pc,
+        });
+
+        // A breakpoint on the current instruction would stop the guest right away, step over it
+        if step || self.breakpoints.contains(&pc) {
+            let on_breakpoint =
+                self.breakpoints.contains(&pc) && !self.emulator_breakpoints.contains(&pc);
+            if on_breakpoint {
+                self.qemu.remove_breakpoint(pc);
+            }
+            let resumed = self.step();
+            if on_breakpoint {
+                self.qemu.set_breakpoint(pc);
+            }
+            if step || !matches!(resumed, Resumed::Stopped) || self.breakpoints.contains(&self.pc())
+            {
+                return Ok(resumed);
+            }
+        }
+
+        Ok(match unsafe { self.qemu.run() } {
+            Ok(QemuExitReason::Breakpoint(addr)) if self.breakpoints.contains(&addr) => {
+                Resumed::Stopped
+            }
+            exit => Resumed::Exited(exit),
+        })
+    }
+
+    /// Runs the guest for one instruction, in translation blocks of a single instruction, until
+    /// the start of the next one
+    fn step(&self) -> Resumed {
+        STEP_STARTED.store(false, Ordering::Relaxed);
+        let cpu = self.cpu.raw_ptr();
+        let cflags = unsafe { (*cpu).tcg_cflags };
+        unsafe {
+            (*cpu).tcg_cflags = (cflags & !CF_COUNT_MASK) | STEP_CFLAGS;
+        }
+        let hook = self
+            .qemu
+            .hooks()
+            .add_block_hooks(0_u64, Some(step_gen), None, Some(step_exec));
+        // The blocks translated before would run several instructions, and not stop the guest
+        self.qemu.flush_jit();
+        let exit = unsafe { self.qemu.run() };
+        hook.remove(true);
+        unsafe {
+            (*cpu).tcg_cflags = cflags;
+        }
+        self.qemu.flush_jit();
+
+        match exit {
+            Ok(QemuExitReason::Breakpoint(addr))
+                if STEP_STARTED.load(Ordering::Relaxed) || self.breakpoints.contains(&addr) =>
+            {
+                Resumed::Stopped
+            }
+            exit => Resumed::Exited(exit),
+        }
+    }
+
+    /// Goes back to the state before the last resume, or before the last resume stopped by a
+    /// breakpoint if `until_breakpoint` is set
+    fn reverse(&mut self, until_breakpoint: bool) -> Result<String, Error> {
+        let mut restored = None;
+        while let Some(entry) = self.history.pop() {
+            let at_breakpoint = self.breakpoints.contains(&entry.pc);
+            if let Some(skipped) = restored.replace(entry) {
+                self.snapshot_manager
+                    .remove(self.qemu, &skipped.snapshot_id);
+            }
+            if !until_breakpoint || at_breakpoint {
+                break;
+            }
+        }
+        let Some(entry) = restored else {
+            return Ok(format!("T{SIGTRAP:02x}replaylog:begin;"));
+        };
+
+        self.snapshot_manager
+            .restore(self.qemu, &entry.snapshot_id)
+            .map_err(|err| {
+                Error::illegal_state(format!("Could not restore a snapshot: {err:?}"))
+            })?;
+        self.cpu.restore_state(&entry.cpu_state);
+        self.qemu.flush_jit();
+        self.snapshot_manager.remove(self.qemu, &entry.snapshot_id);
+
+        Ok(if self.history.is_empty() {
+            format!("T{SIGTRAP:02x}replaylog:begin;")
+        } else {
+            format!("S{SIGTRAP:02x}")
+        })
+    }
+
+    /// Reads the payload of the next packet, or `None` once the debugger disconnected
+    fn read_packet(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let mut byte = [0];
+        // Skip the acknowledgments and the interrupts, the guest is already stopped
+        loop {
+            if self.reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+        let mut packet = Vec::new();
+        loop {
+            if self.reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'#' {
+                break;
+            }
+            packet.push(byte[0]);
+        }
+        let mut checksum = [0; 2];
+        self.reader.read_exact(&mut checksum)?;
+        if !self.no_ack {
+            self.writer.write_all(b"+")?;
+        }
+        Ok(Some(packet))
+    }
+
+    fn write_packet(&mut self, payload: &str) -> Result<(), Error> {
+        self.writer.write_all(packet(payload).as_bytes())?;
+        self.writer.flush()?;
+        if !self.no_ack {
+            let mut ack = [0];
+            self.reader.read_exact(&mut ack)?;
+        }
+        Ok(())
+    }
+}
+
+unsafe extern "C" fn step_gen(_data: u64, _pc: GuestAddr) -> u64 {
+    0
+}
+
+unsafe extern "C" fn step_exec(_data: u64, _id: u64) {
+    // The first block is the one the guest is resumed in
+    if STEP_STARTED.swap(true, Ordering::Relaxed)
+        && let Some(cpu) = Qemu::get().and_then(|qemu| qemu.current_cpu())
+    {
+        cpu.trigger_breakpoint();
+    }
+}
+
+/// The packet holding `payload`, with its checksum
+fn packet(payload: &str) -> String {
+    let checksum = payload.bytes().fold(0_u8, u8::wrapping_add);
+    format!("${payload}#{checksum:02x}")
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn parse_hex(hex: &str) -> Option<GuestAddr> {
+    GuestAddr::from_str_radix(hex, 16).ok()
+}
+
+fn parse_reg(hex: &str) -> Option<i32> {
+    i32::from_str_radix(hex, 16).ok()
+}
+
+/// Parses the arguments of a `Z` or `z` packet, returning `None` for the watchpoints
+fn parse_breakpoint(args: &str) -> Option<GuestAddr> {
+    let mut args = args.split(',');
+    match args.next()? {
+        "0" | "1" => parse_hex(args.next()?),
+        _ => None,
+    }
+}
+
+fn parse_addr_len(args: &str) -> Option<(GuestAddr, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((parse_hex(addr)?, usize::from_str_radix(len, 16).ok()?))
+}
+
+/// Registers are sent in the byte order of the guest
+fn reg_to_hex(value: GuestReg) -> String {
+    #[cfg(feature = "be")]
+    return to_hex(&value.to_be_bytes());
+
+    #[cfg(not(feature = "be"))]
+    return to_hex(&value.to_le_bytes());
+}
+
+fn reg_from_hex(hex: &[u8]) -> Option<GuestReg> {
+    let bytes: [u8; size_of::<GuestReg>()] =
+        from_hex(std::str::from_utf8(hex).ok()?)?.try_into().ok()?;
+
+    #[cfg(feature = "be")]
+    return Some(GuestReg::from_be_bytes(bytes));
+
+    #[cfg(not(feature = "be"))]
+    return Some(GuestReg::from_le_bytes(bytes));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::GuestReg;
+
+    use super::{
+        from_hex, packet, parse_addr_len, parse_breakpoint, parse_hex, parse_reg, reg_from_hex,
+        reg_to_hex, to_hex,
+    };
+
+    #[test]
+    fn test_packet() {
+        assert_eq!(packet("OK"), "$OK#9a");
+        assert_eq!(packet(""), "$#00");
+        assert_eq!(packet("S05"), "$S05#b8");
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(to_hex(&[0x00, 0xab, 0x7f]), "00ab7f");
+        assert_eq!(from_hex("00ab7F"), Some(vec![0x00, 0xab, 0x7f]));
+        assert_eq!(from_hex(""), Some(vec![]));
+        assert_eq!(from_hex("0g"), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_hex("1000"), Some(0x1000));
+        assert_eq!(parse_hex("x"), None);
+        assert_eq!(parse_reg("1f"), Some(31));
+        assert_eq!(parse_addr_len("1000,20"), Some((0x1000, 0x20)));
+        assert_eq!(parse_addr_len("1000"), None);
+        assert_eq!(parse_addr_len("1000,zz"), None);
+    }
+
+    #[test]
+    fn test_parse_breakpoint() {
+        assert_eq!(parse_breakpoint("0,4010,1"), Some(0x4010));
+        assert_eq!(parse_breakpoint("1,4010,1"), Some(0x4010));
+        // Watchpoints are not supported
+        assert_eq!(parse_breakpoint("2,4010,4"), None);
+        assert_eq!(parse_breakpoint("0"), None);
+    }
+
+    #[test]
+    fn test_reg_hex() {
+        let value: GuestReg = 0x1234_5678;
+        let hex = reg_to_hex(value);
+        assert_eq!(hex.len(), 2 * size_of::<GuestReg>());
+        #[cfg(not(feature = "be"))]
+        assert!(hex.starts_with("78563412"));
+        assert_eq!(reg_from_hex(hex.as_bytes()), Some(value));
+        assert_eq!(reg_from_hex(b"78"), None);
+    }
+}
@@ -34,6 +34,9 @@ pub use drivers::*;
 mod snapshot;
 pub use snapshot::*;
 
+mod gdb;
+pub use gdb::*;
+
 #[cfg(feature = "usermode")]
 mod usermode;
 #[cfg(feature = "usermode")]
@@ -450,7 +453,16 @@ where
     /// Should, in general, be safe to call.
     /// Of course, the emulated target is not contained securely and can corrupt state or interact with the operating system.
     pub unsafe fn run_qemu(&self) -> Result<EmulatorExitResult<C>, EmulatorExitError> {
-        match unsafe { self.qemu.run() } {
+        self.exit_result(unsafe { self.qemu.run() })
+    }
+
+    /// Converts the exit of [`Qemu::run`] to the exit of [`Emulator::run_qemu`], parsing the
+    /// command of a sync exit and looking up the breakpoint which was hit.
+    pub fn exit_result(
+        &self,
+        exit: Result<QemuExitReason, QemuExitError>,
+    ) -> Result<EmulatorExitResult<C>, EmulatorExitError> {
+        match exit {
             Ok(qemu_exit_reason) => Ok(match qemu_exit_reason {
                 QemuExitReason::End(qemu_shutdown_cause) => {
                     EmulatorExitResult::QemuExit(qemu_shutdown_cause)
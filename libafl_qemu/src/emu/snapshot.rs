@@ -11,6 +11,8 @@ pub trait IsSnapshotManager: Clone + Debug {
     fn save(&mut self, qemu: Qemu) -> SnapshotId;
     fn restore(&mut self, qemu: Qemu, snapshot_id: &SnapshotId)
     -> Result<(), SnapshotManagerError>;
+    /// Frees the snapshot `snapshot_id`, which will not be restored anymore
+    fn remove(&mut self, _qemu: Qemu, _snapshot_id: &SnapshotId) {}
     fn do_check(
         &self,
         qemu: Qemu,
@@ -35,6 +35,13 @@ impl IsSnapshotManager for SnapshotManager {
         }
     }
 
+    fn remove(&mut self, qemu: Qemu, snapshot_id: &SnapshotId) {
+        match self {
+            SnapshotManager::Qemu(qemu_sm) => qemu_sm.remove(qemu, snapshot_id),
+            SnapshotManager::Fast(fast_sm) => fast_sm.remove(qemu, snapshot_id),
+        }
+    }
+
     fn do_check(
         &self,
         qemu: Qemu,
@@ -116,6 +123,10 @@ impl IsSnapshotManager for QemuSnapshotManager {
         Ok(())
     }
 
+    fn remove(&mut self, qemu: Qemu, snapshot_id: &SnapshotId) {
+        qemu.delete_snapshot(self.snapshot_id_to_name(snapshot_id).as_str());
+    }
+
     fn do_check(
         &self,
         _qemu: Qemu,
@@ -151,6 +162,14 @@ impl IsSnapshotManager for FastSnapshotManager {
         Ok(())
     }
 
+    fn remove(&mut self, qemu: Qemu, snapshot_id: &SnapshotId) {
+        if let Some(fast_snapshot_ptr) = self.snapshots.remove(snapshot_id) {
+            unsafe {
+                qemu.free_fast_snapshot(fast_snapshot_ptr);
+            }
+        }
+    }
+
     fn do_check(
         &self,
         qemu: Qemu,
@@ -1,10 +1,21 @@
-use core::{cell::UnsafeCell, fmt::Debug};
+use core::{
+    cell::UnsafeCell,
+    fmt::Debug,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
 
 use capstone::prelude::*;
 use libafl::{
+    Error,
     executors::ExitKind,
     inputs::Input,
     observers::{ObserversTuple, stacktrace::BacktraceObserver},
+    stages::{HangSampler, hang_analysis::StackSample},
 };
 use libafl_bolts::tuples::{Handle, Handled, MatchFirstType, MatchNameRef};
 use libafl_qemu_sys::GuestAddr;
@@ -675,3 +686,142 @@ impl CallTraceCollector for FullBacktraceCollector {
         self.reset();
     }
 }
+
+/// The maximum number of stack samples of one run taken by a [`CallStackHangSampler`]
+const MAX_HANG_SAMPLES: usize = 256;
+
+/// Keeps a shadow call stack of the target, for a [`CallStackHangSampler`] to sample.
+///
+/// Create it with [`CallStackHangSampler::collector`].
+#[derive(Debug)]
+pub struct HangSampleCollector {
+    callstack: Arc<Mutex<Vec<GuestAddr>>>,
+}
+
+impl CallTraceCollector for HangSampleCollector {
+    #[allow(clippy::unnecessary_cast)] // dependent on the target instruction size
+    fn on_call<ET, I, S>(
+        &mut self,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: Option<&mut S>,
+        pc: GuestAddr,
+        call_len: usize,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+        I: Unpin,
+        S: Unpin,
+    {
+        self.callstack
+            .lock()
+            .unwrap()
+            .push(pc + call_len as GuestAddr);
+    }
+
+    fn on_ret<ET, I, S>(
+        &mut self,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: Option<&mut S>,
+        _pc: GuestAddr,
+        ret_addr: GuestAddr,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+        I: Unpin,
+        S: Unpin,
+    {
+        let mut callstack = self.callstack.lock().unwrap();
+        // Unwind to the frame returned to, like the `FullBacktraceCollector`
+        while let Some(p) = callstack.pop() {
+            if p == ret_addr {
+                break;
+            }
+        }
+    }
+
+    fn pre_exec<I>(&mut self, _qemu: Qemu, _input: &I)
+    where
+        I: Input,
+    {
+        self.callstack.lock().unwrap().clear();
+    }
+}
+
+/// Samples the shadow `callstack` every `interval`, until told to `stop`
+#[allow(clippy::unnecessary_cast)] // dependent on the guest pointer size
+fn sample_callstack(
+    callstack: &Mutex<Vec<GuestAddr>>,
+    interval: Duration,
+    stop: &AtomicBool,
+) -> Vec<StackSample> {
+    let mut samples = Vec::new();
+    while !stop.load(Ordering::Acquire) && samples.len() < MAX_HANG_SAMPLES {
+        thread::sleep(interval);
+        let sample: StackSample = callstack
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .map(|address| *address as usize)
+            .collect();
+        if !sample.is_empty() {
+            samples.push(sample);
+        }
+    }
+    samples
+}
+
+/// A [`HangSampler`] for the `HangAnalysisStage` of `libafl`, sampling the shadow call stack
+/// of a [`HangSampleCollector`] from a separate thread.
+///
+/// The samples have the granularity of calls: the innermost frame of each sample is the return
+/// address of the innermost call, not the current program counter. The analysis executor must run
+/// QEMU in the fuzzer process and report timeouts instead of exiting, as the `QemuExecutor` does
+/// in systemmode after `break_on_timeout`.
+#[derive(Debug, Default)]
+pub struct CallStackHangSampler {
+    callstack: Arc<Mutex<Vec<GuestAddr>>>,
+    stop: Arc<AtomicBool>,
+    sampler: Option<JoinHandle<Vec<StackSample>>>,
+}
+
+impl CallStackHangSampler {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The collector keeping the shadow call stack, for the [`CallTracerModule`] of the analysis
+    /// executor
+    #[must_use]
+    pub fn collector(&self) -> HangSampleCollector {
+        HangSampleCollector {
+            callstack: self.callstack.clone(),
+        }
+    }
+}
+
+impl<E> HangSampler<E> for CallStackHangSampler {
+    fn start(&mut self, _executor: &mut E, interval: Duration) -> Result<(), Error> {
+        if self.sampler.is_some() {
+            return Err(Error::illegal_state("The hang sampler is already running"));
+        }
+        self.stop = Arc::new(AtomicBool::new(false));
+        let stop = self.stop.clone();
+        let callstack = self.callstack.clone();
+        self.sampler = Some(
+            thread::Builder::new()
+                .name("hang_sampler".into())
+                .spawn(move || sample_callstack(&callstack, interval, &stop))?,
+        );
+        Ok(())
+    }
+
+    fn stop(&mut self, _executor: &mut E) -> Result<Vec<StackSample>, Error> {
+        let Some(sampler) = self.sampler.take() else {
+            return Ok(Vec::new());
+        };
+        self.stop.store(true, Ordering::Release);
+        sampler
+            .join()
+            .map_err(|_| Error::unknown("The hang sampler thread panicked"))
+    }
+}
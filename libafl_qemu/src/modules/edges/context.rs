@@ -0,0 +1,190 @@
+//! Context-sensitive edge coverage, the `QEMU` counterpart of the `ctx` pass of `libafl_cc`.
+//!
+//! The edges are hashed with the call sites on the call stack, so that the same edge reached
+//! from different callers hits different map entries. The call stack is tracked by a
+//! [`CallTracerModule`](crate::modules::CallTracerModule) with a [`CallContextCollector`], which
+//! must be registered next to the edge coverage module:
+//!
+//! ```ignore
+//! let modules = tuple_list!(
+//!     StdEdgeCoverageContextModule::builder()
+//!         .map_observer(edges_observer.as_mut())
+//!         .build()?,
+//!     CallTracerModule::new(StdAddressFilter::default(), tuple_list!(CallContextCollector)),
+//! );
+//! ```
+
+use std::cell::UnsafeCell;
+
+use libafl::{HasMetadata, inputs::Input};
+use libafl_bolts::hash_64_fast;
+use libafl_qemu_sys::GuestAddr;
+
+use super::{
+    EdgeCoverageVariant,
+    helpers::{
+        CALL_CONTEXT, gen_hashed_block_ids, trace_block_transition_ctx_hitcount,
+        trace_block_transition_ctx_single,
+    },
+};
+use crate::{
+    EmulatorModules, Hook, Qemu,
+    modules::{
+        AddressFilter, EdgeCoverageModule, EdgeCoverageModuleBuilder, EmulatorModuleTuple,
+        PageFilter,
+        calls::CallTraceCollector,
+        utils::filters::{StdAddressFilter, StdPageFilter},
+    },
+};
+
+// The return addresses on the call stack, with the context to restore when they are reached
+thread_local!(static CONTEXT_STACK : UnsafeCell<Vec<(GuestAddr, u64)>> = const { UnsafeCell::new(Vec::new()) });
+
+#[derive(Debug)]
+pub struct EdgeCoverageContextVariant;
+
+pub type StdEdgeCoverageContextModule =
+    EdgeCoverageModule<StdAddressFilter, StdPageFilter, EdgeCoverageContextVariant, false, 0>;
+pub type StdEdgeCoverageContextModuleBuilder = EdgeCoverageModuleBuilder<
+    StdAddressFilter,
+    StdPageFilter,
+    EdgeCoverageContextVariant,
+    false,
+    false,
+    0,
+>;
+
+impl<AF, PF, const IS_CONST_MAP: bool, const MAP_SIZE: usize>
+    EdgeCoverageVariant<AF, PF, IS_CONST_MAP, MAP_SIZE> for EdgeCoverageContextVariant
+{
+    const DO_SIDE_EFFECTS: bool = false;
+
+    fn fn_hitcount<ET, I, S>(&mut self, emulator_modules: &mut EmulatorModules<ET, I, S>)
+    where
+        AF: AddressFilter,
+        ET: EmulatorModuleTuple<I, S>,
+        PF: PageFilter,
+        I: Unpin,
+        S: HasMetadata + Unpin,
+    {
+        emulator_modules.blocks(
+            Hook::Function(gen_hashed_block_ids::<AF, ET, PF, I, S, Self, IS_CONST_MAP, MAP_SIZE>),
+            Hook::Empty,
+            Hook::Raw(trace_block_transition_ctx_hitcount),
+        );
+    }
+
+    fn fn_no_hitcount<ET, I, S>(&mut self, emulator_modules: &mut EmulatorModules<ET, I, S>)
+    where
+        AF: AddressFilter,
+        ET: EmulatorModuleTuple<I, S>,
+        PF: PageFilter,
+        I: Unpin,
+        S: HasMetadata + Unpin,
+    {
+        emulator_modules.blocks(
+            Hook::Function(gen_hashed_block_ids::<AF, ET, PF, I, S, Self, IS_CONST_MAP, MAP_SIZE>),
+            Hook::Empty,
+            Hook::Raw(trace_block_transition_ctx_single),
+        );
+    }
+
+    fn pre_exec(&mut self) {
+        CallContextCollector.reset();
+    }
+}
+
+impl Default for StdEdgeCoverageContextModuleBuilder {
+    fn default() -> Self {
+        Self {
+            variant: EdgeCoverageContextVariant,
+            address_filter: StdAddressFilter::default(),
+            page_filter: StdPageFilter::default(),
+            use_hitcounts: true,
+            use_jit: true,
+        }
+    }
+}
+
+impl StdEdgeCoverageContextModule {
+    #[must_use]
+    pub fn builder() -> StdEdgeCoverageContextModuleBuilder {
+        EdgeCoverageModuleBuilder::default().jit(false)
+    }
+}
+
+/// Maintains the calling context hashed into the edges by the [`EdgeCoverageContextVariant`]:
+/// the xor of the hashes of the call sites on the call stack.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CallContextCollector;
+
+impl CallContextCollector {
+    /// Clears the call stack of the current thread
+    pub fn reset(&mut self) {
+        // # Safety
+        // The thread locals are only accessed by the hooks of the current thread
+        unsafe {
+            CONTEXT_STACK.with(|stack| (*stack.get()).clear());
+            CALL_CONTEXT.with(|ctx| *ctx.get() = 0);
+        }
+    }
+}
+
+impl CallTraceCollector for CallContextCollector {
+    #[allow(clippy::unnecessary_cast)] // dependent on the target instruction size
+    fn on_call<ET, I, S>(
+        &mut self,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: Option<&mut S>,
+        pc: GuestAddr,
+        call_len: usize,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+        I: Unpin,
+        S: Unpin,
+    {
+        // # Safety
+        // The thread locals are only accessed by the hooks of the current thread
+        unsafe {
+            CALL_CONTEXT.with(|ctx| {
+                CONTEXT_STACK.with(|stack| {
+                    (*stack.get()).push((pc + call_len as GuestAddr, *ctx.get()));
+                });
+                *ctx.get() ^= hash_64_fast(pc as u64);
+            });
+        }
+    }
+
+    fn on_ret<ET, I, S>(
+        &mut self,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: Option<&mut S>,
+        _pc: GuestAddr,
+        ret_addr: GuestAddr,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+        I: Unpin,
+        S: Unpin,
+    {
+        // # Safety
+        // The thread locals are only accessed by the hooks of the current thread
+        unsafe {
+            CONTEXT_STACK.with(|stack| {
+                let stack = &mut *stack.get();
+                // Frames left without a matching return, by longjmp or exceptions, are unwound
+                if let Some(depth) = stack.iter().rposition(|(ret, _)| *ret == ret_addr) {
+                    let (_, ctx) = stack[depth];
+                    stack.truncate(depth);
+                    CALL_CONTEXT.with(|current| *current.get() = ctx);
+                }
+            });
+        }
+    }
+
+    fn pre_exec<I>(&mut self, _qemu: Qemu, _input: &I)
+    where
+        I: Input,
+    {
+        self.reset();
+    }
+}
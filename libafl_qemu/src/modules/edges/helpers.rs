@@ -5,10 +5,16 @@ pub use generators::{gen_hashed_block_ids, gen_hashed_edge_ids, gen_unique_edge_
 use hashbrown::HashMap;
 use libafl_qemu_sys::GuestAddr;
 use serde::{Deserialize, Serialize};
+#[cfg(not(cpu_target = "hexagon"))]
+pub(super) use tracers::CALL_CONTEXT;
+pub(crate) use tracers::trace_hashed_edge;
+pub(super) use tracers::{MAX_NGRAM_SIZE, reset_ngram_history};
 /// Tracers, responsible for propagating an ID in a map.
 pub use tracers::{
-    trace_block_transition_hitcount, trace_block_transition_single, trace_edge_hitcount,
-    trace_edge_hitcount_ptr, trace_edge_single, trace_edge_single_ptr,
+    trace_block_ngram_hitcount, trace_block_ngram_single, trace_block_transition_ctx_hitcount,
+    trace_block_transition_ctx_single, trace_block_transition_hitcount,
+    trace_block_transition_single, trace_edge_hitcount, trace_edge_hitcount_ptr, trace_edge_single,
+    trace_edge_single_ptr,
 };
 
 // Constants used for variable-length maps
@@ -25,6 +31,9 @@ pub(super) static mut LIBAFL_QEMU_EDGES_MAP_ALLOCATED_SIZE: usize = 0;
 #[unsafe(no_mangle)]
 pub(super) static mut LIBAFL_QEMU_EDGES_MAP_MASK_MAX: usize = 0;
 
+/// Whether the edge hooks record coverage. The edges inlined by the JIT are always recorded.
+pub(super) static mut LIBAFL_QEMU_EDGES_ENABLED: bool = true;
+
 #[cfg_attr(
     any(not(feature = "serdeany_autoreg"), miri),
     allow(clippy::unsafe_derive_deserialize)
@@ -293,16 +302,69 @@ mod tracers {
 
     use libafl_targets::EDGES_MAP;
 
-    use super::{LIBAFL_QEMU_EDGES_MAP_MASK_MAX, LIBAFL_QEMU_EDGES_MAP_PTR};
+    use super::{
+        LIBAFL_QEMU_EDGES_ENABLED, LIBAFL_QEMU_EDGES_MAP_MASK_MAX, LIBAFL_QEMU_EDGES_MAP_PTR,
+        LIBAFL_QEMU_EDGES_MAP_SIZE_PTR,
+    };
 
     thread_local!(static PREV_LOC : UnsafeCell<u64> = const { UnsafeCell::new(0) });
 
+    // The hash of the call sites on the call stack, maintained by the call context collector.
+    thread_local!(pub(crate) static CALL_CONTEXT : UnsafeCell<u64> = const { UnsafeCell::new(0) });
+
+    thread_local!(static NGRAM_HISTORY : UnsafeCell<NgramHistory> = const { UnsafeCell::new(NgramHistory::new()) });
+
+    /// The largest supported n for n-gram coverage
+    pub const MAX_NGRAM_SIZE: usize = 16;
+
+    /// The ids of the last `MAX_NGRAM_SIZE - 1` blocks, in a ring buffer
+    struct NgramHistory {
+        ids: [u64; MAX_NGRAM_SIZE - 1],
+        pos: usize,
+    }
+
+    impl NgramHistory {
+        const fn new() -> Self {
+            Self {
+                ids: [0; MAX_NGRAM_SIZE - 1],
+                pos: 0,
+            }
+        }
+
+        /// Hashes `id` with the ids of the `N - 1` previous blocks, then records it.
+        /// The previous ids are rotated by their age, so that the order of the blocks matters.
+        #[expect(clippy::cast_possible_truncation)] // age < MAX_NGRAM_SIZE
+        fn push<const N: usize>(&mut self, id: u64) -> u64 {
+            let len = self.ids.len();
+            let mut hash = id;
+            for age in 0..N - 1 {
+                let prev = self.ids[(self.pos + len - 1 - age) % len];
+                hash ^= prev.rotate_left(age as u32 + 1);
+            }
+            self.ids[self.pos] = id;
+            self.pos = (self.pos + 1) % len;
+            hash
+        }
+    }
+
+    /// Forgets the blocks of the last run in the n-gram history of the current thread
+    pub fn reset_ngram_history() {
+        // # Safety
+        // The thread local is only accessed by the hooks of the current thread
+        unsafe {
+            NGRAM_HISTORY.with(|history| *history.get() = NgramHistory::new());
+        }
+    }
+
     /// # Safety
     ///
     /// - @id should be the one generated by a gen_* function from this module.
     /// - Calling this concurrently for the same id is racey and may lose updates.
     pub unsafe extern "C" fn trace_edge_hitcount(_: *const (), id: u64) {
         unsafe {
+            if !LIBAFL_QEMU_EDGES_ENABLED {
+                return;
+            }
             EDGES_MAP[id as usize] = EDGES_MAP[id as usize].wrapping_add(1);
         }
     }
@@ -314,6 +376,9 @@ mod tracers {
         // # Safety
         // Worst case we set the byte to 1 multiple times..
         unsafe {
+            if !LIBAFL_QEMU_EDGES_ENABLED {
+                return;
+            }
             EDGES_MAP[id as usize] = 1;
         }
     }
@@ -323,6 +388,9 @@ mod tracers {
     /// Increases id at `EDGES_MAP_PTR` - potentially racey if called concurrently.
     pub unsafe extern "C" fn trace_edge_hitcount_ptr(_: *const (), id: u64) {
         unsafe {
+            if !LIBAFL_QEMU_EDGES_ENABLED {
+                return;
+            }
             let ptr = LIBAFL_QEMU_EDGES_MAP_PTR.add(id as usize);
             *ptr = (*ptr).wrapping_add(1);
         }
@@ -334,16 +402,42 @@ mod tracers {
     /// Worst case we set the byte to 1 multiple times.
     pub unsafe extern "C" fn trace_edge_single_ptr(_: *const (), id: u64) {
         unsafe {
+            if !LIBAFL_QEMU_EDGES_ENABLED {
+                return;
+            }
             let ptr = LIBAFL_QEMU_EDGES_MAP_PTR.add(id as usize);
             *ptr = 1;
         }
     }
 
+    /// Hits the edge with the hashed id `id` in the edge map, for the edges emulated by other
+    /// modules, e.g. the split comparisons of the `ValueProfileModule`. Nothing is recorded before
+    /// an edge coverage module set up its map.
+    pub(crate) fn trace_hashed_edge(id: u64) {
+        // # Safety
+        // The map is set up by the edge coverage module, and the index is masked to its size.
+        // Concurrent updates may be lost, as with the other tracers.
+        unsafe {
+            if !LIBAFL_QEMU_EDGES_ENABLED || LIBAFL_QEMU_EDGES_MAP_PTR.is_null() {
+                return;
+            }
+            let x = (id as usize) & LIBAFL_QEMU_EDGES_MAP_MASK_MAX;
+            let entry = LIBAFL_QEMU_EDGES_MAP_PTR.add(x);
+            *entry = (*entry).wrapping_add(1);
+            if !LIBAFL_QEMU_EDGES_MAP_SIZE_PTR.is_null() {
+                *LIBAFL_QEMU_EDGES_MAP_SIZE_PTR = (*LIBAFL_QEMU_EDGES_MAP_SIZE_PTR).max(x + 1);
+            }
+        }
+    }
+
     /// # Safety
     ///
     /// Dereferences the global `PREV_LOC` variable. May not be called concurrently.
     pub unsafe extern "C" fn trace_block_transition_hitcount(_: *const (), id: u64) {
         unsafe {
+            if !LIBAFL_QEMU_EDGES_ENABLED {
+                return;
+            }
             PREV_LOC.with(|prev_loc| {
                 let x = ((*prev_loc.get() ^ id) as usize) & LIBAFL_QEMU_EDGES_MAP_MASK_MAX;
                 let entry = LIBAFL_QEMU_EDGES_MAP_PTR.add(x);
@@ -358,6 +452,9 @@ mod tracers {
     /// Dereferences the global `PREV_LOC` variable. May not be called concurrently.
     pub unsafe extern "C" fn trace_block_transition_single(_: *const (), id: u64) {
         unsafe {
+            if !LIBAFL_QEMU_EDGES_ENABLED {
+                return;
+            }
             PREV_LOC.with(|prev_loc| {
                 let x = ((*prev_loc.get() ^ id) as usize) & LIBAFL_QEMU_EDGES_MAP_MASK_MAX;
                 let entry = LIBAFL_QEMU_EDGES_MAP_PTR.add(x);
@@ -366,4 +463,104 @@ mod tracers {
             });
         }
     }
+
+    /// # Safety
+    ///
+    /// Dereferences the global `PREV_LOC` and `CALL_CONTEXT` variables. May not be called concurrently.
+    pub unsafe extern "C" fn trace_block_transition_ctx_hitcount(_: *const (), id: u64) {
+        unsafe {
+            if !LIBAFL_QEMU_EDGES_ENABLED {
+                return;
+            }
+            let ctx = CALL_CONTEXT.with(|ctx| *ctx.get());
+            PREV_LOC.with(|prev_loc| {
+                let x = ((*prev_loc.get() ^ id ^ ctx) as usize) & LIBAFL_QEMU_EDGES_MAP_MASK_MAX;
+                let entry = LIBAFL_QEMU_EDGES_MAP_PTR.add(x);
+                *entry = (*entry).wrapping_add(1);
+                *prev_loc.get() = id.overflowing_shr(1).0;
+            });
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Dereferences the global `PREV_LOC` and `CALL_CONTEXT` variables. May not be called concurrently.
+    pub unsafe extern "C" fn trace_block_transition_ctx_single(_: *const (), id: u64) {
+        unsafe {
+            if !LIBAFL_QEMU_EDGES_ENABLED {
+                return;
+            }
+            let ctx = CALL_CONTEXT.with(|ctx| *ctx.get());
+            PREV_LOC.with(|prev_loc| {
+                let x = ((*prev_loc.get() ^ id ^ ctx) as usize) & LIBAFL_QEMU_EDGES_MAP_MASK_MAX;
+                let entry = LIBAFL_QEMU_EDGES_MAP_PTR.add(x);
+                *entry = 1;
+                *prev_loc.get() = id.overflowing_shr(1).0;
+            });
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Dereferences the global `NGRAM_HISTORY` variable. May not be called concurrently.
+    pub unsafe extern "C" fn trace_block_ngram_hitcount<const N: usize>(_: *const (), id: u64) {
+        unsafe {
+            if !LIBAFL_QEMU_EDGES_ENABLED {
+                return;
+            }
+            NGRAM_HISTORY.with(|history| {
+                let x = ((*history.get()).push::<N>(id) as usize) & LIBAFL_QEMU_EDGES_MAP_MASK_MAX;
+                let entry = LIBAFL_QEMU_EDGES_MAP_PTR.add(x);
+                *entry = (*entry).wrapping_add(1);
+            });
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Dereferences the global `NGRAM_HISTORY` variable. May not be called concurrently.
+    pub unsafe extern "C" fn trace_block_ngram_single<const N: usize>(_: *const (), id: u64) {
+        unsafe {
+            if !LIBAFL_QEMU_EDGES_ENABLED {
+                return;
+            }
+            NGRAM_HISTORY.with(|history| {
+                let x = ((*history.get()).push::<N>(id) as usize) & LIBAFL_QEMU_EDGES_MAP_MASK_MAX;
+                let entry = LIBAFL_QEMU_EDGES_MAP_PTR.add(x);
+                *entry = 1;
+            });
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::NgramHistory;
+
+        #[test]
+        fn test_ngram_history_push() {
+            let mut history = NgramHistory::new();
+            assert_eq!(history.push::<3>(1), 1);
+            assert_eq!(history.push::<3>(2), 2 ^ (1 << 1));
+            assert_eq!(history.push::<3>(4), 4 ^ (2 << 1) ^ (1 << 2));
+            // Only the last N - 1 blocks count
+            assert_eq!(history.push::<2>(8), 8 ^ (4 << 1));
+
+            // The order of the blocks matters
+            let mut other = NgramHistory::new();
+            other.push::<3>(2);
+            other.push::<3>(1);
+            assert_ne!(other.push::<3>(4), 4 ^ (2 << 1) ^ (1 << 2));
+
+            // The ring buffer wraps around
+            let mut history = NgramHistory::new();
+            for id in 1..=20 {
+                history.push::<16>(id);
+            }
+            let expected = (6..=20)
+                .rev()
+                .zip(1..)
+                .fold(21, |hash, (id, age): (u64, u32)| hash ^ id.rotate_left(age));
+            assert_eq!(history.push::<16>(21), expected);
+        }
+    }
 }
@@ -13,6 +13,9 @@ use crate::{
 };
 
 mod helpers;
+#[cfg(feature = "systemmode")]
+use helpers::LIBAFL_QEMU_EDGES_ENABLED;
+pub(crate) use helpers::trace_hashed_edge;
 use helpers::{
     LIBAFL_QEMU_EDGES_MAP_ALLOCATED_SIZE, LIBAFL_QEMU_EDGES_MAP_MASK_MAX,
     LIBAFL_QEMU_EDGES_MAP_PTR, LIBAFL_QEMU_EDGES_MAP_SIZE_PTR,
@@ -32,7 +35,20 @@ pub mod child;
 pub use child::{
     EdgeCoverageChildVariant, StdEdgeCoverageChildModule, StdEdgeCoverageChildModuleBuilder,
 };
+
+#[cfg(not(cpu_target = "hexagon"))]
+pub mod context;
+#[cfg(not(cpu_target = "hexagon"))]
+pub use context::{
+    CallContextCollector, EdgeCoverageContextVariant, StdEdgeCoverageContextModule,
+    StdEdgeCoverageContextModuleBuilder,
+};
+
+pub mod ngram;
 use libafl::observers::ConstLenMapObserver;
+pub use ngram::{
+    EdgeCoverageNgramVariant, StdEdgeCoverageNgramModule, StdEdgeCoverageNgramModuleBuilder,
+};
 
 use super::utils::filters::HasAddressFilter;
 #[cfg(feature = "systemmode")]
@@ -47,6 +63,19 @@ pub type StdEdgeCoverageModuleBuilder = StdEdgeCoverageFullModuleBuilder;
 pub type CollidingEdgeCoverageModule<AF, PF, const IS_CONST_MAP: bool, const MAP_SIZE: usize> =
     EdgeCoverageModule<AF, PF, EdgeCoverageChildVariant, IS_CONST_MAP, MAP_SIZE>;
 
+/// Starts or stops recording the edges hit by the guest, for all the edge coverage modules. The
+/// edges inlined by the JIT, with [`EdgeCoverageModuleBuilder::jit`], are always recorded.
+///
+/// # Safety
+///
+/// The flag is read by the edge hooks, it must not be written concurrently with the guest.
+#[cfg(feature = "systemmode")]
+pub(crate) unsafe fn set_edges_enabled(enabled: bool) {
+    unsafe {
+        LIBAFL_QEMU_EDGES_ENABLED = enabled;
+    }
+}
+
 /// An edge coverage module variant.
 trait EdgeCoverageVariant<AF, PF, const IS_CONST_MAP: bool, const MAP_SIZE: usize>:
     'static + Debug
@@ -96,6 +125,9 @@ trait EdgeCoverageVariant<AF, PF, const IS_CONST_MAP: bool, const MAP_SIZE: usiz
     {
         panic!("Func no hitcount is not supported.")
     }
+
+    /// Resets the state the hooks keep across blocks, before each run
+    fn pre_exec(&mut self) {}
 }
 
 #[derive(Debug)]
@@ -362,6 +394,18 @@ where
             self.variant.fn_no_hitcount(emulator_modules);
         }
     }
+
+    fn pre_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        self.variant.pre_exec();
+    }
 }
 
 impl<AF, PF, V, const IS_CONST_MAP: bool, const MAP_SIZE: usize> HasAddressFilter
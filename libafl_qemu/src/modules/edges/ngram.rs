@@ -0,0 +1,107 @@
+//! N-gram edge coverage, the `QEMU` counterpart of the `ngram` pass of `libafl_cc`.
+//!
+//! Each block is hashed with the `N - 1` blocks executed before it, so that the map entries
+//! stand for paths of `N` blocks instead of edges. `N` must be between 2 and 16.
+
+use libafl::HasMetadata;
+
+use super::{
+    EdgeCoverageVariant,
+    helpers::{
+        MAX_NGRAM_SIZE, gen_hashed_block_ids, reset_ngram_history, trace_block_ngram_hitcount,
+        trace_block_ngram_single,
+    },
+};
+use crate::{
+    EmulatorModules, Hook,
+    modules::{
+        AddressFilter, EdgeCoverageModule, EdgeCoverageModuleBuilder, EmulatorModuleTuple,
+        PageFilter,
+        utils::filters::{StdAddressFilter, StdPageFilter},
+    },
+};
+
+#[derive(Debug)]
+pub struct EdgeCoverageNgramVariant<const N: usize>;
+
+pub type StdEdgeCoverageNgramModule<const N: usize> =
+    EdgeCoverageModule<StdAddressFilter, StdPageFilter, EdgeCoverageNgramVariant<N>, false, 0>;
+pub type StdEdgeCoverageNgramModuleBuilder<const N: usize> = EdgeCoverageModuleBuilder<
+    StdAddressFilter,
+    StdPageFilter,
+    EdgeCoverageNgramVariant<N>,
+    false,
+    false,
+    0,
+>;
+
+impl<AF, PF, const N: usize, const IS_CONST_MAP: bool, const MAP_SIZE: usize>
+    EdgeCoverageVariant<AF, PF, IS_CONST_MAP, MAP_SIZE> for EdgeCoverageNgramVariant<N>
+{
+    const DO_SIDE_EFFECTS: bool = false;
+
+    fn fn_hitcount<ET, I, S>(&mut self, emulator_modules: &mut EmulatorModules<ET, I, S>)
+    where
+        AF: AddressFilter,
+        ET: EmulatorModuleTuple<I, S>,
+        PF: PageFilter,
+        I: Unpin,
+        S: HasMetadata + Unpin,
+    {
+        const {
+            assert!(
+                N >= 2 && N <= MAX_NGRAM_SIZE,
+                "The n-gram size should be between 2 and 16."
+            );
+        };
+        emulator_modules.blocks(
+            Hook::Function(gen_hashed_block_ids::<AF, ET, PF, I, S, Self, IS_CONST_MAP, MAP_SIZE>),
+            Hook::Empty,
+            Hook::Raw(trace_block_ngram_hitcount::<N>),
+        );
+    }
+
+    fn fn_no_hitcount<ET, I, S>(&mut self, emulator_modules: &mut EmulatorModules<ET, I, S>)
+    where
+        AF: AddressFilter,
+        ET: EmulatorModuleTuple<I, S>,
+        PF: PageFilter,
+        I: Unpin,
+        S: HasMetadata + Unpin,
+    {
+        const {
+            assert!(
+                N >= 2 && N <= MAX_NGRAM_SIZE,
+                "The n-gram size should be between 2 and 16."
+            );
+        };
+        emulator_modules.blocks(
+            Hook::Function(gen_hashed_block_ids::<AF, ET, PF, I, S, Self, IS_CONST_MAP, MAP_SIZE>),
+            Hook::Empty,
+            Hook::Raw(trace_block_ngram_single::<N>),
+        );
+    }
+
+    fn pre_exec(&mut self) {
+        reset_ngram_history();
+    }
+}
+
+impl<const N: usize> Default for StdEdgeCoverageNgramModuleBuilder<N> {
+    fn default() -> Self {
+        Self {
+            variant: EdgeCoverageNgramVariant,
+            address_filter: StdAddressFilter::default(),
+            page_filter: StdPageFilter::default(),
+            use_hitcounts: true,
+            use_jit: true,
+        }
+    }
+}
+
+impl<const N: usize> StdEdgeCoverageNgramModule<N> {
+    #[must_use]
+    pub fn builder() -> StdEdgeCoverageNgramModuleBuilder<N> {
+        EdgeCoverageModuleBuilder::default().jit(false)
+    }
+}
@@ -22,11 +22,16 @@ pub mod systemmode;
 pub use systemmode::*;
 
 pub mod edges;
+#[cfg(not(cpu_target = "hexagon"))]
+pub use edges::{
+    CallContextCollector, StdEdgeCoverageContextModule, StdEdgeCoverageContextModuleBuilder,
+};
 pub use edges::{
     EdgeCoverageModule, EdgeCoverageModuleBuilder, StdEdgeCoverageChildModule,
     StdEdgeCoverageChildModuleBuilder, StdEdgeCoverageClassicModule,
     StdEdgeCoverageClassicModuleBuilder, StdEdgeCoverageFullModule,
     StdEdgeCoverageFullModuleBuilder, StdEdgeCoverageModule, StdEdgeCoverageModuleBuilder,
+    StdEdgeCoverageNgramModule, StdEdgeCoverageNgramModuleBuilder,
 };
 
 #[cfg(not(cpu_target = "hexagon"))]
@@ -39,6 +44,11 @@ pub mod cmplog;
 #[cfg(not(any(cpu_target = "mips", cpu_target = "hexagon")))]
 pub use cmplog::CmpLogModule;
 
+#[cfg(not(any(cpu_target = "mips", cpu_target = "hexagon")))]
+pub mod value_profile;
+#[cfg(not(any(cpu_target = "mips", cpu_target = "hexagon")))]
+pub use value_profile::ValueProfileModule;
+
 #[cfg(not(cpu_target = "hexagon"))]
 pub mod drcov;
 #[cfg(not(cpu_target = "hexagon"))]
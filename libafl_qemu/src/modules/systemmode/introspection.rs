@@ -0,0 +1,548 @@
+//! Linux guest introspection, to restrict the coverage to one process or kernel module.
+//!
+//! The [`LinuxIntrospectionModule`] hooks the context switches of the guest kernel, and reads the
+//! PID and name of the next task from guest memory, with the kernel symbols and the structure
+//! offsets of the guest kernel. While another task than the target process runs, the edge hooks
+//! and cmplog are disabled, so that kernel threads and other processes do not pollute the maps.
+//! The edges inlined by the JIT cannot be disabled: build the edge coverage module without JIT to
+//! discard them.
+//!
+//! The loaded kernel modules are read from the `modules` list of the kernel.
+//! [`LinuxIntrospectionModule::restrict_filters`] registers the text of a kernel module target in
+//! the address filters of all modules, or the paging ids (`CR3` or `ASID`) of a target process in
+//! their page filters, so the code of other processes is not even instrumented.
+//!
+//! Only one vCPU is supported.
+
+#![allow(clippy::needless_pass_by_value)] // default compiler complains about Option<&mut T> otherwise, and this is used extensively.
+
+use std::{fs, ops::Range, path::Path};
+
+use hashbrown::{HashMap, HashSet};
+use libafl::{Error, executors::ExitKind, observers::ObserversTuple};
+use libafl_qemu_sys::{GuestAddr, GuestPhysAddr};
+use libafl_targets::cmps::CMPLOG_ENABLED;
+
+use crate::{
+    Qemu,
+    elf::EasyElf,
+    emu::EmulatorModules,
+    modules::{
+        EmulatorModule, EmulatorModuleTuple,
+        edges::set_edges_enabled,
+        utils::filters::{
+            HasAddressFilter, HasAddressFilterTuple, HasPageFilter, HasPageFilterTuple,
+            NOP_PAGE_FILTER, NopPageFilter, StdAddressFilter,
+        },
+    },
+};
+
+/// The length of the `comm` field of a `task_struct`
+const TASK_COMM_LEN: usize = 16;
+/// The length of the `name` field of a `struct module`
+const MODULE_NAME_LEN: usize = 56;
+/// Bounds the walk of the kernel module list, in case it is corrupted
+const MAX_KERNEL_MODULES: usize = 4096;
+
+/// The symbols of the guest kernel
+#[derive(Debug, Clone, Default)]
+pub struct KernelSymbols(HashMap<String, GuestAddr>);
+
+impl KernelSymbols {
+    /// Reads the symbols of a `System.map` file
+    pub fn from_system_map<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::parse_system_map(&fs::read_to_string(path)?))
+    }
+
+    /// Parses the symbols of a `System.map` file, made of `address type name` lines
+    #[must_use]
+    pub fn parse_system_map(system_map: &str) -> Self {
+        let symbols = system_map
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let addr = GuestAddr::from_str_radix(fields.next()?, 16).ok()?;
+                let name = fields.nth(1)?;
+                Some((name.to_string(), addr))
+            })
+            .collect();
+        Self(symbols)
+    }
+
+    /// Reads the symbols of a `vmlinux` file
+    #[must_use]
+    #[allow(clippy::unnecessary_cast)] // dependent on the target address size
+    pub fn from_elf(elf: &EasyElf) -> Self {
+        let goblin = elf.goblin();
+        let symbols = goblin
+            .syms
+            .iter()
+            .filter(|sym| sym.st_value != 0)
+            .filter_map(|sym| {
+                let name = goblin.strtab.get_at(sym.st_name)?;
+                Some((name.to_string(), sym.st_value as GuestAddr))
+            })
+            .collect();
+        Self(symbols)
+    }
+
+    /// The address of the symbol `name`
+    #[must_use]
+    pub fn resolve(&self, name: &str) -> Option<GuestAddr> {
+        self.0.get(name).copied()
+    }
+}
+
+/// The offsets of the fields read in the structures of the guest kernel, which depend on its
+/// version and configuration. They can be found with `pahole` on the `vmlinux` file.
+#[derive(Debug, Clone, Copy)]
+pub struct LinuxOffsets {
+    /// `task_struct.pid`
+    pub task_pid: GuestAddr,
+    /// `task_struct.tgid`
+    pub task_tgid: GuestAddr,
+    /// `task_struct.comm`
+    pub task_comm: GuestAddr,
+    /// `module.list`
+    pub module_list: GuestAddr,
+    /// `module.name`
+    pub module_name: GuestAddr,
+    /// The text base of a module: `module.mem[MOD_TEXT].base`, or `module.core_layout.base` before
+    /// Linux 6.4
+    pub module_text_base: GuestAddr,
+    /// The text size of a module, as an `unsigned int`: `module.mem[MOD_TEXT].size`, or
+    /// `module.core_layout.text_size` before Linux 6.4
+    pub module_text_size: GuestAddr,
+}
+
+/// A process, selected by PID or by name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessSelector {
+    /// The process with this PID, as seen from user space: all the tasks with this thread group id
+    Pid(i32),
+    /// The tasks with this name, truncated to 15 characters by the kernel
+    Name(String),
+}
+
+/// What the coverage is restricted to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntrospectionTarget {
+    /// The code run by a process, in user space and in the kernel
+    Process(ProcessSelector),
+    /// The text of a kernel module, by name
+    KernelModule(String),
+}
+
+/// A task of the guest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestTask {
+    /// The address of its `task_struct`
+    pub task: GuestAddr,
+    /// Its PID, as seen by the kernel: the thread id
+    pub pid: i32,
+    /// Its thread group id, the PID of its process as seen from user space
+    pub tgid: i32,
+    /// Its name
+    pub comm: String,
+}
+
+/// A kernel module loaded in the guest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestKernelModule {
+    /// Its name
+    pub name: String,
+    /// Its text
+    pub text: Range<GuestAddr>,
+}
+
+/// Restricts the coverage to one process or kernel module of a Linux guest, see the
+/// [module-level documentation](self).
+#[derive(Debug)]
+pub struct LinuxIntrospectionModule {
+    offsets: LinuxOffsets,
+    target: IntrospectionTarget,
+    switch_to: GuestAddr,
+    next_task_arg: u8,
+    modules_list: Option<GuestAddr>,
+    initially_active: bool,
+    address_filter: StdAddressFilter,
+    current: Option<GuestTask>,
+    start_task: Option<Option<GuestTask>>,
+    active: bool,
+    cmplog_enabled: u8,
+    target_paging_ids: HashSet<GuestPhysAddr>,
+    kernel_module: Option<GuestKernelModule>,
+}
+
+impl LinuxIntrospectionModule {
+    /// Creates a new [`LinuxIntrospectionModule`], hooking `__switch_to` in `symbols`
+    pub fn new(
+        symbols: &KernelSymbols,
+        offsets: LinuxOffsets,
+        target: IntrospectionTarget,
+    ) -> Result<Self, Error> {
+        let switch_to = symbols
+            .resolve("__switch_to")
+            .ok_or_else(|| Error::illegal_argument("Symbol __switch_to not found"))?;
+        let modules_list = symbols.resolve("modules");
+        if matches!(target, IntrospectionTarget::KernelModule(_)) && modules_list.is_none() {
+            return Err(Error::illegal_argument("Symbol modules not found"));
+        }
+        Ok(Self {
+            offsets,
+            target,
+            switch_to,
+            next_task_arg: 1,
+            modules_list,
+            initially_active: true,
+            address_filter: StdAddressFilter::default(),
+            current: None,
+            start_task: None,
+            active: true,
+            cmplog_enabled: 0,
+            target_paging_ids: HashSet::new(),
+            kernel_module: None,
+        })
+    }
+
+    /// Hooks another context switch function, taking the next `task_struct` as argument
+    /// `next_task_arg`
+    #[must_use]
+    pub fn with_switch_function(mut self, addr: GuestAddr, next_task_arg: u8) -> Self {
+        self.switch_to = addr;
+        self.next_task_arg = next_task_arg;
+        self
+    }
+
+    /// Whether the target process runs when the first run starts, before any context switch was
+    /// seen (default: `true`, as the harness usually starts in the target process)
+    #[must_use]
+    pub fn with_initially_active(mut self, initially_active: bool) -> Self {
+        self.initially_active = initially_active;
+        self
+    }
+
+    /// The task running on the guest, if a context switch was seen
+    #[must_use]
+    pub fn current_task(&self) -> Option<&GuestTask> {
+        self.current.as_ref()
+    }
+
+    /// Whether the coverage is currently recorded
+    #[must_use]
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// The paging ids (`CR3` or `ASID`) the target process ran with
+    #[must_use]
+    pub fn target_paging_ids(&self) -> &HashSet<GuestPhysAddr> {
+        &self.target_paging_ids
+    }
+
+    /// The kernel module target, once it is loaded
+    #[must_use]
+    pub fn kernel_module(&self) -> Option<&GuestKernelModule> {
+        self.kernel_module.as_ref()
+    }
+
+    /// Reads the task at `task` from guest memory
+    pub fn read_task(&self, qemu: Qemu, task: GuestAddr) -> Option<GuestTask> {
+        let mut pid = [0; 4];
+        qemu.read_mem(task + self.offsets.task_pid, &mut pid).ok()?;
+        let mut tgid = [0; 4];
+        qemu.read_mem(task + self.offsets.task_tgid, &mut tgid)
+            .ok()?;
+        let mut comm = [0; TASK_COMM_LEN];
+        qemu.read_mem(task + self.offsets.task_comm, &mut comm)
+            .ok()?;
+        Some(GuestTask {
+            task,
+            pid: i32::from_le_bytes(pid),
+            tgid: i32::from_le_bytes(tgid),
+            comm: c_string(&comm),
+        })
+    }
+
+    /// Reads the list of loaded kernel modules from guest memory
+    pub fn loaded_modules(&self, qemu: Qemu) -> Vec<GuestKernelModule> {
+        let mut modules = Vec::new();
+        let Some(head) = self.modules_list else {
+            return modules;
+        };
+        let mut node = head;
+        while modules.len() < MAX_KERNEL_MODULES {
+            // # Safety
+            // Pointers of the guest are plain integers
+            let Ok(next) = (unsafe { qemu.read_mem_val::<GuestAddr>(node) }) else {
+                break;
+            };
+            if next == head || next == 0 {
+                break;
+            }
+            node = next;
+            let module = node - self.offsets.module_list;
+            let mut name = [0; MODULE_NAME_LEN];
+            let mut size = [0; 4];
+            if qemu
+                .read_mem(module + self.offsets.module_name, &mut name)
+                .is_err()
+                || qemu
+                    .read_mem(module + self.offsets.module_text_size, &mut size)
+                    .is_err()
+            {
+                break;
+            }
+            // # Safety
+            // Pointers of the guest are plain integers
+            let Ok(base) =
+                (unsafe { qemu.read_mem_val::<GuestAddr>(module + self.offsets.module_text_base) })
+            else {
+                break;
+            };
+            modules.push(GuestKernelModule {
+                name: c_string(&name),
+                text: base..base + GuestAddr::from(u32::from_le_bytes(size)),
+            });
+        }
+        modules
+    }
+
+    /// Registers the target in the filters of all the modules, so that only its code is
+    /// instrumented: the text of a kernel module target in the address filters, or the paging ids
+    /// the target process ran with in the page filters. Returns `false` if the kernel module is
+    /// not loaded, or the target process did not run yet.
+    ///
+    /// The kernel code translated while the target process runs is instrumented for all tasks,
+    /// the edge hooks are still disabled while other tasks run.
+    pub fn restrict_filters<ET, I, S>(
+        qemu: Qemu,
+        emulator_modules: &mut EmulatorModules<ET, I, S>,
+    ) -> bool
+    where
+        ET: EmulatorModuleTuple<I, S> + HasAddressFilterTuple + HasPageFilterTuple,
+        I: Unpin,
+        S: Unpin,
+    {
+        let Some(module) = emulator_modules.get_mut::<Self>() else {
+            return false;
+        };
+        if matches!(module.target, IntrospectionTarget::KernelModule(_)) {
+            let Some(text) = module.find_kernel_module(qemu).map(|module| module.text) else {
+                return false;
+            };
+            emulator_modules
+                .modules_mut()
+                .allow_address_range_all(&text);
+        } else {
+            let paging_ids: Vec<GuestPhysAddr> = module.target_paging_ids.iter().copied().collect();
+            if paging_ids.is_empty() {
+                return false;
+            }
+            for paging_id in paging_ids {
+                emulator_modules.modules_mut().allow_page_id_all(paging_id);
+            }
+        }
+        qemu.flush_jit();
+        true
+    }
+
+    fn find_kernel_module(&mut self, qemu: Qemu) -> Option<GuestKernelModule> {
+        let IntrospectionTarget::KernelModule(name) = &self.target else {
+            return None;
+        };
+        if self.kernel_module.is_none() {
+            self.kernel_module = self
+                .loaded_modules(qemu)
+                .into_iter()
+                .find(|module| module.name == *name);
+        }
+        self.kernel_module.clone()
+    }
+
+    fn is_target(&self, task: &GuestTask) -> bool {
+        match &self.target {
+            IntrospectionTarget::Process(ProcessSelector::Pid(pid)) => task.tgid == *pid,
+            IntrospectionTarget::Process(ProcessSelector::Name(name)) => task.comm == *name,
+            IntrospectionTarget::KernelModule(_) => true,
+        }
+    }
+
+    /// Starts or stops recording the coverage
+    fn set_active(&mut self, active: bool) {
+        if active == self.active {
+            return;
+        }
+        // # Safety
+        // The hooks run on the only vCPU, the edge hooks do not run concurrently
+        unsafe {
+            set_edges_enabled(active);
+        }
+        if self.cmplog_enabled != 0 {
+            unsafe {
+                CMPLOG_ENABLED = u8::from(active);
+            }
+        }
+        self.active = active;
+    }
+
+    fn on_switch(&mut self, qemu: Qemu, next: GuestAddr) {
+        let Some(task) = self.read_task(qemu, next) else {
+            return;
+        };
+        let active = self.is_target(&task);
+        if active
+            && let Some(paging_id) = qemu.current_cpu().and_then(|cpu| cpu.current_paging_id())
+        {
+            self.target_paging_ids.insert(paging_id);
+        }
+        self.current = Some(task);
+        self.set_active(active);
+    }
+}
+
+/// Reads a NUL-terminated string of the guest
+fn c_string(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+impl<I, S> EmulatorModule<I, S> for LinuxIntrospectionModule
+where
+    I: Unpin,
+    S: Unpin,
+{
+    const HOOKS_DO_SIDE_EFFECTS: bool = false;
+
+    fn first_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        emulator_modules.instruction_function(self.switch_to, on_switch_to::<ET, I, S>, true);
+    }
+
+    fn pre_exec<ET>(
+        &mut self,
+        qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        // The guest restarts from the same snapshot, and so from the same task
+        let start_task = self.start_task.get_or_insert_with(|| self.current.clone());
+        self.current.clone_from(start_task);
+        self.active = self
+            .current
+            .as_ref()
+            .map_or(self.initially_active, |task| self.is_target(task));
+
+        // The observers enabled cmplog for this run, or not
+        self.cmplog_enabled = unsafe { CMPLOG_ENABLED };
+        if !self.active {
+            self.active = true;
+            self.set_active(false);
+        }
+
+        if self.kernel_module.is_none() {
+            self.find_kernel_module(qemu);
+        }
+    }
+
+    fn post_exec<OT, ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+        _observers: &mut OT,
+        _exit_kind: &mut ExitKind,
+    ) where
+        OT: ObserversTuple<I, S>,
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        // Discard the edges since the target was switched out
+        self.set_active(true);
+    }
+}
+
+impl HasAddressFilter for LinuxIntrospectionModule {
+    type AddressFilter = StdAddressFilter;
+
+    fn address_filter(&self) -> &Self::AddressFilter {
+        &self.address_filter
+    }
+
+    fn address_filter_mut(&mut self) -> &mut Self::AddressFilter {
+        &mut self.address_filter
+    }
+}
+
+impl HasPageFilter for LinuxIntrospectionModule {
+    type PageFilter = NopPageFilter;
+
+    fn page_filter(&self) -> &Self::PageFilter {
+        &NopPageFilter
+    }
+
+    fn page_filter_mut(&mut self) -> &mut Self::PageFilter {
+        unsafe { (&raw mut NOP_PAGE_FILTER).as_mut().unwrap().get_mut() }
+    }
+}
+
+pub fn on_switch_to<ET, I, S>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    _pc: GuestAddr,
+) where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let Some(h) = emulator_modules.get_mut::<LinuxIntrospectionModule>() else {
+        return;
+    };
+    if let Ok(next) = qemu.read_function_argument(h.next_task_arg) {
+        h.on_switch(qemu, next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KernelSymbols, c_string};
+
+    #[test]
+    fn test_parse_system_map() {
+        let symbols = KernelSymbols::parse_system_map(
+            "c0008000 T _text\n\
+             c0107e4c T __switch_to\n\
+             c0a1b2c0 d modules\n\
+             \n\
+             not_an_address T broken\n\
+             c0008010 T\n",
+        );
+        assert_eq!(symbols.resolve("__switch_to"), Some(0xc010_7e4c));
+        assert_eq!(symbols.resolve("modules"), Some(0xc0a1_b2c0));
+        assert_eq!(symbols.resolve("_text"), Some(0xc000_8000));
+        assert_eq!(symbols.resolve("broken"), None);
+        assert_eq!(symbols.0.len(), 3);
+    }
+
+    #[test]
+    fn test_c_string() {
+        assert_eq!(c_string(b"kworker/0:1\0\0\0\0\0"), "kworker/0:1");
+        // The kernel truncates the names without a NUL
+        assert_eq!(c_string(b"0123456789abcdef"), "0123456789abcdef");
+        assert_eq!(c_string(b"\0garbage"), "");
+        assert_eq!(c_string(b"bad\xffname\0"), "bad\u{fffd}name");
+    }
+}
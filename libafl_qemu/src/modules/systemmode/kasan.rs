@@ -0,0 +1,979 @@
+//! A kernel address sanitizer for system mode, in the style of `KASAN`.
+//!
+//! The [`KasanModule`] hooks the allocation and free functions of the guest kernel or RTOS, like
+//! `kmalloc`/`kfree` or `pvPortMalloc`/`vPortFree`, and keeps a shadow memory of the guest heap on
+//! the host. Guest loads and stores to freed chunks or their redzones, double frees and frees of
+//! pointers inside a chunk are reported with the guest backtrace, and the run ends as a crash.
+//!
+//! Backtraces are collected by a [`crate::modules::CallTracerModule`] with a
+//! [`FullBacktraceCollector`], which must be added to the modules for them to be reported.
+//! Only one vCPU is supported.
+
+#![allow(clippy::needless_pass_by_value)] // default compiler complains about Option<&mut T> otherwise, and this is used extensively.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use hashbrown::{HashMap, HashSet};
+use libafl::{Error, executors::ExitKind, observers::ObserversTuple};
+use libafl_qemu_sys::GuestAddr;
+
+use crate::{
+    ArchExtras, Qemu,
+    elf::EasyElf,
+    emu::EmulatorModules,
+    get_exit_arch_regs,
+    modules::{
+        EmulatorModule, EmulatorModuleTuple,
+        calls::FullBacktraceCollector,
+        utils::filters::{HasAddressFilter, StdAddressFilter},
+    },
+    qemu::{Hook, MemAccessInfo},
+    sync_exit::ExitArgs,
+    sys::TCGTemp,
+};
+
+/// The number of guest bytes described by one shadow byte
+pub const KASAN_GRANULE_SIZE: GuestAddr = 8;
+/// The guest bytes described by one page of the shadow memory
+const SHADOW_PAGE_SIZE: GuestAddr = 4096;
+const SHADOW_PAGE_LEN: usize = (SHADOW_PAGE_SIZE / KASAN_GRANULE_SIZE) as usize;
+
+type ShadowPage = Box<[i8; SHADOW_PAGE_LEN]>;
+
+/// The shadow value of poisoned granules, with the encoding of `ASan`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i8)]
+pub enum KasanPoison {
+    /// The redzone before a chunk
+    HeapLeftRedzone = -6, // 0xfa
+    /// The redzone after a chunk, or its unused tail
+    HeapRightRedzone = -5, // 0xfb
+    /// A freed chunk
+    HeapFreed = -3, // 0xfd
+}
+
+impl KasanPoison {
+    fn from_shadow(shadow: i8) -> Self {
+        match shadow {
+            -6 => Self::HeapLeftRedzone,
+            -3 => Self::HeapFreed,
+            _ => Self::HeapRightRedzone,
+        }
+    }
+}
+
+impl Display for KasanPoison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HeapLeftRedzone | Self::HeapRightRedzone => write!(f, "heap-buffer-overflow"),
+            Self::HeapFreed => write!(f, "use-after-free"),
+        }
+    }
+}
+
+/// A memory error found by the [`KasanModule`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KasanError {
+    /// A read of poisoned memory: address, size and poison
+    Read(GuestAddr, usize, KasanPoison),
+    /// A write of poisoned memory: address, size and poison
+    Write(GuestAddr, usize, KasanPoison),
+    /// A second free of a chunk
+    DoubleFree(GuestAddr),
+    /// A free of a pointer inside a chunk
+    BadFree(GuestAddr, Range<GuestAddr>),
+}
+
+impl Display for KasanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(addr, len, poison) => {
+                write!(f, "{poison}: invalid {len} bytes read at {addr:#x}")
+            }
+            Self::Write(addr, len, poison) => {
+                write!(f, "{poison}: invalid {len} bytes write at {addr:#x}")
+            }
+            Self::DoubleFree(addr) => write!(f, "double-free of {addr:#x}"),
+            Self::BadFree(addr, chunk) => write!(
+                f,
+                "bad free at {addr:#x} in the allocated chunk [{:#x},{:#x})",
+                chunk.start, chunk.end
+            ),
+        }
+    }
+}
+
+/// A chunk of the guest heap
+#[derive(Debug, Clone)]
+pub struct KasanChunk {
+    /// The address returned to the guest
+    pub start: GuestAddr,
+    /// The size requested by the guest
+    pub size: GuestAddr,
+    /// Whether the chunk was not freed yet
+    pub allocated: bool,
+    /// Where the chunk was allocated
+    pub alloc_backtrace: Vec<GuestAddr>,
+    /// Where the chunk was freed
+    pub free_backtrace: Vec<GuestAddr>,
+}
+
+impl KasanChunk {
+    /// The end of the last granule of the chunk
+    fn granules_end(&self) -> GuestAddr {
+        self.start + self.size.next_multiple_of(KASAN_GRANULE_SIZE)
+    }
+}
+
+/// A memory error, with where it happened
+#[derive(Debug, Clone)]
+pub struct KasanReport {
+    /// The error
+    pub error: KasanError,
+    /// The guest backtrace of the access or free, innermost last
+    pub backtrace: Vec<GuestAddr>,
+    /// The chunk the error happened in, if known
+    pub chunk: Option<KasanChunk>,
+}
+
+impl Display for KasanReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let print_bt = |f: &mut Formatter<'_>, backtrace: &[GuestAddr]| {
+            for (i, addr) in backtrace.iter().rev().enumerate() {
+                writeln!(f, "\t#{i} {addr:#x}")?;
+            }
+            Ok(())
+        };
+        writeln!(f, "KernelAddressSanitizer Error: {}", self.error)?;
+        print_bt(f, &self.backtrace)?;
+        if let Some(chunk) = &self.chunk {
+            writeln!(
+                f,
+                "In the {}-byte chunk [{:#x},{:#x})",
+                chunk.size,
+                chunk.start,
+                chunk.start + chunk.size
+            )?;
+            if !chunk.allocated {
+                writeln!(f, "Freed at:")?;
+                print_bt(f, &chunk.free_backtrace)?;
+            }
+            writeln!(f, "Allocated at:")?;
+            print_bt(f, &chunk.alloc_backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+/// The allocation and free functions of a guest
+#[derive(Debug, Clone)]
+pub struct KasanAllocator {
+    /// The symbol of the allocation function
+    pub alloc: String,
+    /// The index of the size argument of the allocation function
+    pub size_arg: u8,
+    /// The symbol of the free function
+    pub free: String,
+    /// The index of the pointer argument of the free function
+    pub ptr_arg: u8,
+}
+
+impl KasanAllocator {
+    /// An allocator whose functions take the size and the pointer as first argument
+    #[must_use]
+    pub fn new(alloc: &str, free: &str) -> Self {
+        Self {
+            alloc: alloc.to_string(),
+            size_arg: 0,
+            free: free.to_string(),
+            ptr_arg: 0,
+        }
+    }
+
+    /// The `kmalloc` allocator of Linux. The symbol of the allocation function depends on the
+    /// kernel version.
+    #[must_use]
+    pub fn linux() -> Self {
+        Self::new("__kmalloc", "kfree")
+    }
+
+    /// The heap of `FreeRTOS`
+    #[must_use]
+    pub fn freertos() -> Self {
+        Self::new("pvPortMalloc", "vPortFree")
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PendingCall {
+    Alloc(GuestAddr),
+    Free,
+    /// A call of the allocator from inside the allocator
+    Nested,
+    /// An allocation too large to add the redzones, whose chunk is not tracked
+    Untracked,
+}
+
+/// Sanitizes the guest heap, see the [module-level documentation](self).
+#[derive(Debug)]
+pub struct KasanModule {
+    filter: StdAddressFilter,
+    alloc_functions: Vec<(GuestAddr, u8)>,
+    free_functions: Vec<(GuestAddr, u8)>,
+    redzone: GuestAddr,
+    halt_on_error: bool,
+    snapshot: bool,
+    shadow: HashMap<GuestAddr, ShadowPage>,
+    chunks: BTreeMap<GuestAddr, KasanChunk>,
+    /// The allocator calls which did not return yet, with their return address
+    pending: Vec<(GuestAddr, PendingCall)>,
+    return_hooks: HashSet<GuestAddr>,
+    snapshot_taken: bool,
+    shadow_undo: HashMap<GuestAddr, Option<ShadowPage>>,
+    chunks_undo: Vec<(GuestAddr, Option<KasanChunk>)>,
+    reports: Vec<KasanReport>,
+}
+
+impl Default for KasanModule {
+    fn default() -> Self {
+        Self::new(StdAddressFilter::default())
+    }
+}
+
+impl KasanModule {
+    /// Creates a new [`KasanModule`], checking the accesses of the instructions allowed by
+    /// `filter`. The allocator functions must be added with [`Self::with_allocator`] or
+    /// [`Self::with_alloc_function`] and [`Self::with_free_function`].
+    #[must_use]
+    pub fn new(filter: StdAddressFilter) -> Self {
+        Self {
+            filter,
+            alloc_functions: Vec::new(),
+            free_functions: Vec::new(),
+            redzone: 0,
+            halt_on_error: true,
+            snapshot: true,
+            shadow: HashMap::new(),
+            chunks: BTreeMap::new(),
+            pending: Vec::new(),
+            return_hooks: HashSet::new(),
+            snapshot_taken: false,
+            shadow_undo: HashMap::new(),
+            chunks_undo: Vec::new(),
+            reports: Vec::new(),
+        }
+    }
+
+    /// Hooks the functions of `allocator`, resolved in the symbols of `elf` loaded at `load_addr`
+    pub fn with_allocator(
+        self,
+        elf: &EasyElf,
+        load_addr: GuestAddr,
+        allocator: &KasanAllocator,
+    ) -> Result<Self, Error> {
+        let resolve = |name: &str| {
+            elf.resolve_symbol(name, load_addr)
+                .ok_or_else(|| Error::illegal_argument(format!("Symbol {name} not found")))
+        };
+        Ok(self
+            .with_alloc_function(resolve(&allocator.alloc)?, allocator.size_arg)
+            .with_free_function(resolve(&allocator.free)?, allocator.ptr_arg))
+    }
+
+    /// Hooks the allocation function at `addr`, taking the size as argument `size_arg`
+    #[must_use]
+    pub fn with_alloc_function(mut self, addr: GuestAddr, size_arg: u8) -> Self {
+        self.alloc_functions.push((addr, size_arg));
+        self
+    }
+
+    /// Hooks the free function at `addr`, taking the pointer as argument `ptr_arg`
+    #[must_use]
+    pub fn with_free_function(mut self, addr: GuestAddr, ptr_arg: u8) -> Self {
+        self.free_functions.push((addr, ptr_arg));
+        self
+    }
+
+    /// Adds redzones of `redzone` bytes around each chunk, by growing the size given to the
+    /// allocator and moving the returned pointer. Every function of the guest taking a heap
+    /// pointer, like `krealloc` or `ksize`, then sees the moved pointer: only use redzones if the
+    /// hooked functions are the only ones. Without redzones, only use-after-free and bad frees are
+    /// found.
+    #[must_use]
+    pub fn with_redzone(mut self, redzone: GuestAddr) -> Self {
+        self.redzone = redzone.next_multiple_of(KASAN_GRANULE_SIZE);
+        self
+    }
+
+    /// Whether to end the run at the first error, or only when the run ends (default: `true`)
+    #[must_use]
+    pub fn with_halt_on_error(mut self, halt_on_error: bool) -> Self {
+        self.halt_on_error = halt_on_error;
+        self
+    }
+
+    /// Whether to restore the heap state of the first run after each run, as the guest memory is
+    /// restored from a snapshot (default: `true`)
+    #[must_use]
+    pub fn with_snapshot(mut self, snapshot: bool) -> Self {
+        self.snapshot = snapshot;
+        self
+    }
+
+    /// The errors found in the last run
+    #[must_use]
+    pub fn reports(&self) -> &[KasanReport] {
+        &self.reports
+    }
+
+    /// The chunk containing `addr`, with its redzones
+    #[must_use]
+    pub fn chunk(&self, addr: GuestAddr) -> Option<&KasanChunk> {
+        self.chunks
+            .range(..=addr.saturating_add(self.redzone))
+            .next_back()
+            .map(|(_, chunk)| chunk)
+            .filter(|chunk| {
+                chunk.start.saturating_sub(self.redzone) <= addr
+                    && addr < chunk.granules_end() + self.redzone
+            })
+    }
+
+    /// Whether the accesses of the instruction at `addr` are checked
+    #[must_use]
+    pub fn must_instrument(&self, addr: GuestAddr) -> bool {
+        self.filter.allowed(&addr)
+    }
+
+    /// Returns the poison of the first invalid byte of the access of `size` bytes at `addr`
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn check(&self, addr: GuestAddr, size: usize) -> Option<KasanPoison> {
+        if self.shadow.is_empty() || size == 0 {
+            return None;
+        }
+        let end = addr.saturating_add(size as GuestAddr);
+        let mut granule = addr & !(KASAN_GRANULE_SIZE - 1);
+        while granule < end {
+            let shadow = self
+                .shadow
+                .get(&(granule / SHADOW_PAGE_SIZE))
+                .map_or(0, |page| {
+                    page[((granule % SHADOW_PAGE_SIZE) / KASAN_GRANULE_SIZE) as usize]
+                });
+            // The offset of the last accessed byte in the granule
+            let last = end.min(granule.saturating_add(KASAN_GRANULE_SIZE)) - granule - 1;
+            if shadow < 0 || (shadow > 0 && last >= shadow as GuestAddr) {
+                return Some(KasanPoison::from_shadow(shadow));
+            }
+            let Some(next) = granule.checked_add(KASAN_GRANULE_SIZE) else {
+                break;
+            };
+            granule = next;
+        }
+        None
+    }
+
+    fn shadow_page_mut(&mut self, page: GuestAddr) -> &mut [i8; SHADOW_PAGE_LEN] {
+        if self.snapshot_taken && !self.shadow_undo.contains_key(&page) {
+            self.shadow_undo
+                .insert(page, self.shadow.get(&page).cloned());
+        }
+        self.shadow
+            .entry(page)
+            .or_insert_with(|| Box::new([0; SHADOW_PAGE_LEN]))
+    }
+
+    /// Sets the shadow of the granules from `start` up to `end`, `start` must be aligned
+    #[allow(clippy::cast_possible_truncation)]
+    fn set_shadow(&mut self, start: GuestAddr, end: GuestAddr, value: i8) {
+        let mut granule = start;
+        while granule < end {
+            let page = self.shadow_page_mut(granule / SHADOW_PAGE_SIZE);
+            page[((granule % SHADOW_PAGE_SIZE) / KASAN_GRANULE_SIZE) as usize] = value;
+            granule += KASAN_GRANULE_SIZE;
+        }
+    }
+
+    /// Marks the `size` bytes at `start` as valid, and the rest of the last granule as invalid
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    fn unpoison(&mut self, start: GuestAddr, size: GuestAddr) {
+        let aligned = size - size % KASAN_GRANULE_SIZE;
+        self.set_shadow(start, start + aligned, 0);
+        if size % KASAN_GRANULE_SIZE != 0 {
+            let tail = start + aligned;
+            self.set_shadow(tail, tail + 1, (size % KASAN_GRANULE_SIZE) as i8);
+        }
+    }
+
+    fn poison(&mut self, start: GuestAddr, end: GuestAddr, poison: KasanPoison) {
+        self.set_shadow(start, end, poison as i8);
+    }
+
+    fn set_chunk(&mut self, start: GuestAddr, chunk: Option<KasanChunk>) {
+        let old = match chunk {
+            Some(chunk) => self.chunks.insert(start, chunk),
+            None => self.chunks.remove(&start),
+        };
+        if self.snapshot_taken {
+            self.chunks_undo.push((start, old));
+        }
+    }
+
+    fn backtrace(pc: GuestAddr) -> Vec<GuestAddr> {
+        let mut backtrace = FullBacktraceCollector::backtrace()
+            .map(<[GuestAddr]>::to_vec)
+            .unwrap_or_default();
+        backtrace.push(pc);
+        backtrace
+    }
+
+    /// Tracks a chunk of `size` bytes returned at `start`. Chunks reaching past the end of the
+    /// address space with their redzone are not tracked.
+    pub fn allocation(&mut self, pc: GuestAddr, start: GuestAddr, size: GuestAddr) {
+        let block_start = start.saturating_sub(self.redzone);
+        let Some(user_end) = size
+            .checked_next_multiple_of(KASAN_GRANULE_SIZE)
+            .and_then(|size| start.checked_add(size))
+        else {
+            return;
+        };
+        let Some(block_end) = user_end.checked_add(self.redzone) else {
+            return;
+        };
+
+        // Forget the chunks this memory was reused from
+        let reused: Vec<GuestAddr> = self
+            .chunks
+            .range(..block_end)
+            .rev()
+            .take_while(|(_, chunk)| chunk.granules_end() + self.redzone > block_start)
+            .map(|(start, _)| *start)
+            .collect();
+        for old in reused {
+            self.set_chunk(old, None);
+        }
+
+        self.poison(block_start, start, KasanPoison::HeapLeftRedzone);
+        self.unpoison(start, size);
+        self.poison(user_end, block_end, KasanPoison::HeapRightRedzone);
+        self.set_chunk(
+            start,
+            Some(KasanChunk {
+                start,
+                size,
+                allocated: true,
+                alloc_backtrace: Self::backtrace(pc),
+                free_backtrace: Vec::new(),
+            }),
+        );
+    }
+
+    /// Checks and poisons the chunk at `ptr` freed by the guest
+    pub fn deallocation(&mut self, qemu: Qemu, pc: GuestAddr, ptr: GuestAddr) {
+        if let Some(error) = self.free_chunk(pc, ptr) {
+            self.report(qemu, pc, error);
+        }
+    }
+
+    /// Poisons the chunk at `ptr`, or returns the error of the free
+    fn free_chunk(&mut self, pc: GuestAddr, ptr: GuestAddr) -> Option<KasanError> {
+        match self.chunks.get(&ptr).cloned() {
+            Some(mut chunk) if chunk.allocated => {
+                self.poison(ptr, chunk.granules_end(), KasanPoison::HeapFreed);
+                chunk.allocated = false;
+                chunk.free_backtrace = Self::backtrace(pc);
+                self.set_chunk(ptr, Some(chunk));
+                None
+            }
+            Some(_) => Some(KasanError::DoubleFree(ptr)),
+            // Pointers outside the tracked chunks were allocated before the module started
+            None => self
+                .chunk(ptr)
+                .filter(|chunk| chunk.allocated)
+                .map(|chunk| KasanError::BadFree(ptr, chunk.start..chunk.start + chunk.size)),
+        }
+    }
+
+    /// Reports an error, and ends the run if [`Self::with_halt_on_error`] is set
+    pub fn report(&mut self, qemu: Qemu, pc: GuestAddr, error: KasanError) {
+        let addr = match &error {
+            KasanError::Read(addr, _, _)
+            | KasanError::Write(addr, _, _)
+            | KasanError::DoubleFree(addr)
+            | KasanError::BadFree(addr, _) => *addr,
+        };
+        let report = KasanReport {
+            error,
+            backtrace: Self::backtrace(pc),
+            chunk: self.chunk(addr).cloned(),
+        };
+        eprintln!("=================================================================");
+        eprint!("{report}");
+        self.reports.push(report);
+
+        if self.halt_on_error
+            && let Some(cpu) = qemu.current_cpu()
+        {
+            // # Safety
+            // Ends the run like a guest panic, which the driver turns into a crash
+            unsafe {
+                libafl_qemu_sys::libafl_exit_request_internal(
+                    cpu.raw_ptr(),
+                    pc.into(),
+                    libafl_qemu_sys::ShutdownCause_SHUTDOWN_CAUSE_GUEST_PANIC,
+                    0,
+                );
+            }
+        }
+    }
+
+    pub fn read(&mut self, qemu: Qemu, pc: GuestAddr, addr: GuestAddr, size: usize) {
+        if !self.in_allocator()
+            && let Some(poison) = self.check(addr, size)
+        {
+            self.report(qemu, pc, KasanError::Read(addr, size, poison));
+        }
+    }
+
+    pub fn write(&mut self, qemu: Qemu, pc: GuestAddr, addr: GuestAddr, size: usize) {
+        if !self.in_allocator()
+            && let Some(poison) = self.check(addr, size)
+        {
+            self.report(qemu, pc, KasanError::Write(addr, size, poison));
+        }
+    }
+
+    /// The allocator manages its own metadata in the poisoned memory, it is not checked
+    fn in_allocator(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Records a call of the allocator, returns its return address if it must be hooked
+    fn enter_call(&mut self, qemu: Qemu, call: PendingCall) -> Option<GuestAddr> {
+        let ret = qemu.read_return_address().ok()?;
+        self.pending.push((ret, call));
+        self.return_hooks.insert(ret).then_some(ret)
+    }
+
+    fn on_alloc(&mut self, qemu: Qemu, size_arg: u8) -> Option<GuestAddr> {
+        if self.in_allocator() {
+            return self.enter_call(qemu, PendingCall::Nested);
+        }
+        let size = qemu.read_function_argument(size_arg).ok()?;
+        if self.redzone > 0 {
+            // Leave the sizes the allocator rejects anyway to it
+            let Some(grown) = size
+                .checked_next_multiple_of(KASAN_GRANULE_SIZE)
+                .and_then(|size| size.checked_add(self.redzone.checked_mul(2)?))
+            else {
+                return self.enter_call(qemu, PendingCall::Untracked);
+            };
+            qemu.write_function_argument(size_arg, grown).ok()?;
+        }
+        self.enter_call(qemu, PendingCall::Alloc(size))
+    }
+
+    fn on_free(&mut self, qemu: Qemu, pc: GuestAddr, ptr_arg: u8) -> Option<GuestAddr> {
+        if self.in_allocator() {
+            return self.enter_call(qemu, PendingCall::Nested);
+        }
+        let ptr = qemu.read_function_argument(ptr_arg).ok()?;
+        if ptr != 0 {
+            let tracked = self.chunks.contains_key(&ptr);
+            self.deallocation(qemu, pc, ptr);
+            if tracked && self.redzone > 0 {
+                // Give the allocator back the pointer it returned
+                let _ = qemu.write_function_argument(ptr_arg, ptr - self.redzone);
+            }
+        }
+        self.enter_call(qemu, PendingCall::Free)
+    }
+
+    fn on_return(&mut self, qemu: Qemu, pc: GuestAddr) {
+        let Some(idx) = self.pending.iter().rposition(|(ret, _)| *ret == pc) else {
+            return;
+        };
+        // The calls above it did not return normally
+        let (_, call) = self.pending.drain(idx..).next().unwrap();
+        if let PendingCall::Alloc(size) = call {
+            let ret_reg = get_exit_arch_regs()[ExitArgs::Ret];
+            let Ok(ptr) = qemu.read_reg(ret_reg) else {
+                return;
+            };
+            let Some(start) = ptr.checked_add(self.redzone).filter(|_| ptr != 0) else {
+                return;
+            };
+            if self.redzone > 0 && qemu.write_reg(ret_reg, start).is_err() {
+                return;
+            }
+            self.allocation(pc, start, size);
+        }
+    }
+
+    /// Restores the shadow memory and the chunks of the first run
+    fn restore(&mut self) {
+        for (page, old) in self.shadow_undo.drain() {
+            match old {
+                Some(old) => self.shadow.insert(page, old),
+                None => self.shadow.remove(&page),
+            };
+        }
+        while let Some((start, old)) = self.chunks_undo.pop() {
+            match old {
+                Some(old) => self.chunks.insert(start, old),
+                None => self.chunks.remove(&start),
+            };
+        }
+    }
+}
+
+impl<I, S> EmulatorModule<I, S> for KasanModule
+where
+    I: Unpin,
+    S: Unpin,
+{
+    fn first_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        for (addr, _) in &self.alloc_functions {
+            emulator_modules.instruction_function(*addr, on_alloc_kasan::<ET, I, S>, true);
+        }
+        for (addr, _) in &self.free_functions {
+            emulator_modules.instruction_function(*addr, on_free_kasan::<ET, I, S>, true);
+        }
+
+        emulator_modules.reads(
+            Hook::Function(gen_readwrite_kasan::<ET, I, S>),
+            Hook::Function(trace_read_kasan::<ET, I, S, 1>),
+            Hook::Function(trace_read_kasan::<ET, I, S, 2>),
+            Hook::Function(trace_read_kasan::<ET, I, S, 4>),
+            Hook::Function(trace_read_kasan::<ET, I, S, 8>),
+            Hook::Function(trace_read_n_kasan::<ET, I, S>),
+        );
+        emulator_modules.writes(
+            Hook::Function(gen_readwrite_kasan::<ET, I, S>),
+            Hook::Function(trace_write_kasan::<ET, I, S, 1>),
+            Hook::Function(trace_write_kasan::<ET, I, S, 2>),
+            Hook::Function(trace_write_kasan::<ET, I, S, 4>),
+            Hook::Function(trace_write_kasan::<ET, I, S, 8>),
+            Hook::Function(trace_write_n_kasan::<ET, I, S>),
+        );
+    }
+
+    fn pre_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        self.reports.clear();
+        self.pending.clear();
+        self.snapshot_taken = self.snapshot;
+    }
+
+    fn post_exec<OT, ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+        _observers: &mut OT,
+        exit_kind: &mut ExitKind,
+    ) where
+        OT: ObserversTuple<I, S>,
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        if !self.reports.is_empty() {
+            *exit_kind = ExitKind::Crash;
+        }
+        if self.snapshot {
+            self.restore();
+        }
+    }
+}
+
+impl HasAddressFilter for KasanModule {
+    type AddressFilter = StdAddressFilter;
+
+    fn address_filter(&self) -> &Self::AddressFilter {
+        &self.filter
+    }
+
+    fn address_filter_mut(&mut self) -> &mut Self::AddressFilter {
+        &mut self.filter
+    }
+}
+
+pub fn on_alloc_kasan<ET, I, S>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    pc: GuestAddr,
+) where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let Some(h) = emulator_modules.get_mut::<KasanModule>() else {
+        return;
+    };
+    let ret = h
+        .alloc_functions
+        .iter()
+        .find_map(|(addr, arg)| (*addr == pc).then_some(*arg))
+        .and_then(|size_arg| h.on_alloc(qemu, size_arg));
+    if let Some(ret) = ret {
+        emulator_modules.instruction_function(ret, on_allocator_return::<ET, I, S>, true);
+    }
+}
+
+pub fn on_free_kasan<ET, I, S>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    pc: GuestAddr,
+) where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let Some(h) = emulator_modules.get_mut::<KasanModule>() else {
+        return;
+    };
+    let ret = h
+        .free_functions
+        .iter()
+        .find_map(|(addr, arg)| (*addr == pc).then_some(*arg))
+        .and_then(|ptr_arg| h.on_free(qemu, pc, ptr_arg));
+    if let Some(ret) = ret {
+        emulator_modules.instruction_function(ret, on_allocator_return::<ET, I, S>, true);
+    }
+}
+
+fn on_allocator_return<ET, I, S>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    pc: GuestAddr,
+) where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    if let Some(h) = emulator_modules.get_mut::<KasanModule>() {
+        h.on_return(qemu, pc);
+    }
+}
+
+pub fn gen_readwrite_kasan<ET, I, S>(
+    _qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    pc: GuestAddr,
+    _addr: *mut TCGTemp,
+    _info: MemAccessInfo,
+) -> Option<u64>
+where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let h = emulator_modules.get_mut::<KasanModule>().unwrap();
+    if h.must_instrument(pc) {
+        Some(pc.into())
+    } else {
+        None
+    }
+}
+
+pub fn trace_read_kasan<ET, I, S, const N: usize>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    id: u64,
+    _pc: GuestAddr,
+    addr: GuestAddr,
+) where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let h = emulator_modules.get_mut::<KasanModule>().unwrap();
+    h.read(qemu, id as GuestAddr, addr, N);
+}
+
+pub fn trace_read_n_kasan<ET, I, S>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    id: u64,
+    _pc: GuestAddr,
+    addr: GuestAddr,
+    size: usize,
+) where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let h = emulator_modules.get_mut::<KasanModule>().unwrap();
+    h.read(qemu, id as GuestAddr, addr, size);
+}
+
+pub fn trace_write_kasan<ET, I, S, const N: usize>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    id: u64,
+    _pc: GuestAddr,
+    addr: GuestAddr,
+) where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let h = emulator_modules.get_mut::<KasanModule>().unwrap();
+    h.write(qemu, id as GuestAddr, addr, N);
+}
+
+pub fn trace_write_n_kasan<ET, I, S>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    id: u64,
+    _pc: GuestAddr,
+    addr: GuestAddr,
+    size: usize,
+) where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let h = emulator_modules.get_mut::<KasanModule>().unwrap();
+    h.write(qemu, id as GuestAddr, addr, size);
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_qemu_sys::GuestAddr;
+
+    use super::{KasanError, KasanModule, KasanPoison};
+
+    #[test]
+    fn test_shadow_encoding() {
+        let mut module = KasanModule::default();
+        module.unpoison(0x1000, 13);
+        module.poison(0x1010, 0x1020, KasanPoison::HeapRightRedzone);
+
+        // One valid granule, then 5 valid bytes
+        assert_eq!(module.shadow[&1][0], 0);
+        assert_eq!(module.shadow[&1][1], 5);
+        assert_eq!(module.shadow[&1][2], KasanPoison::HeapRightRedzone as i8);
+        assert_eq!(module.shadow[&1][2].cast_unsigned(), 0xfb);
+        for poison in [
+            KasanPoison::HeapLeftRedzone,
+            KasanPoison::HeapRightRedzone,
+            KasanPoison::HeapFreed,
+        ] {
+            assert_eq!(KasanPoison::from_shadow(poison as i8), poison);
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let mut module = KasanModule::default();
+        // Nothing is poisoned without shadow memory
+        assert_eq!(module.check(0x1000, 8), None);
+
+        module.unpoison(0x1000, 13);
+        assert_eq!(module.check(0x1000, 8), None);
+        assert_eq!(module.check(0x1008, 5), None);
+        assert_eq!(module.check(0x100c, 1), None);
+        assert_eq!(module.check(0x100d, 1), Some(KasanPoison::HeapRightRedzone));
+        assert_eq!(
+            module.check(0x1004, 10),
+            Some(KasanPoison::HeapRightRedzone)
+        );
+        assert_eq!(module.check(0x1004, 0), None);
+
+        module.poison(0x1010, 0x1018, KasanPoison::HeapFreed);
+        assert_eq!(module.check(0x1010, 1), Some(KasanPoison::HeapFreed));
+        // Granules without shadow are valid
+        assert_eq!(module.check(0x1018, 8), None);
+        assert_eq!(module.check(GuestAddr::MAX - 1, 8), None);
+    }
+
+    #[test]
+    fn test_allocation() {
+        let mut module = KasanModule::default().with_redzone(16);
+        module.allocation(0, 0x1010, 20);
+
+        assert_eq!(module.check(0x1000, 1), Some(KasanPoison::HeapLeftRedzone));
+        assert_eq!(module.check(0x1010, 20), None);
+        assert_eq!(module.check(0x1024, 1), Some(KasanPoison::HeapRightRedzone));
+        assert_eq!(module.check(0x1028, 1), Some(KasanPoison::HeapRightRedzone));
+        assert_eq!(module.check(0x1038, 1), None);
+        assert_eq!(module.chunk(0x1000).map(|chunk| chunk.start), Some(0x1010));
+        assert_eq!(module.chunk(0x1037).map(|chunk| chunk.start), Some(0x1010));
+        assert!(module.chunk(0x1038).is_none());
+
+        // Reusing the memory forgets the old chunk
+        module.allocation(0, 0x1030, 8);
+        assert_eq!(module.chunks.len(), 1);
+        assert_eq!(module.check(0x1030, 8), None);
+
+        // Chunks past the end of the address space are not tracked
+        module.allocation(0, GuestAddr::MAX - 8, 16);
+        assert_eq!(module.chunks.len(), 1);
+    }
+
+    #[test]
+    fn test_deallocation() {
+        let mut module = KasanModule::default().with_redzone(8);
+        module.allocation(0, 0x1008, 16);
+
+        assert_eq!(
+            module.free_chunk(0, 0x100c),
+            Some(KasanError::BadFree(0x100c, 0x1008..0x1018))
+        );
+        assert_eq!(module.free_chunk(0, 0x1008), None);
+        assert_eq!(module.check(0x1008, 1), Some(KasanPoison::HeapFreed));
+        assert!(!module.chunks[&0x1008].allocated);
+        assert_eq!(
+            module.free_chunk(0, 0x1008),
+            Some(KasanError::DoubleFree(0x1008))
+        );
+        // Untracked pointers
+        assert_eq!(module.free_chunk(0, 0x2000), None);
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut module = KasanModule::default();
+        module.allocation(0, 0x1000, 8);
+
+        module.snapshot_taken = true;
+        assert_eq!(module.free_chunk(0, 0x1000), None);
+        module.allocation(0, 0x3000, 8);
+        module.poison(0x3000, 0x3008, KasanPoison::HeapFreed);
+        module.restore();
+
+        assert_eq!(module.check(0x1000, 8), None);
+        assert!(module.chunks[&0x1000].allocated);
+        assert!(!module.chunks.contains_key(&0x3000));
+        assert!(!module.shadow.contains_key(&3));
+        assert!(module.shadow_undo.is_empty() && module.chunks_undo.is_empty());
+    }
+}
@@ -0,0 +1,920 @@
+//! MMIO peripheral modelling for bare-metal firmware fuzzing, in the style of `Fuzzware` and `P2IM`.
+//!
+//! The [`MmioModule`] maps I/O regions over the peripheral ranges of the board, so no hand-written
+//! harness per board is needed. Reads of a peripheral register are served from the fuzz input, or
+//! by the [`MmioModel`] of the register, and writes are remembered for the models. Models are set
+//! by hand or learned from the access patterns of the firmware, and the [`MmioPruningStage`]
+//! turns registers whose values never change the coverage into constants, so they stop consuming
+//! input. Interrupts can be raised at times chosen by the input.
+//!
+//! The learned models are kept in the [`MmioModelsMetadata`] of the state, so they survive restarts
+//! of the fuzzer. Objectives only reproduce with the models of their run, the [`MmioModelsFeedback`]
+//! adds them to the metadata of each objective.
+//!
+//! Reads past the end of the input return `0`. Runs should be bounded with a timeout, or a
+//! breakpoint ending the run, as firmware often polls peripherals forever.
+
+use std::{
+    borrow::Cow,
+    ffi::{c_uint, c_void},
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    mem,
+    ops::Range,
+    ptr,
+};
+
+use hashbrown::{HashMap, HashSet};
+use libafl::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::{HasCurrentCorpusId, Testcase},
+    executors::{Executor, ExitKind, HasObservers},
+    feedbacks::{Feedback, StateInitializer},
+    inputs::{HasTargetBytes, Input},
+    observers::{MapObserver, ObserversTuple},
+    stages::{Restartable, RetryCountRestartHelper, Stage},
+    state::{HasCorpus, HasCurrentTestcase, HasExecutions, HasSolutions},
+};
+use libafl_bolts::{
+    AsSlice, Named, impl_serdeany,
+    tuples::{Handle, Handled},
+};
+use libafl_qemu_sys::{GuestPhysAddr, MemoryRegionOps, hwaddr};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Emulator, GuestReg, Qemu, QemuExecutor, Regs,
+    emu::EmulatorModules,
+    modules::{EmulatorModule, EmulatorModuleTuple},
+    qemu::Hook,
+};
+
+/// The default number of runs with the same access pattern before a model is learned
+pub const DEFAULT_MMIO_LEARN_RUNS: u64 = 64;
+/// The default number of pruning trials without a coverage change before a register is pruned
+pub const DEFAULT_MMIO_PRUNE_TRIALS: u64 = 8;
+
+/// How reads of a peripheral register are served
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MmioModel {
+    /// Each read consumes as many bytes of the input as it is wide
+    #[default]
+    Fuzzed,
+    /// Reads always return the same value, without consuming input
+    Constant(u64),
+    /// Reads return the last value written to the register, as for control registers
+    Passthrough,
+    /// Reads return one of the values, picked by one byte of the input
+    Set(Vec<u64>),
+}
+
+/// The [`MmioModel`]s learned by the [`MmioModule`], [`MmioModel::Passthrough`] registers and the
+/// [`MmioModel::Constant`] registers pruned by the [`MmioPruningStage`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MmioModelsMetadata {
+    /// The learned models, by physical address of the register
+    pub models: HashMap<GuestPhysAddr, MmioModel>,
+}
+
+impl_serdeany!(MmioModelsMetadata);
+
+/// Raises the interrupt `irq` in the guest, see [`MmioModule::with_interrupts`]
+pub type RaiseIrqFn = fn(Qemu, u32);
+
+/// Raises an interrupt of the NVIC of a Cortex-M, `irq` is the exception number: 16 plus the
+/// number of the external interrupt line
+#[cfg(cpu_target = "arm")]
+pub fn raise_nvic_irq(qemu: Qemu, irq: u32) {
+    let (Some(cpu), Ok(irq)) = (qemu.current_cpu(), i32::try_from(irq)) else {
+        return;
+    };
+    // # Safety
+    // The `CPUState` is the first member of the `ARMCPU`
+    unsafe {
+        let arm_cpu = cpu.raw_ptr().cast::<libafl_qemu_sys::ARMCPU>();
+        let nvic = (*arm_cpu).env.nvic;
+        if !nvic.is_null() {
+            libafl_qemu_sys::armv7m_nvic_set_pending(nvic.cast(), irq, false);
+        }
+    }
+}
+
+/// A read of a peripheral register which consumed input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioAccess {
+    /// The physical address of the register
+    pub addr: GuestPhysAddr,
+    /// The program counter of the read
+    pub pc: GuestReg,
+    /// The value read
+    pub value: u64,
+}
+
+#[derive(Debug, Default)]
+struct MmioRegister {
+    model: MmioModel,
+    /// The last value written in this run
+    value: u64,
+    written: bool,
+    read: bool,
+    read_before_write: bool,
+    runs_write_then_read: u64,
+    runs_read_before_write: u64,
+    prune_trials: u64,
+    keep_trials: u64,
+}
+
+#[derive(Clone, Copy)]
+struct MmioInterrupts {
+    interval: u64,
+    irqs: &'static [u32],
+    raise: RaiseIrqFn,
+}
+
+impl Debug for MmioInterrupts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MmioInterrupts")
+            .field("interval", &self.interval)
+            .field("irqs", &self.irqs)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Serves the reads of peripheral registers from the fuzz input, see the [module-level
+/// documentation](self).
+#[derive(Debug)]
+pub struct MmioModule {
+    ranges: Vec<Range<GuestPhysAddr>>,
+    priority: i32,
+    registers: HashMap<GuestPhysAddr, MmioRegister>,
+    input: Vec<u8>,
+    cursor: usize,
+    exhausted: bool,
+    learn_runs: u64,
+    prune_trials: u64,
+    interrupts: Option<MmioInterrupts>,
+    blocks: u64,
+    accesses: Vec<MmioAccess>,
+    overrides: HashMap<GuestPhysAddr, u64>,
+    /// The models learned since they were last stored in the state
+    learned: Vec<(GuestPhysAddr, MmioModel)>,
+}
+
+impl MmioModule {
+    /// Creates a new [`MmioModule`] serving the physical address `ranges` of peripherals
+    #[must_use]
+    pub fn new(ranges: Vec<Range<GuestPhysAddr>>) -> Self {
+        Self {
+            ranges,
+            priority: 1,
+            registers: HashMap::new(),
+            input: Vec::new(),
+            cursor: 0,
+            exhausted: false,
+            learn_runs: DEFAULT_MMIO_LEARN_RUNS,
+            prune_trials: DEFAULT_MMIO_PRUNE_TRIALS,
+            interrupts: None,
+            blocks: 0,
+            accesses: Vec::new(),
+            overrides: HashMap::new(),
+            learned: Vec::new(),
+        }
+    }
+
+    /// Sets the priority of the regions over devices emulated by QEMU in the same ranges
+    #[must_use]
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets the model of the register at `addr`
+    #[must_use]
+    pub fn with_model(mut self, addr: GuestPhysAddr, model: MmioModel) -> Self {
+        self.set_model(addr, model);
+        self
+    }
+
+    /// Sets the number of runs a register must be written before it is read, before it is
+    /// modelled as [`MmioModel::Passthrough`]. `0` disables learning.
+    #[must_use]
+    pub fn with_learn_runs(mut self, learn_runs: u64) -> Self {
+        self.learn_runs = learn_runs;
+        self
+    }
+
+    /// Sets the number of [`MmioPruningStage`] trials without a coverage change before a
+    /// register is modelled as [`MmioModel::Constant`]
+    #[must_use]
+    pub fn with_prune_trials(mut self, prune_trials: u64) -> Self {
+        self.prune_trials = prune_trials;
+        self
+    }
+
+    /// Every `interval` executed blocks, consumes one byte of the input to pick one of the
+    /// `irqs`, or none, and raises it with `raise`
+    #[must_use]
+    pub fn with_interrupts(
+        mut self,
+        interval: u64,
+        irqs: &'static [u32],
+        raise: RaiseIrqFn,
+    ) -> Self {
+        self.interrupts = Some(MmioInterrupts {
+            interval: interval.max(1),
+            irqs,
+            raise,
+        });
+        self
+    }
+
+    /// The model of the register at `addr`
+    #[must_use]
+    pub fn model(&self, addr: GuestPhysAddr) -> Option<&MmioModel> {
+        self.registers.get(&addr).map(|register| &register.model)
+    }
+
+    /// Sets the model of the register at `addr`
+    pub fn set_model(&mut self, addr: GuestPhysAddr, model: MmioModel) {
+        self.registers.entry(addr).or_default().model = model;
+    }
+
+    /// The reads of the last run which consumed input
+    #[must_use]
+    pub fn accesses(&self) -> &[MmioAccess] {
+        &self.accesses
+    }
+
+    /// Whether the last run read past the end of its input
+    #[must_use]
+    pub fn exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    /// Makes all reads of the register at `addr` return `value` in the next runs, or stops it
+    pub fn set_override(&mut self, addr: GuestPhysAddr, value: Option<u64>) {
+        match value {
+            Some(value) => self.overrides.insert(addr, value),
+            None => self.overrides.remove(&addr),
+        };
+    }
+
+    /// The [`MmioModel::Fuzzed`] registers read in the last run, with their first value
+    #[must_use]
+    pub fn pruning_candidates(&self) -> Vec<(GuestPhysAddr, u64)> {
+        let mut seen = HashSet::new();
+        self.accesses
+            .iter()
+            .filter(|access| {
+                self.model(access.addr) == Some(&MmioModel::Fuzzed) && seen.insert(access.addr)
+            })
+            .map(|access| (access.addr, access.value))
+            .collect()
+    }
+
+    /// Records a pruning trial of the register at `addr`, whose reads were replaced by `value`.
+    /// Registers which never change the coverage become [`MmioModel::Constant`].
+    pub fn record_prune_trial(&mut self, addr: GuestPhysAddr, value: u64, changed_coverage: bool) {
+        let prune_trials = self.prune_trials;
+        let register = self.registers.entry(addr).or_default();
+        if changed_coverage {
+            register.keep_trials += 1;
+            return;
+        }
+        register.prune_trials += 1;
+        if register.keep_trials == 0
+            && register.prune_trials >= prune_trials
+            && register.model == MmioModel::Fuzzed
+        {
+            log::info!("Pruning MMIO register {addr:#x}, reads return {value:#x}");
+            register.model = MmioModel::Constant(value);
+            self.learned.push((addr, MmioModel::Constant(value)));
+        }
+    }
+
+    /// Moves the models learned since the last call into the [`MmioModelsMetadata`] of the state
+    pub fn store_learned<S>(&mut self, state: &mut S)
+    where
+        S: HasMetadata,
+    {
+        if self.learned.is_empty() {
+            return;
+        }
+        state
+            .metadata_or_insert_with(MmioModelsMetadata::default)
+            .models
+            .extend(self.learned.drain(..));
+    }
+
+    /// Consumes `len` bytes of the input as a little-endian value
+    fn consume(&mut self, len: usize) -> Option<u64> {
+        let available = self.input.len().saturating_sub(self.cursor).min(len);
+        if available < len {
+            self.exhausted = true;
+        }
+        if available == 0 {
+            return None;
+        }
+        let mut bytes = [0; 8];
+        bytes[..available].copy_from_slice(&self.input[self.cursor..self.cursor + available]);
+        self.cursor += available;
+        Some(u64::from_le_bytes(bytes))
+    }
+
+    /// Consumes one byte of the input, to pick an alternative
+    fn consume_byte(&mut self) -> Option<usize> {
+        let Some(byte) = self.input.get(self.cursor) else {
+            self.exhausted = true;
+            return None;
+        };
+        self.cursor += 1;
+        Some(usize::from(*byte))
+    }
+
+    fn read(&mut self, addr: GuestPhysAddr, size: usize, pc: GuestReg) -> u64 {
+        let mask = if size >= 8 {
+            u64::MAX
+        } else {
+            (1 << (size * 8)) - 1
+        };
+        if let Some(value) = self.overrides.get(&addr) {
+            return value & mask;
+        }
+
+        let register = self.registers.entry(addr).or_default();
+        register.read = true;
+        register.read_before_write |= !register.written;
+        let value = match register.model.clone() {
+            MmioModel::Constant(value) => return value & mask,
+            MmioModel::Passthrough => return register.value & mask,
+            MmioModel::Fuzzed => self.consume(size.min(8)),
+            MmioModel::Set(values) => self
+                .consume_byte()
+                .and_then(|choice| values.get(choice % values.len().max(1)).copied()),
+        };
+        let value = value.unwrap_or(0) & mask;
+        self.accesses.push(MmioAccess { addr, pc, value });
+        value
+    }
+
+    fn write(&mut self, addr: GuestPhysAddr, value: u64) {
+        let register = self.registers.entry(addr).or_default();
+        register.value = value;
+        register.written = true;
+    }
+
+    fn on_block(&mut self, qemu: Qemu) {
+        let Some(interrupts) = self.interrupts else {
+            return;
+        };
+        self.blocks += 1;
+        if self.blocks < interrupts.interval {
+            return;
+        }
+        self.blocks = 0;
+        // One choice more than interrupts, for none
+        if let Some(choice) = self.consume_byte()
+            && let Some(irq) = interrupts.irqs.get(choice % (interrupts.irqs.len() + 1))
+        {
+            (interrupts.raise)(qemu, *irq);
+        }
+    }
+
+    /// Learns [`MmioModel::Passthrough`] models for registers always written before being read
+    fn learn(&mut self) {
+        for (addr, register) in &mut self.registers {
+            if register.read {
+                if register.read_before_write {
+                    register.runs_read_before_write += 1;
+                } else {
+                    register.runs_write_then_read += 1;
+                }
+            }
+            register.value = 0;
+            register.written = false;
+            register.read = false;
+            register.read_before_write = false;
+
+            if self.learn_runs > 0
+                && register.model == MmioModel::Fuzzed
+                && register.runs_read_before_write == 0
+                && register.runs_write_then_read >= self.learn_runs
+            {
+                log::info!("Modelling MMIO register {addr:#x} as passthrough");
+                register.model = MmioModel::Passthrough;
+                self.learned.push((*addr, MmioModel::Passthrough));
+            }
+        }
+    }
+}
+
+/// The module of the current run, called back from QEMU
+unsafe fn mmio_module<'a, ET, I, S>() -> Option<&'a mut MmioModule>
+where
+    ET: EmulatorModuleTuple<I, S>,
+    I: HasTargetBytes + Unpin,
+    S: Unpin,
+{
+    unsafe { EmulatorModules::<ET, I, S>::emulator_modules_mut() }?.get_mut::<MmioModule>()
+}
+
+unsafe extern "C" fn mmio_read<ET, I, S>(opaque: *mut c_void, offset: hwaddr, size: c_uint) -> u64
+where
+    ET: EmulatorModuleTuple<I, S>,
+    I: HasTargetBytes + Unpin,
+    S: Unpin,
+{
+    let qemu = unsafe { Qemu::get_unchecked() };
+    let pc = qemu
+        .current_cpu()
+        .and_then(|cpu| cpu.read_reg(Regs::Pc).ok())
+        .unwrap_or_default();
+    let Some(module) = (unsafe { mmio_module::<ET, I, S>() }) else {
+        return 0;
+    };
+    // The opaque pointer is the index of the range
+    let addr = module.ranges[opaque.addr()].start + offset;
+    module.read(addr, size as usize, pc)
+}
+
+unsafe extern "C" fn mmio_write<ET, I, S>(
+    opaque: *mut c_void,
+    offset: hwaddr,
+    value: u64,
+    _size: c_uint,
+) where
+    ET: EmulatorModuleTuple<I, S>,
+    I: HasTargetBytes + Unpin,
+    S: Unpin,
+{
+    if let Some(module) = unsafe { mmio_module::<ET, I, S>() } {
+        let addr = module.ranges[opaque.addr()].start + offset;
+        module.write(addr, value);
+    }
+}
+
+fn exec_interrupt_block<ET, I, S>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    _id: u64,
+) where
+    ET: EmulatorModuleTuple<I, S>,
+    I: HasTargetBytes + Unpin,
+    S: Unpin,
+{
+    if let Some(module) = emulator_modules.get_mut::<MmioModule>() {
+        module.on_block(qemu);
+    }
+}
+
+impl<I, S> EmulatorModule<I, S> for MmioModule
+where
+    I: HasTargetBytes + Unpin,
+    S: HasMetadata + Unpin,
+{
+    fn post_qemu_init<ET>(&mut self, qemu: Qemu, _emulator_modules: &mut EmulatorModules<ET, I, S>)
+    where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        // # Safety
+        // All-zero is a valid `MemoryRegionOps`, with native endianness and no callbacks
+        let mut ops: MemoryRegionOps = unsafe { mem::zeroed() };
+        ops.read = Some(mmio_read::<ET, I, S>);
+        ops.write = Some(mmio_write::<ET, I, S>);
+        ops.valid.min_access_size = 1;
+        ops.valid.max_access_size = 8;
+        ops.impl_.min_access_size = 1;
+        ops.impl_.max_access_size = 8;
+        let ops = Box::leak(Box::new(ops));
+
+        for (idx, range) in self.ranges.iter().enumerate() {
+            // # Safety
+            // The callbacks only use the index of the range, and find the module through the
+            // emulator modules
+            unsafe {
+                qemu.map_io_region(
+                    range.start,
+                    range.end - range.start,
+                    &format!("libafl-mmio-{idx}"),
+                    ops,
+                    ptr::without_provenance_mut(idx),
+                    self.priority,
+                );
+            }
+        }
+    }
+
+    fn first_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        emulator_modules: &mut EmulatorModules<ET, I, S>,
+        state: &mut S,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        // The models learned before a restart
+        if let Ok(meta) = state.metadata::<MmioModelsMetadata>() {
+            for (addr, model) in &meta.models {
+                self.set_model(*addr, model.clone());
+            }
+        }
+
+        if self.interrupts.is_some() {
+            emulator_modules.blocks(
+                Hook::Empty,
+                Hook::Empty,
+                Hook::Function(exec_interrupt_block::<ET, I, S>),
+            );
+        }
+    }
+
+    fn pre_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        state: &mut S,
+        input: &I,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        // Only the models of this run, learned before it, end up in its objective
+        self.store_learned(state);
+
+        self.input.clear();
+        self.input
+            .extend_from_slice(input.target_bytes().as_slice());
+        self.cursor = 0;
+        self.exhausted = false;
+        self.blocks = 0;
+        self.accesses.clear();
+    }
+
+    fn post_exec<OT, ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+        _observers: &mut OT,
+        _exit_kind: &mut ExitKind,
+    ) where
+        OT: ObserversTuple<I, S>,
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        self.learn();
+    }
+}
+
+/// Adds the [`MmioModelsMetadata`] of the state to each objective, as it only reproduces with the
+/// learned models of its run. Never interesting by itself, it is meant to be combined with the
+/// objective feedbacks, for instance with `feedback_or!(CrashFeedback::new(), MmioModelsFeedback)`.
+/// An `OnDiskCorpus` storing metadata writes them next to the objective.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MmioModelsFeedback;
+
+impl Named for MmioModelsFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("MmioModelsFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for MmioModelsFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for MmioModelsFeedback
+where
+    S: HasMetadata,
+{
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let models = state
+            .metadata::<MmioModelsMetadata>()
+            .cloned()
+            .unwrap_or_default();
+        testcase.add_metadata(models);
+        Ok(())
+    }
+}
+
+/// Executors giving access to their [`MmioModule`]
+pub trait HasMmioModule {
+    /// The [`MmioModule`] of the emulator, if any
+    fn mmio_module_mut(&mut self) -> Option<&mut MmioModule>;
+}
+
+impl<C, CM, ED, EM, ET, H, I, OT, S, SM, Z> HasMmioModule
+    for QemuExecutor<'_, C, CM, ED, EM, ET, H, I, OT, S, SM, Z>
+where
+    ET: EmulatorModuleTuple<I, S>,
+    H: FnMut(&mut Emulator<C, CM, ED, ET, I, S, SM>, &mut S, &I) -> ExitKind,
+    I: Input + HasTargetBytes + Unpin,
+    OT: ObserversTuple<I, S>,
+    S: HasExecutions + HasSolutions<I> + HasCurrentTestcase<I> + Unpin,
+{
+    fn mmio_module_mut(&mut self) -> Option<&mut MmioModule> {
+        self.inner_mut()
+            .exposed_executor_state_mut()
+            .modules_mut()
+            .get_mut::<MmioModule>()
+    }
+}
+
+/// Marks the testcases the [`MmioPruningStage`] ran on
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MmioPruningMetadata {
+    /// The registers tried with a constant value
+    pub trials: usize,
+}
+
+impl_serdeany!(MmioPruningMetadata);
+
+/// The default name of the [`MmioPruningStage`]
+pub const MMIO_PRUNING_STAGE_NAME: &str = "mmio_pruning";
+
+/// For each new testcase, replaces the reads of each [`MmioModel::Fuzzed`] register by its first
+/// value, and compares the coverage with the original run. Registers which never change the
+/// coverage become [`MmioModel::Constant`] in the [`MmioModule`], so the input bytes go to
+/// registers which matter.
+#[derive(Debug)]
+pub struct MmioPruningStage<C, O> {
+    name: Cow<'static, str>,
+    map_observer_handle: Handle<C>,
+    max_trials: usize,
+    phantom: PhantomData<O>,
+}
+
+impl<C, O> MmioPruningStage<C, O>
+where
+    C: Named,
+{
+    /// Creates a new [`MmioPruningStage`], comparing the coverage of the `map_observer`
+    #[must_use]
+    pub fn new(map_observer: &C) -> Self {
+        Self {
+            name: Cow::Borrowed(MMIO_PRUNING_STAGE_NAME),
+            map_observer_handle: map_observer.handle(),
+            max_trials: 16,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the maximal number of registers tried per testcase
+    #[must_use]
+    pub fn with_max_trials(mut self, max_trials: usize) -> Self {
+        self.max_trials = max_trials;
+        self
+    }
+}
+
+impl<C, O> Named for MmioPruningStage<C, O> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+/// Runs `input` and hashes the coverage, or returns `None` if the run did not exit normally
+fn run_and_hash<C, E, EM, I, O, S, Z>(
+    fuzzer: &mut Z,
+    executor: &mut E,
+    state: &mut S,
+    mgr: &mut EM,
+    input: &I,
+    handle: &Handle<C>,
+) -> Result<Option<u64>, Error>
+where
+    C: AsRef<O>,
+    E: Executor<EM, I, S, Z> + HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    O: MapObserver,
+{
+    executor.observers_mut().pre_exec_all(state, input)?;
+    let exit_kind = executor.run_target(fuzzer, state, mgr, input)?;
+    executor
+        .observers_mut()
+        .post_exec_all(state, input, &exit_kind)?;
+    if exit_kind != ExitKind::Ok {
+        return Ok(None);
+    }
+    Ok(Some(executor.observers()[handle].as_ref().hash_simple()))
+}
+
+impl<C, E, EM, I, O, S, Z> Stage<E, EM, S, Z> for MmioPruningStage<C, O>
+where
+    C: AsRef<O>,
+    E: Executor<EM, I, S, Z> + HasObservers + HasMmioModule,
+    E::Observers: ObserversTuple<I, S>,
+    O: MapObserver,
+    S: HasCorpus<I> + HasCurrentTestcase<I> + HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+    I: Clone,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        mgr: &mut EM,
+    ) -> Result<(), Error> {
+        // Try each testcase only once
+        if state
+            .current_testcase()?
+            .has_metadata::<MmioPruningMetadata>()
+        {
+            return Ok(());
+        }
+        let input = state.current_input_cloned()?;
+        let handle = &self.map_observer_handle;
+
+        let baseline =
+            run_and_hash::<C, E, EM, I, O, S, Z>(fuzzer, executor, state, mgr, &input, handle)?;
+        let mut candidates = match (baseline, executor.mmio_module_mut()) {
+            (Some(_), Some(module)) => module.pruning_candidates(),
+            _ => Vec::new(),
+        };
+        candidates.truncate(self.max_trials);
+
+        for (addr, value) in &candidates {
+            if let Some(module) = executor.mmio_module_mut() {
+                module.set_override(*addr, Some(*value));
+            }
+            let hash =
+                run_and_hash::<C, E, EM, I, O, S, Z>(fuzzer, executor, state, mgr, &input, handle);
+            let Some(module) = executor.mmio_module_mut() else {
+                break;
+            };
+            module.set_override(*addr, None);
+            module.record_prune_trial(*addr, *value, hash? != baseline);
+            module.store_learned(state);
+        }
+
+        state
+            .current_testcase_mut()?
+            .add_metadata(MmioPruningMetadata {
+                trials: candidates.len(),
+            });
+        Ok(())
+    }
+}
+
+impl<C, O, S> Restartable<S> for MmioPruningStage<C, O>
+where
+    S: HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        // Constant registers may hang the target, don't retry
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::{HasMetadata, state::NopState};
+
+    use super::{MmioAccess, MmioModel, MmioModelsMetadata, MmioModule};
+
+    fn module_with_input(input: &[u8]) -> MmioModule {
+        let mut module = MmioModule::new(vec![0x4000_0000..0x4000_1000]);
+        module.input = input.to_vec();
+        module
+    }
+
+    #[test]
+    fn test_consume() {
+        let mut module = module_with_input(&[1, 2, 3]);
+        assert_eq!(module.consume(2), Some(0x0201));
+        assert!(!module.exhausted());
+        // Reads past the end get the remaining bytes
+        assert_eq!(module.consume(2), Some(0x03));
+        assert!(module.exhausted());
+        assert_eq!(module.consume(1), None);
+        assert_eq!(module.consume_byte(), None);
+    }
+
+    #[test]
+    fn test_read() {
+        let mut module = module_with_input(&[0xaa, 0xbb, 0xcc, 2])
+            .with_model(0x10, MmioModel::Constant(0x1234))
+            .with_model(0x20, MmioModel::Passthrough)
+            .with_model(0x30, MmioModel::Set(vec![7, 8, 9]));
+
+        assert_eq!(module.read(0x10, 1, 0), 0x34);
+        module.write(0x20, 0x5678);
+        assert_eq!(module.read(0x20, 2, 0), 0x5678);
+        assert_eq!(module.read(0x40, 2, 0x100), 0xbbaa);
+        assert_eq!(module.read(0x40, 1, 0x104), 0xcc);
+        assert_eq!(module.read(0x30, 4, 0x108), 9);
+        // The input is exhausted
+        assert_eq!(module.read(0x40, 4, 0x10c), 0);
+        assert!(module.exhausted());
+
+        module.set_override(0x40, Some(0x1_0000_0001));
+        assert_eq!(module.read(0x40, 4, 0x110), 1);
+        module.set_override(0x40, None);
+
+        // Only the reads consuming input are recorded
+        assert_eq!(
+            module.accesses(),
+            [
+                MmioAccess {
+                    addr: 0x40,
+                    pc: 0x100,
+                    value: 0xbbaa
+                },
+                MmioAccess {
+                    addr: 0x40,
+                    pc: 0x104,
+                    value: 0xcc
+                },
+                MmioAccess {
+                    addr: 0x30,
+                    pc: 0x108,
+                    value: 9
+                },
+                MmioAccess {
+                    addr: 0x40,
+                    pc: 0x10c,
+                    value: 0
+                },
+            ]
+        );
+        assert_eq!(module.pruning_candidates(), [(0x40, 0xbbaa)]);
+    }
+
+    #[test]
+    fn test_learn() {
+        let mut module = module_with_input(&[]).with_learn_runs(2);
+        let mut state = NopState::<()>::new();
+        for _ in 0..2 {
+            // 0x10 is written before it is read, 0x20 is not
+            module.write(0x10, 1);
+            module.read(0x10, 4, 0);
+            module.read(0x20, 4, 0);
+            module.write(0x20, 1);
+            module.learn();
+        }
+        assert_eq!(module.model(0x10), Some(&MmioModel::Passthrough));
+        assert_eq!(module.model(0x20), Some(&MmioModel::Fuzzed));
+
+        module.store_learned(&mut state);
+        let meta = state.metadata::<MmioModelsMetadata>().unwrap();
+        assert_eq!(meta.models.len(), 1);
+        assert_eq!(meta.models[&0x10], MmioModel::Passthrough);
+    }
+
+    #[test]
+    fn test_record_prune_trial() {
+        let mut module = module_with_input(&[]).with_prune_trials(2);
+        let mut state = NopState::<()>::new();
+
+        module.record_prune_trial(0x10, 5, false);
+        assert_eq!(module.model(0x10), Some(&MmioModel::Fuzzed));
+        module.record_prune_trial(0x10, 5, false);
+        assert_eq!(module.model(0x10), Some(&MmioModel::Constant(5)));
+
+        // Registers which changed the coverage once are kept
+        module.record_prune_trial(0x20, 5, true);
+        module.record_prune_trial(0x20, 5, false);
+        module.record_prune_trial(0x20, 5, false);
+        assert_eq!(module.model(0x20), Some(&MmioModel::Fuzzed));
+
+        module.store_learned(&mut state);
+        let meta = state.metadata::<MmioModelsMetadata>().unwrap();
+        assert_eq!(meta.models.len(), 1);
+        assert_eq!(meta.models[&0x10], MmioModel::Constant(5));
+    }
+
+    #[test]
+    fn test_pruning_candidates() {
+        let mut module = module_with_input(&[1, 2, 3, 4]).with_model(0x30, MmioModel::Fuzzed);
+        module.read(0x10, 1, 0);
+        module.read(0x20, 1, 0);
+        module.read(0x10, 1, 0);
+        module.read(0x30, 1, 0);
+        module.set_model(0x30, MmioModel::Constant(0));
+
+        // The first value of each fuzzed register
+        assert_eq!(module.pruning_candidates(), [(0x10, 1), (0x20, 2)]);
+    }
+}
@@ -1 +1,18 @@
+pub mod introspection;
+pub use introspection::{
+    GuestKernelModule, GuestTask, IntrospectionTarget, KernelSymbols, LinuxIntrospectionModule,
+    LinuxOffsets, ProcessSelector,
+};
 
+pub mod kasan;
+pub use kasan::{KasanAllocator, KasanError, KasanModule};
+
+pub mod mmio;
+pub use mmio::{
+    HasMmioModule, MmioModel, MmioModelsFeedback, MmioModelsMetadata, MmioModule, MmioPruningStage,
+};
+
+pub mod replay;
+pub use replay::{
+    RecordReplayModule, ReplayBundle, ReplayBundleFeedback, ReplayExecutor, ReplayOutcome,
+};
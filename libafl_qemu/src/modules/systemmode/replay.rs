@@ -0,0 +1,726 @@
+//! Deterministic record and replay of system-mode executions.
+//!
+//! Flaky crashes of full-system targets usually come from interrupts, timers and clock reads
+//! which differ between runs. With [`Icount`] and [`Rtc::Vm`] in the [`QemuConfig`], the virtual
+//! clock of the guest follows the number of executed instructions, so the runs starting from the
+//! same snapshot are deterministic.
+//!
+//! During fuzzing, the [`RecordReplayModule`] records the snapshot and the instruction counts of
+//! each run, and the [`ReplayBundleFeedback`] writes them with the command line of QEMU in a
+//! [`ReplayBundle`], next to each objective. If QEMU fuzzes with `rr=record`, the non-deterministic
+//! events of each run (interrupts, device inputs, clock reads) are in its record/replay log: with
+//! [`RecordReplayModule::with_record_log`], the events of the run of each objective are copied to
+//! a log of their own, next to the bundle.
+//!
+//! To reproduce an objective, start QEMU with the command line of [`ReplayBundle::qemu_args`] and
+//! run the objective with a [`ReplayExecutor`], which restores the snapshot of the record first.
+//! In [`RecordReplayMode::Record`] mode, QEMU writes the events of the run to a new log, and the
+//! executor checks that the run reproduces the exit kind and the instruction count of the bundle.
+//! In [`RecordReplayMode::Replay`] mode, QEMU replays the events of a log exactly, either the
+//! events recorded during fuzzing, at [`ReplayBundle::events_path`], or a new log, for instance to
+//! debug the run with `gdb`.
+//!
+//! [`Icount`]: crate::config::Icount
+//! [`Rtc::Vm`]: crate::config::Rtc::Vm
+//! [`QemuConfig`]: crate::config::QemuConfig
+
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+use libafl::{
+    Error, HasMetadata,
+    corpus::Testcase,
+    executors::{Executor, ExitKind, HasObservers},
+    feedbacks::{Feedback, StateInitializer},
+    inputs::Input,
+    observers::ObserversTuple,
+    state::{HasCurrentTestcase, HasExecutions, HasSolutions},
+};
+use libafl_bolts::{Named, impl_serdeany, tuples::RefIndexable};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Emulator, Qemu, QemuExecutor,
+    config::{IcountShift, RecordReplay, RecordReplayMode},
+    emu::EmulatorModules,
+    modules::{EmulatorModule, EmulatorModuleTuple},
+};
+
+/// The length of the header of the record/replay logs of QEMU: the version of the log format and
+/// a reserved word
+const RR_HEADER_LEN: usize = 12;
+
+/// The instruction counts and the end of a run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayRecord {
+    /// The snapshot the run starts from
+    pub snapshot: String,
+    /// The instruction count of the guest at the start of the run
+    pub start_icount: u64,
+    /// The instruction count of the guest at the end of the run
+    pub end_icount: u64,
+    /// How the run ended
+    pub exit_kind: ExitKind,
+    /// The byte range of the events of the run in the record/replay log, if QEMU recorded them
+    #[serde(default)]
+    pub events: Option<Range<u64>>,
+}
+
+impl ReplayRecord {
+    /// The number of instructions executed by the run
+    #[must_use]
+    pub fn executed_instructions(&self) -> u64 {
+        self.end_icount.saturating_sub(self.start_icount)
+    }
+}
+
+/// The [`ReplayRecord`] of the last run, in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastReplayRecordMetadata {
+    /// The record
+    pub record: ReplayRecord,
+    /// The record/replay log QEMU writes the events to, if any
+    pub record_log: Option<PathBuf>,
+}
+
+impl_serdeany!(LastReplayRecordMetadata);
+
+/// Everything needed to reproduce an objective, written next to it by the
+/// [`ReplayBundleFeedback`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayBundle {
+    /// The file name of the objective, in the directory of the bundle
+    pub objective: String,
+    /// The record of the run of the objective
+    pub record: ReplayRecord,
+    /// The command line QEMU ran with during fuzzing
+    pub qemu_args: Vec<String>,
+    /// The file name of the record/replay log of the events of the run, in the directory of the
+    /// bundle, if they were recorded
+    #[serde(default)]
+    pub events: Option<String>,
+}
+
+impl_serdeany!(ReplayBundle);
+
+impl ReplayBundle {
+    /// The path of the bundle of the objective at `objective`
+    #[must_use]
+    pub fn path_of(objective: &Path) -> PathBuf {
+        let name = objective.file_name().unwrap_or_default().to_string_lossy();
+        objective.with_file_name(format!(".{name}.replay"))
+    }
+
+    /// The path of the record/replay log of the events of the objective at `objective`
+    #[must_use]
+    pub fn events_path_of(objective: &Path) -> PathBuf {
+        let name = objective.file_name().unwrap_or_default().to_string_lossy();
+        objective.with_file_name(format!(".{name}.rr"))
+    }
+
+    /// The path of the record/replay log of the events of this bundle, stored at `bundle_path`
+    #[must_use]
+    pub fn events_path(&self, bundle_path: &Path) -> Option<PathBuf> {
+        self.events
+            .as_ref()
+            .map(|events| bundle_path.with_file_name(events))
+    }
+
+    /// Writes the events in the byte range `events` of the record/replay log at `log` to a log of
+    /// their own at `path`
+    pub fn extract_events(log: &Path, events: Range<u64>, path: &Path) -> Result<(), Error> {
+        let mut log = File::open(log)?;
+        let mut header = [0; RR_HEADER_LEN];
+        log.read_exact(&mut header)?;
+        let start = events.start.max(RR_HEADER_LEN as u64);
+        log.seek(SeekFrom::Start(start))?;
+
+        let mut file = File::create(path)?;
+        file.write_all(&header)?;
+        let len = events.end.saturating_sub(start);
+        if io::copy(&mut log.take(len), &mut file)? != len {
+            return Err(Error::illegal_state(
+                "The record/replay log ends before the events of the run",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Loads a bundle
+    pub fn load<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        serde_json::from_slice(&fs::read(path)?)
+            .map_err(|err| Error::serialize(format!("Invalid replay bundle: {err}")))
+    }
+
+    /// Stores the bundle
+    pub fn store<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let bundle = serde_json::to_vec_pretty(self)
+            .map_err(|err| Error::serialize(format!("Could not serialize the bundle: {err}")))?;
+        fs::write(path, bundle)?;
+        Ok(())
+    }
+
+    /// The command line of QEMU during fuzzing, recording the events of the execution to the
+    /// log `rr_file`, or replaying them from it, depending on `mode`
+    #[must_use]
+    pub fn qemu_args(&self, mode: RecordReplayMode, rr_file: &Path) -> Vec<String> {
+        let record_replay = RecordReplay::builder()
+            .mode(mode)
+            .file(rr_file)
+            .build()
+            .to_string();
+        let mut args = self.qemu_args.clone();
+        if let Some(pos) = args.iter().position(|arg| arg == "-icount")
+            && let Some(icount) = args.get_mut(pos + 1)
+        {
+            // Keep the shift of the fuzzing runs, but not a previous log
+            let options = icount
+                .split(',')
+                .filter(|option| !option.starts_with("rr"))
+                .chain([record_replay.as_str()])
+                .collect::<Vec<_>>()
+                .join(",");
+            *icount = options;
+        } else {
+            args.push("-icount".to_string());
+            args.push(format!("{},{record_replay}", IcountShift::default()));
+        }
+        args
+    }
+}
+
+/// The outcome of a run checked against a [`ReplayRecord`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayOutcome {
+    /// The run ended the same way after the same number of instructions
+    Reproduced,
+    /// The run diverged from the record
+    Diverged {
+        /// The record of the bundle
+        expected: ReplayRecord,
+        /// The record of the run
+        actual: ReplayRecord,
+    },
+}
+
+impl ReplayOutcome {
+    fn check(expected: &ReplayRecord, actual: ReplayRecord) -> Self {
+        if expected.exit_kind == actual.exit_kind
+            && expected.executed_instructions() == actual.executed_instructions()
+        {
+            Self::Reproduced
+        } else {
+            Self::Diverged {
+                expected: expected.clone(),
+                actual,
+            }
+        }
+    }
+}
+
+/// Records the instruction counts of each run, for the [`ReplayBundleFeedback`], and checks the
+/// runs of a [`ReplayExecutor`] against their bundle. QEMU must run with `-icount`.
+#[derive(Debug)]
+pub struct RecordReplayModule {
+    snapshot: String,
+    start_icount: u64,
+    record_log: Option<PathBuf>,
+    start_events: Option<u64>,
+    expected: Option<ReplayRecord>,
+    outcome: Option<ReplayOutcome>,
+}
+
+impl RecordReplayModule {
+    /// Creates a new [`RecordReplayModule`], for runs starting from the snapshot `snapshot`
+    #[must_use]
+    pub fn new(snapshot: &str) -> Self {
+        Self {
+            snapshot: snapshot.to_string(),
+            start_icount: 0,
+            record_log: None,
+            start_events: None,
+            expected: None,
+            outcome: None,
+        }
+    }
+
+    /// Records where the events of each run are in the record/replay log at `record_log`. QEMU
+    /// must run with `rr=record` to this log.
+    #[must_use]
+    pub fn with_record_log<P>(mut self, record_log: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.record_log = Some(record_log.as_ref().to_path_buf());
+        self
+    }
+
+    /// The current length of the record/replay log
+    fn record_log_len(&self) -> Option<u64> {
+        let record_log = self.record_log.as_ref()?;
+        // QEMU writes the log through a buffered stream
+        unsafe {
+            libc::fflush(ptr::null_mut());
+        }
+        match fs::metadata(record_log) {
+            Ok(metadata) => Some(metadata.len()),
+            Err(err) => {
+                log::warn!("Could not read the record/replay log {record_log:?}: {err}");
+                None
+            }
+        }
+    }
+
+    /// The snapshot the runs start from
+    #[must_use]
+    pub fn snapshot(&self) -> &str {
+        &self.snapshot
+    }
+
+    /// Checks the next run against `record`
+    pub fn expect(&mut self, record: ReplayRecord) {
+        self.expected = Some(record);
+        self.outcome = None;
+    }
+
+    /// The outcome of the last run checked against a record
+    pub fn take_outcome(&mut self) -> Option<ReplayOutcome> {
+        self.outcome.take()
+    }
+}
+
+impl<I, S> EmulatorModule<I, S> for RecordReplayModule
+where
+    I: Unpin,
+    S: Unpin + HasMetadata,
+{
+    fn first_exec<ET>(
+        &mut self,
+        qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        if qemu.icount() == 0 && qemu.record_replay_mode().is_none() {
+            log::warn!("The RecordReplayModule needs QEMU to run with -icount");
+        }
+    }
+
+    fn pre_exec<ET>(
+        &mut self,
+        qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        self.start_icount = qemu.icount();
+        self.start_events = self.record_log_len();
+    }
+
+    fn post_exec<OT, ET>(
+        &mut self,
+        qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        state: &mut S,
+        _input: &I,
+        _observers: &mut OT,
+        exit_kind: &mut ExitKind,
+    ) where
+        OT: ObserversTuple<I, S>,
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        let record = ReplayRecord {
+            snapshot: self.snapshot.clone(),
+            start_icount: self.start_icount,
+            end_icount: qemu.icount(),
+            exit_kind: *exit_kind,
+            events: self
+                .start_events
+                .zip(self.record_log_len())
+                .map(|(start, end)| start..end),
+        };
+        if let Some(expected) = self.expected.take() {
+            self.outcome = Some(ReplayOutcome::check(&expected, record.clone()));
+        }
+        state.add_metadata(LastReplayRecordMetadata {
+            record,
+            record_log: self.record_log.clone(),
+        });
+    }
+}
+
+/// Gives access to the [`RecordReplayModule`] of an executor
+pub trait HasRecordReplayModule {
+    /// The [`RecordReplayModule`] of the emulator, if any
+    fn record_replay_module_mut(&mut self) -> Option<&mut RecordReplayModule>;
+}
+
+impl<C, CM, ED, EM, ET, H, I, OT, S, SM, Z> HasRecordReplayModule
+    for QemuExecutor<'_, C, CM, ED, EM, ET, H, I, OT, S, SM, Z>
+where
+    ET: EmulatorModuleTuple<I, S>,
+    H: FnMut(&mut Emulator<C, CM, ED, ET, I, S, SM>, &mut S, &I) -> ExitKind,
+    I: Input + Unpin,
+    OT: ObserversTuple<I, S>,
+    S: HasExecutions + HasSolutions<I> + HasCurrentTestcase<I> + HasMetadata + Unpin,
+{
+    fn record_replay_module_mut(&mut self) -> Option<&mut RecordReplayModule> {
+        self.inner_mut()
+            .exposed_executor_state_mut()
+            .modules_mut()
+            .get_mut::<RecordReplayModule>()
+    }
+}
+
+/// Runs the objectives of [`ReplayBundle`]s from the snapshot of their record, checking each run
+/// against the record of its bundle
+#[derive(Debug)]
+pub struct ReplayExecutor<E> {
+    inner: E,
+    bundle: Option<ReplayBundle>,
+    outcome: Option<ReplayOutcome>,
+}
+
+impl<E> ReplayExecutor<E> {
+    /// Creates a new [`ReplayExecutor`], running the objectives with `inner`
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            bundle: None,
+            outcome: None,
+        }
+    }
+
+    /// Checks the next runs against `bundle`
+    pub fn set_bundle(&mut self, bundle: ReplayBundle) {
+        self.bundle = Some(bundle);
+    }
+
+    /// The outcome of the last run
+    #[must_use]
+    pub fn outcome(&self) -> Option<&ReplayOutcome> {
+        self.outcome.as_ref()
+    }
+
+    /// The inner executor
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// The inner executor (mutable)
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.inner
+    }
+
+    /// Loads the bundle at `bundle_path` and runs its objective
+    pub fn replay<EM, I, S, Z>(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut S,
+        mgr: &mut EM,
+        bundle_path: &Path,
+    ) -> Result<ReplayOutcome, Error>
+    where
+        E: Executor<EM, I, S, Z> + HasRecordReplayModule,
+        I: Input,
+    {
+        let bundle = ReplayBundle::load(bundle_path)?;
+        let input = I::from_file(bundle_path.with_file_name(&bundle.objective))?;
+        self.set_bundle(bundle);
+        self.run_target(fuzzer, state, mgr, &input)?;
+        self.outcome
+            .clone()
+            .ok_or_else(|| Error::illegal_state("The run was not checked against the bundle"))
+    }
+}
+
+impl<E, EM, I, S, Z> Executor<EM, I, S, Z> for ReplayExecutor<E>
+where
+    E: Executor<EM, I, S, Z> + HasRecordReplayModule,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut S,
+        mgr: &mut EM,
+        input: &I,
+    ) -> Result<ExitKind, Error> {
+        let module = self
+            .inner
+            .record_replay_module_mut()
+            .ok_or_else(|| Error::illegal_state("The ReplayExecutor needs a RecordReplayModule"))?;
+        if let Some(bundle) = &self.bundle {
+            module.expect(bundle.record.clone());
+            Qemu::get()
+                .ok_or_else(|| Error::illegal_state("QEMU is not initialized"))?
+                .load_snapshot(&bundle.record.snapshot, true);
+        }
+
+        let exit_kind = self.inner.run_target(fuzzer, state, mgr, input)?;
+
+        self.outcome = self
+            .inner
+            .record_replay_module_mut()
+            .and_then(RecordReplayModule::take_outcome);
+        if let Some(ReplayOutcome::Diverged { expected, actual }) = &self.outcome {
+            log::warn!("The run diverged from its record: expected {expected:?}, got {actual:?}");
+        }
+        Ok(exit_kind)
+    }
+}
+
+impl<E> HasObservers for ReplayExecutor<E>
+where
+    E: HasObservers,
+{
+    type Observers = E::Observers;
+
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        self.inner.observers()
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        self.inner.observers_mut()
+    }
+}
+
+/// Writes a [`ReplayBundle`] next to each objective, and adds it to the metadata of the
+/// objective. Never interesting by itself, it is meant to be combined with the objective
+/// feedbacks, for instance with `feedback_or!(CrashFeedback::new(), ReplayBundleFeedback::new(..))`.
+///
+/// The bundle of an objective is written at [`ReplayBundle::path_of`], and the events of its run, if
+/// recorded, at [`ReplayBundle::events_path_of`]. The objectives must be stored in an
+/// `OnDiskCorpus` at `objectives_dir`, with the default file names.
+#[derive(Debug, Clone)]
+pub struct ReplayBundleFeedback {
+    objectives_dir: PathBuf,
+    qemu_args: Vec<String>,
+}
+
+impl ReplayBundleFeedback {
+    /// Creates a new [`ReplayBundleFeedback`], for the objectives stored at `objectives_dir`, run
+    /// by QEMU started with `qemu_args`
+    #[must_use]
+    pub fn new<P>(objectives_dir: P, qemu_args: Vec<String>) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            objectives_dir: objectives_dir.as_ref().to_path_buf(),
+            qemu_args,
+        }
+    }
+}
+
+impl Named for ReplayBundleFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ReplayBundleFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for ReplayBundleFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for ReplayBundleFeedback
+where
+    I: Input,
+    S: HasMetadata,
+{
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let LastReplayRecordMetadata { record, record_log } =
+            state.metadata::<LastReplayRecordMetadata>()?.clone();
+        let objective = testcase
+            .input()
+            .as_ref()
+            .ok_or_else(|| Error::empty_optional("The objective has no input"))?
+            .generate_name(None);
+        let objective_path = self.objectives_dir.join(&objective);
+
+        let mut events = None;
+        if let (Some(record_log), Some(range)) = (record_log, record.events.clone()) {
+            let events_path = ReplayBundle::events_path_of(&objective_path);
+            ReplayBundle::extract_events(&record_log, range, &events_path)?;
+            events = events_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned());
+        }
+
+        let bundle = ReplayBundle {
+            objective,
+            record,
+            qemu_args: self.qemu_args.clone(),
+            events,
+        };
+        bundle.store(ReplayBundle::path_of(&objective_path))?;
+        testcase.add_metadata(bundle);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path, process};
+
+    use libafl::executors::ExitKind;
+
+    use super::{RR_HEADER_LEN, ReplayBundle, ReplayOutcome, ReplayRecord};
+    use crate::config::RecordReplayMode;
+
+    fn record(start_icount: u64, end_icount: u64, exit_kind: ExitKind) -> ReplayRecord {
+        ReplayRecord {
+            snapshot: "fuzz".to_string(),
+            start_icount,
+            end_icount,
+            exit_kind,
+            events: None,
+        }
+    }
+
+    fn test_bundle(qemu_args: &[&str]) -> ReplayBundle {
+        ReplayBundle {
+            objective: "crash".to_string(),
+            record: record(100, 300, ExitKind::Crash),
+            qemu_args: qemu_args.iter().map(ToString::to_string).collect(),
+            events: None,
+        }
+    }
+
+    #[test]
+    fn test_executed_instructions() {
+        assert_eq!(record(100, 300, ExitKind::Ok).executed_instructions(), 200);
+        assert_eq!(record(300, 100, ExitKind::Ok).executed_instructions(), 0);
+    }
+
+    #[test]
+    fn test_outcome_check() {
+        let expected = record(100, 300, ExitKind::Crash);
+        // Only the number of executed instructions counts, not the start
+        assert_eq!(
+            ReplayOutcome::check(&expected, record(1000, 1200, ExitKind::Crash)),
+            ReplayOutcome::Reproduced
+        );
+        assert!(matches!(
+            ReplayOutcome::check(&expected, record(100, 301, ExitKind::Crash)),
+            ReplayOutcome::Diverged { .. }
+        ));
+        assert!(matches!(
+            ReplayOutcome::check(&expected, record(100, 300, ExitKind::Timeout)),
+            ReplayOutcome::Diverged { .. }
+        ));
+    }
+
+    #[test]
+    fn test_bundle_paths() {
+        let objective = Path::new("/fuzz/crashes/abcd");
+        assert_eq!(
+            ReplayBundle::path_of(objective),
+            Path::new("/fuzz/crashes/.abcd.replay")
+        );
+        assert_eq!(
+            ReplayBundle::events_path_of(objective),
+            Path::new("/fuzz/crashes/.abcd.rr")
+        );
+
+        let mut bundle = test_bundle(&[]);
+        let bundle_path = ReplayBundle::path_of(objective);
+        assert_eq!(bundle.events_path(&bundle_path), None);
+        bundle.events = Some(".abcd.rr".to_string());
+        assert_eq!(
+            bundle.events_path(&bundle_path).unwrap(),
+            Path::new("/fuzz/crashes/.abcd.rr")
+        );
+    }
+
+    #[test]
+    fn test_qemu_args() {
+        let bundle = test_bundle(&[
+            "-icount",
+            "shift=5,rr=record,rrfile=old.rr",
+            "-rtc",
+            "clock=vm",
+        ]);
+        assert_eq!(
+            bundle.qemu_args(RecordReplayMode::Replay, Path::new("new.rr")),
+            [
+                "-icount",
+                "shift=5,rr=replay,rrfile=new.rr",
+                "-rtc",
+                "clock=vm"
+            ]
+        );
+
+        let bundle = test_bundle(&["-m", "512M"]);
+        assert_eq!(
+            bundle.qemu_args(RecordReplayMode::Record, Path::new("new.rr")),
+            ["-m", "512M", "-icount", "shift=7,rr=record,rrfile=new.rr"]
+        );
+    }
+
+    #[test]
+    fn test_store_load_extract() {
+        let dir = env::temp_dir().join(format!("libafl_qemu_replay_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let objective = dir.join("crash");
+
+        // A header, the events of an earlier run and the events of the run
+        let log = dir.join("fuzz.rr");
+        let mut contents = vec![0xaa; RR_HEADER_LEN];
+        contents.extend([1; 8]);
+        contents.extend([2; 4]);
+        fs::write(&log, &contents).unwrap();
+
+        let events_path = ReplayBundle::events_path_of(&objective);
+        ReplayBundle::extract_events(&log, 20..24, &events_path).unwrap();
+        let mut expected = vec![0xaa; RR_HEADER_LEN];
+        expected.extend([2; 4]);
+        assert_eq!(fs::read(&events_path).unwrap(), expected);
+        assert!(ReplayBundle::extract_events(&log, 20..32, &events_path).is_err());
+
+        let mut bundle = test_bundle(&["-icount", "shift=7"]);
+        bundle.record.events = Some(20..24);
+        bundle.events = Some(".crash.rr".to_string());
+        let bundle_path = ReplayBundle::path_of(&objective);
+        bundle.store(&bundle_path).unwrap();
+        let loaded = ReplayBundle::load(&bundle_path).unwrap();
+        assert_eq!(loaded.objective, bundle.objective);
+        assert_eq!(loaded.record, bundle.record);
+        assert_eq!(loaded.qemu_args, bundle.qemu_args);
+        assert_eq!(loaded.events_path(&bundle_path).unwrap(), events_path);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
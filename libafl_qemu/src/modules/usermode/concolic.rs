@@ -0,0 +1,1736 @@
+//! Binary-only concolic tracing for `x86_64` usermode targets.
+//!
+//! The [`ConcolicModule`] lifts the instructions of every translated block into a small set of
+//! symbolic operations and replays them against shadow registers and shadow memory while the target
+//! runs. The resulting trace uses the same [`SymExpr`] format the `SymCC` runtime writes, so the
+//! [`libafl::observers::concolic::ConcolicObserver`] and the concolic stages work on closed-source
+//! binaries unchanged.
+//!
+//! The instructions are lifted from their machine code with Capstone, when their block is
+//! translated. The hooks of QEMU only expose guest instructions and blocks, not the TCG ops they are
+//! translated to, so the lifting can not happen at the TCG level, and is limited to `x86_64`.
+//!
+//! Lifted are moves, loads and stores, integer arithmetic, logic and shifts, compares, and
+//! conditional jumps, sets and moves. Any other instruction concretizes the registers and memory
+//! it writes. Symbolic addresses are concretized, and SIMD registers are not tracked, so data copied
+//! through vector registers loses its symbolic value.
+
+use std::{cmp::Ordering, io::Cursor, slice};
+
+use capstone::{
+    arch::{
+        ArchOperand,
+        x86::{X86OpMem, X86Operand, X86OperandType},
+    },
+    prelude::*,
+};
+use hashbrown::HashMap;
+use libafl::{
+    executors::ExitKind,
+    observers::{
+        ObserversTuple,
+        concolic::{Location, SymExpr, SymExprRef, serialization_format::MessageFileWriter},
+    },
+};
+use libafl_qemu_sys::GuestAddr;
+
+use crate::{
+    Qemu, QemuRWError, Regs, capstone,
+    modules::{
+        AddressFilter, EmulatorModule, EmulatorModuleTuple, EmulatorModules,
+        utils::filters::{HasAddressFilter, StdAddressFilter},
+    },
+    qemu::Hook,
+};
+
+/// The general purpose registers, in the order of the shadow registers
+const GPRS: [Regs; 16] = [
+    Regs::Rax,
+    Regs::Rbx,
+    Regs::Rcx,
+    Regs::Rdx,
+    Regs::Rsi,
+    Regs::Rdi,
+    Regs::Rbp,
+    Regs::Rsp,
+    Regs::R8,
+    Regs::R9,
+    Regs::R10,
+    Regs::R11,
+    Regs::R12,
+    Regs::R13,
+    Regs::R14,
+    Regs::R15,
+];
+
+const RAX: usize = 0;
+const RBP: usize = 6;
+const RSP: usize = 7;
+
+/// The 16-bit names of the first eight general purpose registers
+const LEGACY_REG_NAMES: [&str; 8] = ["ax", "bx", "cx", "dx", "si", "di", "bp", "sp"];
+
+fn mask(bits: u8) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+fn is_negative(value: u64, bits: u8) -> bool {
+    value & (1 << (bits - 1)) != 0
+}
+
+fn signed_lt(a: u64, b: u64, bits: u8) -> bool {
+    let sign = 1 << (bits - 1);
+    (a ^ sign) < (b ^ sign)
+}
+
+fn sign_extend(value: u64, bits: u8) -> u64 {
+    if is_negative(value, bits) {
+        value | !mask(bits)
+    } else {
+        value & mask(bits)
+    }
+}
+
+/// A part of a general purpose register, as accessed by an operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RegSlice {
+    gpr: usize,
+    shift: u8,
+    bits: u8,
+}
+
+impl RegSlice {
+    fn new(gpr: usize, shift: u8, bits: u8) -> Self {
+        Self { gpr, shift, bits }
+    }
+
+    /// Parses a capstone register name, returns `None` for anything but general purpose registers
+    fn parse(name: &str) -> Option<Self> {
+        let legacy = |name: &str| LEGACY_REG_NAMES.iter().position(|n| *n == name);
+
+        if let Some(gpr) = legacy(name) {
+            return Some(Self::new(gpr, 0, 16));
+        }
+        if let Some(rest) = name.strip_prefix('r') {
+            if let Some(gpr) = legacy(rest) {
+                return Some(Self::new(gpr, 0, 64));
+            }
+            let digits = rest.trim_end_matches(['d', 'w', 'b']);
+            if let Ok(gpr @ 8..=15) = digits.parse::<usize>() {
+                let bits = match &rest[digits.len()..] {
+                    "" => 64,
+                    "d" => 32,
+                    "w" => 16,
+                    "b" => 8,
+                    _ => return None,
+                };
+                return Some(Self::new(gpr, 0, bits));
+            }
+        }
+        if let Some(gpr) = name.strip_prefix('e').and_then(legacy) {
+            return Some(Self::new(gpr, 0, 32));
+        }
+        if let Some(rest) = name.strip_suffix('l') {
+            // al, bl, cl, dl and sil, dil, bpl, spl
+            let gpr = if rest.len() == 1 {
+                legacy(&format!("{rest}x"))
+            } else {
+                legacy(rest)
+            };
+            return gpr.map(|gpr| Self::new(gpr, 0, 8));
+        }
+        if let Some(rest) = name.strip_suffix('h')
+            && rest.len() == 1
+        {
+            return legacy(&format!("{rest}x")).map(|gpr| Self::new(gpr, 8, 8));
+        }
+        None
+    }
+}
+
+/// A memory operand, addressing `offset + base + index * scale`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MemOperand {
+    base: Option<RegSlice>,
+    index: Option<RegSlice>,
+    scale: u8,
+    offset: u64,
+    bits: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Reg(RegSlice),
+    Imm { value: u64, bits: u8 },
+    Mem(MemOperand),
+}
+
+impl Operand {
+    fn bits(&self) -> u8 {
+        match self {
+            Self::Reg(reg) => reg.bits,
+            Self::Imm { bits, .. } => *bits,
+            Self::Mem(mem) => mem.bits,
+        }
+    }
+
+    /// Immediates are sign-extended to the size of the other operand
+    fn fit(self, bits: u8) -> Self {
+        match self {
+            Self::Imm { value, bits: size } => Self::Imm {
+                value: sign_extend(value, size) & mask(bits),
+                bits,
+            },
+            _ => self,
+        }
+    }
+}
+
+/// An `x86` condition code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cond {
+    O,
+    No,
+    B,
+    Ae,
+    E,
+    Ne,
+    Be,
+    A,
+    S,
+    Ns,
+    P,
+    Np,
+    L,
+    Ge,
+    Le,
+    G,
+}
+
+impl Cond {
+    /// Parses the condition suffix of `jcc`, `setcc` and `cmovcc`
+    fn parse(suffix: &str) -> Option<Self> {
+        Some(match suffix {
+            "o" => Self::O,
+            "no" => Self::No,
+            "b" | "c" | "nae" => Self::B,
+            "ae" | "nb" | "nc" => Self::Ae,
+            "e" | "z" => Self::E,
+            "ne" | "nz" => Self::Ne,
+            "be" | "na" => Self::Be,
+            "a" | "nbe" => Self::A,
+            "s" => Self::S,
+            "ns" => Self::Ns,
+            "p" | "pe" => Self::P,
+            "np" | "po" => Self::Np,
+            "l" | "nge" => Self::L,
+            "ge" | "nl" => Self::Ge,
+            "le" | "ng" => Self::Le,
+            "g" | "nle" => Self::G,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    Sar,
+    Mul,
+}
+
+impl BinaryOp {
+    fn parse(mnemonic: &str) -> Option<Self> {
+        Some(match mnemonic {
+            "add" => Self::Add,
+            "sub" => Self::Sub,
+            "and" => Self::And,
+            "or" => Self::Or,
+            "xor" => Self::Xor,
+            "shl" | "sal" => Self::Shl,
+            "shr" => Self::Shr,
+            "sar" => Self::Sar,
+            "imul" => Self::Mul,
+            _ => return None,
+        })
+    }
+
+    fn is_shift(self) -> bool {
+        matches!(self, Self::Shl | Self::Shr | Self::Sar)
+    }
+
+    fn eval(self, a: u64, b: u64, bits: u8) -> u64 {
+        let shift = u32::try_from(b).unwrap_or(u32::MAX);
+        let result = match self {
+            Self::Add => a.wrapping_add(b),
+            Self::Sub => a.wrapping_sub(b),
+            Self::And => a & b,
+            Self::Or => a | b,
+            Self::Xor => a ^ b,
+            Self::Shl => a.checked_shl(shift).unwrap_or(0),
+            Self::Shr => a.checked_shr(shift).unwrap_or(0),
+            Self::Sar => {
+                let a = sign_extend(a, bits);
+                if is_negative(a, 64) {
+                    !((!a).checked_shr(shift).unwrap_or(0))
+                } else {
+                    a.checked_shr(shift).unwrap_or(0)
+                }
+            }
+            Self::Mul => a.wrapping_mul(b),
+        };
+        result & mask(bits)
+    }
+
+    fn expr(self, a: SymExprRef, b: SymExprRef) -> SymExpr {
+        match self {
+            Self::Add => SymExpr::Add { a, b },
+            Self::Sub => SymExpr::Sub { a, b },
+            Self::And => SymExpr::And { a, b },
+            Self::Or => SymExpr::Or { a, b },
+            Self::Xor => SymExpr::Xor { a, b },
+            Self::Shl => SymExpr::ShiftLeft { a, b },
+            Self::Shr => SymExpr::LogicalShiftRight { a, b },
+            Self::Sar => SymExpr::ArithmeticShiftRight { a, b },
+            Self::Mul => SymExpr::Mul { a, b },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Neg,
+    Not,
+    Inc,
+    Dec,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Extend {
+    None,
+    Zero,
+    Sign,
+}
+
+/// An instruction lifted to the operations the [`ConcolicModule`] replays symbolically
+#[derive(Debug, Clone)]
+enum LiftedInsn {
+    Mov {
+        dst: Operand,
+        src: Operand,
+        ext: Extend,
+    },
+    Lea {
+        dst: RegSlice,
+        mem: MemOperand,
+    },
+    Binary {
+        op: BinaryOp,
+        dst: Operand,
+        a: Operand,
+        b: Operand,
+    },
+    Unary {
+        op: UnaryOp,
+        dst: Operand,
+    },
+    Cmp {
+        a: Operand,
+        b: Operand,
+    },
+    Test {
+        a: Operand,
+        b: Operand,
+    },
+    Jcc {
+        cond: Cond,
+    },
+    Setcc {
+        cond: Cond,
+        dst: Operand,
+    },
+    Cmov {
+        cond: Cond,
+        dst: RegSlice,
+        src: Operand,
+    },
+    Push {
+        src: Operand,
+    },
+    Pop {
+        dst: Operand,
+    },
+    Leave,
+    /// An instruction without symbolic semantics, its outputs become concrete
+    Concretize {
+        regs: Vec<usize>,
+        mem: Option<MemOperand>,
+        flags: bool,
+    },
+}
+
+fn lift_mem(cs: &Capstone, mem: &X86OpMem, bits: u8, next: GuestAddr) -> Option<MemOperand> {
+    if mem.segment() != RegId::INVALID_REG {
+        // fs and gs relative accesses need the segment base, which we don't track
+        return None;
+    }
+    let reg = |id: RegId| -> Option<Option<String>> {
+        if id == RegId::INVALID_REG {
+            Some(None)
+        } else {
+            cs.reg_name(id).map(Some)
+        }
+    };
+
+    let (base, offset) = match reg(mem.base())?.as_deref() {
+        None => (None, 0_u64.wrapping_add_signed(mem.disp())),
+        Some("rip") => (None, next.wrapping_add_signed(mem.disp())),
+        Some(name) => (
+            Some(RegSlice::parse(name)?),
+            0_u64.wrapping_add_signed(mem.disp()),
+        ),
+    };
+    let index = match reg(mem.index())?.as_deref() {
+        None => None,
+        Some(name) => Some(RegSlice::parse(name)?),
+    };
+
+    Some(MemOperand {
+        base,
+        index,
+        scale: u8::try_from(mem.scale()).ok()?,
+        offset,
+        bits,
+    })
+}
+
+fn lift_operand(cs: &Capstone, op: &X86Operand, next: GuestAddr) -> Option<Operand> {
+    let bits = op.size.checked_mul(8)?;
+    match &op.op_type {
+        X86OperandType::Reg(reg) => Some(Operand::Reg(RegSlice::parse(&cs.reg_name(*reg)?)?)),
+        X86OperandType::Imm(value) => Some(Operand::Imm {
+            value: u64::from_ne_bytes(value.to_ne_bytes()) & mask(bits),
+            bits,
+        }),
+        X86OperandType::Mem(mem) => lift_mem(cs, mem, bits, next).map(Operand::Mem),
+        X86OperandType::Invalid => None,
+    }
+}
+
+/// Lifts the instructions we model symbolically
+fn lift_semantics(mnemonic: &str, ops: &[Operand], next: GuestAddr) -> Option<LiftedInsn> {
+    let cond = |prefix: &str| mnemonic.strip_prefix(prefix).and_then(Cond::parse);
+
+    Some(match (mnemonic, ops) {
+        ("mov" | "movabs", [dst, src]) => LiftedInsn::Mov {
+            dst: *dst,
+            src: src.fit(dst.bits()),
+            ext: Extend::None,
+        },
+        ("movzx", [dst, src]) => LiftedInsn::Mov {
+            dst: *dst,
+            src: *src,
+            ext: Extend::Zero,
+        },
+        ("movsx" | "movsxd", [dst, src]) => LiftedInsn::Mov {
+            dst: *dst,
+            src: *src,
+            ext: Extend::Sign,
+        },
+        ("cdqe", []) => LiftedInsn::Mov {
+            dst: Operand::Reg(RegSlice::new(RAX, 0, 64)),
+            src: Operand::Reg(RegSlice::new(RAX, 0, 32)),
+            ext: Extend::Sign,
+        },
+        ("cwde", []) => LiftedInsn::Mov {
+            dst: Operand::Reg(RegSlice::new(RAX, 0, 32)),
+            src: Operand::Reg(RegSlice::new(RAX, 0, 16)),
+            ext: Extend::Sign,
+        },
+        ("lea", [Operand::Reg(dst), Operand::Mem(mem)]) => LiftedInsn::Lea {
+            dst: *dst,
+            mem: *mem,
+        },
+        ("imul", [dst, a, b]) => LiftedInsn::Binary {
+            op: BinaryOp::Mul,
+            dst: *dst,
+            a: *a,
+            b: b.fit(dst.bits()),
+        },
+        (
+            "add" | "sub" | "and" | "or" | "xor" | "shl" | "sal" | "shr" | "sar" | "imul",
+            [dst, src],
+        ) => LiftedInsn::Binary {
+            op: BinaryOp::parse(mnemonic)?,
+            dst: *dst,
+            a: *dst,
+            b: src.fit(dst.bits()),
+        },
+        ("neg", [dst]) => LiftedInsn::Unary {
+            op: UnaryOp::Neg,
+            dst: *dst,
+        },
+        ("not", [dst]) => LiftedInsn::Unary {
+            op: UnaryOp::Not,
+            dst: *dst,
+        },
+        ("inc", [dst]) => LiftedInsn::Unary {
+            op: UnaryOp::Inc,
+            dst: *dst,
+        },
+        ("dec", [dst]) => LiftedInsn::Unary {
+            op: UnaryOp::Dec,
+            dst: *dst,
+        },
+        ("cmp", [a, b]) => LiftedInsn::Cmp {
+            a: *a,
+            b: b.fit(a.bits()),
+        },
+        ("test", [a, b]) => LiftedInsn::Test {
+            a: *a,
+            b: b.fit(a.bits()),
+        },
+        ("push", [src]) => LiftedInsn::Push { src: src.fit(64) },
+        ("call", _) => LiftedInsn::Push {
+            // the return address is always concrete
+            src: Operand::Imm {
+                value: next,
+                bits: 64,
+            },
+        },
+        ("pop", [dst]) => LiftedInsn::Pop { dst: *dst },
+        ("leave", _) => LiftedInsn::Leave,
+        _ => {
+            if let Some(cond) = cond("j") {
+                LiftedInsn::Jcc { cond }
+            } else if let (Some(cond), [dst]) = (cond("set"), ops) {
+                LiftedInsn::Setcc { cond, dst: *dst }
+            } else if let (Some(cond), [Operand::Reg(dst), src]) = (cond("cmov"), ops) {
+                LiftedInsn::Cmov {
+                    cond,
+                    dst: *dst,
+                    src: *src,
+                }
+            } else {
+                return None;
+            }
+        }
+    })
+}
+
+/// Concretizes the destination operand and the implicitly written registers and flags
+fn lift_concretize(cs: &Capstone, detail: &InsnDetail, dst: Option<Operand>) -> Option<LiftedInsn> {
+    let mut regs = Vec::new();
+    let mut mem = None;
+    let mut flags = false;
+
+    match dst {
+        Some(Operand::Reg(reg)) => regs.push(reg.gpr),
+        Some(Operand::Mem(dst)) => mem = Some(dst),
+        _ => {}
+    }
+    for reg in detail.regs_write() {
+        let Some(name) = cs.reg_name(RegId(reg.0)) else {
+            continue;
+        };
+        if matches!(name.as_str(), "rflags" | "eflags" | "flags") {
+            flags = true;
+        } else if let Some(reg) = RegSlice::parse(&name) {
+            // the stack pointer never holds symbolic values
+            if reg.gpr != RSP && !regs.contains(&reg.gpr) {
+                regs.push(reg.gpr);
+            }
+        }
+    }
+
+    if regs.is_empty() && mem.is_none() && !flags {
+        None
+    } else {
+        Some(LiftedInsn::Concretize { regs, mem, flags })
+    }
+}
+
+/// Lifts a single instruction, returns `None` if it can not affect the symbolic state
+fn lift(cs: &Capstone, insn: &capstone::Insn, detail: &InsnDetail) -> Option<LiftedInsn> {
+    let mnemonic = insn.mnemonic()?;
+    let next = (insn.address() + insn.bytes().len() as u64) as GuestAddr;
+
+    let operands = detail
+        .arch_detail()
+        .operands()
+        .iter()
+        .map(|op| match op {
+            ArchOperand::X86Operand(op) => lift_operand(cs, op, next),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    operands
+        .iter()
+        .copied()
+        .collect::<Option<Vec<_>>>()
+        .and_then(|ops| lift_semantics(mnemonic, &ops, next))
+        .or_else(|| lift_concretize(cs, detail, operands.first().copied().flatten()))
+}
+
+/// A value read during replay, with its symbolic expression if it has one
+#[derive(Debug, Clone, Copy)]
+struct Value {
+    concrete: u64,
+    expr: Option<SymExprRef>,
+}
+
+impl Value {
+    fn concrete(concrete: u64) -> Self {
+        Self {
+            concrete,
+            expr: None,
+        }
+    }
+}
+
+/// The symbolic value of a register, zero-extended to the full register
+#[derive(Debug, Clone, Copy)]
+struct SymReg {
+    expr: SymExprRef,
+    bits: u8,
+}
+
+/// What the last flag-setting instruction computed, with the concrete values it saw
+#[derive(Debug, Clone, Copy)]
+enum Flags {
+    Concrete,
+    /// Flags of `a - b`, as set by `cmp` and `sub`
+    Sub {
+        a: SymExprRef,
+        b: SymExprRef,
+        concrete_a: u64,
+        concrete_b: u64,
+        bits: u8,
+    },
+    /// Flags of a logic operation, which clears the carry and overflow flags
+    Logic {
+        result: SymExprRef,
+        concrete: u64,
+        bits: u8,
+    },
+    /// Flags of any other operation, of which only the zero and sign flag are modelled
+    Result {
+        result: SymExprRef,
+        concrete: u64,
+        bits: u8,
+    },
+}
+
+/// The shadow state of one execution, and the trace it writes
+#[derive(Debug)]
+struct ShadowState {
+    writer: Option<MessageFileWriter<Cursor<&'static mut [u8]>>>,
+    regs: [Option<SymReg>; 16],
+    mem: HashMap<GuestAddr, SymExprRef>,
+    flags: Flags,
+}
+
+impl ShadowState {
+    fn new() -> Self {
+        Self {
+            writer: None,
+            regs: [None; 16],
+            mem: HashMap::new(),
+            flags: Flags::Concrete,
+        }
+    }
+
+    fn reset(&mut self, writer: Option<MessageFileWriter<Cursor<&'static mut [u8]>>>) {
+        self.writer = writer;
+        self.regs = [None; 16];
+        self.mem.clear();
+        self.flags = Flags::Concrete;
+    }
+
+    /// `true` if nothing is symbolic, so no instruction can produce a symbolic value
+    fn is_concrete(&self) -> bool {
+        self.regs.iter().all(Option::is_none)
+            && self.mem.is_empty()
+            && matches!(self.flags, Flags::Concrete)
+    }
+
+    fn emit(&mut self, expr: SymExpr) -> Option<SymExprRef> {
+        let writer = self.writer.as_mut()?;
+        match writer.write_message(expr) {
+            Ok(expr) => Some(expr),
+            Err(err) => {
+                log::warn!(
+                    "Stopping the concolic trace, failed to write to the trace buffer: {err}"
+                );
+                self.writer = None;
+                None
+            }
+        }
+    }
+
+    fn update_trace_header(&mut self) {
+        if let Some(writer) = self.writer.as_mut()
+            && let Err(err) = writer.update_trace_header()
+        {
+            log::warn!("Stopping the concolic trace, failed to update the trace header: {err}");
+            self.writer = None;
+        }
+    }
+
+    fn expr_of(&mut self, value: Value, bits: u8) -> Option<SymExprRef> {
+        match value.expr {
+            Some(expr) => Some(expr),
+            None => self.emit(SymExpr::Integer {
+                value: value.concrete & mask(bits),
+                bits,
+            }),
+        }
+    }
+
+    /// Resizes an expression of `from` bits to `to` bits
+    fn resize(&mut self, expr: SymExprRef, from: u8, to: u8) -> Option<SymExprRef> {
+        match from.cmp(&to) {
+            Ordering::Less => self.emit(SymExpr::Zext {
+                op: expr,
+                bits: to - from,
+            }),
+            Ordering::Greater => self.emit(SymExpr::Extract {
+                op: expr,
+                first_bit: usize::from(to - 1),
+                last_bit: 0,
+            }),
+            Ordering::Equal => Some(expr),
+        }
+    }
+
+    fn concrete_reg(qemu: Qemu, reg: RegSlice) -> Option<u64> {
+        let value = qemu.read_reg(GPRS[reg.gpr]).ok()?;
+        Some((value >> reg.shift) & mask(reg.bits))
+    }
+
+    fn read_reg(&mut self, qemu: Qemu, reg: RegSlice) -> Option<Value> {
+        let concrete = Self::concrete_reg(qemu, reg)?;
+        let Some(sym) = self.regs[reg.gpr] else {
+            return Some(Value::concrete(concrete));
+        };
+
+        let expr = if reg.shift >= sym.bits {
+            // only reads the zero-extended part
+            None
+        } else if reg.shift == 0 {
+            Some(self.resize(sym.expr, sym.bits, reg.bits)?)
+        } else {
+            Some(self.emit(SymExpr::Extract {
+                op: sym.expr,
+                first_bit: usize::from(reg.shift + reg.bits - 1),
+                last_bit: usize::from(reg.shift),
+            })?)
+        };
+        Some(Value { concrete, expr })
+    }
+
+    fn write_reg(&mut self, qemu: Qemu, reg: RegSlice, value: Value) -> Option<()> {
+        if reg.bits >= 32 {
+            // 32-bit writes zero the upper half of the register
+            self.regs[reg.gpr] = value.expr.map(|expr| SymReg {
+                expr,
+                bits: reg.bits,
+            });
+            return Some(());
+        }
+        if value.expr.is_none() && self.regs[reg.gpr].is_none() {
+            return Some(());
+        }
+
+        // 8 and 16-bit writes keep the rest of the register
+        let full = RegSlice::new(reg.gpr, 0, 64);
+        let old = self.read_reg(qemu, full)?;
+        let old = self.expr_of(old, 64)?;
+        let mut merged = self.expr_of(value, reg.bits)?;
+        if reg.shift > 0 {
+            let low = self.emit(SymExpr::Extract {
+                op: old,
+                first_bit: usize::from(reg.shift - 1),
+                last_bit: 0,
+            })?;
+            merged = self.emit(SymExpr::Concat { a: merged, b: low })?;
+        }
+        let top = reg.shift + reg.bits;
+        if top < 64 {
+            let high = self.emit(SymExpr::Extract {
+                op: old,
+                first_bit: 63,
+                last_bit: usize::from(top),
+            })?;
+            merged = self.emit(SymExpr::Concat { a: high, b: merged })?;
+        }
+        self.regs[reg.gpr] = Some(SymReg {
+            expr: merged,
+            bits: 64,
+        });
+        Some(())
+    }
+
+    fn address(qemu: Qemu, mem: &MemOperand) -> Option<GuestAddr> {
+        let mut addr = mem.offset;
+        if let Some(base) = mem.base {
+            addr = addr.wrapping_add(Self::concrete_reg(qemu, base)?);
+        }
+        if let Some(index) = mem.index {
+            addr =
+                addr.wrapping_add(Self::concrete_reg(qemu, index)?.wrapping_mul(mem.scale.into()));
+        }
+        Some(addr as GuestAddr)
+    }
+
+    fn read_mem(&mut self, qemu: Qemu, addr: GuestAddr, bits: u8) -> Option<Value> {
+        let len = usize::from(bits / 8);
+        let mut bytes = [0_u8; 8];
+        qemu.read_mem(addr, &mut bytes[..len]).ok()?;
+        let concrete = u64::from_le_bytes(bytes);
+
+        if (0..len).all(|i| !self.mem.contains_key(&(addr + i as GuestAddr))) {
+            return Some(Value::concrete(concrete));
+        }
+
+        // little endian, the highest byte ends up leftmost in the concatenation
+        let mut expr = None;
+        for i in (0..len).rev() {
+            let byte = match self.mem.get(&(addr + i as GuestAddr)).copied() {
+                Some(byte) => byte,
+                None => self.emit(SymExpr::Integer {
+                    value: bytes[i].into(),
+                    bits: 8,
+                })?,
+            };
+            expr = Some(match expr {
+                None => byte,
+                Some(high) => self.emit(SymExpr::Concat { a: high, b: byte })?,
+            });
+        }
+        Some(Value { concrete, expr })
+    }
+
+    fn write_mem(&mut self, addr: GuestAddr, bits: u8, value: Value) -> Option<()> {
+        let len = usize::from(bits / 8);
+        match value.expr {
+            None => {
+                for i in 0..len {
+                    self.mem.remove(&(addr + i as GuestAddr));
+                }
+            }
+            Some(expr) if len == 1 => {
+                self.mem.insert(addr, expr);
+            }
+            Some(expr) => {
+                for i in 0..len {
+                    let byte = self.emit(SymExpr::Extract {
+                        op: expr,
+                        first_bit: 8 * i + 7,
+                        last_bit: 8 * i,
+                    })?;
+                    self.mem.insert(addr + i as GuestAddr, byte);
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn read(&mut self, qemu: Qemu, op: &Operand) -> Option<Value> {
+        match op {
+            Operand::Reg(reg) => self.read_reg(qemu, *reg),
+            Operand::Imm { value, .. } => Some(Value::concrete(*value)),
+            Operand::Mem(mem) => {
+                let addr = Self::address(qemu, mem)?;
+                self.read_mem(qemu, addr, mem.bits)
+            }
+        }
+    }
+
+    /// Writes the result of an instruction, or concretizes the destination if there is none
+    fn store(&mut self, qemu: Qemu, dst: &Operand, value: Option<Value>) -> Option<()> {
+        let Some(value) = value else {
+            self.clear(qemu, dst);
+            return None;
+        };
+        match dst {
+            Operand::Reg(reg) => self.write_reg(qemu, *reg, value),
+            Operand::Imm { .. } => Some(()),
+            Operand::Mem(mem) => {
+                let addr = Self::address(qemu, mem)?;
+                self.write_mem(addr, mem.bits, value)
+            }
+        }
+    }
+
+    /// Forgets the symbolic value of an operand whose new value we could not compute
+    fn clear(&mut self, qemu: Qemu, op: &Operand) {
+        match op {
+            Operand::Reg(reg) => self.regs[reg.gpr] = None,
+            Operand::Imm { .. } => {}
+            Operand::Mem(mem) => {
+                if let Some(addr) = Self::address(qemu, mem) {
+                    self.write_mem(addr, mem.bits, Value::concrete(0));
+                }
+            }
+        }
+    }
+
+    fn binary(
+        &mut self,
+        qemu: Qemu,
+        op: BinaryOp,
+        a: &Operand,
+        b: &Operand,
+        bits: u8,
+    ) -> Option<Value> {
+        self.flags = Flags::Concrete;
+        if matches!(op, BinaryOp::Xor | BinaryOp::Sub) && a == b {
+            // zeroing idiom
+            return Some(Value::concrete(0));
+        }
+
+        let va = self.read(qemu, a)?;
+        let vb = self.read(qemu, b)?;
+        let count_mask = if bits == 64 { 63 } else { 31 };
+        let concrete_b = if op.is_shift() {
+            vb.concrete & count_mask
+        } else {
+            vb.concrete
+        };
+        let concrete = op.eval(va.concrete, concrete_b, bits);
+        if va.expr.is_none() && vb.expr.is_none() {
+            return Some(Value::concrete(concrete));
+        }
+
+        let ea = self.expr_of(va, bits)?;
+        let eb = if op.is_shift() {
+            let count = self.expr_of(vb, b.bits())?;
+            let count = self.resize(count, b.bits(), bits)?;
+            let count_mask = self.emit(SymExpr::Integer {
+                value: count_mask,
+                bits,
+            })?;
+            self.emit(SymExpr::And {
+                a: count,
+                b: count_mask,
+            })?
+        } else {
+            self.expr_of(vb, bits)?
+        };
+        let result = self.emit(op.expr(ea, eb))?;
+
+        self.flags = match op {
+            BinaryOp::Sub => Flags::Sub {
+                a: ea,
+                b: eb,
+                concrete_a: va.concrete,
+                concrete_b,
+                bits,
+            },
+            BinaryOp::And | BinaryOp::Or | BinaryOp::Xor => Flags::Logic {
+                result,
+                concrete,
+                bits,
+            },
+            BinaryOp::Add | BinaryOp::Shl | BinaryOp::Shr | BinaryOp::Sar => Flags::Result {
+                result,
+                concrete,
+                bits,
+            },
+            BinaryOp::Mul => Flags::Concrete,
+        };
+        Some(Value {
+            concrete,
+            expr: Some(result),
+        })
+    }
+
+    fn unary(&mut self, qemu: Qemu, op: UnaryOp, dst: &Operand) -> Option<Value> {
+        if op != UnaryOp::Not {
+            // not leaves the flags untouched
+            self.flags = Flags::Concrete;
+        }
+        let bits = dst.bits();
+        let value = self.read(qemu, dst)?;
+        let concrete = match op {
+            UnaryOp::Neg => value.concrete.wrapping_neg(),
+            UnaryOp::Not => !value.concrete,
+            UnaryOp::Inc => value.concrete.wrapping_add(1),
+            UnaryOp::Dec => value.concrete.wrapping_sub(1),
+        } & mask(bits);
+        let Some(expr) = value.expr else {
+            return Some(Value::concrete(concrete));
+        };
+
+        let result = match op {
+            UnaryOp::Neg => self.emit(SymExpr::Neg { op: expr })?,
+            UnaryOp::Not => self.emit(SymExpr::Not { op: expr })?,
+            UnaryOp::Inc | UnaryOp::Dec => {
+                let one = self.emit(SymExpr::Integer { value: 1, bits })?;
+                self.emit(if op == UnaryOp::Inc {
+                    SymExpr::Add { a: expr, b: one }
+                } else {
+                    SymExpr::Sub { a: expr, b: one }
+                })?
+            }
+        };
+        if op != UnaryOp::Not {
+            self.flags = Flags::Result {
+                result,
+                concrete,
+                bits,
+            };
+        }
+        Some(Value {
+            concrete,
+            expr: Some(result),
+        })
+    }
+
+    fn compare(&mut self, qemu: Qemu, a: &Operand, b: &Operand, test: bool) -> Option<()> {
+        self.flags = Flags::Concrete;
+        let bits = a.bits();
+        let va = self.read(qemu, a)?;
+        let vb = self.read(qemu, b)?;
+        if va.expr.is_none() && vb.expr.is_none() {
+            return Some(());
+        }
+
+        let ea = self.expr_of(va, bits)?;
+        let eb = self.expr_of(vb, bits)?;
+        self.flags = if test {
+            Flags::Logic {
+                result: self.emit(SymExpr::And { a: ea, b: eb })?,
+                concrete: va.concrete & vb.concrete,
+                bits,
+            }
+        } else {
+            Flags::Sub {
+                a: ea,
+                b: eb,
+                concrete_a: va.concrete,
+                concrete_b: vb.concrete,
+                bits,
+            }
+        };
+        Some(())
+    }
+
+    /// Builds the symbolic condition and its concrete outcome, if the flags are symbolic
+    fn condition(&mut self, cond: Cond) -> Option<(SymExprRef, bool)> {
+        let (expr, holds) = match self.flags {
+            Flags::Concrete => return None,
+            Flags::Sub {
+                a,
+                b,
+                concrete_a,
+                concrete_b,
+                bits,
+            } => match cond {
+                Cond::E => (SymExpr::Equal { a, b }, concrete_a == concrete_b),
+                Cond::Ne => (SymExpr::NotEqual { a, b }, concrete_a != concrete_b),
+                Cond::B => (SymExpr::UnsignedLessThan { a, b }, concrete_a < concrete_b),
+                Cond::Ae => (
+                    SymExpr::UnsignedGreaterEqual { a, b },
+                    concrete_a >= concrete_b,
+                ),
+                Cond::Be => (
+                    SymExpr::UnsignedLessEqual { a, b },
+                    concrete_a <= concrete_b,
+                ),
+                Cond::A => (
+                    SymExpr::UnsignedGreaterThan { a, b },
+                    concrete_a > concrete_b,
+                ),
+                Cond::L => (
+                    SymExpr::SignedLessThan { a, b },
+                    signed_lt(concrete_a, concrete_b, bits),
+                ),
+                Cond::Ge => (
+                    SymExpr::SignedGreaterEqual { a, b },
+                    !signed_lt(concrete_a, concrete_b, bits),
+                ),
+                Cond::Le => (
+                    SymExpr::SignedLessEqual { a, b },
+                    !signed_lt(concrete_b, concrete_a, bits),
+                ),
+                Cond::G => (
+                    SymExpr::SignedGreaterThan { a, b },
+                    signed_lt(concrete_b, concrete_a, bits),
+                ),
+                Cond::S | Cond::Ns => {
+                    let diff = self.emit(SymExpr::Sub { a, b })?;
+                    let zero = self.emit(SymExpr::Integer { value: 0, bits })?;
+                    let negative = is_negative(concrete_a.wrapping_sub(concrete_b), bits);
+                    if cond == Cond::S {
+                        (SymExpr::SignedLessThan { a: diff, b: zero }, negative)
+                    } else {
+                        (SymExpr::SignedGreaterEqual { a: diff, b: zero }, !negative)
+                    }
+                }
+                Cond::O | Cond::No | Cond::P | Cond::Np => return None,
+            },
+            Flags::Logic {
+                result,
+                concrete,
+                bits,
+            }
+            | Flags::Result {
+                result,
+                concrete,
+                bits,
+            } => {
+                // logic operations clear CF and OF, which makes more conditions expressible
+                let logic = matches!(self.flags, Flags::Logic { .. });
+                let zf = concrete == 0;
+                let sf = is_negative(concrete, bits);
+                let cmp = match cond {
+                    Cond::E => (true, zf),
+                    Cond::Ne => (false, !zf),
+                    Cond::S => (true, sf),
+                    Cond::Ns => (false, !sf),
+                    Cond::Be if logic => (true, zf),
+                    Cond::A if logic => (false, !zf),
+                    Cond::L if logic => (true, sf),
+                    Cond::Ge if logic => (false, !sf),
+                    Cond::Le if logic => (true, zf || sf),
+                    Cond::G if logic => (false, !zf && !sf),
+                    _ => return None,
+                };
+                let zero = self.emit(SymExpr::Integer { value: 0, bits })?;
+                let (a, b) = (result, zero);
+                let expr = match (cond, cmp.0) {
+                    (Cond::E | Cond::Be, _) => SymExpr::Equal { a, b },
+                    (Cond::Ne | Cond::A, _) => SymExpr::NotEqual { a, b },
+                    (Cond::S | Cond::L, _) => SymExpr::SignedLessThan { a, b },
+                    (Cond::Ns | Cond::Ge, _) => SymExpr::SignedGreaterEqual { a, b },
+                    (Cond::Le, _) => SymExpr::SignedLessEqual { a, b },
+                    _ => SymExpr::SignedGreaterThan { a, b },
+                };
+                (expr, cmp.1)
+            }
+        };
+        Some((self.emit(expr)?, holds))
+    }
+
+    fn execute(&mut self, qemu: Qemu, pc: GuestAddr, insn: &LiftedInsn) -> Option<()> {
+        match insn {
+            LiftedInsn::Mov { dst, src, ext } => {
+                let value = self.read(qemu, src).and_then(|value| {
+                    let (from, to) = (src.bits(), dst.bits());
+                    match (value.expr, ext) {
+                        (_, Extend::None) | (None, Extend::Zero) => Some(value),
+                        (None, Extend::Sign) => Some(Value::concrete(
+                            sign_extend(value.concrete, from) & mask(to),
+                        )),
+                        (Some(expr), Extend::Zero) => Some(Value {
+                            concrete: value.concrete,
+                            expr: Some(self.emit(SymExpr::Zext {
+                                op: expr,
+                                bits: to - from,
+                            })?),
+                        }),
+                        (Some(expr), Extend::Sign) => Some(Value {
+                            concrete: sign_extend(value.concrete, from) & mask(to),
+                            expr: Some(self.emit(SymExpr::Sext {
+                                op: expr,
+                                bits: to - from,
+                            })?),
+                        }),
+                    }
+                });
+                self.store(qemu, dst, value)
+            }
+            LiftedInsn::Lea { dst, mem } => {
+                let value = self.lea(qemu, mem, dst.bits);
+                self.store(qemu, &Operand::Reg(*dst), value)
+            }
+            LiftedInsn::Binary { op, dst, a, b } => {
+                let value = self.binary(qemu, *op, a, b, dst.bits());
+                self.store(qemu, dst, value)
+            }
+            LiftedInsn::Unary { op, dst } => {
+                let value = self.unary(qemu, *op, dst);
+                self.store(qemu, dst, value)
+            }
+            LiftedInsn::Cmp { a, b } => self.compare(qemu, a, b, false),
+            LiftedInsn::Test { a, b } => self.compare(qemu, a, b, true),
+            LiftedInsn::Jcc { cond } => {
+                let (constraint, taken) = self.condition(*cond)?;
+                self.emit(SymExpr::PathConstraint {
+                    constraint,
+                    taken,
+                    location: Location::from(pc as usize),
+                })?;
+                self.update_trace_header();
+                Some(())
+            }
+            LiftedInsn::Setcc { cond, dst } => {
+                let value = self.condition(*cond).and_then(|(constraint, taken)| {
+                    let bit = self.emit(SymExpr::BoolToBit { op: constraint })?;
+                    Some(Value {
+                        concrete: taken.into(),
+                        expr: Some(self.emit(SymExpr::Zext { op: bit, bits: 7 })?),
+                    })
+                });
+                self.store(qemu, dst, value)
+            }
+            LiftedInsn::Cmov { cond, dst, src } => {
+                let dst_op = Operand::Reg(*dst);
+                let value = match self.condition(*cond) {
+                    Some((_, true)) => self.read(qemu, src),
+                    // a 32-bit cmov zero-extends its destination even if it does not move
+                    Some((_, false)) => self.read(qemu, &dst_op),
+                    None => None,
+                };
+                self.store(qemu, &dst_op, value)
+            }
+            LiftedInsn::Push { src } => {
+                let value = self.read(qemu, src);
+                let bits = src.bits();
+                let rsp = Self::concrete_reg(qemu, RegSlice::new(RSP, 0, 64))?;
+                let addr = rsp.wrapping_sub(u64::from(bits / 8)) as GuestAddr;
+                self.write_mem(addr, bits, value.unwrap_or(Value::concrete(0)))
+            }
+            LiftedInsn::Pop { dst } => {
+                let rsp = Self::concrete_reg(qemu, RegSlice::new(RSP, 0, 64))?;
+                let value = self.read_mem(qemu, rsp as GuestAddr, dst.bits());
+                self.store(qemu, dst, value)
+            }
+            LiftedInsn::Leave => {
+                let rbp = RegSlice::new(RBP, 0, 64);
+                let addr = Self::concrete_reg(qemu, rbp)?;
+                let value = self.read_mem(qemu, addr as GuestAddr, 64);
+                self.store(qemu, &Operand::Reg(rbp), value)
+            }
+            LiftedInsn::Concretize { regs, mem, flags } => {
+                for gpr in regs {
+                    self.regs[*gpr] = None;
+                }
+                if let Some(mem) = mem {
+                    self.clear(qemu, &Operand::Mem(*mem));
+                }
+                if *flags {
+                    self.flags = Flags::Concrete;
+                }
+                Some(())
+            }
+        }
+    }
+
+    fn lea(&mut self, qemu: Qemu, mem: &MemOperand, bits: u8) -> Option<Value> {
+        let base = match mem.base {
+            Some(base) => Some(self.read_reg(qemu, base)?),
+            None => None,
+        };
+        let index = match mem.index {
+            Some(index) => Some(self.read_reg(qemu, index)?),
+            None => None,
+        };
+        let concrete = mem
+            .offset
+            .wrapping_add(base.map_or(0, |base| base.concrete))
+            .wrapping_add(index.map_or(0, |index| index.concrete.wrapping_mul(mem.scale.into())))
+            & mask(bits);
+        if base.and_then(|base| base.expr).is_none() && index.and_then(|index| index.expr).is_none()
+        {
+            return Some(Value::concrete(concrete));
+        }
+
+        // addresses are computed in 64 bits
+        let mut expr = self.emit(SymExpr::Integer {
+            value: mem.offset,
+            bits: 64,
+        })?;
+        if let (Some(base), Some(reg)) = (base, mem.base) {
+            let base = self.expr_of(base, reg.bits)?;
+            let base = self.resize(base, reg.bits, 64)?;
+            expr = self.emit(SymExpr::Add { a: expr, b: base })?;
+        }
+        if let (Some(index), Some(reg)) = (index, mem.index) {
+            let index = self.expr_of(index, reg.bits)?;
+            let mut index = self.resize(index, reg.bits, 64)?;
+            if mem.scale > 1 {
+                let scale = self.emit(SymExpr::Integer {
+                    value: mem.scale.into(),
+                    bits: 64,
+                })?;
+                index = self.emit(SymExpr::Mul { a: index, b: scale })?;
+            }
+            expr = self.emit(SymExpr::Add { a: expr, b: index })?;
+        }
+        Some(Value {
+            concrete,
+            expr: Some(self.resize(expr, 64, bits)?),
+        })
+    }
+}
+
+/// A module emitting a concolic [`SymExpr`] trace of `x86_64` usermode binaries.
+///
+/// The trace is written to a memory buffer, which a
+/// [`libafl::observers::concolic::ConcolicObserver`] reads after each run. Input bytes become
+/// symbolic once the harness calls [`ConcolicModule::symbolize_input`] for the guest memory it
+/// copied the input to.
+#[derive(Debug)]
+pub struct ConcolicModule {
+    address_filter: StdAddressFilter,
+    cs: Capstone,
+    trace_ptr: *mut u8,
+    trace_len: usize,
+    lifted: HashMap<GuestAddr, LiftedInsn>,
+    shadow: ShadowState,
+}
+
+impl ConcolicModule {
+    /// Creates a new [`ConcolicModule`] writing its trace to `trace`.
+    ///
+    /// # Safety
+    /// The trace buffer has to outlive the module. It is written while the target runs and
+    /// should only be read, i.e. by a [`libafl::observers::concolic::ConcolicObserver`], in between.
+    #[must_use]
+    pub unsafe fn new(address_filter: StdAddressFilter, trace: &mut [u8]) -> Self {
+        Self {
+            address_filter,
+            cs: capstone().detail(true).build().unwrap(),
+            trace_ptr: trace.as_mut_ptr(),
+            trace_len: trace.len(),
+            lifted: HashMap::new(),
+            shadow: ShadowState::new(),
+        }
+    }
+
+    /// `true` if the instructions at `addr` are lifted and traced
+    #[must_use]
+    pub fn must_instrument(&self, addr: GuestAddr) -> bool {
+        self.address_filter.allowed(&addr)
+    }
+
+    /// Makes the `len` bytes at `addr` symbolic, as the input bytes starting at `input_offset`.
+    ///
+    /// Call this from the harness in every run, after writing the input to the guest.
+    pub fn symbolize_input(
+        &mut self,
+        qemu: Qemu,
+        addr: GuestAddr,
+        len: usize,
+        input_offset: usize,
+    ) -> Result<(), QemuRWError> {
+        let bytes = qemu.read_mem_vec(addr, len)?;
+        for (i, value) in bytes.into_iter().enumerate() {
+            if let Some(expr) = self.shadow.emit(SymExpr::InputByte {
+                offset: input_offset + i,
+                value,
+            }) {
+                self.shadow.mem.insert(addr + i as GuestAddr, expr);
+            }
+        }
+        Ok(())
+    }
+
+    fn on_instruction<ET, I, S>(
+        qemu: Qemu,
+        emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: Option<&mut S>,
+        pc: GuestAddr,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+        I: Unpin,
+        S: Unpin,
+    {
+        let Some(h) = emulator_modules.get_mut::<Self>() else {
+            return;
+        };
+        if h.shadow.writer.is_none() || h.shadow.is_concrete() {
+            return;
+        }
+        if let Some(insn) = h.lifted.get(&pc) {
+            h.shadow.execute(qemu, pc, insn);
+        }
+    }
+
+    #[allow(clippy::needless_pass_by_value)] // no longer a problem in nightly
+    fn gen_blocks_lift<ET, I, S>(
+        qemu: Qemu,
+        emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: Option<&mut S>,
+        pc: GuestAddr,
+    ) -> Option<u64>
+    where
+        ET: EmulatorModuleTuple<I, S>,
+        I: Unpin,
+        S: Unpin,
+    {
+        let mut lifted = Vec::new();
+
+        if let Some(h) = emulator_modules.get::<Self>() {
+            if !h.must_instrument(pc) {
+                return None;
+            }
+
+            let mut iaddr = pc;
+            'disasm: loop {
+                // x86 instructions are at most 15 bytes long
+                let code = unsafe { slice::from_raw_parts(qemu.g2h(iaddr), 15) };
+                let Ok(insns) = h.cs.disasm_count(code, iaddr.into(), 1) else {
+                    break;
+                };
+                let Some(insn) = insns.first() else {
+                    break;
+                };
+                let Ok(insn_detail) = h.cs.insn_detail(insn) else {
+                    break;
+                };
+
+                if let Some(lifted_insn) = lift(&h.cs, insn, &insn_detail) {
+                    lifted.push((iaddr, lifted_insn));
+                }
+
+                for detail in insn_detail.groups() {
+                    match u32::from(detail.0) {
+                        capstone::InsnGroupType::CS_GRP_CALL
+                        | capstone::InsnGroupType::CS_GRP_RET
+                        | capstone::InsnGroupType::CS_GRP_INVALID
+                        | capstone::InsnGroupType::CS_GRP_JUMP
+                        | capstone::InsnGroupType::CS_GRP_IRET
+                        | capstone::InsnGroupType::CS_GRP_INT
+                        | capstone::InsnGroupType::CS_GRP_PRIVILEGE => {
+                            break 'disasm;
+                        }
+                        _ => {}
+                    }
+                }
+
+                iaddr += insn.bytes().len() as GuestAddr;
+            }
+        }
+
+        let mut new_addrs = Vec::new();
+        if let Some(h) = emulator_modules.get_mut::<Self>() {
+            for (addr, lifted_insn) in lifted {
+                if h.lifted.insert(addr, lifted_insn).is_none() {
+                    new_addrs.push(addr);
+                }
+            }
+        }
+        for addr in new_addrs {
+            emulator_modules.instruction_function(addr, Self::on_instruction::<ET, I, S>, false);
+        }
+
+        None
+    }
+}
+
+impl<I, S> EmulatorModule<I, S> for ConcolicModule
+where
+    I: Unpin,
+    S: Unpin,
+{
+    fn first_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        emulator_modules.blocks(
+            Hook::Function(Self::gen_blocks_lift::<ET, I, S>),
+            Hook::Empty,
+            Hook::Empty,
+        );
+    }
+
+    fn pre_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        // Safety: the caller of `new` guarantees the buffer outlives the module
+        let trace = unsafe { slice::from_raw_parts_mut(self.trace_ptr, self.trace_len) };
+        match MessageFileWriter::from_writer(Cursor::new(trace)) {
+            Ok(writer) => self.shadow.reset(Some(writer)),
+            Err(err) => {
+                log::error!("Failed to start the concolic trace: {err}");
+                self.shadow.reset(None);
+            }
+        }
+    }
+
+    fn post_exec<OT, ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+        _observers: &mut OT,
+        _exit_kind: &mut ExitKind,
+    ) where
+        OT: ObserversTuple<I, S>,
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        self.shadow.update_trace_header();
+        self.shadow.writer = None;
+    }
+}
+
+impl HasAddressFilter for ConcolicModule {
+    type AddressFilter = StdAddressFilter;
+
+    fn address_filter(&self) -> &Self::AddressFilter {
+        &self.address_filter
+    }
+
+    fn address_filter_mut(&mut self) -> &mut Self::AddressFilter {
+        &mut self.address_filter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, slice};
+
+    use libafl::observers::concolic::{
+        SymExpr,
+        serialization_format::{MessageFileReader, MessageFileWriter},
+    };
+
+    use super::{
+        BinaryOp, Cond, Extend, Flags, LiftedInsn, MemOperand, Operand, RegSlice, ShadowState,
+        UnaryOp, Value, lift_semantics, sign_extend, signed_lt,
+    };
+
+    fn reg(gpr: usize, bits: u8) -> Operand {
+        Operand::Reg(RegSlice::new(gpr, 0, bits))
+    }
+
+    const TRACE_LEN: usize = 4096;
+
+    /// A shadow state writing its trace to a fresh buffer
+    fn shadow() -> (ShadowState, *const u8) {
+        let trace: &'static mut [u8] = Box::leak(vec![0; TRACE_LEN].into_boxed_slice());
+        let ptr = trace.as_ptr();
+        let mut shadow = ShadowState::new();
+        shadow.reset(Some(
+            MessageFileWriter::from_writer(Cursor::new(trace)).unwrap(),
+        ));
+        (shadow, ptr)
+    }
+
+    /// Ends the trace of `shadow` and reads it back
+    fn messages(mut shadow: ShadowState, trace: *const u8) -> Vec<SymExpr> {
+        shadow.update_trace_header();
+        shadow.writer = None;
+        let trace = unsafe { slice::from_raw_parts(trace, TRACE_LEN) };
+        let mut reader = MessageFileReader::from_length_prefixed_buffer(trace).unwrap();
+        let mut messages = Vec::new();
+        while let Some(msg) = reader.next_message() {
+            messages.push(msg.unwrap().1);
+        }
+        messages
+    }
+
+    #[test]
+    fn test_parse_regs() {
+        assert_eq!(RegSlice::parse("rax"), Some(RegSlice::new(0, 0, 64)));
+        assert_eq!(RegSlice::parse("eax"), Some(RegSlice::new(0, 0, 32)));
+        assert_eq!(RegSlice::parse("ax"), Some(RegSlice::new(0, 0, 16)));
+        assert_eq!(RegSlice::parse("al"), Some(RegSlice::new(0, 0, 8)));
+        assert_eq!(RegSlice::parse("ah"), Some(RegSlice::new(0, 8, 8)));
+        assert_eq!(RegSlice::parse("sil"), Some(RegSlice::new(4, 0, 8)));
+        assert_eq!(RegSlice::parse("rsp"), Some(RegSlice::new(7, 0, 64)));
+        assert_eq!(RegSlice::parse("r9d"), Some(RegSlice::new(9, 0, 32)));
+        assert_eq!(RegSlice::parse("r15b"), Some(RegSlice::new(15, 0, 8)));
+        assert_eq!(RegSlice::parse("rip"), None);
+        assert_eq!(RegSlice::parse("xmm0"), None);
+    }
+
+    #[test]
+    fn test_conditions() {
+        assert_eq!(Cond::parse("nz"), Some(Cond::Ne));
+        assert_eq!(Cond::parse("nle"), Some(Cond::G));
+        assert_eq!(Cond::parse("mp"), None);
+        assert!(signed_lt(0xff, 0, 8));
+        assert!(!signed_lt(0x7f, 0x80, 8));
+        assert_eq!(sign_extend(0x80, 8), 0xffff_ffff_ffff_ff80);
+        assert_eq!(BinaryOp::Sar.eval(0x80, 1, 8), 0xc0);
+        assert_eq!(BinaryOp::Shl.eval(0x81, 1, 8), 0x02);
+    }
+
+    #[test]
+    fn test_lift_semantics() {
+        let imm = Operand::Imm {
+            value: 0xff,
+            bits: 8,
+        };
+        assert_eq!(
+            imm.fit(64),
+            Operand::Imm {
+                value: u64::MAX,
+                bits: 64
+            }
+        );
+        assert_eq!(
+            Operand::Imm {
+                value: 0x7f,
+                bits: 8
+            }
+            .fit(16),
+            Operand::Imm {
+                value: 0x7f,
+                bits: 16
+            }
+        );
+
+        assert!(matches!(
+            lift_semantics("mov", &[reg(0, 32), imm], 0),
+            Some(LiftedInsn::Mov {
+                src: Operand::Imm {
+                    value: 0xffff_ffff,
+                    bits: 32
+                },
+                ext: Extend::None,
+                ..
+            })
+        ));
+        assert!(matches!(
+            lift_semantics("sub", &[reg(1, 64), imm], 0),
+            Some(LiftedInsn::Binary {
+                op: BinaryOp::Sub,
+                b: Operand::Imm { bits: 64, .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            lift_semantics("dec", &[reg(1, 64)], 0),
+            Some(LiftedInsn::Unary {
+                op: UnaryOp::Dec,
+                ..
+            })
+        ));
+        assert!(matches!(
+            lift_semantics("jnz", &[imm], 0),
+            Some(LiftedInsn::Jcc { cond: Cond::Ne })
+        ));
+        assert!(matches!(
+            lift_semantics("setl", &[reg(0, 8)], 0),
+            Some(LiftedInsn::Setcc { cond: Cond::L, .. })
+        ));
+        assert!(matches!(
+            lift_semantics("call", &[imm], 0x1234),
+            Some(LiftedInsn::Push {
+                src: Operand::Imm {
+                    value: 0x1234,
+                    bits: 64
+                }
+            })
+        ));
+
+        let mem = MemOperand {
+            base: Some(RegSlice::new(6, 0, 64)),
+            index: None,
+            scale: 1,
+            offset: 8,
+            bits: 64,
+        };
+        assert!(matches!(
+            lift_semantics("lea", &[reg(0, 64), Operand::Mem(mem)], 0),
+            Some(LiftedInsn::Lea { .. })
+        ));
+        assert!(lift_semantics("lea", &[reg(0, 64), reg(1, 64)], 0).is_none());
+        assert!(lift_semantics("cpuid", &[], 0).is_none());
+    }
+
+    #[test]
+    fn test_shadow_exprs() {
+        let (mut shadow, trace) = shadow();
+        assert!(shadow.is_concrete());
+
+        let byte = shadow
+            .emit(SymExpr::InputByte {
+                offset: 0,
+                value: 0x41,
+            })
+            .unwrap();
+        let wide = shadow.resize(byte, 8, 32).unwrap();
+        assert_eq!(shadow.resize(wide, 32, 32), Some(wide));
+        shadow.resize(wide, 32, 16).unwrap();
+        shadow
+            .expr_of(super::Value::concrete(0x1_0042), 16)
+            .unwrap();
+
+        assert_eq!(
+            messages(shadow, trace),
+            vec![
+                SymExpr::InputByte {
+                    offset: 0,
+                    value: 0x41
+                },
+                SymExpr::Zext { op: byte, bits: 24 },
+                SymExpr::Extract {
+                    op: wide,
+                    first_bit: 15,
+                    last_bit: 0
+                },
+                SymExpr::Integer {
+                    value: 0x42,
+                    bits: 16
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shadow_conditions() {
+        let (mut shadow, trace) = shadow();
+        assert_eq!(shadow.condition(Cond::E), None);
+
+        let a = shadow
+            .emit(SymExpr::InputByte {
+                offset: 0,
+                value: 0x80,
+            })
+            .unwrap();
+        let b = shadow.emit(SymExpr::Integer { value: 1, bits: 8 }).unwrap();
+        shadow.flags = Flags::Sub {
+            a,
+            b,
+            concrete_a: 0x80,
+            concrete_b: 1,
+            bits: 8,
+        };
+        // 0x80 is above 1 unsigned, but below it signed
+        assert!(shadow.condition(Cond::A).unwrap().1);
+        assert!(shadow.condition(Cond::L).unwrap().1);
+        assert!(!shadow.condition(Cond::E).unwrap().1);
+        assert_eq!(shadow.condition(Cond::O), None);
+
+        shadow.flags = Flags::Logic {
+            result: a,
+            concrete: 0x80,
+            bits: 8,
+        };
+        assert!(shadow.condition(Cond::S).unwrap().1);
+        assert!(!shadow.condition(Cond::G).unwrap().1);
+
+        let messages = messages(shadow, trace);
+        assert!(messages.contains(&SymExpr::UnsignedGreaterThan { a, b }));
+        assert!(messages.contains(&SymExpr::SignedLessThan { a, b }));
+    }
+}
@@ -1,21 +1,45 @@
 //! Detect injection vulnerabilities
+//!
+//! The [`InjectionModule`] is a taint-less injection oracle: it hooks the sinks of its
+//! [`InjectionDetector`]s, the functions of the target or of its shared libraries receiving a
+//! query, a path or a format, and checks their argument for a sign of injection. The detectors
+//! defined in `YAML` or `TOML` look for substrings or regexes, while the built-in
+//! [`PathTraversalDetector`] and [`FormatStringDetector`] check the structure of the argument.
+//! The `execve` syscall is watched for command injections.
+//!
+//! The target keeps running after a finding, the run is only marked as a crash in `post_exec`.
+//! The module keeps the first [`InjectionMetadata`] of each detector, and hands them to its
+//! [`InjectionObserver`] at the end of the run, where the [`InjectionFeedback`] reads them.
+//! Restricted to one detector, each [`InjectionFeedback`] reports the injections of its detector
+//! as objectives of their own.
 
 /*
- * TODOs:
- *  - read in export addresses of shared libraries to resolve functions
- *
  * Maybe:
  *  - return code analysis support (not needed currently)
- *  - regex support (not needed currently)
- *  - std::string and Rust String support (would need such target functions added)
  *
  */
 
-use std::{ffi::CStr, fmt::Display, fs, os::raw::c_char, path::Path};
+use std::{
+    borrow::Cow,
+    fmt::{Debug, Display},
+    fs,
+    path::{Component, Path, PathBuf},
+};
 
 use hashbrown::HashMap;
-use libafl::Error;
+use libafl::{
+    Error, HasMetadata,
+    corpus::Testcase,
+    executors::ExitKind,
+    feedbacks::{Feedback, StateInitializer},
+    observers::{Observer, ObserversTuple},
+};
+use libafl_bolts::{
+    Named, impl_serdeany,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
 use libafl_qemu_sys::GuestAddr;
+use regex::bytes::Regex;
 use serde::{Deserialize, Serialize};
 
 #[cfg(not(cpu_target = "hexagon"))]
@@ -37,6 +61,9 @@ use crate::{
 #[expect(non_upper_case_globals)]
 const SYS_execve: u8 = 221;
 
+/// The longest argument read from the guest
+const MAX_ARGUMENT_LEN: usize = 0x10000;
+
 /// Parses `injections.yaml`
 fn parse_yaml<P: AsRef<Path> + Display>(path: P) -> Result<Vec<YamlInjectionEntry>, Error> {
     serde_yaml::from_str(&fs::read_to_string(&path)?)
@@ -64,14 +91,17 @@ fn yaml_entries_to_definition(
                 function.function.clone(),
                 FunctionDescription {
                     param: function.parameter,
+                    kind: function.kind,
                 },
             );
         }
 
         let mut matches = Vec::new();
+        let mut regexes = Vec::new();
         let mut tokens = Vec::new();
         for test in &entry.tests {
-            matches.push(test.match_value.clone());
+            matches.extend(test.match_value.clone());
+            regexes.extend(test.match_regex.clone());
             tokens.push(test.input_value.clone());
         }
 
@@ -81,6 +111,7 @@ fn yaml_entries_to_definition(
                 InjectionDefinition {
                     tokens,
                     matches,
+                    regexes,
                     functions,
                 },
             )
@@ -112,13 +143,18 @@ impl LibInfo {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Test {
     input_value: String,
-    match_value: String,
+    #[serde(default)]
+    match_value: Option<String>,
+    #[serde(default)]
+    match_regex: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Functions {
     function: String,
     parameter: u8,
+    #[serde(default)]
+    kind: ArgumentKind,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -131,33 +167,464 @@ struct YamlInjectionEntry {
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 struct FunctionDescription {
     param: u8,
+    #[serde(default)]
+    kind: ArgumentKind,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct InjectionDefinition {
     tokens: Vec<String>,
+    #[serde(default)]
     matches: Vec<String>,
+    #[serde(default)]
+    regexes: Vec<String>,
     functions: HashMap<String, FunctionDescription>,
 }
 
+/// How the string checked by a detector is passed to a sink
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgumentKind {
+    /// A pointer to a NUL-terminated string
+    #[default]
+    CStr,
+    /// A pointer to a libstdc++ `std::string`
+    StdString,
+    /// A Rust `&str`, passed as a pointer in the parameter and a length in the next one
+    RustStr,
+    /// A pointer to a Rust `String`, laid out as capacity, pointer and length like current
+    /// versions of rustc do
+    RustString,
+}
+
+/// A function whose argument is checked by an [`InjectionDetector`]
+#[derive(Debug, Clone)]
+pub struct InjectionSink {
+    /// The name of the function, or its address in hexadecimal starting with `0x`
+    pub function: String,
+    /// The parameter holding the argument, starting from 0
+    pub param: u8,
+    /// How the argument is passed
+    pub kind: ArgumentKind,
+}
+
+impl InjectionSink {
+    /// A sink taking a C string as parameter `param`
+    #[must_use]
+    pub fn new<F>(function: F, param: u8) -> Self
+    where
+        F: Into<String>,
+    {
+        Self {
+            function: function.into(),
+            param,
+            kind: ArgumentKind::CStr,
+        }
+    }
+
+    /// Sets how the argument is passed
+    #[must_use]
+    pub fn with_kind(mut self, kind: ArgumentKind) -> Self {
+        self.kind = kind;
+        self
+    }
+}
+
+/// Detects a kind of injection from the arguments of its sinks
+pub trait InjectionDetector: Debug {
+    /// The name of the detector, reported in the [`InjectionMetadata`]
+    fn name(&self) -> &str;
+
+    /// The functions whose arguments are checked
+    fn sinks(&self) -> &[InjectionSink];
+
+    /// The strings likely to trigger an injection, to add to the tokens of the fuzzer
+    fn tokens(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Checks the argument passed to a sink, describing the injection if one is found
+    fn check(&self, argument: &[u8]) -> Option<String>;
+}
+
 #[derive(Clone, Debug)]
-pub struct Matches {
-    id: usize,
-    lib_name: String,
+enum Match {
+    Substring {
+        bytes_lower: Vec<u8>,
+        original_value: String,
+    },
+    Regex(Regex),
+}
+
+/// Detects an injection when the argument contains one of the given substrings, compared without
+/// case, or matches one of the given regexes. The detectors defined in `YAML` or `TOML` are
+/// [`MatchDetector`]s.
+#[derive(Clone, Debug)]
+pub struct MatchDetector {
+    name: String,
+    sinks: Vec<InjectionSink>,
+    tokens: Vec<String>,
     matches: Vec<Match>,
 }
 
+impl MatchDetector {
+    /// Creates a new [`MatchDetector`] named `name`, checking nothing
+    #[must_use]
+    pub fn new<N>(name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            sinks: Vec::new(),
+            tokens: Vec::new(),
+            matches: Vec::new(),
+        }
+    }
+
+    /// Creates a [`MatchDetector`] from an entry of the `YAML` or `TOML` definitions
+    pub fn from_definition(name: &str, definition: &InjectionDefinition) -> Result<Self, Error> {
+        let mut detector = Self::new(name);
+        for (function, description) in &definition.functions {
+            detector = detector.with_sink(
+                InjectionSink::new(function.clone(), description.param).with_kind(description.kind),
+            );
+        }
+        for token in &definition.tokens {
+            detector = detector.with_token(token.clone());
+        }
+        for match_value in &definition.matches {
+            detector = detector.with_match(match_value.clone());
+        }
+        for regex in &definition.regexes {
+            detector = detector.with_regex(regex)?;
+        }
+        Ok(detector)
+    }
+
+    /// Detects SQL injections in the queries passed to `SQLite`, `PostgreSQL` and `MySQL`
+    #[must_use]
+    pub fn sql() -> Self {
+        let mut detector = Self::new("sql")
+            .with_token("'\"\"'\"\n")
+            .with_token("\"1\" OR '1'=\"1\"")
+            .with_match("'\"\"'\"")
+            .with_match("1\" OR '1'=\"1");
+        for function in [
+            "sqlite3_exec",
+            "sqlite3_prepare",
+            "sqlite3_prepare_v2",
+            "sqlite3_prepare_v3",
+            "PQexec",
+            "PQexecParams",
+            "mysql_query",
+            "mysql_real_query",
+            "mysql_send_query",
+        ] {
+            detector = detector.with_sink(InjectionSink::new(function, 1));
+        }
+        // The statement name comes first
+        detector.with_sink(InjectionSink::new("PQprepare", 2))
+    }
+
+    /// Detects LDAP injections in the filters passed to `OpenLDAP`
+    #[must_use]
+    pub fn ldap() -> Self {
+        let mut detector = Self::new("ldap")
+            .with_token("*)(FUZZ=*))(|")
+            .with_match("*)(FUZZ=*))(|");
+        for function in [
+            "ldap_search",
+            "ldap_search_s",
+            "ldap_search_st",
+            "ldap_search_ext",
+            "ldap_search_ext_s",
+        ] {
+            detector = detector.with_sink(InjectionSink::new(function, 3));
+        }
+        detector
+    }
+
+    /// Checks the argument of `sink`
+    #[must_use]
+    pub fn with_sink(mut self, sink: InjectionSink) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Adds `token` to the tokens of the fuzzer
+    #[must_use]
+    pub fn with_token<T>(mut self, token: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.tokens.push(token.into());
+        self
+    }
+
+    /// Detects an injection when the argument contains `value`, compared without case
+    #[must_use]
+    pub fn with_match<T>(mut self, value: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let original_value = value.into();
+        let mut bytes_lower = original_value.as_bytes().to_vec();
+        bytes_lower.make_ascii_lowercase();
+        self.matches.push(Match::Substring {
+            bytes_lower,
+            original_value,
+        });
+        self
+    }
+
+    /// Detects an injection when the argument matches `regex`
+    pub fn with_regex(mut self, regex: &str) -> Result<Self, Error> {
+        let regex = Regex::new(regex).map_err(|e| {
+            Error::illegal_argument(format!("Invalid regex {regex} for {}: {e}", self.name))
+        })?;
+        self.matches.push(Match::Regex(regex));
+        Ok(self)
+    }
+}
+
+impl InjectionDetector for MatchDetector {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sinks(&self) -> &[InjectionSink] {
+        &self.sinks
+    }
+
+    fn tokens(&self) -> Vec<String> {
+        self.tokens.clone()
+    }
+
+    fn check(&self, argument: &[u8]) -> Option<String> {
+        let argument_lower = argument.to_ascii_lowercase();
+        self.matches
+            .iter()
+            .find_map(|match_value| match match_value {
+                Match::Substring {
+                    bytes_lower,
+                    original_value,
+                } => find_subsequence(&argument_lower, bytes_lower)
+                    .map(|_| format!("Found value \"{original_value}\"")),
+                Match::Regex(regex) => regex
+                    .find(argument)
+                    .map(|found| format!("Matched regex \"{regex}\" at {}", found.start())),
+            })
+    }
+}
+
+/// Detects the paths starting under `root` and going above it with `..`, when passed to `open`,
+/// `fopen` and friends. Relative paths are resolved from `root`, the absolute paths outside of
+/// `root` are never flagged.
+#[derive(Clone, Debug)]
+pub struct PathTraversalDetector {
+    root: PathBuf,
+    sinks: Vec<InjectionSink>,
+}
+
+impl PathTraversalDetector {
+    /// Creates a new [`PathTraversalDetector`] for the files under `root`
+    #[must_use]
+    pub fn new<P>(root: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            sinks: vec![
+                InjectionSink::new("open", 0),
+                InjectionSink::new("open64", 0),
+                InjectionSink::new("openat", 1),
+                InjectionSink::new("openat64", 1),
+                InjectionSink::new("creat", 0),
+                InjectionSink::new("fopen", 0),
+                InjectionSink::new("fopen64", 0),
+            ],
+        }
+    }
+}
+
+impl InjectionDetector for PathTraversalDetector {
+    fn name(&self) -> &str {
+        "path_traversal"
+    }
+
+    fn sinks(&self) -> &[InjectionSink] {
+        &self.sinks
+    }
+
+    fn tokens(&self) -> Vec<String> {
+        vec![
+            "../".to_string(),
+            "../../../../../../../../etc/passwd".to_string(),
+        ]
+    }
+
+    fn check(&self, argument: &[u8]) -> Option<String> {
+        let path = Path::new(std::str::from_utf8(argument).ok()?);
+        if !path.components().any(|c| c == Component::ParentDir)
+            || (path.is_absolute() && !path.starts_with(&self.root))
+        {
+            return None;
+        }
+
+        let mut resolved = PathBuf::new();
+        for component in self.root.join(path).components() {
+            match component {
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::CurDir => {}
+                component => resolved.push(component),
+            }
+        }
+        (!resolved.starts_with(&self.root)).then(|| {
+            format!(
+                "{} escapes {} to {}",
+                path.display(),
+                self.root.display(),
+                resolved.display()
+            )
+        })
+    }
+}
+
+/// Detects the formats passed to the `printf` family writing to memory with a `%n` conversion,
+/// which the programs never need with formats they control.
 #[derive(Clone, Debug)]
-pub struct Match {
-    bytes_lower: Vec<u8>,
-    original_value: String,
+pub struct FormatStringDetector {
+    sinks: Vec<InjectionSink>,
+    conversion: Regex,
+}
+
+impl Default for FormatStringDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FormatStringDetector {
+    /// Creates a new [`FormatStringDetector`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            sinks: vec![
+                InjectionSink::new("printf", 0),
+                InjectionSink::new("vprintf", 0),
+                InjectionSink::new("fprintf", 1),
+                InjectionSink::new("vfprintf", 1),
+                InjectionSink::new("dprintf", 1),
+                InjectionSink::new("vdprintf", 1),
+                InjectionSink::new("sprintf", 1),
+                InjectionSink::new("vsprintf", 1),
+                InjectionSink::new("snprintf", 2),
+                InjectionSink::new("vsnprintf", 2),
+                InjectionSink::new("syslog", 1),
+                InjectionSink::new("vsyslog", 1),
+            ],
+            // A `%` preceded by an odd number of `%` is escaped
+            conversion: Regex::new(r"(?:^|[^%])(?:%%)*(%[-+ #0-9.*$']*[hlLqjzt]*n)").unwrap(),
+        }
+    }
+}
+
+impl InjectionDetector for FormatStringDetector {
+    fn name(&self) -> &str {
+        "format_string"
+    }
+
+    fn sinks(&self) -> &[InjectionSink] {
+        &self.sinks
+    }
+
+    fn tokens(&self) -> Vec<String> {
+        vec![
+            "%n".to_string(),
+            "%x%x%x%x%n".to_string(),
+            "%1$n".to_string(),
+        ]
+    }
+
+    fn check(&self, argument: &[u8]) -> Option<String> {
+        let conversion = self.conversion.captures(argument)?.get(1)?;
+        Some(format!(
+            "Found the writing conversion \"{}\"",
+            String::from_utf8_lossy(conversion.as_bytes())
+        ))
+    }
+}
+
+/// An injection found during the last run, in the [`InjectionObserver`] and in the metadata of the
+/// objective
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InjectionMetadata {
+    /// The name of the detector
+    pub detector: String,
+    /// The sink which received the argument
+    pub function: String,
+    /// The argument, lossily converted to UTF-8
+    pub argument: String,
+    /// What the detector found
+    pub description: String,
+}
+
+impl_serdeany!(InjectionMetadata);
+
+/// The injections found by the [`InjectionModule`] during the last run, at most one per detector
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InjectionObserver {
+    name: Cow<'static, str>,
+    findings: Vec<InjectionMetadata>,
+}
+
+impl InjectionObserver {
+    /// Creates a new [`InjectionObserver`] named `name`
+    #[must_use]
+    pub fn new<N>(name: N) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        Self {
+            name: name.into(),
+            findings: Vec::new(),
+        }
+    }
+
+    /// The injections found during the last run
+    #[must_use]
+    pub fn findings(&self) -> &[InjectionMetadata] {
+        &self.findings
+    }
+}
+
+impl<I, S> Observer<I, S> for InjectionObserver {
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.findings.clear();
+        Ok(())
+    }
+}
+
+impl Named for InjectionObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
 }
 
 #[derive(Debug)]
 pub struct InjectionModule {
     pub tokens: Vec<String>,
-    definitions: HashMap<String, InjectionDefinition>,
-    matches_list: Vec<Matches>,
+    detectors: Vec<Box<dyn InjectionDetector>>,
+    findings: Vec<InjectionMetadata>,
+    observer_handle: Option<Handle<InjectionObserver>>,
 }
 
 impl InjectionModule {
@@ -177,89 +644,100 @@ impl InjectionModule {
     }
 
     pub fn new(definitions: HashMap<String, InjectionDefinition>) -> Result<Self, Error> {
-        let tokens = definitions
+        let mut module = Self {
+            tokens: Vec::new(),
+            detectors: Vec::new(),
+            findings: Vec::new(),
+            observer_handle: None,
+        };
+        for (name, definition) in &definitions {
+            module = module.with_detector(MatchDetector::from_definition(name, definition)?);
+        }
+        Ok(module)
+    }
+
+    /// Adds `detector`, and its tokens to [`InjectionModule::tokens`]
+    #[must_use]
+    pub fn with_detector<D>(mut self, detector: D) -> Self
+    where
+        D: InjectionDetector + 'static,
+    {
+        self.tokens.extend(detector.tokens());
+        self.detectors.push(Box::new(detector));
+        self
+    }
+
+    /// Hands the injections found during each run to `observer`, for the [`InjectionFeedback`]
+    #[must_use]
+    pub fn with_observer(mut self, observer: &InjectionObserver) -> Self {
+        self.observer_handle = Some(observer.handle());
+        self
+    }
+
+    /// The injections found during the current run, at most one per detector
+    #[must_use]
+    pub fn findings(&self) -> &[InjectionMetadata] {
+        &self.findings
+    }
+
+    /// Adds the built-in SQL, LDAP and format string detectors, and the path traversal detector
+    /// for the files under `root`
+    #[must_use]
+    pub fn with_builtin_detectors<P>(self, root: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.with_detector(MatchDetector::sql())
+            .with_detector(MatchDetector::ldap())
+            .with_detector(FormatStringDetector::new())
+            .with_detector(PathTraversalDetector::new(root))
+    }
+
+    /// Records the first injection of each detector during the run, reported when it ends
+    fn report(&mut self, finding: InjectionMetadata) {
+        log::info!(
+            "Injections: {} injection in {}: {} ({:?})",
+            finding.detector,
+            finding.function,
+            finding.description,
+            finding.argument
+        );
+        if !self
+            .findings
             .iter()
-            .flat_map(|(_lib_name, definition)| &definition.tokens)
-            .map(ToString::to_string)
-            .collect();
-
-        let mut matches_list = Vec::with_capacity(definitions.len());
-
-        for (lib_name, definition) in &definitions {
-            let matches: Vec<Match> = definition
-                .matches
-                .iter()
-                .map(|match_str| {
-                    let mut bytes_lower = match_str.as_bytes().to_vec();
-                    bytes_lower.make_ascii_lowercase();
-
-                    Match {
-                        original_value: match_str.clone(),
-                        bytes_lower,
-                    }
-                })
-                .collect();
-
-            let id = matches_list.len();
-            matches_list.push(Matches {
-                lib_name: lib_name.clone(),
-                id,
-                matches,
-            });
-        }
-
-        Ok(Self {
-            tokens,
-            definitions,
-            matches_list,
-        })
+            .any(|found| found.detector == finding.detector)
+        {
+            self.findings.push(finding);
+        }
     }
 
     fn on_call_check<ET, I, S>(
         qemu: Qemu,
         emulator_modules: &mut EmulatorModules<ET, I, S>,
-        id: usize,
-        parameter: u8,
+        detector_id: usize,
+        sink_id: usize,
     ) where
         ET: EmulatorModuleTuple<I, S>,
         I: Unpin,
         S: Unpin,
     {
-        let reg: GuestAddr = qemu
-            .current_cpu()
-            .unwrap()
-            .read_function_argument_with_cc(parameter, CallingConvention::Default)
-            .unwrap_or_default();
-
         let module = emulator_modules.get_mut::<Self>().unwrap();
-        let matches = &module.matches_list[id];
+        let detector = &module.detectors[detector_id];
+        let sink = &detector.sinks()[sink_id];
 
-        //println!("reg value = {:x}", reg);
+        let Some(argument) = read_argument(qemu, sink.param, sink.kind) else {
+            return;
+        };
+        log::trace!("Checking {} in {}", detector.name(), sink.function);
 
-        if reg != 0x00 {
-            let mut query = unsafe {
-                let c_str_ptr = reg as *const c_char;
-                let c_str = CStr::from_ptr(c_str_ptr);
-                c_str.to_bytes().to_vec()
+        if let Some(description) = detector.check(&argument) {
+            let finding = InjectionMetadata {
+                detector: detector.name().to_string(),
+                function: sink.function.clone(),
+                argument: String::from_utf8_lossy(&argument).into_owned(),
+                description,
             };
-            query.make_ascii_lowercase();
-
-            //println!("query={}", query);
-            log::trace!("Checking {}", matches.lib_name);
-
-            for match_value in &matches.matches {
-                if match_value.bytes_lower.len() > matches.matches.len() {
-                    continue;
-                }
-
-                // "crash" if we found the right value
-                assert!(
-                    find_subsequence(&query, &match_value.bytes_lower).is_none(),
-                    "Found value \"{}\" for {query:?} in {}",
-                    match_value.original_value,
-                    matches.lib_name
-                );
-            }
+            module.report(finding);
         }
     }
 }
@@ -301,24 +779,24 @@ where
             }
         }
 
-        for matches in &self.matches_list {
-            let id = matches.id;
-            let lib_name = &matches.lib_name;
+        for (detector_id, detector) in self.detectors.iter().enumerate() {
+            let detector_name = detector.name();
 
-            for (name, func_definition) in &self.definitions[lib_name].functions {
-                let hook_addrs = if name.to_lowercase().starts_with(&"0x".to_string()) {
-                    let func_pc = u64::from_str_radix(&name[2..], 16)
+            for (sink_id, sink) in detector.sinks().iter().enumerate() {
+                let name = &sink.function;
+                let hook_addrs = if name.to_lowercase().starts_with("0x") {
+                    let func_pc = GuestAddr::from_str_radix(&name[2..], 16)
                         .map_err(|e| {
                             Error::illegal_argument(format!(
-                            "Failed to parse hex string {name} from definition for {lib_name}: {e}"
+                            "Failed to parse hex string {name} from definition for {detector_name}: {e}"
                         ))
                         })
-                        .unwrap() as GuestAddr;
+                        .unwrap();
                     log::info!("Injections: Hooking hardcoded function {func_pc:#x}");
                     vec![func_pc]
                 } else {
                     libs.iter()
-                        .filter_map(|lib| find_function(qemu, &lib.name, name, lib.off).unwrap())
+                        .filter_map(|lib| find_function(qemu, &lib.name, name, lib.off))
                         .inspect(|&func_pc| {
                             log::info!("Injections: Function {name} found at {func_pc:#x}");
                         })
@@ -326,16 +804,14 @@ where
                 };
 
                 if hook_addrs.is_empty() {
-                    log::warn!("Injections: Function not found for {lib_name}: {name}",);
+                    log::debug!("Injections: Function not found for {detector_name}: {name}");
                 }
 
-                let param = func_definition.param;
-
                 for hook_addr in hook_addrs {
                     emulator_modules.instructions(
                         hook_addr,
                         Hook::Closure(Box::new(move |qemu, hooks, _state, _guest_addr| {
-                            Self::on_call_check(qemu, hooks, id, param);
+                            Self::on_call_check(qemu, hooks, detector_id, sink_id);
                         })),
                         true,
                     );
@@ -343,6 +819,42 @@ where
             }
         }
     }
+
+    fn pre_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        self.findings.clear();
+    }
+
+    fn post_exec<OT, ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+        observers: &mut OT,
+        exit_kind: &mut ExitKind,
+    ) where
+        OT: ObserversTuple<I, S>,
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        if self.findings.is_empty() {
+            return;
+        }
+        *exit_kind = ExitKind::Crash;
+        if let Some(observer_handle) = &self.observer_handle {
+            let observer = observers
+                .get_mut(observer_handle)
+                .expect("The InjectionModule needs its InjectionObserver");
+            observer.findings.clone_from(&self.findings);
+        }
+    }
 }
 
 impl HasAddressFilter for InjectionModule {
@@ -357,11 +869,121 @@ impl HasAddressFilter for InjectionModule {
     }
 }
 
+/// Reports the runs during which the [`InjectionModule`] found an injection, read from its
+/// [`InjectionObserver`], adding the [`InjectionMetadata`] to the objective. Meant to be combined
+/// with the other objective feedbacks, for instance with
+/// `feedback_or_fast!(InjectionFeedback::new(&observer), CrashFeedback::new())`.
+///
+/// With [`InjectionFeedback::with_detector`], only the injections of one detector are reported.
+/// Combining one feedback per detector, the objectives of each detector are told apart, for
+/// instance with `feedback_or!(InjectionFeedback::new(&observer).with_detector("sql"),
+/// InjectionFeedback::new(&observer).with_detector("path_traversal"))`.
+#[derive(Debug, Clone)]
+pub struct InjectionFeedback {
+    name: Cow<'static, str>,
+    observer_handle: Handle<InjectionObserver>,
+    detector: Option<String>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    last_result: Option<bool>,
+}
+
+impl InjectionFeedback {
+    /// Creates a new [`InjectionFeedback`], reporting the injections of every detector found in
+    /// `observer`
+    #[must_use]
+    pub fn new(observer: &InjectionObserver) -> Self {
+        Self {
+            name: Cow::Borrowed("InjectionFeedback"),
+            observer_handle: observer.handle(),
+            detector: None,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+
+    /// Only reports the injections found by the detector named `detector`
+    #[must_use]
+    pub fn with_detector<D>(mut self, detector: D) -> Self
+    where
+        D: Into<String>,
+    {
+        let detector = detector.into();
+        self.name = Cow::Owned(format!("InjectionFeedback_{detector}"));
+        self.detector = Some(detector);
+        self
+    }
+
+    /// The injection of the last run reported by this feedback
+    fn finding<'a, OT>(&self, observers: &'a OT) -> Option<&'a InjectionMetadata>
+    where
+        OT: MatchName,
+    {
+        observers
+            .get(&self.observer_handle)
+            .expect("An InjectionFeedback needs an InjectionObserver")
+            .findings
+            .iter()
+            .find(|finding| {
+                self.detector
+                    .as_ref()
+                    .is_none_or(|detector| *detector == finding.detector)
+            })
+    }
+}
+
+impl Named for InjectionFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> StateInitializer<S> for InjectionFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for InjectionFeedback
+where
+    OT: MatchName,
+{
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let interesting = self.finding(observers).is_some();
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(interesting);
+        }
+        Ok(interesting)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result
+            .ok_or_else(|| Error::illegal_state("last_result called before Feedback was run"))
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        if let Some(finding) = self.finding(observers) {
+            testcase.add_metadata(finding.clone());
+        }
+        Ok(())
+    }
+}
+
 #[expect(clippy::too_many_arguments)]
 #[allow(clippy::needless_pass_by_value)] // no longer a problem with nightly
 fn syscall_hook<ET, I, S>(
     // Our instantiated [`EmulatorModules`]
-    _qemu: Qemu,
+    qemu: Qemu,
     emulator_modules: &mut EmulatorModules<ET, I, S>,
     _state: Option<&mut S>,
     // Syscall number
@@ -384,40 +1006,43 @@ where
     log::trace!("syscall_hook {syscall} {SYS_execve}");
     debug_assert!(i32::try_from(SYS_execve).is_ok());
     if syscall == SYS_execve as i32 {
-        let _module = emulator_modules.get_mut::<InjectionModule>().unwrap();
+        let module = emulator_modules.get_mut::<InjectionModule>().unwrap();
         if x0 > 0 && x1 > 0 {
-            let c_array = x1 as *const *const c_char;
-            let cmd = unsafe {
-                let c_str_ptr = x0 as *const c_char;
-                CStr::from_ptr(c_str_ptr).to_string_lossy()
+            let Some(cmd) = read_c_string(qemu, x0) else {
+                return SyscallHookResult::new(Some(0));
             };
-            assert_ne!(
-                cmd.to_lowercase(),
-                "fuzz",
-                "Found verified command injection!"
-            );
-            //println!("CMD {}", cmd);
+            let cmd = String::from_utf8_lossy(&cmd).into_owned();
+            if cmd.to_lowercase() == "fuzz" {
+                module.report(InjectionMetadata {
+                    detector: "cmd".to_string(),
+                    function: "execve".to_string(),
+                    argument: cmd,
+                    description: "Found verified command injection".to_string(),
+                });
+                return SyscallHookResult::new(Some(0));
+            }
 
-            let first_parameter = unsafe {
-                if (*c_array.offset(1)).is_null() {
-                    return SyscallHookResult::new(None);
-                }
-                CStr::from_ptr(*c_array.offset(1)).to_string_lossy()
+            let ptr_size = size_of::<GuestAddr>() as GuestAddr;
+            let parameter = |idx: GuestAddr| {
+                let ptr = read_guest_addr(qemu, x1 + idx * ptr_size)?;
+                (ptr != 0).then(|| read_c_string(qemu, ptr)).flatten()
             };
-            let second_parameter = unsafe {
-                if (*c_array.offset(2)).is_null() {
-                    return SyscallHookResult::new(None);
-                }
-                CStr::from_ptr(*c_array.offset(2)).to_string_lossy()
+            let (Some(first_parameter), Some(second_parameter)) = (parameter(1), parameter(2))
+            else {
+                return SyscallHookResult::new(None);
             };
-            if first_parameter == "-c"
-                && (second_parameter.to_lowercase().contains("';fuzz;'")
-                    || second_parameter.to_lowercase().contains("\";fuzz;\""))
+            let second_parameter = String::from_utf8_lossy(&second_parameter).to_lowercase();
+            if first_parameter == b"-c"
+                && (second_parameter.contains("';fuzz;'")
+                    || second_parameter.contains("\";fuzz;\""))
             {
-                panic!("Found command injection!");
+                module.report(InjectionMetadata {
+                    detector: "cmd".to_string(),
+                    function: "execve".to_string(),
+                    argument: second_parameter,
+                    description: "Found command injection".to_string(),
+                });
             }
-
-            //println!("PARAMETERS First {} Second {}", first_parameter, second_
         }
         SyscallHookResult::new(Some(0))
     } else {
@@ -425,23 +1050,104 @@ where
     }
 }
 
-fn find_function(
-    qemu: Qemu,
-    file: &str,
-    function: &str,
-    loadaddr: GuestAddr,
-) -> Result<Option<GuestAddr>, Error> {
+/// Reads the argument of the current call, passed as the parameter `param` in the way of `kind`
+fn read_argument(qemu: Qemu, param: u8, kind: ArgumentKind) -> Option<Vec<u8>> {
+    let cpu = qemu.current_cpu()?;
+    let arg: GuestAddr = cpu
+        .read_function_argument_with_cc(param, CallingConvention::Default)
+        .ok()?;
+    if arg == 0 {
+        return None;
+    }
+
+    let ptr_size = size_of::<GuestAddr>() as GuestAddr;
+    match kind {
+        ArgumentKind::CStr => read_c_string(qemu, arg),
+        ArgumentKind::StdString => {
+            let data = read_guest_addr(qemu, arg)?;
+            let len = read_guest_addr(qemu, arg + ptr_size)?;
+            read_bytes(qemu, data, len)
+        }
+        ArgumentKind::RustStr => {
+            let len: GuestAddr = cpu
+                .read_function_argument_with_cc(param + 1, CallingConvention::Default)
+                .ok()?;
+            read_bytes(qemu, arg, len)
+        }
+        ArgumentKind::RustString => {
+            let data = read_guest_addr(qemu, arg + ptr_size)?;
+            let len = read_guest_addr(qemu, arg + 2 * ptr_size)?;
+            read_bytes(qemu, data, len)
+        }
+    }
+}
+
+/// Reads a pointer-sized value from the guest memory
+fn read_guest_addr(qemu: Qemu, addr: GuestAddr) -> Option<GuestAddr> {
+    let mut bytes = [0; size_of::<GuestAddr>()];
+    qemu.read_mem(addr, &mut bytes).ok()?;
+
+    #[cfg(feature = "be")]
+    return Some(GuestAddr::from_be_bytes(bytes));
+
+    #[cfg(not(feature = "be"))]
+    return Some(GuestAddr::from_le_bytes(bytes));
+}
+
+/// Reads `len` bytes from the guest memory, truncated to [`MAX_ARGUMENT_LEN`]
+fn read_bytes(qemu: Qemu, addr: GuestAddr, len: GuestAddr) -> Option<Vec<u8>> {
+    let len = usize::try_from(len).map_or(MAX_ARGUMENT_LEN, |len| len.min(MAX_ARGUMENT_LEN));
+    qemu.read_mem_vec(addr, len).ok()
+}
+
+/// Reads a NUL-terminated string from the guest memory, truncated to [`MAX_ARGUMENT_LEN`]
+fn read_c_string(qemu: Qemu, addr: GuestAddr) -> Option<Vec<u8>> {
+    const CHUNK: usize = 64;
+
+    let mut string = Vec::new();
+    let mut chunk = [0; CHUNK];
+    let mut chunk_addr = addr;
+    while string.len() < MAX_ARGUMENT_LEN {
+        // The string may end right before an unmapped page, fall back to a single byte
+        let len = if qemu.read_mem(chunk_addr, &mut chunk).is_ok() {
+            CHUNK
+        } else {
+            qemu.read_mem(chunk_addr, &mut chunk[..1]).ok()?;
+            1
+        };
+        if let Some(nul) = chunk[..len].iter().position(|b| *b == 0) {
+            string.extend_from_slice(&chunk[..nul]);
+            return Some(string);
+        }
+        string.extend_from_slice(&chunk[..len]);
+        chunk_addr += len as GuestAddr;
+    }
+    Some(string)
+}
+
+/// Resolves `function` in `file` loaded at `loadaddr`, from its symbols or from its exports
+fn find_function(qemu: Qemu, file: &str, function: &str, loadaddr: GuestAddr) -> Option<GuestAddr> {
     let mut elf_buffer = Vec::new();
-    let elf = EasyElf::from_file(file, &mut elf_buffer)?;
+    let elf = match EasyElf::from_file(file, &mut elf_buffer) {
+        Ok(elf) => elf,
+        Err(e) => {
+            log::debug!("Injections: Could not parse {file}: {e}");
+            return None;
+        }
+    };
     let offset = if loadaddr > 0 {
         loadaddr
     } else {
         qemu.load_addr()
     };
-    Ok(elf.resolve_symbol(function, offset))
+    elf.resolve_symbol(function, offset)
+        .or_else(|| elf.resolve_export(function, offset))
 }
 
 fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return None;
+    }
     haystack
         .windows(needle.len())
         .position(|window| window == needle)
@@ -450,8 +1156,26 @@ fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
 #[cfg(test)]
 mod tests {
     use hashbrown::HashMap;
+    use libafl::{
+        executors::ExitKind, feedbacks::Feedback, inputs::BytesInput, observers::Observer,
+        state::NopState,
+    };
+    use libafl_bolts::tuples::tuple_list;
+
+    use super::{
+        FormatStringDetector, InjectionDefinition, InjectionDetector, InjectionFeedback,
+        InjectionMetadata, InjectionModule, InjectionObserver, MatchDetector,
+        PathTraversalDetector, YamlInjectionEntry, yaml_entries_to_definition,
+    };
 
-    use super::{InjectionDefinition, YamlInjectionEntry, yaml_entries_to_definition};
+    fn finding(detector: &str, argument: &str) -> InjectionMetadata {
+        InjectionMetadata {
+            detector: detector.to_string(),
+            function: "sink".to_string(),
+            argument: argument.to_string(),
+            description: String::new(),
+        }
+    }
 
     #[test]
     fn test_yaml_parsing() {
@@ -467,7 +1191,7 @@ mod tests {
               tests:
                 - input_value: "*)(FUZZ=*))(|"
                   match_value: "*)(FUZZ=*))(|"
-            
+
             # XSS injection tests
             # This is a minimal example that only checks for libxml2
             - name: "xss"
@@ -515,4 +1239,99 @@ mod tests {
         .unwrap();
         assert_eq!(injections.len(), 2);
     }
+
+    #[test]
+    fn test_definition_detector() {
+        let injections: HashMap<String, InjectionDefinition> = toml::from_str(
+            r#"
+            [template]
+            tokens = ["{{7*7}}"]
+            regexes = ["\\{\\{[^}]*\\}\\}"]
+            [template.functions]
+            render = {param = 1, kind = "std_string"}
+            "#,
+        )
+        .unwrap();
+        let detector = MatchDetector::from_definition("template", &injections["template"]).unwrap();
+        assert_eq!(detector.sinks().len(), 1);
+        assert!(detector.check(b"Hello {{7*7}}").is_some());
+        assert!(detector.check(b"Hello {7*7}").is_none());
+
+        let sql = MatchDetector::sql();
+        assert!(
+            sql.check(b"SELECT * FROM t WHERE a = \"1\" or '1'=\"1\"")
+                .is_some()
+        );
+        assert!(sql.check(b"SELECT * FROM t").is_none());
+    }
+
+    #[test]
+    fn test_path_traversal_detector() {
+        let detector = PathTraversalDetector::new("/srv/www");
+        assert!(detector.check(b"../../etc/passwd").is_some());
+        assert!(detector.check(b"/srv/www/../secret").is_some());
+        assert!(detector.check(b"static/../index.html").is_none());
+        assert!(detector.check(b"/etc/passwd").is_none());
+        // Paths outside of the root may go up freely
+        assert!(detector.check(b"/etc/../etc/passwd").is_none());
+        assert!(detector.check(b"/srv/other/../www/index.html").is_none());
+    }
+
+    #[test]
+    fn test_report() {
+        let mut module = InjectionModule::new(HashMap::new()).unwrap();
+        module.report(finding("sql", "first"));
+        module.report(finding("sql", "second"));
+        module.report(finding("format_string", "%n"));
+        let findings = module.findings();
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].argument, "first");
+        assert_eq!(findings[1].detector, "format_string");
+    }
+
+    #[test]
+    fn test_injection_feedback() {
+        let mut observer = InjectionObserver::new("injections");
+        let mut all = InjectionFeedback::new(&observer);
+        let mut sql = InjectionFeedback::new(&observer).with_detector("sql");
+        let mut format_string = InjectionFeedback::new(&observer).with_detector("format_string");
+
+        let mut state = NopState::<BytesInput>::new();
+        let input = BytesInput::new(vec![]);
+        observer.findings.push(finding("sql", "first"));
+        let observers = tuple_list!(observer);
+        let mut is_interesting = |feedback: &mut InjectionFeedback| {
+            Feedback::<(), _, _, _>::is_interesting(
+                feedback,
+                &mut state,
+                &mut (),
+                &input,
+                &observers,
+                &ExitKind::Crash,
+            )
+            .unwrap()
+        };
+        assert!(is_interesting(&mut all));
+        assert!(is_interesting(&mut sql));
+        assert!(!is_interesting(&mut format_string));
+
+        let mut observer = observers.0;
+        Observer::<BytesInput, NopState<BytesInput>>::pre_exec(
+            &mut observer,
+            &mut NopState::new(),
+            &input,
+        )
+        .unwrap();
+        assert!(observer.findings().is_empty());
+    }
+
+    #[test]
+    fn test_format_string_detector() {
+        let detector = FormatStringDetector::new();
+        assert!(detector.check(b"AAAA%x%x%n").is_some());
+        assert!(detector.check(b"%1$hhn").is_some());
+        assert!(detector.check(b"%s: %d%%").is_none());
+        assert!(detector.check(b"100%%n").is_none());
+        assert!(detector.check(b"100%%%n").is_some());
+    }
 }
@@ -1,13 +1,23 @@
 #[cfg(feature = "injections")]
 pub mod injections;
 #[cfg(feature = "injections")]
-pub use injections::InjectionModule;
+pub use injections::{InjectionFeedback, InjectionMetadata, InjectionModule, InjectionObserver};
+
+#[cfg(all(feature = "concolic", cpu_target = "x86_64"))]
+pub mod concolic;
+#[cfg(all(feature = "concolic", cpu_target = "x86_64"))]
+pub use concolic::ConcolicModule;
 
 #[cfg(not(cpu_target = "hexagon"))]
 pub mod snapshot;
 #[cfg(not(cpu_target = "hexagon"))]
 pub use snapshot::{IntervalSnapshotFilter, SnapshotModule};
 
+#[cfg(not(cpu_target = "hexagon"))]
+pub mod threads;
+#[cfg(not(cpu_target = "hexagon"))]
+pub use threads::{ThreadPolicy, ThreadSnapshotModule};
+
 #[cfg(not(cpu_target = "hexagon"))]
 pub mod asan;
 #[cfg(not(cpu_target = "hexagon"))]
@@ -17,3 +27,6 @@ pub use asan::AsanModule;
 pub mod asan_guest;
 #[cfg(not(cpu_target = "hexagon"))]
 pub use asan_guest::AsanGuestModule;
+
+pub mod vfs;
+pub use vfs::{VfsInput, VfsModule, VfsSource};
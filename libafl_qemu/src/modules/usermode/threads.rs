@@ -0,0 +1,917 @@
+//! Snapshots of multi-threaded usermode targets.
+//!
+//! The [`SnapshotModule`](super::SnapshotModule) restores the memory and the mappings of the
+//! guest, which is enough for a single thread. The [`ThreadSnapshotModule`] takes care of the other
+//! threads: it records their state at snapshot time, reaps the threads created during a run, brings
+//! the others back to their snapshot state, wakes up the futexes they wait on to do so, and restores
+//! the signal dispositions changed during the run. A [`ThreadPolicy`] chooses which threads run
+//! during the runs, so that servers with a pool of workers can be snapshot-fuzzed.
+//!
+//! QEMU runs each guest thread in its own host thread, which can only be controlled from its
+//! syscalls. The threads are held in the syscall hooks of the module, and are moved by restarting
+//! their syscall with other registers, as QEMU does for the syscalls interrupted by a signal: a
+//! restored thread gets its snapshot registers back and enters the syscall it was in at snapshot
+//! time again, a reaped thread calls `exit`, and the signal dispositions are restored by the held
+//! threads calling `rt_sigaction`. The threads should therefore be blocked in a syscall, typically
+//! a futex wait, at snapshot time and at the end of the runs. The threads still busy are waited for
+//! up to a timeout, and restored at their next syscall otherwise. The signal masks of the threads
+//! are not restored.
+//!
+//! The module must come after the [`SnapshotModule`](super::SnapshotModule) in the tuple of
+//! modules, so that the threads are held while the memory is snapshot and restored.
+
+#![allow(clippy::needless_pass_by_value)] // default compiler complains about Option<&mut T> otherwise, and this is used extensively.
+
+use std::{
+    sync::{Condvar, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use hashbrown::{HashMap, HashSet};
+use libafl::{executors::ExitKind, observers::ObserversTuple};
+use libafl_qemu_sys::{CPUArchState, CPUArchStatePtr, GuestAddr};
+
+#[cfg(not(cpu_target = "riscv32"))]
+use crate::SYS_futex;
+use crate::{
+    CPU, GuestReg, Qemu, Regs, SYS_exit, SYS_rt_sigaction,
+    emu::EmulatorModules,
+    modules::{
+        EmulatorModule, EmulatorModuleTuple,
+        utils::filters::{HasAddressFilter, NOP_ADDRESS_FILTER, NopAddressFilter},
+    },
+    qemu::{Hook, SyscallHookResult},
+};
+
+/// The value ending a syscall to run it again, `-QEMU_ERESTARTSYS`
+const RESTART_SYSCALL: GuestAddr = GuestAddr::wrapping_neg(512);
+
+/// How far below the stack pointer the arguments of the injected syscalls are written
+const SCRATCH_OFFSET: GuestAddr = 0x1000;
+
+/// The pointer-sized fields of a `struct sigaction`, before the signal mask
+const SIGACTION_PTRS: usize = 3;
+
+/// The registers holding the syscall number and its first four arguments
+#[cfg(cpu_target = "x86_64")]
+const SYSCALL_REGS: [Regs; 5] = [Regs::Rax, Regs::Rdi, Regs::Rsi, Regs::Rdx, Regs::R10];
+#[cfg(cpu_target = "i386")]
+const SYSCALL_REGS: [Regs; 5] = [Regs::Eax, Regs::Ebx, Regs::Ecx, Regs::Edx, Regs::Esi];
+#[cfg(cpu_target = "aarch64")]
+const SYSCALL_REGS: [Regs; 5] = [Regs::X8, Regs::X0, Regs::X1, Regs::X2, Regs::X3];
+#[cfg(cpu_target = "arm")]
+const SYSCALL_REGS: [Regs; 5] = [Regs::R7, Regs::R0, Regs::R1, Regs::R2, Regs::R3];
+#[cfg(cpu_target = "mips")]
+const SYSCALL_REGS: [Regs; 5] = [Regs::V0, Regs::A0, Regs::A1, Regs::A2, Regs::A3];
+#[cfg(cpu_target = "ppc")]
+const SYSCALL_REGS: [Regs; 5] = [Regs::R0, Regs::R3, Regs::R4, Regs::R5, Regs::R6];
+#[cfg(any(cpu_target = "riscv32", cpu_target = "riscv64"))]
+const SYSCALL_REGS: [Regs; 5] = [Regs::A7, Regs::A0, Regs::A1, Regs::A2, Regs::A3];
+
+/// Which threads run during the runs of a [`ThreadSnapshotModule`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThreadPolicy {
+    /// Every thread runs. The threads created during a run are reaped at its end.
+    #[default]
+    All,
+    /// The threads alive at snapshot time run. The threads created during a run are held at their
+    /// first syscall, and reaped at its end.
+    Snapshotted,
+    /// Only the thread running the harness runs. The other threads are held at their next
+    /// syscall until the end of the run.
+    Harness,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Phase {
+    /// Before the snapshot, the threads run freely
+    #[default]
+    Startup,
+    /// The threads are held to be snapshot
+    Snapshot,
+    /// A run is in progress, the threads run according to the policy
+    Run,
+    /// The threads are held to be restored
+    Restore,
+}
+
+/// What a held thread does once released
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Release {
+    /// Go on with its syscall
+    Resume,
+    /// Go back to its snapshot state
+    Restore,
+    /// Exit
+    Reap,
+}
+
+/// The syscall a thread is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SyscallSite {
+    num: i32,
+    pc: GuestReg,
+    /// The address and the private flag of the futex waited on
+    futex: Option<(GuestAddr, i32)>,
+}
+
+impl SyscallSite {
+    fn new(cpu: CPU, num: i32, a0: GuestAddr, a1: GuestAddr) -> Self {
+        Self {
+            num,
+            pc: cpu.read_reg(Regs::Pc).unwrap_or_default(),
+            futex: futex_wait(num, a0, a1),
+        }
+    }
+}
+
+/// A signal disposition, as the `struct sigaction` of the target
+#[derive(Debug, Clone)]
+struct SignalAction {
+    act: Vec<u8>,
+    sigsetsize: GuestAddr,
+}
+
+struct ThreadSnapshot {
+    state: Box<CPUArchState>,
+    site: SyscallSite,
+    /// The result of the syscall, if the thread was held once it returned
+    result: Option<GuestAddr>,
+}
+
+#[derive(Default)]
+struct ThreadInfo {
+    cpu: Option<CPU>,
+    created_during_run: bool,
+    syscall: Option<SyscallSite>,
+    /// The result of the syscall, while held once it returned
+    returning: Option<GuestAddr>,
+    held: bool,
+    release: Option<Release>,
+    /// The thread was not held at the last restore, and is restored at its next syscall
+    restore_pending: bool,
+    /// The signal dispositions to restore before the release
+    signal_restores: Vec<(GuestAddr, SignalAction)>,
+    /// The release to apply once the injected syscalls are done
+    injecting: Option<Release>,
+    /// The result of the current syscall was chosen in the pre-syscall hook
+    skipped: bool,
+    snapshot: Option<ThreadSnapshot>,
+}
+
+impl ThreadInfo {
+    /// Releases the current thread, returning the value to end its syscall with
+    fn apply_release(&mut self, release: Release) -> Option<GuestAddr> {
+        let result = self.run_release(release);
+        self.skipped = result.is_some();
+        result
+    }
+
+    fn run_release(&mut self, release: Release) -> Option<GuestAddr> {
+        let cpu = self.cpu?;
+
+        while let Some((signum, action)) = self.signal_restores.pop() {
+            // Below the stack pointer, where the contents do not matter
+            let sp: GuestAddr = cpu.read_reg(Regs::Sp).ok()?;
+            let scratch = sp.wrapping_sub(SCRATCH_OFFSET) & !0xf;
+            if cpu.write_mem(scratch, &action.act).is_ok() {
+                self.injecting = Some(release);
+                return Some(restart_as(
+                    cpu,
+                    SYS_rt_sigaction,
+                    &[signum, scratch, 0, action.sigsetsize],
+                ));
+            }
+            log::warn!("Could not restore the action of signal {signum}");
+        }
+        self.injecting = None;
+
+        match release {
+            Release::Resume => None,
+            Release::Restore => {
+                let snapshot = self.snapshot.as_ref()?;
+                cpu.restore_state(&snapshot.state);
+                Some(snapshot.result.unwrap_or(RESTART_SYSCALL))
+            }
+            Release::Reap => Some(restart_as(cpu, SYS_exit, &[0])),
+        }
+    }
+
+    /// The release of a thread which missed the restore
+    fn missed_release(&self) -> Release {
+        if self.created_during_run {
+            Release::Reap
+        } else {
+            Release::Restore
+        }
+    }
+}
+
+#[derive(Default)]
+struct Threads {
+    phase: Phase,
+    harness: Option<u32>,
+    threads: HashMap<u32, ThreadInfo>,
+    actions: HashMap<GuestAddr, SignalAction>,
+    snapshot_actions: HashMap<GuestAddr, SignalAction>,
+    changed_actions: HashSet<GuestAddr>,
+}
+
+impl Threads {
+    fn must_hold(&self, tid: u32, policy: ThreadPolicy) -> bool {
+        if self.harness == Some(tid) {
+            return false;
+        }
+        match self.phase {
+            Phase::Startup => false,
+            Phase::Snapshot | Phase::Restore => true,
+            Phase::Run => match policy {
+                ThreadPolicy::All => false,
+                ThreadPolicy::Snapshotted => self
+                    .threads
+                    .get(&tid)
+                    .is_some_and(|thread| thread.created_during_run),
+                ThreadPolicy::Harness => true,
+            },
+        }
+    }
+
+    /// The threads neither held nor in a syscall
+    fn running(&self) -> Vec<u32> {
+        self.threads
+            .iter()
+            .filter(|(tid, thread)| {
+                Some(**tid) != self.harness && !thread.held && thread.syscall.is_none()
+            })
+            .map(|(tid, _)| *tid)
+            .collect()
+    }
+}
+
+/// Snapshots and restores the threads of the target other than the harness, running them during
+/// the runs according to its [`ThreadPolicy`]
+pub struct ThreadSnapshotModule {
+    policy: ThreadPolicy,
+    timeout: Duration,
+    threads: Mutex<Threads>,
+    changed: Condvar,
+}
+
+impl core::fmt::Debug for ThreadSnapshotModule {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ThreadSnapshotModule")
+            .field("policy", &self.policy)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ThreadSnapshotModule {
+    /// Creates a new [`ThreadSnapshotModule`] running the threads according to `policy`, waiting
+    /// for the busy threads up to a second
+    #[must_use]
+    pub fn new(policy: ThreadPolicy) -> Self {
+        Self {
+            policy,
+            timeout: Duration::from_secs(1),
+            threads: Mutex::new(Threads::default()),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Sets how long the threads still running are waited for, at snapshot time and at the end of
+    /// the runs
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Which threads run during the runs
+    #[must_use]
+    pub fn policy(&self) -> ThreadPolicy {
+        self.policy
+    }
+
+    fn add_thread(&self, tid: u32) {
+        let mut threads = self.threads.lock().unwrap();
+        let created_during_run = threads.phase != Phase::Startup;
+        threads.threads.insert(
+            tid,
+            ThreadInfo {
+                created_during_run,
+                ..ThreadInfo::default()
+            },
+        );
+    }
+
+    /// Waits for every thread but the harness to be held or in a syscall
+    fn wait_threads<'a>(&'a self, threads: MutexGuard<'a, Threads>) -> MutexGuard<'a, Threads> {
+        let (threads, wait) = self
+            .changed
+            .wait_timeout_while(threads, self.timeout, |threads| {
+                !threads.running().is_empty()
+            })
+            .unwrap();
+        if wait.timed_out() {
+            log::warn!(
+                "Threads {:?} still running after {:?}",
+                threads.running(),
+                self.timeout
+            );
+        }
+        threads
+    }
+
+    /// Holds the threads before the memory is snapshot
+    fn hold_for_snapshot(&self) {
+        let mut threads = self.threads.lock().unwrap();
+        threads.harness = Some(current_tid());
+        threads.phase = Phase::Snapshot;
+        drop(self.wait_threads(threads));
+    }
+
+    /// Records the state of the threads and releases them
+    fn snapshot(&self) {
+        let mut guard = self.threads.lock().unwrap();
+        let threads = &mut *guard;
+        for (tid, thread) in &mut threads.threads {
+            if Some(*tid) == threads.harness {
+                continue;
+            }
+            if let (Some(cpu), Some(site)) = (thread.cpu, thread.syscall) {
+                thread.snapshot = Some(ThreadSnapshot {
+                    state: Box::new(cpu.save_state()),
+                    site,
+                    result: thread.returning,
+                });
+            } else {
+                log::warn!("Thread {tid} is not in a syscall, it will not be restored");
+            }
+            if thread.held {
+                thread.release = Some(Release::Resume);
+            }
+        }
+        threads.snapshot_actions = threads.actions.clone();
+        threads.phase = Phase::Run;
+        self.changed.notify_all();
+    }
+
+    /// Holds the threads at the end of a run, before the memory is restored
+    fn hold_for_restore(&self, qemu: Qemu) {
+        let mut guard = self.threads.lock().unwrap();
+        let threads = &mut *guard;
+        threads.phase = Phase::Restore;
+        for (tid, thread) in &mut threads.threads {
+            if Some(*tid) == threads.harness || thread.held {
+                continue;
+            }
+            let Some(site) = thread.syscall else {
+                // Held at its next syscall
+                continue;
+            };
+            let at_snapshot = !thread.created_during_run
+                && thread
+                    .snapshot
+                    .as_ref()
+                    .is_some_and(|snapshot| snapshot.site == site && snapshot.result.is_none());
+            if at_snapshot {
+                continue;
+            }
+
+            thread.restore_pending = true;
+            if let Some((addr, private)) = site.futex {
+                // The futex wait is spurious for the thread, which returns from it to be held
+                wake_futex(qemu, addr, private);
+            }
+        }
+        drop(self.wait_threads(guard));
+    }
+
+    /// Releases the held threads once the memory is restored
+    fn release(&self) {
+        let mut guard = self.threads.lock().unwrap();
+        let threads = &mut *guard;
+
+        let mut signal_restores: Vec<(GuestAddr, SignalAction)> = threads
+            .changed_actions
+            .drain()
+            .filter_map(|signum| {
+                let action = threads.snapshot_actions.get(&signum).cloned().or_else(|| {
+                    // Back to the default action
+                    let current = threads.actions.get(&signum)?;
+                    Some(SignalAction {
+                        act: vec![0; current.act.len()],
+                        sigsetsize: current.sigsetsize,
+                    })
+                })?;
+                Some((signum, action))
+            })
+            .collect();
+
+        for thread in threads.threads.values_mut() {
+            if !thread.held {
+                continue;
+            }
+            let release = if thread.created_during_run {
+                Release::Reap
+            } else if thread.snapshot.is_some() {
+                Release::Restore
+            } else {
+                Release::Resume
+            };
+            if release != Release::Resume {
+                thread.signal_restores.append(&mut signal_restores);
+            }
+            thread.restore_pending = false;
+            thread.release = Some(release);
+        }
+
+        if !signal_restores.is_empty() {
+            log::debug!("No held thread to restore the signal dispositions, retrying next time");
+            threads
+                .changed_actions
+                .extend(signal_restores.into_iter().map(|(signum, _)| signum));
+        }
+        threads.phase = Phase::Run;
+        self.changed.notify_all();
+    }
+
+    /// Holds the current thread until it is released, returning the value to end its syscall with
+    fn hold(
+        &self,
+        mut threads: MutexGuard<'_, Threads>,
+        tid: u32,
+        returning: Option<GuestAddr>,
+    ) -> Option<GuestAddr> {
+        let thread = threads.threads.get_mut(&tid)?;
+        thread.held = true;
+        thread.returning = returning;
+        self.changed.notify_all();
+
+        let mut threads = self
+            .changed
+            .wait_while(threads, |threads| {
+                threads
+                    .threads
+                    .get(&tid)
+                    .is_some_and(|thread| thread.release.is_none())
+            })
+            .unwrap();
+        let thread = threads.threads.get_mut(&tid)?;
+        thread.held = false;
+        thread.returning = None;
+        if returning.is_some() {
+            thread.syscall = None;
+        }
+        let release = thread.release.take()?;
+        thread.apply_release(release)
+    }
+
+    fn pre_syscall(
+        &self,
+        qemu: Qemu,
+        sys_num: i32,
+        a0: GuestAddr,
+        a1: GuestAddr,
+    ) -> Option<GuestAddr> {
+        let tid = current_tid();
+        let cpu = qemu.current_cpu()?;
+        let mut threads = self.threads.lock().unwrap();
+        let phase = threads.phase;
+        let must_hold = threads.must_hold(tid, self.policy);
+
+        let thread = threads.threads.entry(tid).or_default();
+        thread.cpu = Some(cpu);
+        thread.skipped = false;
+        thread.syscall = Some(SyscallSite::new(cpu, sys_num, a0, a1));
+
+        if i64::from(sys_num) == SYS_exit {
+            if thread.injecting.is_some() || thread.created_during_run || phase == Phase::Startup {
+                threads.threads.remove(&tid);
+                self.changed.notify_all();
+                return None;
+            }
+            // A thread alive at snapshot time can not come back once exited, restore it instead
+            return self.hold(threads, tid, None);
+        }
+
+        if thread.injecting.is_some() {
+            None
+        } else if thread.restore_pending && phase == Phase::Run {
+            thread.restore_pending = false;
+            let release = thread.missed_release();
+            thread.apply_release(release)
+        } else if must_hold {
+            self.hold(threads, tid, None)
+        } else {
+            None
+        }
+    }
+
+    fn post_syscall(
+        &self,
+        qemu: Qemu,
+        result: GuestAddr,
+        sys_num: i32,
+        a0: GuestAddr,
+        a1: GuestAddr,
+        a3: GuestAddr,
+    ) -> GuestAddr {
+        let tid = current_tid();
+        let mut guard = self.threads.lock().unwrap();
+        let threads = &mut *guard;
+        let phase = threads.phase;
+        let must_hold = threads.must_hold(tid, self.policy);
+
+        let Some(thread) = threads.threads.get_mut(&tid) else {
+            return result;
+        };
+        let site = thread.syscall.take();
+        if thread.skipped {
+            // The result was chosen in the pre-syscall hook
+            thread.skipped = false;
+            return result;
+        }
+
+        if i64::from(sys_num) == SYS_rt_sigaction && result == 0 && a1 != 0 {
+            #[allow(clippy::cast_possible_truncation)] // platform dependent
+            let len = SIGACTION_PTRS * size_of::<GuestAddr>() + a3 as usize;
+            if let Ok(act) = qemu.read_mem_vec(a1, len) {
+                threads.actions.insert(
+                    a0,
+                    SignalAction {
+                        act,
+                        sigsetsize: a3,
+                    },
+                );
+                if phase == Phase::Run && thread.injecting.is_none() {
+                    threads.changed_actions.insert(a0);
+                }
+            }
+        }
+
+        if let Some(release) = thread.injecting {
+            // The next injected syscall, or the release once they are done
+            thread.apply_release(release).unwrap_or(result)
+        } else if thread.restore_pending && phase == Phase::Run {
+            thread.restore_pending = false;
+            let release = thread.missed_release();
+            thread.apply_release(release).unwrap_or(result)
+        } else if must_hold {
+            thread.syscall = site;
+            self.hold(guard, tid, Some(result)).unwrap_or(result)
+        } else {
+            result
+        }
+    }
+}
+
+impl<I, S> EmulatorModule<I, S> for ThreadSnapshotModule
+where
+    I: Unpin,
+    S: Unpin,
+{
+    fn post_qemu_init<ET>(&mut self, _qemu: Qemu, emulator_modules: &mut EmulatorModules<ET, I, S>)
+    where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        emulator_modules.thread_creation(Hook::Function(threads_new_thread::<ET, I, S>));
+        emulator_modules.pre_syscalls(Hook::Function(threads_pre_syscall::<ET, I, S>));
+        emulator_modules.post_syscalls(Hook::Function(threads_post_syscall::<ET, I, S>));
+    }
+
+    fn first_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        self.hold_for_snapshot();
+    }
+
+    fn pre_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        let phase = self.threads.lock().unwrap().phase;
+        match phase {
+            Phase::Snapshot => self.snapshot(),
+            Phase::Restore => self.release(),
+            Phase::Startup | Phase::Run => {}
+        }
+    }
+
+    fn post_exec<OT, ET>(
+        &mut self,
+        qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        _input: &I,
+        _observers: &mut OT,
+        _exit_kind: &mut ExitKind,
+    ) where
+        OT: ObserversTuple<I, S>,
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        self.hold_for_restore(qemu);
+    }
+}
+
+impl HasAddressFilter for ThreadSnapshotModule {
+    type AddressFilter = NopAddressFilter;
+    fn address_filter(&self) -> &Self::AddressFilter {
+        &NopAddressFilter
+    }
+
+    fn address_filter_mut(&mut self) -> &mut Self::AddressFilter {
+        unsafe { (&raw mut NOP_ADDRESS_FILTER).as_mut().unwrap().get_mut() }
+    }
+}
+
+fn threads_new_thread<ET, I, S>(
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    _env: CPUArchStatePtr,
+    tid: u32,
+) -> bool
+where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let h = emulator_modules.get::<ThreadSnapshotModule>().unwrap();
+    h.add_thread(tid);
+    true
+}
+
+#[expect(clippy::too_many_arguments)]
+fn threads_pre_syscall<ET, I, S>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    sys_num: i32,
+    a0: GuestAddr,
+    a1: GuestAddr,
+    _a2: GuestAddr,
+    _a3: GuestAddr,
+    _a4: GuestAddr,
+    _a5: GuestAddr,
+    _a6: GuestAddr,
+    _a7: GuestAddr,
+) -> SyscallHookResult
+where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let h = emulator_modules.get::<ThreadSnapshotModule>().unwrap();
+    SyscallHookResult::new(h.pre_syscall(qemu, sys_num, a0, a1))
+}
+
+#[expect(clippy::too_many_arguments)]
+fn threads_post_syscall<ET, I, S>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    result: GuestAddr,
+    sys_num: i32,
+    a0: GuestAddr,
+    a1: GuestAddr,
+    _a2: GuestAddr,
+    a3: GuestAddr,
+    _a4: GuestAddr,
+    _a5: GuestAddr,
+    _a6: GuestAddr,
+    _a7: GuestAddr,
+) -> GuestAddr
+where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let h = emulator_modules.get::<ThreadSnapshotModule>().unwrap();
+    h.post_syscall(qemu, result, sys_num, a0, a1, a3)
+}
+
+/// The id of the current thread, the same for the guest and the host
+#[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn current_tid() -> u32 {
+    unsafe { libc::syscall(libc::SYS_gettid) as u32 }
+}
+
+/// The futex waited on by the syscall `num`, with its private flag
+#[cfg_attr(cpu_target = "riscv32", expect(unused_variables))]
+fn futex_wait(num: i32, uaddr: GuestAddr, op: GuestAddr) -> Option<(GuestAddr, i32)> {
+    #[cfg(not(cpu_target = "riscv32"))]
+    if i64::from(num) == SYS_futex {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)] // platform dependent
+        let op = op as i32;
+        let cmd = op & !(libc::FUTEX_PRIVATE_FLAG | libc::FUTEX_CLOCK_REALTIME);
+        if cmd == libc::FUTEX_WAIT || cmd == libc::FUTEX_WAIT_BITSET {
+            return Some((uaddr, op & libc::FUTEX_PRIVATE_FLAG));
+        }
+    }
+    None
+}
+
+/// Wakes up the threads waiting on the futex at `addr`
+fn wake_futex(qemu: Qemu, addr: GuestAddr, private: i32) {
+    // QEMU runs the futex syscalls of the guest on the host, at the translated address
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            qemu.g2h::<u32>(addr),
+            libc::FUTEX_WAKE | private,
+            i32::MAX,
+        );
+    }
+}
+
+/// Makes the current syscall of `cpu` run again as the syscall `num` with `args`, returning the
+/// value to end the current syscall with
+fn restart_as(cpu: CPU, num: i64, args: &[GuestAddr]) -> GuestAddr {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // platform dependent
+    let num = num as GuestReg;
+    cpu.write_reg(SYSCALL_REGS[0], num).unwrap();
+    for (reg, arg) in SYSCALL_REGS[1..].iter().zip(args) {
+        cpu.write_reg(*reg, *arg).unwrap();
+    }
+    RESTART_SYSCALL
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_qemu_sys::{CPUArchState, GuestAddr};
+
+    #[cfg(not(cpu_target = "riscv32"))]
+    use super::SYS_futex;
+    use super::{
+        Phase, Release, SignalAction, SyscallSite, ThreadInfo, ThreadPolicy, ThreadSnapshot,
+        ThreadSnapshotModule, Threads, futex_wait,
+    };
+
+    fn thread(created_during_run: bool, held: bool, snapshot: bool) -> ThreadInfo {
+        ThreadInfo {
+            created_during_run,
+            held,
+            snapshot: snapshot.then(|| ThreadSnapshot {
+                state: unsafe { Box::<CPUArchState>::new_zeroed().assume_init() },
+                site: SyscallSite {
+                    num: 0,
+                    pc: 0,
+                    futex: None,
+                },
+                result: None,
+            }),
+            ..ThreadInfo::default()
+        }
+    }
+
+    fn action(byte: u8) -> SignalAction {
+        SignalAction {
+            act: vec![byte; 16],
+            sigsetsize: 8,
+        }
+    }
+
+    #[test]
+    fn test_must_hold() {
+        let mut threads = Threads {
+            harness: Some(1),
+            ..Threads::default()
+        };
+        threads.threads.insert(1, thread(false, false, false));
+        threads.threads.insert(2, thread(false, false, false));
+        threads.threads.insert(3, thread(true, false, false));
+
+        for policy in [
+            ThreadPolicy::All,
+            ThreadPolicy::Snapshotted,
+            ThreadPolicy::Harness,
+        ] {
+            assert!(!threads.must_hold(2, policy));
+            for phase in [Phase::Snapshot, Phase::Restore] {
+                threads.phase = phase;
+                assert!(threads.must_hold(2, policy));
+                assert!(threads.must_hold(3, policy));
+                assert!(!threads.must_hold(1, policy));
+            }
+            threads.phase = Phase::Startup;
+        }
+
+        threads.phase = Phase::Run;
+        assert!(!threads.must_hold(2, ThreadPolicy::All));
+        assert!(!threads.must_hold(3, ThreadPolicy::All));
+        assert!(!threads.must_hold(2, ThreadPolicy::Snapshotted));
+        assert!(threads.must_hold(3, ThreadPolicy::Snapshotted));
+        assert!(threads.must_hold(2, ThreadPolicy::Harness));
+        assert!(threads.must_hold(3, ThreadPolicy::Harness));
+        assert!(!threads.must_hold(1, ThreadPolicy::Harness));
+        // Unknown threads are held only with the harness policy
+        assert!(!threads.must_hold(4, ThreadPolicy::Snapshotted));
+        assert!(threads.must_hold(4, ThreadPolicy::Harness));
+    }
+
+    #[test]
+    fn test_missed_release() {
+        assert_eq!(
+            thread(false, false, true).missed_release(),
+            Release::Restore
+        );
+        assert_eq!(thread(true, false, false).missed_release(), Release::Reap);
+    }
+
+    #[test]
+    fn test_release() {
+        let module = ThreadSnapshotModule::new(ThreadPolicy::All);
+        {
+            let mut threads = module.threads.lock().unwrap();
+            threads.phase = Phase::Restore;
+            threads.threads.insert(2, thread(false, true, true));
+            threads.threads.insert(3, thread(true, true, false));
+            threads.threads.insert(4, thread(false, true, false));
+            threads.threads.insert(5, thread(false, false, true));
+            // Signal 10 changed during the run, signal 11 was set during the run only
+            threads.snapshot_actions.insert(10, action(1));
+            threads.actions.insert(10, action(2));
+            threads.actions.insert(11, action(3));
+            threads.changed_actions.extend([10, 11]);
+        }
+        module.release();
+
+        let threads = module.threads.lock().unwrap();
+        assert_eq!(threads.phase, Phase::Run);
+        assert!(threads.changed_actions.is_empty());
+        assert_eq!(threads.threads[&2].release, Some(Release::Restore));
+        assert_eq!(threads.threads[&3].release, Some(Release::Reap));
+        assert_eq!(threads.threads[&4].release, Some(Release::Resume));
+        assert_eq!(threads.threads[&5].release, None);
+
+        // The dispositions are restored by one of the threads going back or exiting
+        assert!(threads.threads[&4].signal_restores.is_empty());
+        let mut restores: Vec<(GuestAddr, Vec<u8>)> = threads
+            .threads
+            .values()
+            .flat_map(|thread| &thread.signal_restores)
+            .map(|(signum, action)| (*signum, action.act.clone()))
+            .collect();
+        restores.sort();
+        assert_eq!(restores, [(10, vec![1; 16]), (11, vec![0; 16])]);
+    }
+
+    #[test]
+    fn test_release_without_restore() {
+        let module = ThreadSnapshotModule::new(ThreadPolicy::All);
+        {
+            let mut threads = module.threads.lock().unwrap();
+            threads.threads.insert(2, thread(false, true, false));
+            threads.snapshot_actions.insert(10, action(1));
+            threads.changed_actions.insert(10);
+        }
+        module.release();
+
+        // No thread goes back to restore the disposition, it is restored at the next release
+        let threads = module.threads.lock().unwrap();
+        assert_eq!(threads.threads[&2].release, Some(Release::Resume));
+        assert!(threads.threads[&2].signal_restores.is_empty());
+        assert!(threads.changed_actions.contains(&10));
+    }
+
+    #[test]
+    #[cfg(not(cpu_target = "riscv32"))]
+    fn test_futex_wait() {
+        let futex = i32::try_from(SYS_futex).unwrap();
+        let op = |op: i32| GuestAddr::from(op.unsigned_abs());
+
+        assert_eq!(
+            futex_wait(futex, 0x1000, op(libc::FUTEX_WAIT)),
+            Some((0x1000, 0))
+        );
+        assert_eq!(
+            futex_wait(futex, 0x1000, op(libc::FUTEX_WAIT_PRIVATE)),
+            Some((0x1000, libc::FUTEX_PRIVATE_FLAG))
+        );
+        assert_eq!(
+            futex_wait(
+                futex,
+                0x1000,
+                op(libc::FUTEX_WAIT_BITSET | libc::FUTEX_CLOCK_REALTIME)
+            ),
+            Some((0x1000, 0))
+        );
+        assert_eq!(futex_wait(futex, 0x1000, op(libc::FUTEX_WAKE)), None);
+        assert_eq!(
+            futex_wait(futex, 0x1000, op(libc::FUTEX_WAKE_PRIVATE)),
+            None
+        );
+        assert_eq!(futex_wait(futex + 1, 0x1000, op(libc::FUTEX_WAIT)), None);
+    }
+}
@@ -0,0 +1,592 @@
+//! An in-memory virtual filesystem, serving files from the current input.
+//!
+//! Targets reading their input from a file by path would otherwise need the input to be written
+//! to disk before each execution. The [`VfsModule`] intercepts the `open` and `openat` syscalls of
+//! the guest, and serves the paths it was given from the current input, from a part of a
+//! multipart input, or from a read-only copy of host files, without touching the disk.
+//!
+//! Each file opened from the virtual filesystem is backed by a host `memfd` holding its contents,
+//! so the file descriptors of the guest stay unique, and `read`, `pread64`, `lseek`, `fstat` and
+//! `mmap` follow the syscall ABI of every guest architecture. The `close` syscalls are traced to
+//! forget the files, and the files still open at the end of a run are closed before the next one,
+//! if their file descriptor still refers to their `memfd`.
+
+#![allow(clippy::needless_pass_by_value)] // default compiler complains about Option<&mut T> otherwise, and this is used extensively.
+
+use std::{
+    env,
+    ffi::{CString, OsString},
+    fs::{self, File},
+    io::{Seek, SeekFrom, Write},
+    mem::MaybeUninit,
+    os::{
+        fd::{FromRawFd, IntoRawFd},
+        unix::ffi::OsStringExt,
+    },
+    path::{Component, Path, PathBuf},
+};
+
+use hashbrown::HashMap;
+use libafl::{
+    Error,
+    inputs::{BytesInput, HasTargetBytes, MultipartInput},
+};
+use libafl_bolts::ownedref::OwnedSlice;
+use libafl_qemu_sys::GuestAddr;
+
+#[cfg(any(
+    cpu_target = "x86_64",
+    cpu_target = "i386",
+    cpu_target = "arm",
+    cpu_target = "mips",
+    cpu_target = "ppc"
+))]
+use crate::SYS_open;
+use crate::{
+    Qemu, SYS_close, SYS_openat,
+    emu::EmulatorModules,
+    modules::{
+        EmulatorModule, EmulatorModuleTuple,
+        utils::filters::{HasAddressFilter, NOP_ADDRESS_FILTER, NopAddressFilter},
+    },
+    qemu::{Hook, SyscallHookResult},
+};
+
+/// The longest path read from the guest
+const PATH_MAX: usize = 4096;
+
+/// An input serving the contents of the files of a [`VfsModule`]
+pub trait VfsInput {
+    /// The contents of the part `part` of the input, or of the whole input if `part` is `None`
+    fn vfs_contents(&self, part: Option<&str>) -> Option<OwnedSlice<'_, u8>>;
+}
+
+impl VfsInput for BytesInput {
+    fn vfs_contents(&self, part: Option<&str>) -> Option<OwnedSlice<'_, u8>> {
+        part.is_none().then(|| self.target_bytes())
+    }
+}
+
+impl<I, K> VfsInput for MultipartInput<I, K>
+where
+    I: HasTargetBytes,
+    K: AsRef<str>,
+{
+    fn vfs_contents(&self, part: Option<&str>) -> Option<OwnedSlice<'_, u8>> {
+        let part = part?;
+        self.parts()
+            .iter()
+            .find(|(key, _)| key.as_ref() == part)
+            .map(|(_, input)| input.target_bytes())
+    }
+}
+
+/// Where the contents of a file of the [`VfsModule`] come from
+#[derive(Debug, Clone)]
+pub enum VfsSource {
+    /// The whole input
+    Input,
+    /// The part of a multipart input with this key. The file is empty if the input has no such
+    /// part.
+    InputPart(String),
+    /// A host file, read once and served read-only
+    Host(PathBuf),
+    /// Fixed contents, served read-only
+    Bytes(Vec<u8>),
+}
+
+impl VfsSource {
+    fn is_read_only(&self) -> bool {
+        matches!(self, Self::Host(_) | Self::Bytes(_))
+    }
+}
+
+/// Serves files from the current input, see the [module-level documentation](self).
+#[derive(Debug, Default)]
+pub struct VfsModule {
+    files: HashMap<PathBuf, VfsSource>,
+    overlays: Vec<(PathBuf, PathBuf)>,
+    host_cache: HashMap<PathBuf, Vec<u8>>,
+    input_contents: HashMap<PathBuf, Vec<u8>>,
+    open_fds: HashMap<i32, FileId>,
+}
+
+impl VfsModule {
+    /// Creates a new [`VfsModule`], serving no file
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves the guest path `path` from `source`
+    #[must_use]
+    pub fn with_file<P>(mut self, path: P, source: VfsSource) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.files.insert(normalize(path.as_ref()), source);
+        self
+    }
+
+    /// Serves the guest paths under `guest_dir` from the host files under `host_dir`, read-only.
+    /// The host files are read once, when the guest first opens them.
+    #[must_use]
+    pub fn with_host_overlay<P, Q>(mut self, guest_dir: P, host_dir: Q) -> Self
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        self.overlays.push((
+            normalize(guest_dir.as_ref()),
+            host_dir.as_ref().to_path_buf(),
+        ));
+        self
+    }
+
+    /// The number of files of the virtual filesystem opened by the guest
+    #[must_use]
+    pub fn open_files(&self) -> usize {
+        self.open_fds.len()
+    }
+
+    /// Reads the contents of the inputs files from `input`, for the next run
+    fn load_input<I>(&mut self, input: &I)
+    where
+        I: VfsInput,
+    {
+        self.input_contents.clear();
+        for (path, source) in &self.files {
+            let contents = match source {
+                VfsSource::Input => input.vfs_contents(None),
+                VfsSource::InputPart(part) => input.vfs_contents(Some(part)),
+                VfsSource::Host(_) | VfsSource::Bytes(_) => continue,
+            };
+            self.input_contents.insert(
+                path.clone(),
+                contents
+                    .map(|contents| contents.to_vec())
+                    .unwrap_or_default(),
+            );
+        }
+    }
+
+    /// Closes the files left open by the previous run. The guest may have closed them with other
+    /// syscalls than `close`, such as `dup2` or `close_range`, and reused their file descriptors,
+    /// so only the descriptors still referring to the `memfd` of a file are closed.
+    fn close_all(&mut self) {
+        for (fd, id) in self.open_fds.drain() {
+            if file_id(fd) == Some(id) {
+                unsafe {
+                    libc::close(fd);
+                }
+            }
+        }
+    }
+
+    /// The contents of the guest file `path`, and whether it is read-only, if it is served
+    fn contents(&mut self, path: &Path) -> Result<Option<(&[u8], bool)>, Error> {
+        if let Some(source) = self.files.get(path) {
+            let read_only = source.is_read_only();
+            let contents = match source {
+                // Before the first run, no input was loaded yet
+                VfsSource::Input | VfsSource::InputPart(_) => {
+                    self.input_contents.get(path).map_or(&[][..], Vec::as_slice)
+                }
+                VfsSource::Bytes(bytes) => bytes.as_slice(),
+                VfsSource::Host(host_path) => {
+                    if !self.host_cache.contains_key(host_path) {
+                        let contents = fs::read(host_path)?;
+                        self.host_cache.insert(host_path.clone(), contents);
+                    }
+                    self.host_cache[host_path].as_slice()
+                }
+            };
+            return Ok(Some((contents, read_only)));
+        }
+
+        let Some(host_path) = self.overlays.iter().find_map(|(guest_dir, host_dir)| {
+            path.strip_prefix(guest_dir)
+                .ok()
+                .map(|relative| host_dir.join(relative))
+        }) else {
+            return Ok(None);
+        };
+        if !self.host_cache.contains_key(&host_path) {
+            if !host_path.is_file() {
+                return Ok(None);
+            }
+            let contents = fs::read(&host_path)?;
+            self.host_cache.insert(host_path.clone(), contents);
+        }
+        Ok(Some((self.host_cache[&host_path].as_slice(), true)))
+    }
+
+    /// Opens the guest file `path` with the `open` flags `flags`, returning the result of the
+    /// syscall if the file is served
+    fn open(&mut self, path: &Path, flags: GuestAddr) -> Option<GuestAddr> {
+        let contents = match self.contents(path) {
+            Ok(Some((contents, read_only))) => {
+                if read_only && flags & GuestAddr::from(libc::O_ACCMODE.unsigned_abs()) != 0 {
+                    return Some(errno(libc::EROFS));
+                }
+                contents.to_vec()
+            }
+            Ok(None) => return None,
+            Err(err) => {
+                log::warn!(
+                    "Could not read the file served at {}: {err}",
+                    path.display()
+                );
+                return Some(errno(libc::EIO));
+            }
+        };
+        match memfd(path, &contents) {
+            Ok(fd) => {
+                if let Some(id) = file_id(fd) {
+                    self.open_fds.insert(fd, id);
+                }
+                GuestAddr::try_from(fd).ok()
+            }
+            Err(err) => {
+                log::warn!("Could not create a memfd for {}: {err}", path.display());
+                Some(errno(libc::EMFILE))
+            }
+        }
+    }
+}
+
+/// The guest result of a syscall failing with `err`
+fn errno(err: i32) -> GuestAddr {
+    GuestAddr::try_from(err).map_or(GuestAddr::MAX, GuestAddr::wrapping_neg)
+}
+
+/// A file descriptor passed as syscall argument
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)] // file descriptors are ints
+fn fd_arg(arg: GuestAddr) -> i32 {
+    arg as i32
+}
+
+/// Makes `path` absolute, from the current directory of the guest, and removes its `.` and `..`
+fn normalize(path: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The device and inode of a host file, identifying it
+type FileId = (libc::dev_t, libc::ino_t);
+
+/// The [`FileId`] of the host file open at `fd`, if any
+fn file_id(fd: i32) -> Option<FileId> {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+    if unsafe { libc::fstat(fd, stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let stat = unsafe { stat.assume_init() };
+    Some((stat.st_dev, stat.st_ino))
+}
+
+/// Creates a host memfd holding `contents`
+fn memfd(path: &Path, contents: &[u8]) -> Result<i32, Error> {
+    let name = CString::new(path.to_string_lossy().as_bytes()).unwrap_or_default();
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), 0) };
+    if fd < 0 {
+        return Err(Error::last_os_error("memfd_create failed"));
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(contents)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file.into_raw_fd())
+}
+
+/// Reads the path at `addr` in the guest memory
+#[allow(clippy::cast_possible_truncation)] // dependent on the guest address size
+fn read_path(qemu: Qemu, addr: GuestAddr) -> Option<PathBuf> {
+    const CHUNK: usize = 64;
+
+    if addr == 0 {
+        return None;
+    }
+    let mut path = Vec::new();
+    let mut chunk = [0; CHUNK];
+    let mut chunk_addr = addr;
+    while path.len() < PATH_MAX {
+        // The path may end right before an unmapped page, fall back to a single byte
+        let len = if qemu.read_mem(chunk_addr, &mut chunk).is_ok() {
+            CHUNK
+        } else {
+            qemu.read_mem(chunk_addr, &mut chunk[..1]).ok()?;
+            1
+        };
+        if let Some(nul) = chunk[..len].iter().position(|b| *b == 0) {
+            path.extend_from_slice(&chunk[..nul]);
+            return Some(PathBuf::from(OsString::from_vec(path)));
+        }
+        path.extend_from_slice(&chunk[..len]);
+        chunk_addr += len as GuestAddr;
+    }
+    None
+}
+
+impl<I, S> EmulatorModule<I, S> for VfsModule
+where
+    I: VfsInput + Unpin,
+    S: Unpin,
+{
+    fn post_qemu_init<ET>(&mut self, _qemu: Qemu, emulator_modules: &mut EmulatorModules<ET, I, S>)
+    where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        emulator_modules.pre_syscalls(Hook::Function(vfs_pre_syscall::<ET, I, S>));
+        emulator_modules.post_syscalls(Hook::Function(vfs_post_syscall::<ET, I, S>));
+    }
+
+    fn pre_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        _emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+        input: &I,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        self.close_all();
+        self.load_input(input);
+    }
+}
+
+impl HasAddressFilter for VfsModule {
+    type AddressFilter = NopAddressFilter;
+    fn address_filter(&self) -> &Self::AddressFilter {
+        &NopAddressFilter
+    }
+
+    fn address_filter_mut(&mut self) -> &mut Self::AddressFilter {
+        unsafe { (&raw mut NOP_ADDRESS_FILTER).as_mut().unwrap().get_mut() }
+    }
+}
+
+#[expect(clippy::too_many_arguments)]
+pub fn vfs_pre_syscall<ET, I, S>(
+    qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    sys_num: i32,
+    a0: GuestAddr,
+    a1: GuestAddr,
+    a2: GuestAddr,
+    _a3: GuestAddr,
+    _a4: GuestAddr,
+    _a5: GuestAddr,
+    _a6: GuestAddr,
+    _a7: GuestAddr,
+) -> SyscallHookResult
+where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    let (dirfd, path, flags) = match i64::from(sys_num) {
+        #[cfg(any(
+            cpu_target = "x86_64",
+            cpu_target = "i386",
+            cpu_target = "arm",
+            cpu_target = "mips",
+            cpu_target = "ppc"
+        ))]
+        SYS_open => (libc::AT_FDCWD, a0, a1),
+        SYS_openat => (fd_arg(a0), a1, a2),
+        _ => return SyscallHookResult::new(None),
+    };
+    let Some(path) = read_path(qemu, path) else {
+        return SyscallHookResult::new(None);
+    };
+    // Paths relative to a directory file descriptor are not served
+    if path.is_relative() && dirfd != libc::AT_FDCWD {
+        return SyscallHookResult::new(None);
+    }
+    let h = emulator_modules.get_mut::<VfsModule>().unwrap();
+    SyscallHookResult::new(h.open(&normalize(&path), flags))
+}
+
+#[expect(clippy::too_many_arguments)]
+pub fn vfs_post_syscall<ET, I, S>(
+    _qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    result: GuestAddr,
+    sys_num: i32,
+    a0: GuestAddr,
+    _a1: GuestAddr,
+    _a2: GuestAddr,
+    _a3: GuestAddr,
+    _a4: GuestAddr,
+    _a5: GuestAddr,
+    _a6: GuestAddr,
+    _a7: GuestAddr,
+) -> GuestAddr
+where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    if i64::from(sys_num) == SYS_close && result == 0 {
+        let h = emulator_modules.get_mut::<VfsModule>().unwrap();
+        h.open_fds.remove(&fd_arg(a0));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path, process};
+
+    use libafl::inputs::{BytesInput, MultipartInput};
+    use libafl_qemu_sys::GuestAddr;
+
+    use super::{VfsModule, VfsSource, errno, fd_arg, file_id, normalize};
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), Path::new("/a/c"));
+        assert_eq!(normalize(Path::new("/../a//b/")), Path::new("/a/b"));
+        assert_eq!(
+            normalize(Path::new("a/../b")),
+            env::current_dir().unwrap().join("b")
+        );
+    }
+
+    #[test]
+    fn test_errno() {
+        assert_eq!(errno(libc::ENOENT), GuestAddr::MAX - 1);
+        assert_eq!(
+            errno(libc::EROFS).wrapping_neg(),
+            GuestAddr::from(libc::EROFS.unsigned_abs())
+        );
+    }
+
+    #[test]
+    fn test_load_input() {
+        let mut vfs = VfsModule::new()
+            .with_file("/input", VfsSource::Input)
+            .with_file("/a", VfsSource::InputPart("a".to_string()))
+            .with_file("/b", VfsSource::InputPart("b".to_string()))
+            .with_file("/conf", VfsSource::Bytes(b"conf".to_vec()));
+
+        // Nothing is loaded before the first run
+        assert_eq!(
+            vfs.contents(Path::new("/a")).unwrap(),
+            Some((&[][..], false))
+        );
+
+        let input = MultipartInput::new(vec![
+            ("a".to_string(), BytesInput::new(b"first".to_vec())),
+            ("c".to_string(), BytesInput::new(b"other".to_vec())),
+        ]);
+        vfs.load_input(&input);
+        assert_eq!(
+            vfs.contents(Path::new("/a")).unwrap(),
+            Some((&b"first"[..], false))
+        );
+        // Missing parts, and the whole multipart input, are empty
+        assert_eq!(
+            vfs.contents(Path::new("/b")).unwrap(),
+            Some((&[][..], false))
+        );
+        assert_eq!(
+            vfs.contents(Path::new("/input")).unwrap(),
+            Some((&[][..], false))
+        );
+        assert_eq!(
+            vfs.contents(Path::new("/conf")).unwrap(),
+            Some((&b"conf"[..], true))
+        );
+        assert_eq!(vfs.contents(Path::new("/other")).unwrap(), None);
+
+        vfs.load_input(&BytesInput::new(b"whole".to_vec()));
+        assert_eq!(
+            vfs.contents(Path::new("/input")).unwrap(),
+            Some((&b"whole"[..], false))
+        );
+        assert_eq!(
+            vfs.contents(Path::new("/a")).unwrap(),
+            Some((&[][..], false))
+        );
+    }
+
+    #[test]
+    fn test_overlay() {
+        let host_dir = env::temp_dir().join(format!("libafl_qemu_vfs_{}", process::id()));
+        fs::create_dir_all(host_dir.join("sub")).unwrap();
+        fs::write(host_dir.join("sub/file"), b"host").unwrap();
+
+        let mut vfs = VfsModule::new()
+            .with_file("/etc/sub/file", VfsSource::Bytes(b"file".to_vec()))
+            .with_host_overlay("/etc", &host_dir);
+        // The files take precedence over the overlays
+        assert_eq!(
+            vfs.contents(Path::new("/etc/sub/file")).unwrap(),
+            Some((&b"file"[..], true))
+        );
+
+        let mut vfs = VfsModule::new().with_host_overlay("/etc/../etc", &host_dir);
+        assert_eq!(
+            vfs.contents(Path::new("/etc/sub/file")).unwrap(),
+            Some((&b"host"[..], true))
+        );
+        assert_eq!(vfs.contents(Path::new("/etc/sub")).unwrap(), None);
+        assert_eq!(vfs.contents(Path::new("/etc/missing")).unwrap(), None);
+        assert_eq!(vfs.contents(Path::new("/etcetera/sub/file")).unwrap(), None);
+
+        // The host files are read once
+        fs::remove_dir_all(&host_dir).unwrap();
+        assert_eq!(
+            vfs.contents(Path::new("/etc/sub/file")).unwrap(),
+            Some((&b"host"[..], true))
+        );
+    }
+
+    #[test]
+    fn test_close_all() {
+        let mut vfs = VfsModule::new().with_file("/conf", VfsSource::Bytes(b"conf".to_vec()));
+        let write_only = GuestAddr::from(libc::O_WRONLY.unsigned_abs());
+        assert_eq!(
+            vfs.open(Path::new("/conf"), write_only),
+            Some(errno(libc::EROFS))
+        );
+
+        let kept = fd_arg(vfs.open(Path::new("/conf"), 0).unwrap());
+        let reused = fd_arg(vfs.open(Path::new("/conf"), 0).unwrap());
+        assert_eq!(vfs.open_files(), 2);
+        let kept_id = file_id(kept);
+
+        // The guest replaces a file without closing it
+        let other = unsafe { libc::memfd_create(c"other".as_ptr(), 0) };
+        let other_id = file_id(other);
+        assert_eq!(unsafe { libc::dup2(other, reused) }, reused);
+        unsafe {
+            libc::close(other);
+        }
+
+        vfs.close_all();
+        assert_eq!(vfs.open_files(), 0);
+        assert_ne!(file_id(kept), kept_id);
+        assert_eq!(file_id(reused), other_id);
+        unsafe {
+            libc::close(reused);
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! Value profile and comparison splitting for binary-only targets.
+//!
+//! The [`ValueProfileModule`] hooks the comparisons of the target and records how close their
+//! operands are in [`CMP_MAP`], as the value profile of in-process targets does: each comparison
+//! gets an entry holding the highest number of equal bits seen between its operands. Used with a
+//! `MaxMapFeedback`, the inputs getting closer to a magic value are kept, and the fuzzer can climb
+//! it without a `RedQueen` stage.
+//!
+//! The optional `laf-intel` mode splits the comparisons of several bytes in byte-wise comparisons,
+//! from the most significant byte to the least significant one, as the LLVM pass does. The hook of
+//! each comparison walks the chain of byte-wise comparisons and hits the edges the split code would
+//! take in the edge map of the edge coverage module: one edge for each byte matched in order, and
+//! the edge leaving the chain at the first differing byte. The inputs matching one more byte of a
+//! magic value reach a new edge, as with an instrumented target. The edges are hashed into the map
+//! like the edges of the hashed edge coverage variants, and are only recorded together with an
+//! edge coverage module.
+
+use core::cmp::max;
+
+use libafl_bolts::hash_64_fast;
+use libafl_qemu_sys::GuestAddr;
+pub use libafl_targets::{CMP_MAP, CMP_MAP_SIZE};
+
+#[cfg(feature = "systemmode")]
+use crate::modules::utils::filters::{HasPageFilter, NOP_PAGE_FILTER, NopPageFilter};
+use crate::{
+    Qemu,
+    emu::EmulatorModules,
+    modules::{
+        AddressFilter, EmulatorModule, EmulatorModuleTuple,
+        edges::trace_hashed_edge,
+        utils::filters::{HasAddressFilter, StdAddressFilter},
+    },
+    qemu::Hook,
+};
+
+#[derive(Debug)]
+pub struct ValueProfileModule {
+    address_filter: StdAddressFilter,
+    laf_intel: bool,
+}
+
+impl ValueProfileModule {
+    #[must_use]
+    pub fn new(address_filter: StdAddressFilter) -> Self {
+        Self {
+            address_filter,
+            laf_intel: false,
+        }
+    }
+
+    /// Splits the comparisons in byte-wise comparisons, hitting the edges of the split code in the
+    /// map of the edge coverage module
+    #[must_use]
+    pub fn with_laf_intel(mut self, laf_intel: bool) -> Self {
+        self.laf_intel = laf_intel;
+        self
+    }
+
+    /// Whether the comparisons are split in byte-wise comparisons
+    #[must_use]
+    pub fn laf_intel(&self) -> bool {
+        self.laf_intel
+    }
+
+    #[must_use]
+    pub fn must_instrument(&self, addr: GuestAddr) -> bool {
+        self.address_filter.allowed(&addr)
+    }
+}
+
+impl Default for ValueProfileModule {
+    fn default() -> Self {
+        Self::new(StdAddressFilter::default())
+    }
+}
+
+impl<I, S> EmulatorModule<I, S> for ValueProfileModule
+where
+    I: Unpin,
+    S: Unpin,
+{
+    fn first_exec<ET>(
+        &mut self,
+        _qemu: Qemu,
+        emulator_modules: &mut EmulatorModules<ET, I, S>,
+        _state: &mut S,
+    ) where
+        ET: EmulatorModuleTuple<I, S>,
+    {
+        if self.laf_intel {
+            emulator_modules.cmps(
+                Hook::Function(gen_value_profile_cmp_ids::<ET, I, S>),
+                Hook::Raw(trace_cmp1_value_profile::<true>),
+                Hook::Raw(trace_cmp2_value_profile::<true>),
+                Hook::Raw(trace_cmp4_value_profile::<true>),
+                Hook::Raw(trace_cmp8_value_profile::<true>),
+            );
+        } else {
+            emulator_modules.cmps(
+                Hook::Function(gen_value_profile_cmp_ids::<ET, I, S>),
+                Hook::Raw(trace_cmp1_value_profile::<false>),
+                Hook::Raw(trace_cmp2_value_profile::<false>),
+                Hook::Raw(trace_cmp4_value_profile::<false>),
+                Hook::Raw(trace_cmp8_value_profile::<false>),
+            );
+        }
+    }
+}
+
+impl HasAddressFilter for ValueProfileModule {
+    type AddressFilter = StdAddressFilter;
+
+    fn address_filter(&self) -> &Self::AddressFilter {
+        &self.address_filter
+    }
+
+    fn address_filter_mut(&mut self) -> &mut Self::AddressFilter {
+        &mut self.address_filter
+    }
+}
+
+#[cfg(feature = "systemmode")]
+impl HasPageFilter for ValueProfileModule {
+    type PageFilter = NopPageFilter;
+
+    fn page_filter(&self) -> &Self::PageFilter {
+        &NopPageFilter
+    }
+
+    fn page_filter_mut(&mut self) -> &mut Self::PageFilter {
+        unsafe { (&raw mut NOP_PAGE_FILTER).as_mut().unwrap().get_mut() }
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)] // no longer a problem with nightly
+pub fn gen_value_profile_cmp_ids<ET, I, S>(
+    _qemu: Qemu,
+    emulator_modules: &mut EmulatorModules<ET, I, S>,
+    _state: Option<&mut S>,
+    pc: GuestAddr,
+    _size: usize,
+) -> Option<u64>
+where
+    ET: EmulatorModuleTuple<I, S>,
+    I: Unpin,
+    S: Unpin,
+{
+    if let Some(h) = emulator_modules.get::<ValueProfileModule>() {
+        if !h.must_instrument(pc) {
+            return None;
+        }
+    }
+    Some(hash_64_fast(pc.into()) & (CMP_MAP_SIZE as u64 - 1))
+}
+
+/// Raises the entry `idx` of [`CMP_MAP`] to `value`
+#[inline]
+fn update_cmp_map(idx: u64, value: u8) {
+    #[allow(clippy::cast_possible_truncation)] // the index is masked to the map size
+    let idx = (idx & (CMP_MAP_SIZE as u64 - 1)) as usize;
+    unsafe {
+        let entry = (&raw mut CMP_MAP).cast::<u8>().add(idx);
+        *entry = max(*entry, value);
+    }
+}
+
+/// The id of the edge of the byte-wise comparison `byte` of the comparison `id`, taken if the
+/// bytes are `equal`
+fn laf_intel_edge_id(id: u64, byte: u32, equal: bool) -> u64 {
+    hash_64_fast(id ^ (u64::from(byte + 1) << 56) ^ (u64::from(equal) << 48))
+}
+
+/// The edges taken by the comparison `id` of the operands `v0` and `v1` of `size` bytes, split in
+/// byte-wise comparisons from the most significant byte
+fn laf_intel_edge_ids(id: u64, v0: u64, v1: u64, size: u32) -> impl Iterator<Item = u64> {
+    let diff = v0 ^ v1;
+    let equal_bytes = if diff == 0 {
+        size
+    } else {
+        // The operands are zero-extended, the bits above their size are always equal
+        (diff.leading_zeros() - (64 - size * 8)) / 8
+    };
+    (0..equal_bytes)
+        .map(move |byte| laf_intel_edge_id(id, byte, true))
+        .chain((equal_bytes < size).then(|| laf_intel_edge_id(id, equal_bytes, false)))
+}
+
+/// Records the comparison `id` of the operands `v0` and `v1`, of `size` bytes
+#[inline]
+fn trace_cmp_value_profile<const LAF_INTEL: bool>(id: u64, v0: u64, v1: u64, size: u32) {
+    let diff = v0 ^ v1;
+
+    // The operands are zero-extended, the bits above their size are always equal
+    #[expect(clippy::cast_possible_truncation)] // at most 64
+    let equal_bits = (size * 8 - diff.count_ones()) as u8;
+    update_cmp_map(id, equal_bits);
+
+    // A single byte is compared as it is
+    if LAF_INTEL && size > 1 {
+        for edge in laf_intel_edge_ids(id, v0, v1, size) {
+            trace_hashed_edge(edge);
+        }
+    }
+}
+
+pub extern "C" fn trace_cmp1_value_profile<const LAF_INTEL: bool>(
+    _: *const (),
+    id: u64,
+    v0: u8,
+    v1: u8,
+) {
+    trace_cmp_value_profile::<LAF_INTEL>(id, u64::from(v0), u64::from(v1), 1);
+}
+
+pub extern "C" fn trace_cmp2_value_profile<const LAF_INTEL: bool>(
+    _: *const (),
+    id: u64,
+    v0: u16,
+    v1: u16,
+) {
+    trace_cmp_value_profile::<LAF_INTEL>(id, u64::from(v0), u64::from(v1), 2);
+}
+
+pub extern "C" fn trace_cmp4_value_profile<const LAF_INTEL: bool>(
+    _: *const (),
+    id: u64,
+    v0: u32,
+    v1: u32,
+) {
+    trace_cmp_value_profile::<LAF_INTEL>(id, u64::from(v0), u64::from(v1), 4);
+}
+
+pub extern "C" fn trace_cmp8_value_profile<const LAF_INTEL: bool>(
+    _: *const (),
+    id: u64,
+    v0: u64,
+    v1: u64,
+) {
+    trace_cmp_value_profile::<LAF_INTEL>(id, v0, v1, 8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CMP_MAP, CMP_MAP_SIZE, laf_intel_edge_id, laf_intel_edge_ids, trace_cmp_value_profile,
+        trace_cmp1_value_profile, trace_cmp4_value_profile,
+    };
+
+    fn entry(idx: u64) -> u8 {
+        #[allow(clippy::cast_possible_truncation)] // the index is masked to the map size
+        let idx = (idx & (CMP_MAP_SIZE as u64 - 1)) as usize;
+        unsafe { (&raw const CMP_MAP).cast::<u8>().add(idx).read() }
+    }
+
+    #[test]
+    fn test_equal_bits() {
+        // 0x5678 has 8 bits set
+        trace_cmp4_value_profile::<false>(core::ptr::null(), 0x10, 0x1234_5678, 0x1234_0000);
+        assert_eq!(entry(0x10), 24);
+        // Lower values do not replace the entry
+        trace_cmp4_value_profile::<false>(core::ptr::null(), 0x10, 0xffff_ffff, 0);
+        assert_eq!(entry(0x10), 24);
+        trace_cmp4_value_profile::<false>(core::ptr::null(), 0x10, 7, 7);
+        assert_eq!(entry(0x10), 32);
+
+        trace_cmp1_value_profile::<false>(core::ptr::null(), 0x11, 0x0f, 0x0e);
+        assert_eq!(entry(0x11), 7);
+        trace_cmp_value_profile::<false>(0x12, u64::MAX, u64::MAX - 1, 8);
+        assert_eq!(entry(0x12), 63);
+    }
+
+    #[test]
+    fn test_laf_intel_edges() {
+        let edges = |v0, v1, size| laf_intel_edge_ids(0x20, v0, v1, size).collect::<Vec<_>>();
+        let equal = |byte| laf_intel_edge_id(0x20, byte, true);
+        let different = |byte| laf_intel_edge_id(0x20, byte, false);
+
+        // The two most significant bytes match, the chain is left at the third one
+        assert_eq!(
+            edges(0x1234_5678, 0x1234_0000, 4),
+            [equal(0), equal(1), different(2)]
+        );
+        // Equal operands go through the whole chain
+        assert_eq!(
+            edges(0x1122_3344, 0x1122_3344, 4),
+            [equal(0), equal(1), equal(2), equal(3)]
+        );
+        // A different most significant byte leaves the chain right away
+        assert_eq!(edges(0x8000, 0x0000, 2), [different(0)]);
+        assert_eq!(edges(u64::MAX, u64::MAX - 1, 8).len(), 8);
+
+        // Every byte-wise comparison has distinct edges
+        let mut ids: Vec<u64> = (0..8)
+            .flat_map(|byte| [equal(byte), different(byte)])
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 16);
+    }
+}
@@ -440,6 +440,115 @@ impl From<bool> for DefaultDevices {
     }
 }
 
+/// The number of host nanoseconds per guest instruction of the `-icount` option
+#[cfg(feature = "systemmode")]
+#[derive(Debug, Clone, Copy)]
+pub enum IcountShift {
+    /// One instruction takes `2^shift` ns
+    Fixed(u8),
+    /// Adjusted to the host speed. Only deterministic while QEMU records or replays the
+    /// adjustments, see [`RecordReplay`].
+    Auto,
+}
+
+/// The default shift of [`IcountShift::Fixed`]
+#[cfg(feature = "systemmode")]
+pub const DEFAULT_ICOUNT_SHIFT: u8 = 7;
+
+/// The fixed shift, as runs with the same instructions then see the same virtual time
+#[cfg(feature = "systemmode")]
+impl Default for IcountShift {
+    fn default() -> Self {
+        Self::Fixed(DEFAULT_ICOUNT_SHIFT)
+    }
+}
+
+#[cfg(feature = "systemmode")]
+impl Display for IcountShift {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IcountShift::Fixed(shift) => write!(f, "shift={shift}"),
+            IcountShift::Auto => write!(f, "shift=auto"),
+        }
+    }
+}
+
+/// Whether QEMU records or replays the execution
+#[cfg(feature = "systemmode")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::Display)]
+#[strum(prefix = "rr=", serialize_all = "lowercase")]
+pub enum RecordReplayMode {
+    Record,
+    Replay,
+}
+
+/// The record/replay part of the `-icount` option: the non-deterministic events (interrupts,
+/// device inputs, clock reads) are written to or read from `file`, starting from the VM snapshot
+/// `snapshot` of the disk image.
+#[cfg(feature = "systemmode")]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct RecordReplay {
+    mode: RecordReplayMode,
+    #[builder(setter(into))]
+    file: PathBuf,
+    #[builder(default, setter(strip_option, into))]
+    snapshot: Option<String>,
+}
+
+#[cfg(feature = "systemmode")]
+impl Display for RecordReplay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{},rrfile={}", self.mode, self.file.to_str().unwrap())?;
+        if let Some(snapshot) = &self.snapshot {
+            write!(f, ",rrsnapshot={snapshot}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The `-icount` option: the virtual clock follows the number of executed instructions, so the
+/// timers and interrupts of the guest are deterministic.
+#[cfg(feature = "systemmode")]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct Icount {
+    #[builder(default)]
+    shift: IcountShift,
+    #[builder(default, setter(strip_option))]
+    sleep: Option<bool>,
+    #[builder(default, setter(strip_option))]
+    align: Option<bool>,
+    #[builder(default, setter(strip_option))]
+    record_replay: Option<RecordReplay>,
+}
+
+#[cfg(feature = "systemmode")]
+impl Display for Icount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "-icount {}", self.shift)?;
+        if let Some(sleep) = self.sleep {
+            write!(f, ",sleep={}", if sleep { "on" } else { "off" })?;
+        }
+        if let Some(align) = self.align {
+            write!(f, ",align={}", if align { "on" } else { "off" })?;
+        }
+        if let Some(record_replay) = &self.record_replay {
+            write!(f, ",{record_replay}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The clock of the real time clock of the guest. With [`Rtc::Vm`], the clock reads of the guest
+/// follow the virtual clock.
+#[cfg(feature = "systemmode")]
+#[derive(Debug, Clone, strum_macros::Display)]
+#[strum(prefix = "-rtc clock=", serialize_all = "lowercase")]
+pub enum Rtc {
+    Host,
+    Rt,
+    Vm,
+}
+
 #[cfg(feature = "usermode")]
 #[derive(Debug, Clone)]
 pub struct Program {
@@ -506,6 +615,12 @@ pub struct QemuConfig {
     #[cfg(feature = "systemmode")]
     #[builder(default, setter(strip_option, into))]
     initrd: Option<InitRD>,
+    #[cfg(feature = "systemmode")]
+    #[builder(default, setter(strip_option))]
+    icount: Option<Icount>,
+    #[cfg(feature = "systemmode")]
+    #[builder(default, setter(strip_option))]
+    rtc: Option<Rtc>,
     #[cfg(feature = "usermode")]
     #[builder(setter(into))]
     program: Program,
@@ -536,6 +651,38 @@ mod test {
         assert_eq!(drive.to_string(), "-drive format=raw,if=ide");
     }
 
+    #[test]
+    #[cfg(feature = "systemmode")]
+    fn icount_record_to_string() {
+        let icount = Icount::builder()
+            .shift(IcountShift::Fixed(7))
+            .sleep(false)
+            .record_replay(
+                RecordReplay::builder()
+                    .mode(RecordReplayMode::Record)
+                    .file("crash.rr")
+                    .snapshot("fuzz")
+                    .build(),
+            )
+            .build();
+        assert_eq!(
+            icount.to_string(),
+            "-icount shift=7,sleep=off,rr=record,rrfile=crash.rr,rrsnapshot=fuzz"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "systemmode")]
+    fn icount_default_to_string() {
+        let icount = Icount::builder().build();
+        assert_eq!(icount.to_string(), "-icount shift=7");
+        let icount = Icount::builder()
+            .shift(IcountShift::Auto)
+            .align(true)
+            .build();
+        assert_eq!(icount.to_string(), "-icount shift=auto,align=on");
+    }
+
     #[test]
     #[cfg(feature = "systemmode")]
     fn accelerator_kvm_to_string() {
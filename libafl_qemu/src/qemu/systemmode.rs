@@ -17,7 +17,7 @@ use num_traits::Zero;
 
 use crate::{
     CPU, FastSnapshotPtr, GuestAddrKind, MemAccessInfo, Qemu, QemuMemoryChunk,
-    QemuSnapshotCheckResult,
+    QemuSnapshotCheckResult, config::RecordReplayMode,
 };
 
 pub(super) extern "C" fn qemu_cleanup_atexit() {
@@ -239,6 +239,33 @@ impl Qemu {
         unsafe { libafl_load_qemu_snapshot(s.as_ptr().cast_mut(), sync) };
     }
 
+    /// Deletes the snapshot `name` from the disk images
+    pub fn delete_snapshot(&self, name: &str) {
+        let s = CString::new(name).expect("Invalid snapshot name");
+        if !unsafe { libafl_qemu_sys::delete_snapshot(s.as_ptr(), false, null_mut(), null_mut()) } {
+            log::warn!("Could not delete the snapshot {name}");
+        }
+    }
+
+    /// The number of instructions executed by the guest, if QEMU runs with `-icount`
+    #[must_use]
+    pub fn icount(&self) -> u64 {
+        unsafe { libafl_qemu_sys::replay_get_current_icount() }
+    }
+
+    /// Whether QEMU records or replays the execution, see [`RecordReplay`](crate::config::RecordReplay)
+    #[must_use]
+    pub fn record_replay_mode(&self) -> Option<RecordReplayMode> {
+        let mode = unsafe { libafl_qemu_sys::replay_mode };
+        if mode == libafl_qemu_sys::ReplayMode_REPLAY_MODE_RECORD {
+            Some(RecordReplayMode::Record)
+        } else if mode == libafl_qemu_sys::ReplayMode_REPLAY_MODE_PLAY {
+            Some(RecordReplayMode::Replay)
+        } else {
+            None
+        }
+    }
+
     #[must_use]
     pub fn create_fast_snapshot(&self, track: bool) -> FastSnapshotPtr {
         unsafe {
@@ -275,6 +302,14 @@ impl Qemu {
         }
     }
 
+    /// Frees a fast snapshot, which must not be used afterwards
+    #[expect(clippy::missing_safety_doc)]
+    pub unsafe fn free_fast_snapshot(&self, snapshot: FastSnapshotPtr) {
+        unsafe {
+            libafl_qemu_sys::syx_snapshot_free(snapshot);
+        }
+    }
+
     #[must_use]
     #[expect(clippy::missing_safety_doc)]
     pub unsafe fn check_fast_snapshot(
@@ -313,6 +348,46 @@ impl Qemu {
     pub fn target_page_size(&self) -> usize {
         unsafe { libafl_qemu_sys::qemu_target_page_size() }
     }
+
+    /// Map an I/O region of `size` bytes at the physical address `base`, whose accesses are
+    /// served by the callbacks of `ops`. The region overlaps what is already mapped there with
+    /// the given `priority`, so it can also shadow emulated devices.
+    ///
+    /// # Safety
+    ///
+    /// `opaque` is passed to the callbacks of `ops`, and must stay valid as long as the VM runs.
+    pub unsafe fn map_io_region(
+        &self,
+        base: GuestPhysAddr,
+        size: u64,
+        name: &str,
+        ops: &'static libafl_qemu_sys::MemoryRegionOps,
+        opaque: *mut c_void,
+        priority: i32,
+    ) {
+        let name = CString::new(name).expect("Invalid region name");
+        // QEMU keeps the region for the lifetime of the VM
+        let region = Box::leak(Box::new(
+            MaybeUninit::<libafl_qemu_sys::MemoryRegion>::zeroed(),
+        ))
+        .as_mut_ptr();
+        unsafe {
+            libafl_qemu_sys::memory_region_init_io(
+                region,
+                null_mut(),
+                ops,
+                opaque,
+                name.as_ptr(),
+                size,
+            );
+            libafl_qemu_sys::memory_region_add_subregion_overlap(
+                libafl_qemu_sys::get_system_memory(),
+                base,
+                region,
+                priority,
+            );
+        }
+    }
 }
 
 impl QemuMemoryChunk {
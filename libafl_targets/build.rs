@@ -270,6 +270,22 @@ fn main() {
         }
     }
 
+    #[cfg(feature = "libc_faults")]
+    if env::var("CARGO_CFG_TARGET_OS").unwrap() == "linux" {
+        println!("cargo:rerun-if-changed=src/libc_faults.c");
+
+        let mut libc_faults = cc::Build::new();
+
+        #[cfg(feature = "whole_archive")]
+        {
+            libc_faults.link_lib_modifier("+whole-archive");
+        }
+
+        libc_faults
+            .file(src_dir.join("libc_faults.c"))
+            .compile("libc_faults");
+    }
+
     #[cfg(feature = "windows_asan")]
     if target_family == "windows" {
         println!("cargo:rerun-if-changed=src/windows_asan.c");
@@ -122,6 +122,18 @@ void __libafl_targets_cmplog_routines_len(uintptr_t k, const uint8_t *ptr1,
                                           const uint8_t *ptr2, size_t len) {
   if (!libafl_cmplog_enabled) { return; }
 
+  if (len > CMPLOG_RTN_LEN) {
+    int l1, l2;
+    len = MIN(len, (size_t)CMPLOG_RTN_LONG_LEN);
+    if ((l1 = area_is_valid(ptr1, len)) <= 0 ||
+        (l2 = area_is_valid(ptr2, len)) <= 0) {
+      return;
+    }
+
+    cmplog_routines_long_checked(k, ptr1, ptr2, MIN(l1, l2));
+    return;
+  }
+
   if (area_is_valid(ptr1, CMPLOG_RTN_LEN) <= 0 ||
       area_is_valid(ptr2, CMPLOG_RTN_LEN) <= 0) {
     return;
@@ -129,6 +141,13 @@ void __libafl_targets_cmplog_routines_len(uintptr_t k, const uint8_t *ptr1,
 
   cmplog_routines_checked(k, ptr1, ptr2, len);
 }
+
+// Generic cmplog callback for floating point compares, passing the raw bits
+void __libafl_targets_cmplog_instructions_fp(uintptr_t k, uint8_t shape,
+                                             uint64_t arg1, uint64_t arg2) {
+  cmplog_instructions_kind_checked(k, CMPLOG_KIND_INS_FP, shape, arg1, arg2,
+                                   0);
+}
 /*
   CMPLOG Callback for instructions
 */
@@ -226,6 +245,27 @@ void __cmplog_ins_hookN(uint128_t arg1, uint128_t arg2, uint8_t size) {
   cmplog_instructions_checked(k, size, arg1, arg2, 0);
 }
 #endif
+
+/*
+  CMPLOG Callback for floating point instructions.
+  The operands are passed as their raw bits.
+*/
+void __cmplog_ins_hook_fp4(uint32_t arg1, uint32_t arg2) {
+  uintptr_t k = RETADDR;
+  k = (k >> 4) ^ (k << 8);
+  k &= CMPLOG_MAP_W - 1;
+
+  cmplog_instructions_kind_checked(k, CMPLOG_KIND_INS_FP, 4, arg1, arg2, 0);
+}
+
+void __cmplog_ins_hook_fp8(uint64_t arg1, uint64_t arg2) {
+  uintptr_t k = RETADDR;
+  k = (k >> 4) ^ (k << 8);
+  k &= CMPLOG_MAP_W - 1;
+
+  cmplog_instructions_kind_checked(k, CMPLOG_KIND_INS_FP, 8, arg1, arg2, 0);
+}
+
 /*
   CMPLOG Callback for routines
 */
@@ -266,10 +306,35 @@ void __cmplog_rtn_hook_extended(const uint8_t *ptr1, const uint8_t *ptr2) {
 
 void __cmplog_rtn_hook_n(const uint8_t *ptr1, const uint8_t *ptr2,
                          uint64_t len) {
-  // this code is from AFL++
-  // but i don't know why afl++ just ignores the len argument
-  (void)(len);
-  __cmplog_rtn_hook(ptr1, ptr2);
+  if (len <= CMPLOG_RTN_LEN) {
+    // this code is from AFL++
+    // but i don't know why afl++ just ignores the len argument
+    __cmplog_rtn_hook(ptr1, ptr2);
+    return;
+  }
+
+  // long memory compare, log (the start of) the whole compared area
+  if (!libafl_cmplog_enabled) { return; }
+
+  int l1, l2;
+  len = MIN(len, (uint64_t)CMPLOG_RTN_LONG_LEN);
+  if ((l1 = area_is_valid(ptr1, len)) <= 0 ||
+      (l2 = area_is_valid(ptr2, len)) <= 0) {
+    return;
+  }
+  int l = MIN(l1, l2);
+
+  // area_is_valid stops at page boundaries, fall back to a normal routine
+  if (l <= CMPLOG_RTN_LEN) {
+    __cmplog_rtn_hook(ptr1, ptr2);
+    return;
+  }
+
+  uintptr_t k = RETADDR;
+  k = (k >> 4) ^ (k << 8);
+  k &= CMPLOG_MAP_W - 1;
+
+  cmplog_routines_long_checked(k, ptr1, ptr2, l);
 }
 void __cmplog_rtn_hook_n_extended(const uint8_t *ptr1, const uint8_t *ptr2,
                                   uint64_t len) {
@@ -371,6 +436,83 @@ void __cmplog_rtn_hook_strn_extended(uint8_t *ptr1, uint8_t *ptr2,
   cmplog_routines_checked_extended(k, ptr1, ptr2, l);
 }
 
+// Copies a wide string of `width` bytes per character into `buf`, up to
+// `max_chars` characters or the terminating null character.
+// Returns the number of bytes copied, or 0 if the string can not be read.
+static inline size_t cmplog_copy_wide_string(uint8_t *buf, const uint8_t *ptr,
+                                             size_t max_chars, uint8_t width) {
+  long valid = area_is_valid(ptr, max_chars * width);
+  if (valid <= 0) { return 0; }
+
+  size_t chars = MIN(max_chars, (size_t)valid / width);
+  size_t i;
+  for (i = 0; i < chars; i++) {
+    const uint8_t *c = ptr + i * width;
+    MEMCPY(buf + i * width, c, width);
+
+    int is_null = 1;
+    for (uint8_t j = 0; j < width; j++) {
+      if (c[j]) { is_null = 0; }
+    }
+    if (is_null) { break; }
+  }
+
+  return MIN(i + 1, chars) * width;
+}
+
+static inline void cmplog_wide_string(uintptr_t k, const uint8_t *ptr1,
+                                      const uint8_t *ptr2, size_t max_chars,
+                                      uint8_t width) {
+  uint8_t kind;
+  if (width == 2) {
+    kind = CMPLOG_KIND_RTN_WIDE16;
+  } else if (width == 4) {
+    kind = CMPLOG_KIND_RTN_WIDE32;
+  } else {
+    return;
+  }
+
+  // the buffers are zeroed, so the logged strings are always terminated
+  uint8_t buf1[CMPLOG_RTN_LEN] = {0};
+  uint8_t buf2[CMPLOG_RTN_LEN] = {0};
+
+  max_chars = MIN(max_chars, (size_t)(CMPLOG_RTN_LEN / width) - 1);
+
+  size_t l1 = cmplog_copy_wide_string(buf1, ptr1, max_chars, width);
+  size_t l2 = cmplog_copy_wide_string(buf2, ptr2, max_chars, width);
+  if (l1 < 2 * width && l2 < 2 * width) { return; }
+
+  cmplog_routines_kind_checked(k, kind, buf1, buf2, CMPLOG_RTN_LEN);
+}
+
+/* hook for wide string functions, eg. wcscmp, u_strcmp etc.
+   `width` is the size of a single character in bytes (2 or 4). */
+void __cmplog_rtn_hook_wstr(const uint8_t *ptr1, const uint8_t *ptr2,
+                            uint8_t width) {
+  if (!libafl_cmplog_enabled) { return; }
+  if (unlikely(!ptr1 || !ptr2)) return;
+
+  uintptr_t k = RETADDR;
+  k = (k >> 4) ^ (k << 8);
+  k &= CMPLOG_MAP_W - 1;
+
+  cmplog_wide_string(k, ptr1, ptr2, CMPLOG_RTN_LEN, width);
+}
+
+/* hook for wide string with length functions, eg. wcsncmp, wmemcmp etc.
+   `len` is the number of characters, not bytes. */
+void __cmplog_rtn_hook_wstrn(const uint8_t *ptr1, const uint8_t *ptr2,
+                             uint64_t len, uint8_t width) {
+  if (!libafl_cmplog_enabled) { return; }
+  if (unlikely(!ptr1 || !ptr2)) return;
+
+  uintptr_t k = RETADDR;
+  k = (k >> 4) ^ (k << 8);
+  k &= CMPLOG_MAP_W - 1;
+
+  cmplog_wide_string(k, ptr1, ptr2, MIN(len, (uint64_t)CMPLOG_RTN_LEN), width);
+}
+
 // gcc libstdc++
 // _ZNKSt7__cxx1112basic_stringIcSt11char_traitsIcESaIcEE7compareEPKc
 static inline const uint8_t *get_gcc_stdstring(const uint8_t *string) {
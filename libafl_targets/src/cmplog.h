@@ -30,6 +30,18 @@
 
 #define CMPLOG_KIND_INS 0
 #define CMPLOG_KIND_RTN 1
+// floating point compares, shape is the size of the operands in bytes
+#define CMPLOG_KIND_INS_FP 2
+// wide string routines, with 2 or 4 bytes per character
+#define CMPLOG_KIND_RTN_WIDE16 3
+#define CMPLOG_KIND_RTN_WIDE32 4
+// memory compares longer than CMPLOG_RTN_LEN, spanning several routine slots
+#define CMPLOG_KIND_RTN_LONG 5
+
+#define CMPLOG_RTN_LONG_LEN 128
+#define CMPLOG_RTN_LONG_CHUNKS (CMPLOG_RTN_LONG_LEN / CMPLOG_RTN_LEN)
+
+#define CMPLOG_MAP_RTN_LONG_H (CMPLOG_MAP_RTN_H / CMPLOG_RTN_LONG_CHUNKS)
 
 typedef struct CmpLogHeader {
   uint16_t hits;
@@ -106,15 +118,17 @@ extern uint8_t libafl_cmplog_enabled;
 // cmplog_routines_checked,
 // cmplog_routines_checked_extended
 
-static inline void cmplog_instructions_checked(uintptr_t k, uint8_t shape,
-                                               uint64_t arg1, uint64_t arg2,
-                                               uint8_t arg1_is_const) {
+static inline void cmplog_instructions_kind_checked(uintptr_t k, uint8_t kind,
+                                                    uint8_t  shape,
+                                                    uint64_t arg1,
+                                                    uint64_t arg2,
+                                                    uint8_t  arg1_is_const) {
   if (!libafl_cmplog_enabled) { return; }
   libafl_cmplog_enabled = false;
 
   uint16_t hits;
-  if (libafl_cmplog_map_ptr->headers[k].kind != CMPLOG_KIND_INS) {
-    libafl_cmplog_map_ptr->headers[k].kind = CMPLOG_KIND_INS;
+  if (libafl_cmplog_map_ptr->headers[k].kind != kind) {
+    libafl_cmplog_map_ptr->headers[k].kind = kind;
     libafl_cmplog_map_ptr->headers[k].hits = 1;
     libafl_cmplog_map_ptr->headers[k].shape = shape;
     hits = 0;
@@ -132,6 +146,13 @@ static inline void cmplog_instructions_checked(uintptr_t k, uint8_t shape,
   libafl_cmplog_enabled = true;
 }
 
+static inline void cmplog_instructions_checked(uintptr_t k, uint8_t shape,
+                                               uint64_t arg1, uint64_t arg2,
+                                               uint8_t arg1_is_const) {
+  cmplog_instructions_kind_checked(k, CMPLOG_KIND_INS, shape, arg1, arg2,
+                                   arg1_is_const);
+}
+
 static inline void cmplog_instructions_extended_checked(
     uintptr_t k, uint8_t shape, uint64_t arg1, uint64_t arg2, uint8_t attr) {
 #ifdef CMPLOG_EXTENDED
@@ -168,13 +189,15 @@ static inline void cmplog_instructions_extended_checked(
 }
 
 // cmplog routines after area check
-static inline void cmplog_routines_checked(uintptr_t k, const uint8_t *ptr1,
-                                           const uint8_t *ptr2, size_t len) {
+static inline void cmplog_routines_kind_checked(uintptr_t k, uint8_t kind,
+                                                const uint8_t *ptr1,
+                                                const uint8_t *ptr2,
+                                                size_t         len) {
   libafl_cmplog_enabled = false;
   uint32_t hits;
 
-  if (libafl_cmplog_map_ptr->headers[k].kind != CMPLOG_KIND_RTN) {
-    libafl_cmplog_map_ptr->headers[k].kind = CMPLOG_KIND_RTN;
+  if (libafl_cmplog_map_ptr->headers[k].kind != kind) {
+    libafl_cmplog_map_ptr->headers[k].kind = kind;
     libafl_cmplog_map_ptr->headers[k].hits = 1;
     libafl_cmplog_map_ptr->headers[k].shape = len;
     hits = 0;
@@ -192,6 +215,56 @@ static inline void cmplog_routines_checked(uintptr_t k, const uint8_t *ptr1,
   libafl_cmplog_enabled = true;
 }
 
+static inline void cmplog_routines_checked(uintptr_t k, const uint8_t *ptr1,
+                                           const uint8_t *ptr2, size_t len) {
+  cmplog_routines_kind_checked(k, CMPLOG_KIND_RTN, ptr1, ptr2, len);
+}
+
+// cmplog long memory compares after area check.
+// Each logged compare takes CMPLOG_RTN_LONG_CHUNKS consecutive routine slots.
+static inline void cmplog_routines_long_checked(uintptr_t      k,
+                                                const uint8_t *ptr1,
+                                                const uint8_t *ptr2,
+                                                size_t         len) {
+  if (CMPLOG_MAP_RTN_LONG_H == 0) {
+    // the map is too small to hold long compares
+    cmplog_routines_checked(k, ptr1, ptr2, MIN(len, (size_t)CMPLOG_RTN_LEN));
+    return;
+  }
+
+  libafl_cmplog_enabled = false;
+  uint32_t hits;
+
+  if (len > CMPLOG_RTN_LONG_LEN) { len = CMPLOG_RTN_LONG_LEN; }
+
+  if (libafl_cmplog_map_ptr->headers[k].kind != CMPLOG_KIND_RTN_LONG) {
+    libafl_cmplog_map_ptr->headers[k].kind = CMPLOG_KIND_RTN_LONG;
+    libafl_cmplog_map_ptr->headers[k].hits = 1;
+    libafl_cmplog_map_ptr->headers[k].shape = len;
+    hits = 0;
+  } else {
+    hits = libafl_cmplog_map_ptr->headers[k].hits++;
+    if (libafl_cmplog_map_ptr->headers[k].shape < len) {
+      libafl_cmplog_map_ptr->headers[k].shape = len;
+    }
+  }
+
+  uint32_t slot = (hits % CMPLOG_MAP_RTN_LONG_H) * CMPLOG_RTN_LONG_CHUNKS;
+  for (size_t i = 0; i < CMPLOG_RTN_LONG_CHUNKS; i++) {
+    size_t         off = i * CMPLOG_RTN_LEN;
+    size_t         chunk_len = 0;
+    CmpLogRoutine *rtn = &libafl_cmplog_map_ptr->vals.routines[k][slot + i];
+
+    if (off < len) { chunk_len = MIN(len - off, (size_t)CMPLOG_RTN_LEN); }
+
+    MEMSET(rtn->v0, 0, CMPLOG_RTN_LEN);
+    MEMSET(rtn->v1, 0, CMPLOG_RTN_LEN);
+    MEMCPY(rtn->v0, ptr1 + off, chunk_len);
+    MEMCPY(rtn->v1, ptr2 + off, chunk_len);
+  }
+  libafl_cmplog_enabled = true;
+}
+
 // cmplog routines after area check
 static inline void cmplog_routines_checked_extended(uintptr_t      k,
                                                     const uint8_t *ptr1,
@@ -229,7 +302,7 @@ static inline void cmplog_routines_checked_extended(uintptr_t      k,
 #endif
 }
 
-// Expose these three APIs so that you can still call into them from outside
+// Expose these APIs so that you can still call into them from outside
 // libafl_targets
 
 void __libafl_targets_cmplog_instructions(uintptr_t k, uint8_t shape,
@@ -241,4 +314,7 @@ void __libafl_targets_cmplog_routines(uintptr_t k, const uint8_t *ptr1,
 void __libafl_targets_cmplog_routines_len(uintptr_t k, const uint8_t *ptr1,
                                           const uint8_t *ptr2, size_t len);
 
+void __libafl_targets_cmplog_instructions_fp(uintptr_t k, uint8_t shape,
+                                             uint64_t arg1, uint64_t arg2);
+
 #endif
@@ -42,6 +42,23 @@ pub const CMPLOG_MAP_RTN_EXTENDED_H: usize =
 pub const CMPLOG_KIND_INS: u8 = 0;
 /// `CmpLog` routine kind
 pub const CMPLOG_KIND_RTN: u8 = 1;
+/// `CmpLog` floating point instruction kind
+pub const CMPLOG_KIND_INS_FP: u8 = 2;
+/// `CmpLog` wide string routine kind, with 2 bytes per character
+pub const CMPLOG_KIND_RTN_WIDE16: u8 = 3;
+/// `CmpLog` wide string routine kind, with 4 bytes per character
+pub const CMPLOG_KIND_RTN_WIDE32: u8 = 4;
+/// `CmpLog` long memory compare kind, spanning several routine slots
+pub const CMPLOG_KIND_RTN_LONG: u8 = 5;
+
+/// The maximum number of bytes logged for a long memory compare
+pub const CMPLOG_RTN_LONG_LEN: usize = 128;
+
+/// The number of routine slots a single long memory compare takes
+pub const CMPLOG_RTN_LONG_CHUNKS: usize = CMPLOG_RTN_LONG_LEN / CMPLOG_RTN_LEN;
+
+/// The height of a cmplog long memory compare map
+pub const CMPLOG_MAP_RTN_LONG_H: usize = CMPLOG_MAP_RTN_H / CMPLOG_RTN_LONG_CHUNKS;
 
 // EXTERNS, GLOBALS
 
@@ -51,6 +68,9 @@ unsafe extern "C" {
     /// Logs an instruction for feedback during fuzzing
     pub fn __libafl_targets_cmplog_instructions(k: usize, shape: u8, arg1: u64, arg2: u64);
 
+    /// Logs a floating point instruction, passing the raw bits of the operands
+    pub fn __libafl_targets_cmplog_instructions_fp(k: usize, shape: u8, arg1: u64, arg2: u64);
+
     /// Logs a routine for feedback during fuzzing
     pub fn __libafl_targets_cmplog_routines(k: usize, ptr1: *const u8, ptr2: *const u8);
 
@@ -353,49 +373,22 @@ impl CmpMap for CmpLogMap {
     }
 
     fn usable_executions_for(&self, idx: usize) -> usize {
-        if self.headers[idx].kind == CMPLOG_KIND_INS {
-            if self.executions_for(idx) < CMPLOG_MAP_H {
-                self.executions_for(idx)
-            } else {
-                CMPLOG_MAP_H
-            }
-        } else if self.executions_for(idx) < CMPLOG_MAP_RTN_H {
-            self.executions_for(idx)
-        } else {
-            CMPLOG_MAP_RTN_H
-        }
+        let height = match self.headers[idx].kind {
+            CMPLOG_KIND_INS | CMPLOG_KIND_INS_FP => CMPLOG_MAP_H,
+            CMPLOG_KIND_RTN_LONG => CMPLOG_MAP_RTN_LONG_H,
+            _ => CMPLOG_MAP_RTN_H,
+        };
+        core::cmp::min(self.executions_for(idx), height)
     }
 
     fn values_of(&self, idx: usize, execution: usize) -> Option<CmpValues> {
-        if self.headers[idx].kind == CMPLOG_KIND_INS {
-            unsafe {
-                match self.headers[idx].shape {
-                    1 => Some(CmpValues::U8((
-                        self.vals.operands[idx][execution].0 as u8,
-                        self.vals.operands[idx][execution].1 as u8,
-                        self.vals.operands[idx][execution].2 == 1,
-                    ))),
-                    2 => Some(CmpValues::U16((
-                        self.vals.operands[idx][execution].0 as u16,
-                        self.vals.operands[idx][execution].1 as u16,
-                        self.vals.operands[idx][execution].2 == 1,
-                    ))),
-                    4 => Some(CmpValues::U32((
-                        self.vals.operands[idx][execution].0 as u32,
-                        self.vals.operands[idx][execution].1 as u32,
-                        self.vals.operands[idx][execution].2 == 1,
-                    ))),
-                    8 => Some(CmpValues::U64((
-                        self.vals.operands[idx][execution].0,
-                        self.vals.operands[idx][execution].1,
-                        self.vals.operands[idx][execution].2 == 1,
-                    ))),
-                    // other => panic!("Invalid CmpLog shape {}", other),
-                    _ => None,
-                }
-            }
-        } else {
-            unsafe {
+        match self.headers[idx].kind {
+            CMPLOG_KIND_INS => self.ins_values_of(idx, execution),
+            CMPLOG_KIND_INS_FP => self.fp_values_of(idx, execution),
+            CMPLOG_KIND_RTN_WIDE16 => Some(self.wide_values_of(idx, execution, 2)),
+            CMPLOG_KIND_RTN_WIDE32 => Some(self.wide_values_of(idx, execution, 4)),
+            CMPLOG_KIND_RTN_LONG => Some(self.long_values_of(idx, execution)),
+            _ => unsafe {
                 Some(CmpValues::Bytes((
                     CmplogBytes::from_buf_and_len(
                         self.vals.routines[idx][execution].0,
@@ -406,7 +399,7 @@ impl CmpMap for CmpLogMap {
                         CMPLOG_RTN_LEN as u8,
                     ),
                 )))
-            }
+            },
         }
     }
 
@@ -422,6 +415,71 @@ impl CmpMap for CmpLogMap {
     }
 }
 
+impl CmpLogMap {
+    fn ins_values_of(&self, idx: usize, execution: usize) -> Option<CmpValues> {
+        unsafe {
+            match self.headers[idx].shape {
+                1 => Some(CmpValues::U8((
+                    self.vals.operands[idx][execution].0 as u8,
+                    self.vals.operands[idx][execution].1 as u8,
+                    self.vals.operands[idx][execution].2 == 1,
+                ))),
+                2 => Some(CmpValues::U16((
+                    self.vals.operands[idx][execution].0 as u16,
+                    self.vals.operands[idx][execution].1 as u16,
+                    self.vals.operands[idx][execution].2 == 1,
+                ))),
+                4 => Some(CmpValues::U32((
+                    self.vals.operands[idx][execution].0 as u32,
+                    self.vals.operands[idx][execution].1 as u32,
+                    self.vals.operands[idx][execution].2 == 1,
+                ))),
+                8 => Some(CmpValues::U64((
+                    self.vals.operands[idx][execution].0,
+                    self.vals.operands[idx][execution].1,
+                    self.vals.operands[idx][execution].2 == 1,
+                ))),
+                // other => panic!("Invalid CmpLog shape {}", other),
+                _ => None,
+            }
+        }
+    }
+
+    fn fp_values_of(&self, idx: usize, execution: usize) -> Option<CmpValues> {
+        let CmpLogInstruction(v0, v1, v0_is_const) = unsafe { self.vals.operands[idx][execution] };
+        match self.headers[idx].shape {
+            4 => Some(CmpValues::F32((v0 as u32, v1 as u32, v0_is_const == 1))),
+            8 => Some(CmpValues::F64((v0, v1, v0_is_const == 1))),
+            _ => None,
+        }
+    }
+
+    fn wide_values_of(&self, idx: usize, execution: usize, width: u8) -> CmpValues {
+        let CmpLogRoutine(v0, v1) = unsafe { self.vals.routines[idx][execution] };
+        CmpValues::WideBytes((
+            CmplogBytes::from_buf_and_len(v0, CMPLOG_RTN_LEN as u8),
+            CmplogBytes::from_buf_and_len(v1, CMPLOG_RTN_LEN as u8),
+            width,
+        ))
+    }
+
+    fn long_values_of(&self, idx: usize, execution: usize) -> CmpValues {
+        let len = core::cmp::min(self.headers[idx].shape as usize, CMPLOG_RTN_LONG_LEN);
+        let first = execution * CMPLOG_RTN_LONG_CHUNKS;
+        let routines = unsafe { &self.vals.routines[idx][first..first + CMPLOG_RTN_LONG_CHUNKS] };
+
+        let mut v0 = Vec::with_capacity(CMPLOG_RTN_LONG_LEN);
+        let mut v1 = Vec::with_capacity(CMPLOG_RTN_LONG_LEN);
+        for routine in routines {
+            v0.extend_from_slice(&routine.0);
+            v1.extend_from_slice(&routine.1);
+        }
+        v0.truncate(len);
+        v1.truncate(len);
+        CmpValues::LongBytes((v0, v1))
+    }
+}
+
 /// The global `CmpLog` map for the current `LibAFL` run.
 #[unsafe(no_mangle)]
 #[allow(non_upper_case_globals)] // expect breaks here for some reason
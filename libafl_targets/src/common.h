@@ -81,11 +81,13 @@ typedef uint128_t         u128;
       _a < _b ? _a : _b;      \
     })
   #define MEMCPY __builtin_memcpy
+  #define MEMSET __builtin_memset
 #else
   #include <string.h>  // needed to use memcpy on windows
   #define MAX(a, b) (((a) > (b)) ? (a) : (b))
   #define MIN(a, b) (((a) < (b)) ? (a) : (b))
   #define MEMCPY memcpy
+  #define MEMSET memset
 #endif
 
 #if defined _WIN32
@@ -201,6 +201,43 @@ pub fn edges_max_num() -> usize {
     }
 }
 
+#[cfg(feature = "thread_local_maps")]
+std::thread_local! {
+    /// The edges map of the current fuzzing thread, or null to use the global map
+    static THREAD_EDGES_MAP_PTR: core::cell::Cell<*mut u8> =
+        const { core::cell::Cell::new(core::ptr::null_mut()) };
+}
+
+/// Gets the edges map registered for the current thread with [`thread_edges_map_observer`],
+/// or null if this thread uses the global edges map.
+#[cfg(feature = "thread_local_maps")]
+#[inline]
+#[must_use]
+pub fn thread_edges_map_ptr() -> *mut u8 {
+    THREAD_EDGES_MAP_PTR.with(core::cell::Cell::get)
+}
+
+/// Allocates an edges map for the current thread, and returns a [`StdMapObserver`] for it.
+/// From now on, the `sancov_pcguard` instrumentation running on this thread writes to this map
+/// instead of the global [`EDGES_MAP`], so that fuzzing threads in one process get their own coverage.
+///
+/// The map lives until the end of the process, create one per fuzzing thread.
+#[cfg(all(
+    feature = "thread_local_maps",
+    any(feature = "sancov_pcguard_edges", feature = "sancov_pcguard_hitcounts")
+))]
+#[must_use]
+pub fn thread_edges_map_observer<S>(name: S) -> StdMapObserver<'static, u8, false>
+where
+    S: Into<Cow<'static, str>>,
+{
+    // Guards are numbered up to the size of the global map, keep the same bound
+    let map: &'static mut [u8] =
+        alloc::boxed::Box::leak(alloc::vec![0; EDGES_MAP_ALLOCATED_SIZE].into_boxed_slice());
+    THREAD_EDGES_MAP_PTR.with(|ptr| ptr.set(map.as_mut_ptr()));
+    StdMapObserver::from_mut_slice(name, OwnedMutSlice::from(&mut map[..edges_max_num()]))
+}
+
 #[cfg(feature = "pointer_maps")]
 pub use swap::*;
 
@@ -26,6 +26,11 @@
 #define SHMEM_FUZZ_HDR_SIZE 4
 #define SHM_ENV_VAR "__AFL_SHM_ID"
 #define SHM_FUZZ_ENV_VAR "__AFL_SHM_FUZZ_ID"
+#define SHM_BATCH_ENV_VAR "__AFL_SHM_BATCH_ID"
+#define PERSIST_ENV_VAR "__AFL_PERSISTENT"
+// The batch results start with the number of finished inputs, the stride, and
+// whether the shared memory testcase holds a batch or a single input
+#define SHMEM_BATCH_HDR_SIZE 12
 #define DEFAULT_PERMISSION 0600
 
 /* Reporting errors */
@@ -44,6 +49,7 @@
 #define FS_NEW_OPT_MAPSIZE 0x1
 #define FS_NEW_OPT_SHDMEM_FUZZ 0x2
 #define FS_NEW_OPT_AUTODICT 0x800
+#define FS_NEW_OPT_BATCH 0x1000
 
 /* Reporting options */
 #define FS_OPT_ENABLED 0x80000001
@@ -81,6 +87,15 @@ static void (*old_sigterm_handler)(int) = 0;
 
 static uint8_t is_persistent;
 
+/* The shared memory testcase, and the batch results in batched mode */
+static uint8_t *fuzz_map;
+static uint8_t *batch_map;
+
+/* The inputs of the current batch */
+static uint32_t  batch_count;
+static uint32_t *batch_lens;
+static uint8_t  *batch_next_ptr;
+
 void __afl_set_persistent_mode(uint8_t mode) {
   is_persistent = mode;
 }
@@ -204,6 +219,7 @@ static void map_input_shared_memory() {
       exit(1);
     }
 
+    fuzz_map = map;
     __afl_fuzz_len = (uint32_t *)map;
     __afl_fuzz_ptr = map + sizeof(uint32_t);
 
@@ -214,6 +230,132 @@ static void map_input_shared_memory() {
   }
 }
 
+/* Attaches the shared memory testcase and the batch results */
+
+void __afl_batch_attach(uint8_t *fuzz, uint8_t *batch) {
+  fuzz_map = fuzz;
+  batch_map = batch;
+}
+
+/* Batch results setup, the map is shared by all children of the forkserver.
+   Returns 0 if batched execution was not requested. */
+
+static int map_batch_shared_memory() {
+  char *id_str = getenv(SHM_BATCH_ENV_VAR);
+
+  if (!id_str) { return 0; }
+
+  uint8_t *map = NULL;
+
+#ifdef USEMMAP
+  const char *shm_file_path = id_str;
+  int         shm_fd = -1;
+  struct stat st;
+
+  shm_fd = shm_open(shm_file_path, O_RDWR, DEFAULT_PERMISSION);
+  if (shm_fd == -1 || fstat(shm_fd, &st) == -1) {
+    fprintf(stderr, "shm_open() failed for batch\n");
+    send_forkserver_error(FS_ERROR_SHM_OPEN);
+    exit(1);
+  }
+
+  map = (uint8_t *)mmap(0, st.st_size, PROT_READ | PROT_WRITE, MAP_SHARED,
+                        shm_fd, 0);
+  close(shm_fd);
+
+#else
+  uint32_t shm_id = atoi(id_str);
+  map = (uint8_t *)shmat(shm_id, NULL, 0);
+
+#endif
+
+  if (!map || map == (void *)-1) {
+    perror("Could not access batch shared memory");
+    send_forkserver_error(FS_ERROR_SHM_OPEN);
+    exit(1);
+  }
+
+  __afl_batch_attach(fuzz_map, map);
+  return 1;
+}
+
+/* Points __afl_fuzz_ptr and __afl_fuzz_len to the next input of the batch */
+
+static void batch_select(uint32_t index) {
+  __afl_fuzz_len_local = batch_lens[index];
+  __afl_fuzz_len = &__afl_fuzz_len_local;
+  __afl_fuzz_ptr = batch_next_ptr;
+  batch_next_ptr += batch_lens[index];
+}
+
+/* Reads the layout of a new batch from the shared memory testcase:
+   the total length, the number of inputs, their lengths, then their contents.
+   A single input uses the classic layout, and keeps its coverage in the
+   regular map. */
+
+static void batch_start(void) {
+  if (!*(uint32_t *)(batch_map + 2 * sizeof(uint32_t))) {
+    batch_count = 0;
+    __afl_fuzz_len = (uint32_t *)fuzz_map;
+    __afl_fuzz_ptr = fuzz_map + sizeof(uint32_t);
+    return;
+  }
+
+  batch_count = *(uint32_t *)(fuzz_map + sizeof(uint32_t));
+  batch_lens = (uint32_t *)(fuzz_map + 2 * sizeof(uint32_t));
+  batch_next_ptr = (uint8_t *)(batch_lens + batch_count);
+  *(uint32_t *)batch_map = 0;
+  if (batch_count) { batch_select(0); }
+}
+
+/* Saves the coverage of the finished input, and moves to the next one.
+   The coverage of the last input also stays in the regular map.
+   Returns 0 at the end of the batch. */
+
+static int batch_next(void) {
+  uint32_t done = *(uint32_t *)batch_map;
+  uint32_t stride = *(uint32_t *)(batch_map + sizeof(uint32_t));
+  size_t   len = __afl_map_size < stride ? __afl_map_size : stride;
+
+  if (done >= batch_count) { return 0; }
+
+  memcpy(batch_map + SHMEM_BATCH_HDR_SIZE + (size_t)done * stride,
+         __afl_area_ptr, len);
+  *(uint32_t *)batch_map = ++done;
+
+  if (done >= batch_count) { return 0; }
+  memset(__afl_area_ptr, 0, __afl_map_size);
+  batch_select(done);
+  return 1;
+}
+
+/* Persistent loop, in the style of AFL++'s __AFL_LOOP. In batched mode, every
+   iteration runs the next input of the batch before waiting for a new one. */
+
+int __afl_persistent_loop(unsigned int max_cnt) {
+  static uint8_t  first_pass = 1;
+  static uint32_t cycle_cnt;
+
+  if (first_pass) {
+    memset(__afl_area_ptr, 0, __afl_map_size);
+    cycle_cnt = max_cnt;
+    first_pass = 0;
+    if (batch_map) { batch_start(); }
+    return 1;
+  }
+
+  if (batch_map && batch_next()) { return 1; }
+
+  if (is_persistent && --cycle_cnt) {
+    raise(SIGSTOP);
+    memset(__afl_area_ptr, 0, __afl_map_size);
+    if (batch_map) { batch_start(); }
+    return 1;
+  }
+
+  return 0;
+}
+
 /* Fork server logic. */
 
 void __afl_start_forkserver(void) {
@@ -241,6 +383,8 @@ void __afl_start_forkserver(void) {
 
   int autotokens_on = __token_start != NULL && __token_stop != NULL;
 
+  if (getenv(PERSIST_ENV_VAR)) { is_persistent = 1; }
+
   /* Phone home and tell the parent that we're OK. If parent isn't there,
      assume we're not running in forkserver mode and just execute program. */
 
@@ -257,6 +401,9 @@ void __afl_start_forkserver(void) {
   status = FS_NEW_OPT_MAPSIZE;
   if (__afl_sharedmem_fuzzing) { status |= FS_NEW_OPT_SHDMEM_FUZZ; }
   if (autotokens_on) { status |= FS_NEW_OPT_AUTODICT; }
+  if (__afl_sharedmem_fuzzing && map_batch_shared_memory()) {
+    status |= FS_NEW_OPT_BATCH;
+  }
 
   if (write(FORKSRV_FD + 1, msg, 4) != 4) { _exit(1); }
 
@@ -5,6 +5,17 @@ unsafe extern "C" {
     fn __afl_map_shm();
     /// Start the forkserver.
     fn __afl_start_forkserver();
+    /// The persistent loop, running the inputs of a batch back-to-back.
+    fn __afl_persistent_loop(max_cnt: u32) -> i32;
+    /// Attach the shared memory testcase and the batch results.
+    #[cfg(test)]
+    fn __afl_batch_attach(fuzz: *mut u8, batch: *mut u8);
+    /// The current input of the persistent loop.
+    #[cfg(test)]
+    static __afl_fuzz_ptr: *const u8;
+    /// The length of the current input of the persistent loop.
+    #[cfg(test)]
+    static __afl_fuzz_len: *const u32;
 }
 
 /// Map a shared memory region for the edge coverage map.
@@ -24,3 +35,77 @@ pub fn map_shared_memory() {
 pub fn start_forkserver() {
     unsafe { __afl_start_forkserver() }
 }
+
+/// The persistent loop: returns `true` while there is another input to run, in the style of
+/// `__AFL_LOOP`. Read the input from `__afl_fuzz_ptr` and `__afl_fuzz_len` in every iteration.
+///
+/// When the fuzzer requests batched execution, each iteration runs the next input of the batch,
+/// and its coverage map is saved for the fuzzer. After `max_cnt` batches, or single inputs, the
+/// loop ends so that the forkserver forks a fresh child.
+///
+/// # Note
+///
+/// The loop logic is written in C and this code is a wrapper.
+#[must_use]
+pub fn persistent_loop(max_cnt: u32) -> bool {
+    unsafe { __afl_persistent_loop(max_cnt) != 0 }
+}
+
+#[cfg(all(test, feature = "coverage"))]
+mod tests {
+    use alloc::{vec, vec::Vec};
+    use core::slice;
+
+    use super::{__afl_batch_attach, __afl_fuzz_len, __afl_fuzz_ptr, persistent_loop};
+    use crate::coverage::{__afl_map_size, EDGES_MAP_PTR};
+
+    #[test]
+    fn test_persistent_loop_batch() {
+        const MAP_SIZE: usize = 4;
+        let inputs: [&[u8]; 3] = [&[1, 1], &[2], &[3, 3, 3]];
+
+        // The total length, the number of inputs, their lengths, then their contents
+        let mut fuzz = vec![0_u8; 4];
+        fuzz.extend_from_slice(&(inputs.len() as u32).to_ne_bytes());
+        for input in inputs {
+            fuzz.extend_from_slice(&(input.len() as u32).to_ne_bytes());
+        }
+        for input in inputs {
+            fuzz.extend_from_slice(input);
+        }
+        let total = (fuzz.len() - 4) as u32;
+        fuzz[..4].copy_from_slice(&total.to_ne_bytes());
+
+        // The finished inputs, the stride, and the batch flag, then the coverage of each input
+        let mut batch = vec![0_u8; 12 + inputs.len() * MAP_SIZE];
+        batch[4..8].copy_from_slice(&(MAP_SIZE as u32).to_ne_bytes());
+        batch[8..12].copy_from_slice(&1_u32.to_ne_bytes());
+
+        let mut area = [0_u8; MAP_SIZE];
+        let mut seen = Vec::new();
+        unsafe {
+            let (area_ptr, map_size) = (EDGES_MAP_PTR, __afl_map_size);
+            EDGES_MAP_PTR = area.as_mut_ptr();
+            __afl_map_size = MAP_SIZE;
+            __afl_batch_attach(fuzz.as_mut_ptr(), batch.as_mut_ptr());
+
+            while persistent_loop(1) {
+                let input = slice::from_raw_parts(__afl_fuzz_ptr, *__afl_fuzz_len as usize);
+                // Each input covers the entry of its first byte
+                *EDGES_MAP_PTR.add(input[0] as usize) += 1;
+                seen.push(input.to_vec());
+            }
+
+            EDGES_MAP_PTR = area_ptr;
+            __afl_map_size = map_size;
+        }
+
+        assert_eq!(seen, inputs);
+        assert_eq!(batch[..4], 3_u32.to_ne_bytes());
+        assert_eq!(batch[12..16], [0, 1, 0, 0]);
+        assert_eq!(batch[16..20], [0, 0, 1, 0]);
+        assert_eq!(batch[20..24], [0, 0, 0, 1]);
+        // The last input keeps its coverage in the regular map
+        assert_eq!(area, [0, 0, 0, 1]);
+    }
+}
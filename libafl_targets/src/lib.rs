@@ -127,6 +127,11 @@ pub mod windows_asan;
 #[cfg(all(windows, feature = "std", feature = "windows_asan"))]
 pub use windows_asan::*;
 
+#[cfg(all(target_os = "linux", feature = "libc_faults"))]
+pub mod libc_faults;
+#[cfg(all(target_os = "linux", feature = "libc_faults"))]
+pub use libc_faults::*;
+
 #[cfg(all(unix, feature = "forkserver"))]
 pub mod forkserver;
 #[cfg(all(unix, feature = "forkserver"))]
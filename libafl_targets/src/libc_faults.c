@@ -0,0 +1,247 @@
+// Fault injection into libc functions for in-process fuzzing.
+//
+// The functions below interpose the libc functions of the same name. While injection is
+// enabled, each call is counted per function, and the calls scheduled in `libafl_libc_faults`
+// fail with the scheduled errno, or return a short read. The schedule is set by `LibcFaultHook`
+// from the input before each run.
+//
+// As in desyscall, the interposed syscall wrappers issue the raw syscalls themselves. The other
+// functions are resolved like the libFuzzer interceptors do.
+
+#ifndef _GNU_SOURCE
+  #define _GNU_SOURCE
+#endif
+
+#include "common.h"
+
+#if defined(__linux__)
+
+  #include <dlfcn.h>
+  #include <errno.h>
+  #include <fcntl.h>
+  #include <stdarg.h>
+  #include <stdio.h>
+  #include <stdlib.h>
+  #include <sys/syscall.h>
+  #include <sys/types.h>
+  #include <unistd.h>
+
+  // Keep in sync with `LibcFunction` in libc_faults.rs
+  #define LIBAFL_LIBC_MALLOC 0
+  #define LIBAFL_LIBC_CALLOC 1
+  #define LIBAFL_LIBC_REALLOC 2
+  #define LIBAFL_LIBC_OPEN 3
+  #define LIBAFL_LIBC_READ 4
+  #define LIBAFL_LIBC_WRITE 5
+  #define LIBAFL_LIBC_FOPEN 6
+  #define LIBAFL_LIBC_FUNCTIONS 7
+
+  #define LIBAFL_MAX_LIBC_FAULTS 16
+  #define LIBAFL_LIBC_FAULT_MAP_NTH 64
+
+// A fault with an `err` of 0 is a short read of at most `max_len` bytes
+struct libafl_libc_fault {
+  uint32_t function;
+  uint32_t nth;
+  int32_t  err;
+  uint64_t max_len;
+};
+
+uint8_t                  libafl_libc_faults_enabled;
+struct libafl_libc_fault libafl_libc_faults[LIBAFL_MAX_LIBC_FAULTS];
+size_t                   libafl_libc_faults_len;
+// The number of calls per function in this run
+uint32_t libafl_libc_calls[LIBAFL_LIBC_FUNCTIONS];
+// One entry per function and call (capped), set when a fault was injected there
+uint8_t libafl_libc_fault_map[LIBAFL_LIBC_FUNCTIONS * LIBAFL_LIBC_FAULT_MAP_NTH];
+
+// Whether a crash, timeout or panic of the target is being handled, in libc_faults.rs
+extern uint8_t libafl_libc_faults_in_handler(void);
+
+static void libafl_libc_fault_hit(uint32_t function, uint32_t nth) {
+  uint32_t idx =
+      nth < LIBAFL_LIBC_FAULT_MAP_NTH ? nth : LIBAFL_LIBC_FAULT_MAP_NTH - 1;
+  libafl_libc_fault_map[function * LIBAFL_LIBC_FAULT_MAP_NTH + idx] = 1;
+}
+
+// Counts the call, and returns the fault scheduled for it, or NULL
+static struct libafl_libc_fault *libafl_libc_fault(uint32_t function) {
+  if (!libafl_libc_faults_enabled) { return NULL; }
+
+  // The handlers of the fuzzer run with the libc functions of the target
+  if (libafl_libc_faults_in_handler()) {
+    libafl_libc_faults_enabled = 0;
+    return NULL;
+  }
+
+  uint32_t nth = libafl_libc_calls[function]++;
+  for (size_t i = 0; i < libafl_libc_faults_len; i++) {
+    if (libafl_libc_faults[i].function == function &&
+        libafl_libc_faults[i].nth == nth) {
+      return &libafl_libc_faults[i];
+    }
+  }
+  return NULL;
+}
+
+// Returns the errno to fail the call with, or 0. Short reads do not apply.
+static int32_t libafl_libc_fault_errno(uint32_t function) {
+  struct libafl_libc_fault *fault = libafl_libc_fault(function);
+  if (!fault || !fault->err) { return 0; }
+  libafl_libc_fault_hit(function, fault->nth);
+  return fault->err;
+}
+
+// Returns the errno to fail the read with, or 0, and caps `count` for a
+// short read
+static int32_t libafl_libc_fault_read(size_t *count) {
+  struct libafl_libc_fault *fault = libafl_libc_fault(LIBAFL_LIBC_READ);
+  if (!fault) { return 0; }
+  if (!fault->err && fault->max_len >= *count) { return 0; }
+  libafl_libc_fault_hit(LIBAFL_LIBC_READ, fault->nth);
+  if (!fault->err) { *count = fault->max_len; }
+  return fault->err;
+}
+
+  #if defined(__GLIBC__)
+
+// The allocator must not be looked up with dlsym, which allocates itself.
+extern void *__libc_malloc(size_t size);
+extern void *__libc_calloc(size_t nmemb, size_t size);
+extern void *__libc_realloc(void *ptr, size_t size);
+
+void *malloc(size_t size) {
+  int32_t err = libafl_libc_fault_errno(LIBAFL_LIBC_MALLOC);
+  if (err) {
+    errno = err;
+    return NULL;
+  }
+  return __libc_malloc(size);
+}
+
+void *calloc(size_t nmemb, size_t size) {
+  int32_t err = libafl_libc_fault_errno(LIBAFL_LIBC_CALLOC);
+  if (err) {
+    errno = err;
+    return NULL;
+  }
+  return __libc_calloc(nmemb, size);
+}
+
+void *realloc(void *ptr, size_t size) {
+  int32_t err = libafl_libc_fault_errno(LIBAFL_LIBC_REALLOC);
+  if (err) {
+    errno = err;
+    return NULL;
+  }
+  return __libc_realloc(ptr, size);
+}
+
+  #endif
+
+// Resolves the next definition of `name`, or the first one if a sanitizer
+// runtime defining it comes later in the search order, as the libFuzzer
+// interceptors do
+static void *libafl_libc_real(const char *name, void *wrapper) {
+  void *addr = dlsym(RTLD_NEXT, name);
+  if (!addr) {
+    addr = dlsym(RTLD_DEFAULT, name);
+    if (addr == wrapper) { addr = NULL; }
+  }
+  return addr;
+}
+
+// Whether `open` gets a mode, see `__OPEN_NEEDS_MODE` in glibc
+static int libafl_open_needs_mode(int flags) {
+  return (flags & O_CREAT) || (flags & O_TMPFILE) == O_TMPFILE;
+}
+
+static int libafl_openat(int dirfd, const char *path, int flags, mode_t mode) {
+  int32_t err = libafl_libc_fault_errno(LIBAFL_LIBC_OPEN);
+  if (err) {
+    errno = err;
+    return -1;
+  }
+  return syscall(SYS_openat, dirfd, path, flags, mode);
+}
+
+int open(const char *path, int flags, ...) {
+  mode_t mode = 0;
+  if (libafl_open_needs_mode(flags)) {
+    va_list args;
+    va_start(args, flags);
+    mode = va_arg(args, mode_t);
+    va_end(args);
+  }
+  return libafl_openat(AT_FDCWD, path, flags, mode);
+}
+
+int open64(const char *path, int flags, ...) {
+  mode_t mode = 0;
+  if (libafl_open_needs_mode(flags)) {
+    va_list args;
+    va_start(args, flags);
+    mode = va_arg(args, mode_t);
+    va_end(args);
+  }
+  return libafl_openat(AT_FDCWD, path, flags | O_LARGEFILE, mode);
+}
+
+int openat(int dirfd, const char *path, int flags, ...) {
+  mode_t mode = 0;
+  if (libafl_open_needs_mode(flags)) {
+    va_list args;
+    va_start(args, flags);
+    mode = va_arg(args, mode_t);
+    va_end(args);
+  }
+  return libafl_openat(dirfd, path, flags, mode);
+}
+
+ssize_t read(int fd, void *buf, size_t count) {
+  int32_t err = libafl_libc_fault_read(&count);
+  if (err) {
+    errno = err;
+    return -1;
+  }
+  return syscall(SYS_read, fd, buf, count);
+}
+
+ssize_t pread(int fd, void *buf, size_t count, off_t offset) {
+  int32_t err = libafl_libc_fault_read(&count);
+  if (err) {
+    errno = err;
+    return -1;
+  }
+  return syscall(SYS_pread64, fd, buf, count, offset);
+}
+
+ssize_t write(int fd, const void *buf, size_t count) {
+  int32_t err = libafl_libc_fault_errno(LIBAFL_LIBC_WRITE);
+  if (err) {
+    errno = err;
+    return -1;
+  }
+  return syscall(SYS_write, fd, buf, count);
+}
+
+FILE *fopen(const char *path, const char *mode) {
+  static FILE *(*real_fopen)(const char *, const char *);
+  if (!real_fopen) {
+    real_fopen = libafl_libc_real("fopen", (void *)fopen);
+  }
+
+  int32_t err = libafl_libc_fault_errno(LIBAFL_LIBC_FOPEN);
+  if (err) {
+    errno = err;
+    return NULL;
+  }
+  if (!real_fopen) {
+    // No libc defines `fopen` after us
+    errno = ENOSYS;
+    return NULL;
+  }
+  return real_fopen(path, mode);
+}
+
+#endif
@@ -0,0 +1,408 @@
+//! Fault injection into libc functions for in-process fuzzing, making error handling paths
+//! (`malloc` failing, `open` failing, `read` returning an error or a short read) reachable.
+//!
+//! Each testcase carries a schedule of [`LibcFault`]s in a [`LibcFaultInput`]: which call of
+//! which [`LibcFunction`] fails, and how. The [`LibcFaultHook`] installs the schedule of the input
+//! before each run, and the libc functions interposed by this runtime fail accordingly. The
+//! [`LibcFaultMutator`] mutates the schedule, guided by the number of calls of each function the
+//! [`LibcFaultFeedback`] recorded for the testcase. Coverage reached only with an injected fault
+//! is rewarded by the usual edges feedback; [`libc_fault_map_observer`] additionally rewards
+//! faults injected at new call sites.
+//!
+//! The schedule, the mutator and the call counts are shared with the syscall faults of the
+//! `PtraceExecutor`, see [`libafl::inputs::FaultInput`] and [`libafl::mutators::FaultMutator`].
+//!
+//! Injection stops as soon as a crash, timeout or panic of the target is handled, so that the
+//! objectives run with a working libc. The allocator functions are only interposed on glibc, and
+//! clash with sanitizers interposing them, e.g. `ASan`.
+
+use alloc::borrow::Cow;
+use core::marker::PhantomData;
+
+use libafl::{
+    Error, HasMetadata,
+    corpus::Testcase,
+    executors::{ExitKind, hooks::ExecutorHook, hooks::inprocess::current_handler_data},
+    feedbacks::{Feedback, StateInitializer},
+    inputs::{Fault, FaultInput, FaultKind, FaultTarget, HasFaults},
+    mutators::{FaultCallsMetadata, FaultMutator},
+    observers::StdMapObserver,
+};
+use libafl_bolts::Named;
+use serde::{Deserialize, Serialize};
+
+/// The number of calls per function the [`libc_fault_map_observer`] tells apart
+pub const LIBC_FAULT_MAP_NTH: usize = 64;
+
+/// The number of interposed functions
+pub const LIBC_FUNCTIONS: usize = 7;
+
+/// The maximum number of faults injected in one run, further faults of the input are ignored
+pub const MAX_LIBC_FAULTS: usize = 16;
+
+/// The size of the map of the [`libc_fault_map_observer`]
+pub const LIBC_FAULT_MAP_SIZE: usize = LIBC_FUNCTIONS * LIBC_FAULT_MAP_NTH;
+
+/// A fault with an `err` of 0 is a short read of at most `max_len` bytes
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RawLibcFault {
+    function: u32,
+    nth: u32,
+    err: i32,
+    max_len: u64,
+}
+
+unsafe extern "C" {
+    static mut libafl_libc_faults_enabled: u8;
+    static mut libafl_libc_faults: [RawLibcFault; MAX_LIBC_FAULTS];
+    static mut libafl_libc_faults_len: usize;
+    static mut libafl_libc_calls: [u32; LIBC_FUNCTIONS];
+    static mut libafl_libc_fault_map: [u8; LIBC_FAULT_MAP_SIZE];
+}
+
+/// Called by the runtime before injecting a fault. While the executor handles a crash, timeout or
+/// panic of the target, the runtime disables injection instead.
+#[unsafe(no_mangle)]
+extern "C" fn libafl_libc_faults_in_handler() -> u8 {
+    // # Safety
+    // The handler data is only written by the executor of this thread
+    u8::from(unsafe { (*current_handler_data()).in_signal_handler() })
+}
+
+/// The libc functions faults can be injected into
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LibcFunction {
+    /// `malloc`, glibc only
+    Malloc = 0,
+    /// `calloc`, glibc only
+    Calloc = 1,
+    /// `realloc`, glibc only
+    Realloc = 2,
+    /// `open`, `open64` and `openat`
+    Open = 3,
+    /// `read` and `pread`
+    Read = 4,
+    /// `write`
+    Write = 5,
+    /// `fopen`
+    Fopen = 6,
+}
+
+impl LibcFunction {
+    /// All functions faults can be injected into
+    pub const ALL: [Self; LIBC_FUNCTIONS] = [
+        Self::Malloc,
+        Self::Calloc,
+        Self::Realloc,
+        Self::Open,
+        Self::Read,
+        Self::Write,
+        Self::Fopen,
+    ];
+}
+
+impl FaultTarget for LibcFunction {
+    fn id(self) -> u64 {
+        self as u64
+    }
+
+    fn errnos(self) -> &'static [i32] {
+        match self {
+            Self::Malloc | Self::Calloc | Self::Realloc => &[libc::ENOMEM],
+            Self::Open | Self::Fopen => &[libc::ENOENT, libc::EACCES, libc::EMFILE],
+            Self::Read | Self::Write => &[libc::EINTR, libc::EIO, libc::EAGAIN],
+        }
+    }
+
+    fn reads(self) -> bool {
+        self == Self::Read
+    }
+}
+
+/// A failure of one call of a libc function
+pub type LibcFault = Fault<LibcFunction>;
+
+/// An input extended by a schedule of [`LibcFault`]s
+pub type LibcFaultInput<I> = FaultInput<I, LibcFunction>;
+
+/// Adds, removes and changes the [`LibcFault`]s of an input, see [`libc_fault_mutator`]
+pub type LibcFaultMutator = FaultMutator<LibcFunction>;
+
+/// Creates a new [`LibcFaultMutator`] for all interposed functions.
+///
+/// New faults target calls that happened for the current testcase, according to the
+/// [`FaultCallsMetadata`] of the [`LibcFaultFeedback`]. Without metadata, any of the first
+/// [`LIBC_FAULT_MAP_NTH`] calls of any function is picked.
+#[must_use]
+pub fn libc_fault_mutator() -> LibcFaultMutator {
+    FaultMutator::new(LibcFunction::ALL.to_vec(), vec![])
+        .with_max_faults(MAX_LIBC_FAULTS)
+        .with_max_nth(LIBC_FAULT_MAP_NTH as u32)
+}
+
+/// The number of calls of each [`LibcFunction`] in the last run
+#[must_use]
+pub fn libc_calls() -> [u32; LIBC_FUNCTIONS] {
+    unsafe { (&raw const libafl_libc_calls).read() }
+}
+
+/// Gets a new [`StdMapObserver`] over the call sites faults were injected at, one entry for each
+/// function and call (calls above [`LIBC_FAULT_MAP_NTH`] share the last entry).
+///
+/// # Safety
+/// The map is global, only one observer may be created.
+#[must_use]
+pub unsafe fn libc_fault_map_observer<S>(name: S) -> StdMapObserver<'static, u8, false>
+where
+    S: Into<Cow<'static, str>>,
+{
+    unsafe {
+        StdMapObserver::from_mut_ptr(
+            name,
+            (&raw mut libafl_libc_fault_map).cast(),
+            LIBC_FAULT_MAP_SIZE,
+        )
+    }
+}
+
+/// The hook installing the [`LibcFault`] schedule of the input for the run.
+///
+/// Faults are only injected between the hooks, so the fuzzer itself is not affected.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LibcFaultHook<I, S> {
+    phantom: PhantomData<(I, S)>,
+}
+
+impl<I, S> LibcFaultHook<I, S> {
+    /// The constructor
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S> ExecutorHook<I, S> for LibcFaultHook<I, S>
+where
+    I: HasFaults<LibcFunction>,
+{
+    fn init(&mut self, _state: &mut S) {}
+
+    fn pre_exec(&mut self, _state: &mut S, input: &I) {
+        let faults = input.faults();
+        let len = faults.len().min(MAX_LIBC_FAULTS);
+        // # Safety
+        // The runtime only reads these while injection is enabled, which happens on this thread
+        unsafe {
+            let raw_faults = (&raw mut libafl_libc_faults).cast::<RawLibcFault>();
+            for (i, fault) in faults[..len].iter().enumerate() {
+                let (err, max_len) = match fault.kind {
+                    FaultKind::Errno(errno) => (errno, 0),
+                    FaultKind::ShortRead(max_len) => (0, max_len),
+                };
+                raw_faults.add(i).write(RawLibcFault {
+                    function: fault.target as u32,
+                    nth: fault.nth,
+                    err,
+                    max_len,
+                });
+            }
+            libafl_libc_faults_len = len;
+            (&raw mut libafl_libc_calls).write([0; LIBC_FUNCTIONS]);
+            (&raw mut libafl_libc_fault_map).write([0; LIBC_FAULT_MAP_SIZE]);
+            libafl_libc_faults_enabled = 1;
+        }
+    }
+
+    fn post_exec(&mut self, _state: &mut S, _input: &I) {
+        unsafe {
+            libafl_libc_faults_enabled = 0;
+        }
+    }
+}
+
+/// A feedback recording the calls of the interposed libc functions of new testcases as
+/// [`FaultCallsMetadata`]. It never considers a run interesting on its own.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LibcFaultFeedback;
+
+impl LibcFaultFeedback {
+    /// Creates a new [`LibcFaultFeedback`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for LibcFaultFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("LibcFaultFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for LibcFaultFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for LibcFaultFeedback {
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let calls = libc_calls();
+        testcase.add_metadata(FaultCallsMetadata {
+            calls: LibcFunction::ALL
+                .iter()
+                .map(|function| (function.id(), calls[*function as usize]))
+                .collect(),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::{
+        executors::hooks::ExecutorHook,
+        inputs::{BytesInput, FaultKind, FaultTarget, HasFaults},
+        state::NopState,
+    };
+
+    use super::{
+        LIBC_FAULT_MAP_NTH, LibcFault, LibcFaultHook, LibcFaultInput, LibcFunction,
+        libafl_libc_fault_map, libafl_libc_faults_in_handler, libc_calls,
+    };
+
+    type TestInput = LibcFaultInput<BytesInput>;
+
+    #[test]
+    fn test_libc_function() {
+        assert_eq!(LibcFunction::Fopen.id(), 6);
+        assert!(LibcFunction::Read.reads());
+        assert!(!LibcFunction::Write.reads());
+        assert_eq!(LibcFunction::Malloc.errnos(), [libc::ENOMEM]);
+        assert_eq!(libafl_libc_faults_in_handler(), 0);
+    }
+
+    /// Injects the faults of `input` and returns, in this order, the results of the calls (`fd`,
+    /// `failed`, `errno`, `short`, `full`), the calls of `open` and `read`, the sum of their fault
+    /// map entries and the number of fault map entries hit.
+    fn run_libc_faults(
+        hook: &mut LibcFaultHook<TestInput, NopState<TestInput>>,
+        state: &mut NopState<TestInput>,
+        input: &TestInput,
+    ) -> [i64; 9] {
+        let mut buf = [0_u8; 16];
+        hook.pre_exec(state, input);
+        let (fd, failed, errno, short, full) = unsafe {
+            let fd = libc::open(c"/dev/zero".as_ptr(), libc::O_RDONLY);
+            let failed = libc::open(c"/dev/zero".as_ptr(), libc::O_RDONLY);
+            let errno = *libc::__errno_location();
+            let short = libc::read(fd, buf.as_mut_ptr().cast(), buf.len());
+            let full = libc::read(fd, buf.as_mut_ptr().cast(), buf.len());
+            (fd, failed, errno, short, full)
+        };
+        hook.post_exec(state, input);
+        let calls = libc_calls();
+        let map = unsafe { (&raw const libafl_libc_fault_map).read() };
+        unsafe { libc::close(fd) };
+
+        [
+            fd.into(),
+            failed.into(),
+            errno.into(),
+            short as i64,
+            full as i64,
+            calls[LibcFunction::Open as usize].into(),
+            calls[LibcFunction::Read as usize].into(),
+            (map[LibcFunction::Open as usize * LIBC_FAULT_MAP_NTH + 1]
+                + map[LibcFunction::Read as usize * LIBC_FAULT_MAP_NTH])
+                .into(),
+            i64::try_from(map.iter().filter(|entry| **entry != 0).count()).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_libc_faults() {
+        // The interposition counts the calls of the whole process, so the faults are injected in
+        // a child, where no other test runs concurrently.
+        let mut input = LibcFaultInput::new(BytesInput::new(vec![]));
+        input.faults_mut().push(LibcFault::new(
+            LibcFunction::Open,
+            1,
+            FaultKind::Errno(libc::EMFILE),
+        ));
+        input.faults_mut().push(LibcFault::new(
+            LibcFunction::Read,
+            0,
+            FaultKind::ShortRead(4),
+        ));
+        let mut hook = LibcFaultHook::new();
+        let mut state = NopState::new();
+
+        let mut pipe = [0; 2];
+        assert_eq!(unsafe { libc::pipe(pipe.as_mut_ptr()) }, 0);
+        let mut results = [0_i64; 9];
+        unsafe {
+            let pid = libc::fork();
+            assert!(pid >= 0);
+            if pid == 0 {
+                let results = run_libc_faults(&mut hook, &mut state, &input);
+                libc::write(pipe[1], results.as_ptr().cast(), size_of_val(&results));
+                libc::_exit(0);
+            }
+            libc::close(pipe[1]);
+            let len = libc::read(pipe[0], results.as_mut_ptr().cast(), size_of_val(&results));
+            assert_eq!(usize::try_from(len), Ok(size_of_val(&results)));
+            libc::close(pipe[0]);
+            libc::waitpid(pid, core::ptr::null_mut(), 0);
+        }
+        let [fd, failed, errno, short, full, opens, reads, faulted, hit] = results;
+
+        // The second open fails, the first read is short
+        assert!(fd >= 0);
+        assert_eq!((failed, errno), (-1, libc::EMFILE.into()));
+        assert_eq!((short, full), (4, 16));
+        assert_eq!((opens, reads), (2, 2));
+        assert_eq!((faulted, hit), (2, 2));
+    }
+
+    #[test]
+    fn test_open_tmpfile_mode() {
+        let dir =
+            std::ffi::CString::new(std::env::temp_dir().into_os_string().into_encoded_bytes())
+                .unwrap();
+        let fd = unsafe { libc::open(dir.as_ptr(), libc::O_TMPFILE | libc::O_RDWR, 0o600) };
+        if fd < 0 {
+            // The file system does not support `O_TMPFILE`
+            return;
+        }
+        let mut stat = unsafe { core::mem::zeroed::<libc::stat>() };
+        unsafe {
+            assert_eq!(libc::fstat(fd, &raw mut stat), 0);
+            libc::close(fd);
+        }
+        assert_eq!(stat.st_mode & 0o777, 0o600);
+    }
+}
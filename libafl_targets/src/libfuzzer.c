@@ -117,4 +117,28 @@ EXPORT_FN size_t libafl_check_malloc_size(void *ptr) {
 #else
   return 0;
 #endif
-}
\ No newline at end of file
+}
+
+// Identifies the allocation site of the allocation currently being hooked, by
+// hashing the return addresses of the innermost `depth` frames. The frames of
+// the sanitizer allocator are the same for all allocations through the same
+// function, so `depth` must reach past them into the caller.
+EXPORT_FN size_t libafl_alloc_site(size_t depth) {
+#if defined(__GNUC__) && !defined(_WIN32)
+  uintptr_t *fp = (uintptr_t *)__builtin_frame_address(0);
+  size_t     hash = 0;
+  for (size_t i = 0; i < depth && fp && ((uintptr_t)fp & 7) == 0; i++) {
+    uintptr_t ret = fp[1];
+    if (!ret) { break; }
+    hash = (hash ^ ret) * 0x100000001b3ULL;
+    uintptr_t *next = (uintptr_t *)fp[0];
+    // The stack grows down, give up on chains not pointing to callers
+    if (next <= fp || (uintptr_t)next - (uintptr_t)fp > (1 << 20)) { break; }
+    fp = next;
+  }
+  return hash;
+#else
+  (void)depth;
+  return 0;
+#endif
+}
@@ -0,0 +1,507 @@
+//! Heap usage of the target, for memory-exhaustion fuzzing.
+//!
+//! The malloc hooks of the [`super::oom`] module report each allocation of the target to this
+//! module while a [`HeapObserver`] is active. It tracks the peak of the live heap, the total of all
+//! allocations and the bytes allocated by each allocation site. The [`HeapFeedback`] finds inputs
+//! raising the peak or the allocations of any site to a new maximum, the
+//! [`AllocationAmplificationFeedback`] is an objective for inputs allocating far more bytes than
+//! they are long.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use hashbrown::HashMap;
+use libafl::{
+    Error, HasMetadata,
+    corpus::Testcase,
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle, StateInitializer},
+    observers::Observer,
+};
+use libafl_bolts::{
+    HasLen, Named, impl_serdeany,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+unsafe extern "C" {
+    fn libafl_alloc_site(depth: usize) -> usize;
+}
+
+/// The number of allocation sites tracked per execution
+const SITE_SLOTS: usize = 1 << 12;
+/// The number of slots probed for a site, before its allocations are dropped
+const SITE_PROBES: usize = 16;
+
+static TRACKING: AtomicBool = AtomicBool::new(false);
+static SITE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static TOTAL: AtomicUsize = AtomicUsize::new(0);
+
+// The hooks run inside the allocator and must not allocate, the sites live in a fixed table
+static SITE_KEYS: [AtomicUsize; SITE_SLOTS] = [const { AtomicUsize::new(0) }; SITE_SLOTS];
+static SITE_BYTES: [AtomicUsize; SITE_SLOTS] = [const { AtomicUsize::new(0) }; SITE_SLOTS];
+
+/// Whether a [`HeapObserver`] tracks the current execution
+pub(crate) fn tracking() -> bool {
+    TRACKING.load(Ordering::Relaxed)
+}
+
+/// Records an allocation of `size` bytes, called from the malloc hook
+pub(crate) fn record_malloc(size: usize) {
+    let live = LIVE.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(live, Ordering::Relaxed);
+    TOTAL.fetch_add(size, Ordering::Relaxed);
+
+    let depth = SITE_DEPTH.load(Ordering::Relaxed);
+    if depth == 0 {
+        return;
+    }
+    let site = unsafe { libafl_alloc_site(depth) };
+    if site == 0 {
+        return;
+    }
+    let start = site % SITE_SLOTS;
+    for probe in 0..SITE_PROBES {
+        let slot = (start + probe) % SITE_SLOTS;
+        match SITE_KEYS[slot].compare_exchange(0, site, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => {}
+            Err(key) if key == site => {}
+            Err(_) => continue,
+        }
+        SITE_BYTES[slot].fetch_add(size, Ordering::Relaxed);
+        return;
+    }
+}
+
+/// Records a free of `size` bytes, called from the free hook
+pub(crate) fn record_free(size: usize) {
+    LIVE.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |live| {
+        Some(live.saturating_sub(size))
+    })
+    .expect("must complete successfully");
+}
+
+static HEAP_OBS_NAME: Cow<'static, str> = Cow::Borrowed("libfuzzer-like-heap");
+
+/// Observer of the heap usage of the target in each execution, through the malloc hooks of the
+/// sanitizers. Allocation sites are identified by the return addresses of the innermost frames of
+/// the allocation, which needs a target built with frame pointers. The sites are code addresses,
+/// so they only stay the same across restarts of the fuzzer without address space randomization.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeapObserver {
+    peak: usize,
+    total: usize,
+    sites: Vec<(usize, usize)>,
+}
+
+impl HeapObserver {
+    /// Create a [`HeapObserver`] identifying allocation sites by the innermost `site_depth`
+    /// frames of each allocation, which must reach past the frames of the sanitizer allocator.
+    /// A `site_depth` of `0` only tracks the peak and the total heap usage.
+    #[must_use]
+    pub fn new(site_depth: usize) -> Self {
+        SITE_DEPTH.store(site_depth, Ordering::Relaxed);
+        Self {
+            peak: 0,
+            total: 0,
+            sites: Vec::new(),
+        }
+    }
+
+    /// The peak of the live heap in the last execution, in bytes
+    #[must_use]
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    /// The bytes allocated in the last execution, freed or not
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// The bytes allocated by each allocation site in the last execution, sorted by site
+    #[must_use]
+    pub fn sites(&self) -> &[(usize, usize)] {
+        &self.sites
+    }
+}
+
+impl Named for HeapObserver {
+    // strictly one name, the usage is tracked globally
+    fn name(&self) -> &Cow<'static, str> {
+        &HEAP_OBS_NAME
+    }
+}
+
+impl<I, S> Observer<I, S> for HeapObserver {
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        LIVE.store(0, Ordering::Relaxed);
+        PEAK.store(0, Ordering::Relaxed);
+        TOTAL.store(0, Ordering::Relaxed);
+        if SITE_DEPTH.load(Ordering::Relaxed) != 0 {
+            for (key, bytes) in SITE_KEYS.iter().zip(&SITE_BYTES) {
+                key.store(0, Ordering::Relaxed);
+                bytes.store(0, Ordering::Relaxed);
+            }
+        }
+        TRACKING.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _input: &I,
+        _exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        TRACKING.store(false, Ordering::Relaxed);
+        self.peak = PEAK.load(Ordering::Relaxed);
+        self.total = TOTAL.load(Ordering::Relaxed);
+        self.sites.clear();
+        if SITE_DEPTH.load(Ordering::Relaxed) != 0 {
+            self.sites.extend(
+                SITE_KEYS
+                    .iter()
+                    .zip(&SITE_BYTES)
+                    .map(|(key, bytes)| {
+                        (key.load(Ordering::Relaxed), bytes.load(Ordering::Relaxed))
+                    })
+                    .filter(|(site, _)| *site != 0),
+            );
+            self.sites.sort_unstable();
+        }
+        Ok(())
+    }
+
+    fn pre_exec_child(&mut self, state: &mut S, input: &I) -> Result<(), Error> {
+        self.pre_exec(state, input)
+    }
+
+    fn post_exec_child(
+        &mut self,
+        state: &mut S,
+        input: &I,
+        exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.post_exec(state, input, exit_kind)
+    }
+}
+
+/// The heap usage of the execution of a testcase
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HeapUsageMetadata {
+    /// The peak of the live heap, in bytes
+    pub peak: usize,
+    /// The bytes allocated, freed or not
+    pub total: usize,
+}
+
+impl_serdeany!(HeapUsageMetadata);
+
+/// The maximal heap usage seen so far, in the state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HeapMaximaMetadata {
+    /// The maximal peak of the live heap, in bytes
+    pub peak: usize,
+    /// The maximal bytes allocated by each allocation site
+    pub sites: HashMap<usize, usize>,
+}
+
+impl_serdeany!(HeapMaximaMetadata);
+
+/// The power of two bucket of a byte count, so only growing it by about double is new
+#[inline]
+fn size_bucket(bytes: usize) -> u32 {
+    usize::BITS - bytes.leading_zeros()
+}
+
+/// Feedback for the [`HeapObserver`], considering an execution interesting if it raises the peak
+/// heap usage or the bytes allocated by any allocation site to a new maximum. Sizes are compared by
+/// their power of two bucket, so inputs allocating a few more bytes each do not flood the corpus.
+#[derive(Debug)]
+pub struct HeapFeedback {
+    observer_handle: Handle<HeapObserver>,
+    new_peak: Option<usize>,
+    new_sites: Vec<(usize, usize)>,
+}
+
+impl HeapFeedback {
+    /// Create a [`HeapFeedback`] for the `observer`
+    #[must_use]
+    pub fn new(observer: &HeapObserver) -> Self {
+        Self {
+            observer_handle: observer.handle(),
+            new_peak: None,
+            new_sites: Vec::new(),
+        }
+    }
+}
+
+impl Named for HeapFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("heap");
+        &NAME
+    }
+}
+
+impl HasObserverHandle for HeapFeedback {
+    type Observer = HeapObserver;
+
+    fn observer_handle(&self) -> &Handle<HeapObserver> {
+        &self.observer_handle
+    }
+}
+
+impl<S> StateInitializer<S> for HeapFeedback
+where
+    S: HasMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.metadata_or_insert_with(HeapMaximaMetadata::default);
+        Ok(())
+    }
+}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for HeapFeedback
+where
+    OT: MatchName,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = observers
+            .get(&self.observer_handle)
+            .ok_or_else(|| Error::key_not_found("HeapObserver not found"))?;
+        let maxima = state.metadata_or_insert_with(HeapMaximaMetadata::default);
+
+        self.new_peak =
+            (size_bucket(observer.peak()) > size_bucket(maxima.peak)).then_some(observer.peak());
+        self.new_sites.clear();
+        self.new_sites
+            .extend(observer.sites().iter().filter(|(site, bytes)| {
+                maxima
+                    .sites
+                    .get(site)
+                    .is_none_or(|max| size_bucket(*bytes) > size_bucket(*max))
+            }));
+        Ok(self.new_peak.is_some() || !self.new_sites.is_empty())
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(self.new_peak.is_some() || !self.new_sites.is_empty())
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let observer = observers
+            .get(&self.observer_handle)
+            .ok_or_else(|| Error::key_not_found("HeapObserver not found"))?;
+        testcase.add_metadata(HeapUsageMetadata {
+            peak: observer.peak(),
+            total: observer.total(),
+        });
+
+        // The new maxima only count once the testcase is kept
+        let maxima = state.metadata_or_insert_with(HeapMaximaMetadata::default);
+        if let Some(peak) = self.new_peak.take() {
+            maxima.peak = maxima.peak.max(peak);
+        }
+        for (site, bytes) in self.new_sites.drain(..) {
+            let max = maxima.sites.entry(site).or_default();
+            *max = (*max).max(bytes);
+        }
+        Ok(())
+    }
+}
+
+/// Objective for the [`HeapObserver`], firing for executions allocating more than `max_ratio`
+/// bytes per input byte, to find memory-exhaustion denial of service bugs
+#[derive(Debug)]
+pub struct AllocationAmplificationFeedback {
+    observer_handle: Handle<HeapObserver>,
+    max_ratio: usize,
+    min_bytes: usize,
+    last_total: usize,
+    #[cfg(feature = "track_hit_feedbacks")]
+    last_result: Option<bool>,
+}
+
+impl AllocationAmplificationFeedback {
+    /// Create an [`AllocationAmplificationFeedback`] for the `observer`, firing for executions
+    /// allocating more than `max_ratio` bytes per input byte
+    #[must_use]
+    pub fn new(observer: &HeapObserver, max_ratio: usize) -> Self {
+        Self {
+            observer_handle: observer.handle(),
+            max_ratio,
+            min_bytes: 0,
+            last_total: 0,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+
+    /// Only fire for executions allocating at least `min_bytes` in total, so short inputs with
+    /// the fixed allocations of the target are not reported
+    #[must_use]
+    pub fn with_min_bytes(mut self, min_bytes: usize) -> Self {
+        self.min_bytes = min_bytes;
+        self
+    }
+}
+
+impl Named for AllocationAmplificationFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("allocation_amplification");
+        &NAME
+    }
+}
+
+impl HasObserverHandle for AllocationAmplificationFeedback {
+    type Observer = HeapObserver;
+
+    fn observer_handle(&self) -> &Handle<HeapObserver> {
+        &self.observer_handle
+    }
+}
+
+impl<S> StateInitializer<S> for AllocationAmplificationFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for AllocationAmplificationFeedback
+where
+    I: HasLen,
+    OT: MatchName,
+{
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = observers
+            .get(&self.observer_handle)
+            .ok_or_else(|| Error::key_not_found("HeapObserver not found"))?;
+        self.last_total = observer.total();
+        let res = self.last_total >= self.min_bytes
+            && self.last_total / input.len().max(1) > self.max_ratio;
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or_else(|| {
+            Error::illegal_state(
+                "No last result set in `AllocationAmplificationFeedback`. Either `is_interesting` has never been called or the fuzzer restarted in the meantime.",
+            )
+        })
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let peak = observers
+            .get(&self.observer_handle)
+            .map_or(0, HeapObserver::peak);
+        testcase.add_metadata(HeapUsageMetadata {
+            peak,
+            total: self.last_total,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::{
+        HasMetadata,
+        executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer},
+        inputs::BytesInput,
+        state::NopState,
+    };
+    use libafl_bolts::{serdeany::RegistryBuilder, tuples::tuple_list};
+
+    use super::{HeapFeedback, HeapMaximaMetadata, HeapObserver, HeapUsageMetadata, size_bucket};
+
+    #[test]
+    fn test_size_bucket() {
+        assert_eq!(size_bucket(0), 0);
+        assert_eq!(size_bucket(1), 1);
+        assert_eq!(size_bucket(1023), 10);
+        assert_eq!(size_bucket(1024), 11);
+        assert_eq!(size_bucket(usize::MAX), usize::BITS);
+    }
+
+    #[test]
+    fn test_heap_feedback() {
+        // # Safety
+        // The registry is not finalized in tests
+        unsafe {
+            RegistryBuilder::register::<HeapMaximaMetadata>();
+            RegistryBuilder::register::<HeapUsageMetadata>();
+        }
+
+        let mut observers = tuple_list!(HeapObserver::new(0));
+        let mut feedback = HeapFeedback::new(&observers.0);
+        let mut state = NopState::<BytesInput>::new();
+        let input = BytesInput::new(vec![]);
+        feedback.init_state(&mut state).unwrap();
+
+        let mut run = |peak: usize, sites: &[(usize, usize)]| {
+            observers.0.peak = peak;
+            observers.0.sites = sites.to_vec();
+            let interesting = feedback
+                .is_interesting(&mut state, &mut (), &input, &observers, &ExitKind::Ok)
+                .unwrap();
+            if interesting {
+                let mut testcase = input.clone().into();
+                feedback
+                    .append_metadata(&mut state, &mut (), &observers, &mut testcase)
+                    .unwrap();
+            }
+            interesting
+        };
+
+        assert!(run(100, &[(1, 100)]));
+        // Same buckets
+        assert!(!run(120, &[(1, 127)]));
+        // A new bucket for the peak, then for a site, then a new site
+        assert!(run(128, &[(1, 100)]));
+        assert!(run(100, &[(1, 200)]));
+        assert!(run(100, &[(1, 200), (2, 1)]));
+        assert!(!run(255, &[(1, 255), (2, 1)]));
+
+        let maxima = state.metadata::<HeapMaximaMetadata>().unwrap();
+        assert_eq!(maxima.peak, 128);
+        assert_eq!(maxima.sites[&1], 200);
+        assert_eq!(maxima.sites[&2], 1);
+    }
+}
@@ -3,3 +3,12 @@
 pub mod oom;
 #[cfg(feature = "libfuzzer_oom")]
 pub use oom::*;
+
+/// heap usage observer and feedbacks
+#[cfg(feature = "libfuzzer_oom")]
+pub mod heap;
+#[cfg(feature = "libfuzzer_oom")]
+pub use heap::{
+    AllocationAmplificationFeedback, HeapFeedback, HeapMaximaMetadata, HeapObserver,
+    HeapUsageMetadata,
+};
@@ -15,6 +15,8 @@ use libafl_bolts::Named;
 use libc::SIGABRT;
 use serde::{Deserialize, Serialize};
 
+use super::heap;
+
 unsafe extern "C" {
     fn libafl_check_malloc_size(ptr: *const c_void) -> usize;
 }
@@ -34,11 +36,19 @@ static MALLOC_SIZE: AtomicUsize = AtomicUsize::new(0);
 /// Is only safe to call with valid freshly allocated pointers backed by allocations of `size`.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn __sanitizer_malloc_hook(ptr: *const c_void, size: usize) {
-    if RUNNING.load(Ordering::Relaxed) {
+    let running = RUNNING.load(Ordering::Relaxed);
+    let tracking = heap::tracking();
+    if running || tracking {
         let size = match unsafe { libafl_check_malloc_size(ptr) } {
             0 => size, // either the malloc size function didn't work or it's really zero-sized
             real => real,
         };
+        if tracking {
+            heap::record_malloc(size);
+        }
+        if !running {
+            return;
+        }
 
         let total = MALLOC_SIZE.fetch_add(size, Ordering::Relaxed) + size;
         if (size > MALLOC_MAX.load(Ordering::Relaxed) || total > RSS_MAX.load(Ordering::Relaxed))
@@ -59,8 +69,16 @@ pub unsafe extern "C" fn __sanitizer_malloc_hook(ptr: *const c_void, size: usize
 /// Is only safe to call with valid allocated pointers, about to be freed.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn __sanitizer_free_hook(ptr: *const c_void) {
-    if RUNNING.load(Ordering::Relaxed) {
+    let running = RUNNING.load(Ordering::Relaxed);
+    let tracking = heap::tracking();
+    if running || tracking {
         let size = unsafe { libafl_check_malloc_size(ptr) };
+        if tracking {
+            heap::record_free(size);
+        }
+        if !running {
+            return;
+        }
         MALLOC_SIZE
             .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |existing| {
                 Some(existing.saturating_sub(size))
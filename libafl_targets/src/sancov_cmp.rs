@@ -6,7 +6,7 @@ use core::{
     ptr,
 };
 
-use crate::CMPLOG_MAP_W;
+use crate::{CMPLOG_MAP_W, cmps::CMPLOG_RTN_LONG_LEN};
 
 unsafe extern "C" {
 
@@ -55,7 +55,7 @@ pub unsafe extern "C" fn __sanitizer_weak_hook_memcmp(
                 k,
                 s1 as *const u8,
                 s2 as *const u8,
-                cmp::min(n, 32),
+                cmp::min(n, CMPLOG_RTN_LONG_LEN),
             );
         }
     }
@@ -223,6 +223,20 @@ pub unsafe extern "C" fn __sanitizer_cov_trace_pc_guard(guard: *mut u32) {
             // println!("Wrinting to {} {}", pos, EDGES_MAP_DEFAULT_SIZE);
         }
 
+        #[cfg(feature = "thread_local_maps")]
+        #[cfg(any(feature = "sancov_pcguard_hitcounts", feature = "sancov_pcguard_edges"))]
+        {
+            let thread_map = crate::coverage::thread_edges_map_ptr();
+            if !thread_map.is_null() {
+                let addr = thread_map.add(pos);
+                #[cfg(feature = "sancov_pcguard_edges")]
+                addr.write(1);
+                #[cfg(feature = "sancov_pcguard_hitcounts")]
+                addr.write(addr.read().wrapping_add(1));
+                return;
+            }
+        }
+
         #[cfg(feature = "pointer_maps")]
         {
             #[cfg(feature = "sancov_pcguard_edges")]
@@ -311,9 +325,13 @@ unsafe extern "C" fn __sanitizer_cov_pcs_init(pcs_beg: *const usize, pcs_end: *c
             "Unaligned PC Table - start: {pcs_beg:x?} end: {pcs_end:x?}"
         );
 
+        // Each entry holds the PC and the flags
         let pc_tables_ptr = &raw mut PC_TABLES;
         let pc_tables = &mut *pc_tables_ptr;
-        pc_tables.push(slice::from_raw_parts(pcs_beg as *const PcTableEntry, len));
+        pc_tables.push(slice::from_raw_parts(
+            pcs_beg as *const PcTableEntry,
+            len / 2,
+        ));
     }
 }
 
@@ -349,3 +367,50 @@ pub fn sanitizer_cov_pc_table<'a>() -> impl Iterator<Item = &'a [PcTableEntry]>
         pc_tables.iter().copied()
     }
 }
+
+/// Returns the PC of the edge at `index` in the edges map, looked up in the PC tables.
+///
+/// Guards are numbered in the order the modules register them, like their PC tables, so this only
+/// holds for plain edges maps, not for the `sancov_ngram` or `sancov_ctx` hashed indices.
+#[must_use]
+pub fn sanitizer_cov_pc_of_edge(index: usize) -> Option<usize> {
+    let mut index = index;
+    for table in sanitizer_cov_pc_table() {
+        if let Some(entry) = table.get(index) {
+            return Some(entry.addr());
+        }
+        index -= table.len();
+    }
+    None
+}
+
+/// Symbolizes the edge at `index` in the edges map as `symbol+offset (module)`, or as the plain
+/// PC if no symbol is found, for reports on map entries.
+#[cfg(unix)]
+#[must_use]
+pub fn sanitizer_cov_symbolize_edge(index: usize) -> Option<alloc::string::String> {
+    use alloc::format;
+    use core::ffi::CStr;
+
+    let pc = sanitizer_cov_pc_of_edge(index)?;
+    // SAFETY: `dladdr` only fills `info`, the strings live as long as the module is loaded
+    unsafe {
+        let mut info: libc::Dl_info = core::mem::zeroed();
+        if libc::dladdr(pc as *const libc::c_void, &raw mut info) == 0 {
+            return Some(format!("{pc:#x}"));
+        }
+        let module = if info.dli_fname.is_null() {
+            "?".into()
+        } else {
+            CStr::from_ptr(info.dli_fname).to_string_lossy()
+        };
+        if info.dli_sname.is_null() {
+            let offset = pc - info.dli_fbase as usize;
+            Some(format!("{module}+{offset:#x}"))
+        } else {
+            let symbol = CStr::from_ptr(info.dli_sname).to_string_lossy();
+            let offset = pc - info.dli_saddr as usize;
+            Some(format!("{symbol}+{offset:#x} ({module})"))
+        }
+    }
+}